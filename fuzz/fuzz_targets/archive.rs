@@ -0,0 +1,29 @@
+#![no_main]
+
+//! Parse arbitrary bytes as a layer archive and, if the header parses,
+//! run every verify check over it. Checks must report problems as
+//! findings rather than panic.
+
+use std::sync::OnceLock;
+
+use libfuzzer_sys::fuzz_target;
+use surgery::{archive::Archive, header::HeaderReport, verify::verify};
+use tokio::runtime::Runtime;
+
+fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+    })
+}
+
+fuzz_target!(|data: &[u8]| {
+    runtime().block_on(async {
+        let _ = HeaderReport::from_reader(&mut std::io::Cursor::new(data)).await;
+        if let Ok(archive) = Archive::parse(bytes::Bytes::copy_from_slice(data)).await {
+            verify(&archive, false).await;
+        }
+    });
+});
@@ -0,0 +1,30 @@
+#![no_main]
+
+//! Decode arbitrary bytes as the blocks of a dictionary, both through the
+//! streaming decoder and the block length scanner salvage relies on.
+
+use std::sync::OnceLock;
+
+use futures::StreamExt;
+use libfuzzer_sys::fuzz_target;
+use surgery::dict::{block_len, stream_blocks};
+use tokio::runtime::Runtime;
+
+fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+    })
+}
+
+fuzz_target!(|data: &[u8]| {
+    if let Some(len) = block_len(data) {
+        assert!(len <= data.len(), "block length {len} runs past the data");
+    }
+    runtime().block_on(async {
+        let mut entries = stream_blocks(std::io::Cursor::new(data));
+        while let Some(Ok(_)) = entries.next().await {}
+    });
+});
@@ -0,0 +1,18 @@
+#![no_main]
+
+//! Parse arbitrary text as a label file, with and without history, and
+//! arbitrary bytes as a rollup segment.
+
+use libfuzzer_sys::fuzz_target;
+use surgery::{
+    archive::parse_rollup,
+    store::{parse_label, parse_label_history},
+};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = parse_rollup(data);
+    if let Ok(contents) = std::str::from_utf8(data) {
+        let _ = parse_label(contents);
+        let _ = parse_label_history(contents);
+    }
+});
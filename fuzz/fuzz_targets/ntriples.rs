@@ -0,0 +1,12 @@
+#![no_main]
+
+//! Parse arbitrary text as N-Triples lines the way build-layer reads them.
+
+use libfuzzer_sys::fuzz_target;
+use surgery::build_layer::parse_line;
+
+fuzz_target!(|data: &str| {
+    for line in data.lines() {
+        let _ = parse_line(line);
+    }
+});
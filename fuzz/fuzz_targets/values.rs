@@ -0,0 +1,33 @@
+#![no_main]
+
+//! Decode arbitrary bytes as a value of each datatype the store encodes.
+//! A value that decodes must parse back from its own lexical form.
+
+use libfuzzer_sys::fuzz_target;
+use surgery::values::{decode, Decoded};
+
+const DATATYPES: &[&str] = &[
+    "String",
+    "LangString",
+    "Boolean",
+    "Int32",
+    "UInt32",
+    "Int64",
+    "UInt64",
+    "Float32",
+    "Float64",
+    "BigInt",
+    "Decimal",
+];
+
+fuzz_target!(|data: &[u8]| {
+    for datatype in DATATYPES {
+        if let Some(decoded) = decode(datatype, data) {
+            let lexical = decoded.to_string();
+            assert!(
+                Decoded::parse(&lexical, datatype).is_some(),
+                "{datatype} value {lexical:?} doesn't parse back"
+            );
+        }
+    }
+});
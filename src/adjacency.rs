@@ -0,0 +1,98 @@
+use bytes::Bytes;
+use terminus_store::structure::{bitarray::BitArray, LogArray};
+
+use crate::archive::{all_segment_types, Archive};
+
+/// The most problems reported per adjacency list.
+const MAX_FINDINGS: usize = 20;
+
+/// Check the invariants of an adjacency list given as its nums logarray
+/// and bits bitarray:
+/// - every num has a bit, and the last bit closes the last group
+/// - nums within a group are strictly increasing
+/// - unless `allow_empty`, no group is empty (a single 0 num)
+pub fn check_adjacency(nums: Bytes, bits: Bytes, allow_empty: bool) -> Vec<String> {
+    let nums = match LogArray::parse(nums) {
+        Ok(nums) => nums,
+        Err(e) => return vec![format!("nums: {e}")],
+    };
+    let bits = match BitArray::from_bits(bits) {
+        Ok(bits) => bits,
+        Err(e) => return vec![format!("bits: {e}")],
+    };
+    let mut findings = Vec::new();
+    if nums.len() != bits.len() {
+        findings.push(format!(
+            "nums has {} entries but bits has {} bits",
+            nums.len(),
+            bits.len()
+        ));
+    }
+    let len = nums.len().min(bits.len());
+    if len != 0 && !bits.get(len - 1) {
+        findings.push("last group is not terminated by a set bit".to_string());
+    }
+
+    let mut group = 1;
+    let mut group_start = true;
+    let mut previous = 0;
+    for ix in 0..len {
+        if findings.len() >= MAX_FINDINGS {
+            findings.push("too many problems, giving up".to_string());
+            break;
+        }
+        let num = nums.entry(ix);
+        let last_in_group = bits.get(ix);
+        if num == 0 {
+            if !(group_start && last_in_group) {
+                findings.push(format!(
+                    "entry {ix} (group {group}): 0 inside a non-empty group"
+                ));
+            } else if !allow_empty {
+                findings.push(format!("entry {ix}: group {group} is empty"));
+            }
+        } else if !group_start && num <= previous {
+            findings.push(format!(
+                "entry {ix} (group {group}): {num} does not follow {previous} in increasing order"
+            ));
+        }
+
+        previous = num;
+        group_start = last_in_group;
+        if last_in_group {
+            group += 1;
+        }
+    }
+
+    findings
+}
+
+/// Check every adjacency list in an archive, returning the findings per
+/// list. Only sp_o lists must not have empty groups: subjects without
+/// predicates and objects without subjects are normal.
+pub fn check_archive(archive: &Archive) -> Vec<(String, Vec<String>)> {
+    let mut result = Vec::new();
+    for nums_type in all_segment_types() {
+        let name = format!("{nums_type:?}");
+        let prefix = match name.strip_suffix("AdjacencyListNums") {
+            Some(prefix) => prefix,
+            None => continue,
+        };
+        let bits_name = format!("{prefix}AdjacencyListBits");
+        let bits_type = match all_segment_types().find(|t| format!("{t:?}") == bits_name) {
+            Some(bits_type) => bits_type,
+            None => continue,
+        };
+        let findings = match (archive.segment(nums_type), archive.segment(bits_type)) {
+            (Ok(None), Ok(None)) => continue,
+            (Ok(Some(nums)), Ok(Some(bits))) => {
+                check_adjacency(nums, bits, !prefix.contains("SpO"))
+            }
+            (Err(e), _) | (_, Err(e)) => vec![e.to_string()],
+            _ => vec!["only one of nums and bits is present".to_string()],
+        };
+        result.push((prefix.to_string(), findings));
+    }
+
+    result
+}
@@ -0,0 +1,68 @@
+use std::{io, path::Path};
+
+use terminus_store::storage::name_to_string;
+
+use crate::{
+    archive::Archive,
+    output::{paint, Color},
+    stats::triple_counts,
+    store::layer_path,
+};
+
+/// Walk the ancestry of a layer, printing for each layer whether the store
+/// has it, its triple additions and removals, and its rollup. The walk
+/// stops at the first ancestor that is missing, unreadable or has a
+/// broken parent reference. Returns whether it reached a base layer.
+pub async fn print_ancestry(store: &Path, head: [u32; 5]) -> io::Result<bool> {
+    let mut current = Some(head);
+    let mut depth = 0;
+    while let Some(name) = current {
+        let id = name_to_string(name);
+        let path = layer_path(store, name);
+        if !path.exists() {
+            println!("{depth:>4} {id}  {}", paint("MISSING", Color::Red));
+            return Ok(false);
+        }
+        let archive = match Archive::open(&path).await {
+            Ok(archive) => archive,
+            Err(e) => {
+                println!("{depth:>4} {id}  {}: {e}", paint("UNREADABLE", Color::Red));
+                return Ok(false);
+            }
+        };
+        let counts = match triple_counts(&archive) {
+            Ok((added, removed)) => format!("+{added} -{removed}"),
+            Err(_) => "counts unreadable".to_string(),
+        };
+        let rollup = match archive.rollup() {
+            Ok(None) => String::new(),
+            Ok(Some(rollup)) if layer_path(store, rollup).exists() => {
+                format!("  rollup {}", name_to_string(rollup))
+            }
+            Ok(Some(rollup)) => format!(
+                "  rollup {} {}",
+                name_to_string(rollup),
+                paint("(missing)", Color::Yellow)
+            ),
+            Err(e) => format!(
+                "  rollup {}",
+                paint(&format!("unreadable: {e}"), Color::Yellow)
+            ),
+        };
+        current = match archive.parent() {
+            Ok(parent) => parent,
+            Err(e) => {
+                println!(
+                    "{depth:>4} {id}  {counts}{rollup}  {}: {e}",
+                    paint("BROKEN PARENT", Color::Red)
+                );
+                return Ok(false);
+            }
+        };
+        let kind = if current.is_none() { "  base" } else { "" };
+        println!("{depth:>4} {id}  {counts}{rollup}{kind}");
+        depth += 1;
+    }
+
+    Ok(true)
+}
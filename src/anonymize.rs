@@ -0,0 +1,128 @@
+use std::{collections::HashMap, io, path::Path};
+
+use sha2::{Digest, Sha256};
+use terminus_store::{
+    layer::{IdTriple, ObjectType, ValueTriple},
+    store::sync::{open_sync_archive_store, SyncStoreLayer},
+    Layer,
+};
+
+/// What an anonymization rewrote.
+pub struct AnonymizeSummary {
+    pub labels: usize,
+    pub layers: usize,
+}
+
+/// Replaces strings with pseudonyms. The same string always gets the same
+/// pseudonym for a given salt, so the shape of the graph is kept, but the
+/// original can't be recovered without the salt.
+struct Pseudonyms {
+    salt: String,
+}
+
+impl Pseudonyms {
+    fn hash(&self, kind: &str, original: &[u8]) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.salt.as_bytes());
+        hasher.update(kind.as_bytes());
+        hasher.update(original);
+        format!("{:x}", hasher.finalize())[..16].to_string()
+    }
+
+    fn node(&self, iri: &str) -> String {
+        format!(
+            "http://anonymized.invalid/node/{}",
+            self.hash("node", iri.as_bytes())
+        )
+    }
+
+    fn predicate(&self, iri: &str) -> String {
+        format!(
+            "http://anonymized.invalid/predicate/{}",
+            self.hash("predicate", iri.as_bytes())
+        )
+    }
+
+    /// Values of different datatypes with the same bytes get different
+    /// pseudonyms.
+    fn value(&self, datatype: &str, bytes: &[u8]) -> String {
+        self.hash(datatype, bytes)
+    }
+
+    fn triple(&self, layer: &SyncStoreLayer, triple: &IdTriple) -> io::Result<ValueTriple> {
+        let resolved = layer.id_triple_to_string(triple).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "triple {} {} {} does not resolve to strings",
+                    triple.subject, triple.predicate, triple.object
+                ),
+            )
+        })?;
+        let subject = self.node(&resolved.subject);
+        let predicate = self.predicate(&resolved.predicate);
+        Ok(match resolved.object {
+            ObjectType::Node(node) => {
+                ValueTriple::new_node(&subject, &predicate, &self.node(&node))
+            }
+            ObjectType::Value(value) => ValueTriple::new_string_value(
+                &subject,
+                &predicate,
+                &self.value(&format!("{:?}", value.datatype()), &value.to_bytes()),
+            ),
+        })
+    }
+}
+
+/// Rewrite the chains of the given label heads into a fresh store at
+/// `output`, replacing every node, predicate and value with a pseudonym.
+/// Layers shared between chains are rewritten once, and each layer keeps
+/// its additions and removals, so the new store has the same chain shapes
+/// and triple counts as the original. Values all become strings, as
+/// their pseudonyms are.
+pub fn anonymize(
+    heads: &[(String, SyncStoreLayer)],
+    salt: &str,
+    output: &Path,
+) -> io::Result<AnonymizeSummary> {
+    let pseudonyms = Pseudonyms {
+        salt: salt.to_string(),
+    };
+    std::fs::create_dir_all(output)?;
+    let new_store = open_sync_archive_store(output, 512);
+    let mut rewritten: HashMap<[u32; 5], SyncStoreLayer> = HashMap::new();
+    for (label, head) in heads {
+        // the part of the chain not rewritten yet, from the head down
+        let mut layers = vec![head.clone()];
+        while !rewritten.contains_key(&layers.last().unwrap().name()) {
+            match layers.last().unwrap().parent()? {
+                Some(parent) => layers.push(parent),
+                None => break,
+            }
+        }
+        for layer in layers.iter().rev() {
+            if rewritten.contains_key(&layer.name()) {
+                continue;
+            }
+            let builder = match layer.parent()? {
+                None => new_store.create_base_layer()?,
+                Some(parent) => rewritten[&parent.name()].open_write()?,
+            };
+            for triple in layer.triple_additions() {
+                builder.add_value_triple(pseudonyms.triple(layer, &triple)?)?;
+            }
+            for triple in layer.triple_removals() {
+                builder.remove_value_triple(pseudonyms.triple(layer, &triple)?)?;
+            }
+            rewritten.insert(layer.name(), builder.commit()?);
+        }
+        new_store
+            .create(label)?
+            .set_head(&rewritten[&head.name()])?;
+    }
+
+    Ok(AnonymizeSummary {
+        labels: heads.len(),
+        layers: rewritten.len(),
+    })
+}
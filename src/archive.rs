@@ -0,0 +1,313 @@
+use std::{
+    fmt,
+    io::{self, SeekFrom},
+    ops::Range,
+    path::Path,
+};
+
+use bytes::Bytes;
+use num::FromPrimitive;
+use terminus_store::storage::{
+    archive::{ArchiveHeader, ArchiveSliceReader},
+    consts::LayerFileEnum,
+};
+use tokio::io::AsyncSeekExt;
+
+use crate::limits;
+
+/// Iterate over every segment type an archive header can describe, in
+/// header order.
+pub fn all_segment_types() -> impl Iterator<Item = LayerFileEnum> {
+    (0..=(LayerFileEnum::Rollup as usize)).map(|i| LayerFileEnum::from_usize(i).unwrap())
+}
+
+/// The (bits, blocks, sblocks) segment triples that make up a bitindex.
+pub fn bitindex_segments() -> Vec<(LayerFileEnum, LayerFileEnum, LayerFileEnum)> {
+    let find = |name: &str| all_segment_types().find(|t| format!("{t:?}") == name);
+    all_segment_types()
+        .filter_map(|bits| {
+            let name = format!("{bits:?}");
+            let prefix = name.strip_suffix("Bits")?;
+            let blocks = find(&format!("{prefix}BitIndexBlocks"))?;
+            let sblocks = find(&format!("{prefix}BitIndexSBlocks"))?;
+            Some((bits, blocks, sblocks))
+        })
+        .collect()
+}
+
+/// A fully loaded `.larch` archive.
+pub struct Archive {
+    pub header: ArchiveHeader,
+    header_len: usize,
+    contents: Bytes,
+}
+
+impl Archive {
+    /// Load an archive whole. Fails if it is larger than `--max-memory`.
+    pub async fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let size = tokio::fs::metadata(&path).await?.len();
+        limits::check_load(path.as_ref(), size)?;
+        let contents = tokio::fs::read(path).await?;
+        Self::parse(contents.into()).await
+    }
+
+    pub async fn parse(contents: Bytes) -> io::Result<Self> {
+        let mut reader: &[u8] = &contents;
+        let header = ArchiveHeader::parse_from_reader(&mut reader).await?;
+        let header_len = contents.len() - reader.len();
+
+        Ok(Self {
+            header,
+            header_len,
+            contents,
+        })
+    }
+
+    /// Size in bytes of the header itself. Segment ranges are relative to
+    /// the end of the header.
+    pub fn header_len(&self) -> usize {
+        self.header_len
+    }
+
+    /// The raw contents of the whole archive file.
+    pub fn contents(&self) -> &Bytes {
+        &self.contents
+    }
+
+    /// The offset in the file just past the last segment.
+    pub fn segments_end(&self) -> usize {
+        let end = self.segments().iter().map(|(_, r)| r.end).max();
+        (self.header_len + end.unwrap_or(0)).min(self.contents.len())
+    }
+
+    /// Any bytes after the last segment.
+    pub fn trailer(&self) -> Bytes {
+        self.contents.slice(self.segments_end()..)
+    }
+
+    /// All segments present in the header, with their relative ranges.
+    pub fn segments(&self) -> Vec<(LayerFileEnum, Range<usize>)> {
+        all_segment_types()
+            .filter_map(|t| self.header.range_for(t).map(|r| (t, r)))
+            .collect()
+    }
+
+    /// The range of a segment within the archive file, accounting for the
+    /// header.
+    pub fn absolute_range(&self, file_type: LayerFileEnum) -> Option<Range<usize>> {
+        self.header
+            .range_for(file_type)
+            .map(|r| self.header_len + r.start..self.header_len + r.end)
+    }
+
+    /// The raw bytes of a segment, or None if the header doesn't list it.
+    ///
+    /// Fails if the header claims the segment extends past the end of the file.
+    pub fn segment(&self, file_type: LayerFileEnum) -> io::Result<Option<Bytes>> {
+        match self.absolute_range(file_type) {
+            None => Ok(None),
+            Some(range) => {
+                if range.end > self.contents.len() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        format!(
+                            "segment {file_type:?} ends at offset {}, but archive is only {} bytes",
+                            range.end,
+                            self.contents.len()
+                        ),
+                    ));
+                }
+                Ok(Some(self.contents.slice(range)))
+            }
+        }
+    }
+
+    /// The name of the parent layer, if this is a child layer.
+    pub fn parent(&self) -> io::Result<Option<[u32; 5]>> {
+        match self.segment(LayerFileEnum::Parent)? {
+            None => Ok(None),
+            Some(bytes) => parse_layer_name(&bytes).map(Some),
+        }
+    }
+
+    /// The name of the layer that rolls this one up, if any.
+    pub fn rollup(&self) -> io::Result<Option<[u32; 5]>> {
+        match self.segment(LayerFileEnum::Rollup)? {
+            None => Ok(None),
+            Some(bytes) => parse_rollup(&bytes).map(Some),
+        }
+    }
+}
+
+/// Serialize an archive header for segments of the given sizes, which must
+/// be in header order. The header is a big-endian u64 with a bit set for
+/// every segment type present, the most significant bit for the first
+/// type, followed by the size of each present segment as a big-endian
+/// u64.
+pub fn encode_header(segments: &[(LayerFileEnum, usize)]) -> Vec<u8> {
+    let presence = segments.iter().fold(0u64, |presence, (t, _)| {
+        presence | (0x8000_0000_0000_0000u64 >> (*t as usize))
+    });
+    let mut result = presence.to_be_bytes().to_vec();
+    for (_, size) in segments {
+        result.extend_from_slice(&(*size as u64).to_be_bytes());
+    }
+    result
+}
+
+/// A segment the header of a layer file doesn't list. Some layers
+/// legitimately lack segments, such as base layers without removals, so
+/// commands can skip these where other failures must stop them.
+#[derive(Debug)]
+struct AbsentSegment(LayerFileEnum);
+
+impl fmt::Display for AbsentSegment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "layer does not contain {:?}", self.0)
+    }
+}
+
+impl std::error::Error for AbsentSegment {}
+
+/// The error for a segment that a layer file doesn't have.
+pub fn absent_segment(file_type: LayerFileEnum) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, AbsentSegment(file_type))
+}
+
+/// Whether an error is only that a segment is absent, as opposed to the
+/// file or its header being unreadable.
+pub fn is_absent(e: &io::Error) -> bool {
+    e.get_ref()
+        .map(|e| e.is::<AbsentSegment>())
+        .unwrap_or(false)
+}
+
+/// Decode a layer name stored as five big-endian u32s.
+pub fn parse_layer_name(bytes: &[u8]) -> io::Result<[u32; 5]> {
+    if bytes.len() != 20 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("expected a 20 byte layer name, found {} bytes", bytes.len()),
+        ));
+    }
+    let mut name = [0; 5];
+    for (i, chunk) in bytes.chunks(4).enumerate() {
+        name[i] = u32::from_be_bytes(chunk.try_into().unwrap());
+    }
+
+    Ok(name)
+}
+
+/// Decode the layer name in a rollup segment, which holds a two byte
+/// version before the name.
+pub fn parse_rollup(bytes: &[u8]) -> io::Result<[u32; 5]> {
+    match bytes.len() {
+        22 => parse_layer_name(&bytes[2..]),
+        _ => parse_layer_name(bytes),
+    }
+}
+
+/// Open a reader over one segment of a layer file, reading only the header
+/// up front.
+pub async fn open_slice<P: AsRef<Path>>(
+    path: P,
+    file_type: LayerFileEnum,
+) -> io::Result<ArchiveSliceReader> {
+    let mut reader = tokio::fs::File::open(path).await?;
+    let header = ArchiveHeader::parse_from_reader(&mut reader).await?;
+
+    let range = header
+        .range_for(file_type)
+        .ok_or_else(|| absent_segment(file_type))?;
+    let remaining = range.len();
+    reader.seek(SeekFrom::Current((range.start) as i64)).await?;
+
+    Ok(ArchiveSliceReader::new(reader, remaining))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// An archive of the given segments, which must be in header order,
+    /// followed by `trailer`.
+    pub(crate) async fn archive(segments: &[(LayerFileEnum, &[u8])], trailer: &[u8]) -> Archive {
+        let sizes: Vec<_> = segments.iter().map(|(t, s)| (*t, s.len())).collect();
+        let mut contents = encode_header(&sizes);
+        for (_, segment) in segments {
+            contents.extend_from_slice(segment);
+        }
+        contents.extend_from_slice(trailer);
+        Archive::parse(contents.into()).await.unwrap()
+    }
+
+    const NAME: [u32; 5] = [1, 2, 3, 4, 5];
+
+    fn name_bytes(name: [u32; 5]) -> Vec<u8> {
+        name.iter().flat_map(|n| n.to_be_bytes()).collect()
+    }
+
+    #[test]
+    fn encode_header_sets_a_bit_per_segment_and_lists_sizes() {
+        let header = encode_header(&[(LayerFileEnum::Parent, 20), (LayerFileEnum::Rollup, 22)]);
+        assert_eq!(header.len(), 24);
+        let presence = u64::from_be_bytes(header[..8].try_into().unwrap());
+        let bit = |t: LayerFileEnum| (1u64 << 63) >> (t as usize);
+        assert_eq!(
+            presence,
+            bit(LayerFileEnum::Parent) | bit(LayerFileEnum::Rollup)
+        );
+        assert_eq!(u64::from_be_bytes(header[8..16].try_into().unwrap()), 20);
+        assert_eq!(u64::from_be_bytes(header[16..].try_into().unwrap()), 22);
+    }
+
+    #[test]
+    fn encode_header_of_no_segments_is_an_empty_presence_word() {
+        assert_eq!(encode_header(&[]), vec![0; 8]);
+    }
+
+    #[tokio::test]
+    async fn encoded_header_parses_back_to_the_same_ranges() {
+        let rollup: Vec<u8> = [0, 1].into_iter().chain(name_bytes(NAME)).collect();
+        let archive = archive(
+            &[
+                (LayerFileEnum::Parent, &name_bytes(NAME)[..]),
+                (LayerFileEnum::Rollup, &rollup[..]),
+            ],
+            &[],
+        )
+        .await;
+        assert_eq!(archive.header_len(), 24);
+        assert_eq!(archive.header.range_for(LayerFileEnum::Parent), Some(0..20));
+        assert_eq!(
+            archive.header.range_for(LayerFileEnum::Rollup),
+            Some(20..42)
+        );
+        assert_eq!(archive.parent().unwrap(), Some(NAME));
+        assert_eq!(archive.rollup().unwrap(), Some(NAME));
+        assert!(archive.trailer().is_empty());
+    }
+
+    #[tokio::test]
+    async fn segment_past_the_end_of_the_file_is_an_error() {
+        let mut contents = encode_header(&[(LayerFileEnum::Parent, 20)]);
+        contents.extend_from_slice(&[0; 10]);
+        let archive = Archive::parse(contents.into()).await.unwrap();
+        let e = archive.segment(LayerFileEnum::Parent).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn layer_names_must_be_20_bytes() {
+        let e = parse_layer_name(&[0; 19]).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rollup_names_may_carry_a_version() {
+        let bare = name_bytes(NAME);
+        let versioned: Vec<u8> = [0, 1].into_iter().chain(bare.clone()).collect();
+        assert_eq!(parse_rollup(&bare).unwrap(), NAME);
+        assert_eq!(parse_rollup(&versioned).unwrap(), NAME);
+    }
+}
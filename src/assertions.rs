@@ -0,0 +1,317 @@
+use std::{fmt, io};
+
+use serde_json::json;
+use terminus_store::{
+    layer::{IdTriple, ObjectType, ValueTriple},
+    store::sync::SyncStoreLayer,
+    Layer,
+};
+
+use crate::{
+    output::{ndjson, paint, Color, OutputFormat},
+    schema::RDF_TYPE,
+};
+
+/// One position of a triple pattern.
+#[derive(Clone, Debug)]
+pub enum Term {
+    /// `*`, matching anything
+    Any,
+    /// `<iri>`
+    Node(String),
+    /// `"text"`, a string value
+    Value(String),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "=" | "==" => Some(Comparison::Eq),
+            "!=" => Some(Comparison::Ne),
+            "<" => Some(Comparison::Lt),
+            "<=" => Some(Comparison::Le),
+            ">" => Some(Comparison::Gt),
+            ">=" => Some(Comparison::Ge),
+            _ => None,
+        }
+    }
+
+    fn holds(self, actual: u64, expected: u64) -> bool {
+        match self {
+            Comparison::Eq => actual == expected,
+            Comparison::Ne => actual != expected,
+            Comparison::Lt => actual < expected,
+            Comparison::Le => actual <= expected,
+            Comparison::Gt => actual > expected,
+            Comparison::Ge => actual >= expected,
+        }
+    }
+}
+
+/// An assertion about the triples of a layer.
+#[derive(Clone, Debug)]
+pub enum Assertion {
+    /// `exists S P O`: some triple matches the pattern.
+    Exists([Term; 3]),
+    /// `absent S P O`: no triple matches the pattern.
+    Absent([Term; 3]),
+    /// `count S P O OP N`: the number of matching triples compares to N.
+    Count([Term; 3], Comparison, u64),
+    /// `node <iri>`: the node is the subject or object of some triple.
+    Node(String),
+    /// `class <iri> OP N`: the number of instances of the class compares
+    /// to N.
+    Class(String, Comparison, u64),
+}
+
+/// An assertion with where it was written.
+pub struct AssertionLine {
+    pub number: usize,
+    pub text: String,
+    pub assertion: Assertion,
+}
+
+enum Token {
+    Iri(String),
+    Literal(String),
+    Word(String),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Iri(iri) => write!(f, "<{iri}>"),
+            Token::Literal(text) => write!(f, "{text:?}"),
+            Token::Word(word) => write!(f, "{word}"),
+        }
+    }
+}
+
+fn tokenize(line: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = line.trim_start();
+    while !rest.is_empty() {
+        // `<` and `<=` on their own are comparisons
+        let iri = rest.len() > 1 && !rest[1..].starts_with(|c: char| c == '=' || c.is_whitespace());
+        let (token, len) = if rest.starts_with('<') && iri {
+            let end = rest.find('>').ok_or("unterminated <iri>")?;
+            (Token::Iri(rest[1..end].to_string()), end + 1)
+        } else if rest.starts_with('"') {
+            let mut escaped = false;
+            let end = rest[1..]
+                .find(|c| {
+                    let close = c == '"' && !escaped;
+                    escaped = c == '\\' && !escaped;
+                    close
+                })
+                .ok_or("unterminated string")?;
+            let text = serde_json::from_str(&rest[..end + 2]).map_err(|e| e.to_string())?;
+            (Token::Literal(text), end + 2)
+        } else {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            (Token::Word(rest[..end].to_string()), end)
+        };
+        tokens.push(token);
+        rest = rest[len..].trim_start();
+    }
+
+    Ok(tokens)
+}
+
+fn term(token: &Token) -> Result<Term, String> {
+    match token {
+        Token::Word(word) if word == "*" => Ok(Term::Any),
+        Token::Iri(iri) => Ok(Term::Node(iri.clone())),
+        Token::Literal(text) => Ok(Term::Value(text.clone())),
+        Token::Word(word) => Err(format!("expected <iri>, \"text\" or *, found {word}")),
+    }
+}
+
+fn iri(token: &Token) -> Result<String, String> {
+    match token {
+        Token::Iri(iri) => Ok(iri.clone()),
+        other => Err(format!("expected <iri>, found {other}")),
+    }
+}
+
+fn comparison(op: &Token, n: &Token) -> Result<(Comparison, u64), String> {
+    let op = match op {
+        Token::Word(op) => Comparison::parse(op),
+        _ => None,
+    }
+    .ok_or_else(|| format!("expected one of = != < <= > >=, found {op}"))?;
+    let n = match n {
+        Token::Word(n) => n.parse().ok(),
+        _ => None,
+    }
+    .ok_or_else(|| format!("expected a count, found {n}"))?;
+
+    Ok((op, n))
+}
+
+fn parse_assertion(tokens: &[Token]) -> Result<Assertion, String> {
+    let keyword = match tokens.first() {
+        Some(Token::Word(keyword)) => keyword.as_str(),
+        _ => return Err("expected exists, absent, count, node or class".to_string()),
+    };
+    let pattern = |s: &Token, p: &Token, o: &Token| -> Result<[Term; 3], String> {
+        Ok([term(s)?, term(p)?, term(o)?])
+    };
+    match (keyword, &tokens[1..]) {
+        ("exists", [s, p, o]) => Ok(Assertion::Exists(pattern(s, p, o)?)),
+        ("absent", [s, p, o]) => Ok(Assertion::Absent(pattern(s, p, o)?)),
+        ("count", [s, p, o, op, n]) => {
+            let (op, n) = comparison(op, n)?;
+            Ok(Assertion::Count(pattern(s, p, o)?, op, n))
+        }
+        ("node", [node]) => Ok(Assertion::Node(iri(node)?)),
+        ("class", [class, op, n]) => {
+            let (op, n) = comparison(op, n)?;
+            Ok(Assertion::Class(iri(class)?, op, n))
+        }
+        ("exists" | "absent", _) => Err(format!("usage: {keyword} S P O")),
+        ("count", _) => Err("usage: count S P O OP N".to_string()),
+        ("node", _) => Err("usage: node <iri>".to_string()),
+        ("class", _) => Err("usage: class <iri> OP N".to_string()),
+        _ => Err(format!("unknown assertion {keyword}")),
+    }
+}
+
+/// Parse an assertions file: one assertion per line, with blank lines and
+/// lines starting with `#` ignored. Terms are written `<iri>`, `"text"`
+/// for a string value, or `*` for anything.
+pub fn parse(contents: &str) -> io::Result<Vec<AssertionLine>> {
+    let mut result = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        let text = line.trim();
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+        let assertion = tokenize(text)
+            .and_then(|tokens| parse_assertion(&tokens))
+            .map_err(|e| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {e}", i + 1))
+            })?;
+        result.push(AssertionLine {
+            number: i + 1,
+            text: text.to_string(),
+            assertion,
+        });
+    }
+
+    Ok(result)
+}
+
+/// Count the triples of a layer that match a pattern. A term the layer has
+/// never seen matches nothing.
+pub fn count(layer: &SyncStoreLayer, pattern: &[Term; 3]) -> u64 {
+    let node = |term: &Term, lookup: &dyn Fn(&str) -> Option<u64>| match term {
+        Term::Any => Some(None),
+        Term::Node(iri) => lookup(iri).map(Some),
+        Term::Value(_) => None,
+    };
+    let subject = node(&pattern[0], &|s| layer.subject_id(s));
+    let predicate = node(&pattern[1], &|p| layer.predicate_id(p));
+    let object = match &pattern[2] {
+        Term::Value(text) => match ValueTriple::new_string_value("", "", text).object {
+            ObjectType::Value(value) => layer.object_value_id(&value).map(Some),
+            ObjectType::Node(_) => None,
+        },
+        term => node(term, &|o| layer.object_node_id(o)),
+    };
+    let (subject, predicate, object) = match (subject, predicate, object) {
+        (Some(s), Some(p), Some(o)) => (s, p, o),
+        _ => return 0,
+    };
+
+    let triples: Box<dyn Iterator<Item = IdTriple>> = match (subject, predicate, object) {
+        (Some(s), Some(p), _) => Box::new(layer.triples_sp(s, p)),
+        (Some(s), None, _) => Box::new(layer.triples_s(s)),
+        (None, _, Some(o)) => Box::new(layer.triples_o(o)),
+        (None, Some(p), None) => Box::new(layer.triples_p(p)),
+        (None, None, None) => Box::new(layer.triples()),
+    };
+    triples
+        .filter(|t| {
+            subject.map(|s| s == t.subject).unwrap_or(true)
+                && predicate.map(|p| p == t.predicate).unwrap_or(true)
+                && object.map(|o| o == t.object).unwrap_or(true)
+        })
+        .count() as u64
+}
+
+/// Evaluate an assertion against a layer. Returns whether it holds, and
+/// the count it was decided on.
+pub fn evaluate(layer: &SyncStoreLayer, assertion: &Assertion) -> (bool, u64) {
+    match assertion {
+        Assertion::Exists(pattern) => {
+            let n = count(layer, pattern);
+            (n > 0, n)
+        }
+        Assertion::Absent(pattern) => {
+            let n = count(layer, pattern);
+            (n == 0, n)
+        }
+        Assertion::Count(pattern, op, expected) => {
+            let n = count(layer, pattern);
+            (op.holds(n, *expected), n)
+        }
+        Assertion::Node(iri) => {
+            let node = Term::Node(iri.clone());
+            let n = count(layer, &[node.clone(), Term::Any, Term::Any])
+                + count(layer, &[Term::Any, Term::Any, node]);
+            (n > 0, n)
+        }
+        Assertion::Class(class, op, expected) => {
+            let pattern = [
+                Term::Any,
+                Term::Node(RDF_TYPE.to_string()),
+                Term::Node(class.clone()),
+            ];
+            let n = count(layer, &pattern);
+            (op.holds(n, *expected), n)
+        }
+    }
+}
+
+/// Print whether an assertion held, with the count it was decided on.
+pub fn print_outcome(line: &AssertionLine, pass: bool, actual: u64, format: OutputFormat) {
+    match format {
+        OutputFormat::Ndjson => println!(
+            "{}",
+            ndjson(json!({
+                "line": line.number,
+                "assertion": line.text,
+                "status": if pass { "pass" } else { "fail" },
+                "actual": actual,
+            }))
+        ),
+        OutputFormat::Pretty => {
+            let status = if pass {
+                paint("PASS", Color::Green)
+            } else {
+                paint("FAIL", Color::Red)
+            };
+            println!(
+                "{status}  {:>4}  {}  {}",
+                line.number,
+                line.text,
+                paint(&format!("(found {actual})"), Color::Dim)
+            );
+        }
+        OutputFormat::Text => {
+            let status = if pass { "PASS" } else { "FAIL" };
+            println!("{status} {} {} ({actual})", line.number, line.text);
+        }
+    }
+}
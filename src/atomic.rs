@@ -0,0 +1,67 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+/// Where to write a file before renaming it into place. It's in the same
+/// directory so the rename stays on one filesystem, and its name doesn't
+/// end in `.larch` or `.label`, so a leftover is never taken for a layer or
+/// label.
+pub fn temp_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{name}.{}.tmp", std::process::id()))
+}
+
+/// Write a file by writing a temporary file next to it and renaming that
+/// over it once complete. On failure the temporary file is removed and the
+/// target is left as it was.
+pub async fn write<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, contents: C) -> io::Result<()> {
+    let path = path.as_ref();
+    let tmp = temp_path(path);
+    let result = match tokio::fs::write(&tmp, contents).await {
+        Ok(()) => tokio::fs::rename(&tmp, path).await,
+        Err(e) => Err(e),
+    };
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(&tmp).await;
+    }
+
+    result
+}
+
+/// Finish an operation that wrote several files through their temporary
+/// paths: rename them all into place if it succeeded, or remove them if
+/// it failed.
+pub async fn finish(targets: &[PathBuf], result: io::Result<()>) -> io::Result<()> {
+    if let Err(e) = result {
+        for target in targets {
+            let _ = tokio::fs::remove_file(temp_path(target)).await;
+        }
+        return Err(e);
+    }
+    for target in targets {
+        tokio::fs::rename(temp_path(target), target).await?;
+    }
+
+    Ok(())
+}
+
+/// Like `write`, but leave the file alone if it already has exactly these
+/// contents, so rerunning a repair is harmless. Returns whether anything
+/// was written.
+pub async fn write_if_changed<P: AsRef<Path>, C: AsRef<[u8]>>(
+    path: P,
+    contents: C,
+) -> io::Result<bool> {
+    if let Ok(existing) = tokio::fs::read(path.as_ref()).await {
+        if existing == contents.as_ref() {
+            return Ok(false);
+        }
+    }
+    write(path, contents).await?;
+
+    Ok(true)
+}
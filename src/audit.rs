@@ -0,0 +1,178 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+
+fn audit_log_path(store: &Path) -> PathBuf {
+    let mut path = store.to_path_buf();
+    path.push(".surgery");
+    path.push("audit.log");
+    path
+}
+
+/// The store whose audit log records a change to `layer_file`: the one
+/// given, or else the store the file sits in when it is laid out as a
+/// store keeps it, `<store>/<abc>/<abc...>.larch`. Fails if neither is
+/// known rather than log the change wherever the command happens to run.
+pub fn log_store(store: Option<String>, layer_file: &Path) -> io::Result<PathBuf> {
+    if let Some(store) = store {
+        return Ok(PathBuf::from(store));
+    }
+    let in_store = || {
+        let path = layer_file.canonicalize().ok()?;
+        let name = path.file_name()?.to_str()?.strip_suffix(".larch")?;
+        let prefix = path.parent()?;
+        if name.len() != 40
+            || !name.chars().all(|c| c.is_ascii_hexdigit())
+            || prefix.file_name()?.to_str()? != &name[..3]
+        {
+            return None;
+        }
+        prefix.parent().map(Path::to_path_buf)
+    };
+    in_store().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{} is not inside a store; give --store for the audit log",
+                layer_file.display()
+            ),
+        )
+    })
+}
+
+/// The user to record, from the environment, or else the uid the command
+/// runs as.
+fn user() -> String {
+    ["USER", "LOGNAME", "USERNAME"]
+        .iter()
+        .find_map(|var| std::env::var(var).ok().filter(|user| !user.is_empty()))
+        .or_else(|| {
+            let status = std::fs::read_to_string("/proc/self/status").ok()?;
+            let uid = status.lines().find_map(|line| line.strip_prefix("Uid:"))?;
+            Some(format!("uid {}", uid.split_whitespace().next()?))
+        })
+        .unwrap_or_default()
+}
+
+/// Hash a file's contents, or None if it doesn't exist.
+pub async fn sha256_file(path: &Path) -> io::Result<Option<String>> {
+    match tokio::fs::read(path).await {
+        Ok(contents) => Ok(Some(format!("{:x}", Sha256::digest(&contents)))),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// A record of one mutating operation, written to the store's audit log
+/// once the operation has finished.
+pub struct Audit {
+    command: String,
+    started: SystemTime,
+    files: Vec<(PathBuf, Option<String>)>,
+    details: Vec<(String, String)>,
+}
+
+impl Audit {
+    pub fn begin(command: &str) -> Self {
+        Self {
+            command: command.to_string(),
+            started: SystemTime::now(),
+            files: Vec::new(),
+            details: Vec::new(),
+        }
+    }
+
+    /// Record a piece of information that the file checksums don't
+    /// capture, such as the layer a label pointed at before.
+    pub fn note(&mut self, key: &str, value: String) {
+        self.details.push((key.to_string(), value));
+    }
+
+    /// Remember the current checksum of a file the operation will change.
+    pub async fn track(&mut self, path: &Path) -> io::Result<()> {
+        let before = sha256_file(path).await?;
+        self.files.push((path.to_path_buf(), before));
+        Ok(())
+    }
+
+    /// Append the record, with the checksums of the tracked files as they
+    /// are now.
+    pub async fn commit(self, store: &Path) -> io::Result<()> {
+        let mut files = Vec::new();
+        for (path, before) in self.files {
+            let after = sha256_file(&path).await?;
+            files.push(json!({
+                "path": path.display().to_string(),
+                "before": before,
+                "after": after,
+            }));
+        }
+        let record = json!({
+            "timestamp": humantime::format_rfc3339_seconds(self.started).to_string(),
+            "user": user(),
+            "command": self.command,
+            "arguments": std::env::args().skip(1).collect::<Vec<_>>(),
+            "files": files,
+            "details": self
+                .details
+                .into_iter()
+                .map(|(key, value)| (key, Value::String(value)))
+                .collect::<serde_json::Map<_, _>>(),
+        });
+
+        let path = audit_log_path(store);
+        tokio::fs::create_dir_all(path.parent().unwrap()).await?;
+        let mut log = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await?;
+        log.write_all(format!("{record}\n").as_bytes()).await?;
+        log.flush().await
+    }
+}
+
+/// Print the audit log of a store, oldest record first.
+pub async fn show(store: &Path) -> io::Result<()> {
+    let contents = match tokio::fs::read_to_string(audit_log_path(store)).await {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    for line in contents.lines() {
+        let record: Value = serde_json::from_str(line)?;
+        println!(
+            "{} {} {}: {}",
+            record["timestamp"].as_str().unwrap_or("?"),
+            record["user"].as_str().unwrap_or("?"),
+            record["command"].as_str().unwrap_or("?"),
+            record["arguments"]
+                .as_array()
+                .map(|args| args
+                    .iter()
+                    .filter_map(|a| a.as_str())
+                    .collect::<Vec<_>>()
+                    .join(" "))
+                .unwrap_or_default()
+        );
+        for file in record["files"].as_array().into_iter().flatten() {
+            println!(
+                "    {} {} -> {}",
+                file["path"].as_str().unwrap_or("?"),
+                file["before"].as_str().unwrap_or("(none)"),
+                file["after"].as_str().unwrap_or("(none)")
+            );
+        }
+        for (key, value) in record["details"].as_object().into_iter().flatten() {
+            println!("    {key}: {}", value.as_str().unwrap_or("?"));
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,261 @@
+use std::{
+    io::{self, Cursor, SeekFrom},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use bytes::Bytes;
+use clap::ValueEnum;
+use terminus_store::storage::consts::LayerFileEnum;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use crate::{archive::absent_segment, atomic, header::HeaderReport};
+
+/// How much of a remote layer is fetched to parse its header, doubled
+/// until the header fits.
+const HEADER_FETCH: u64 = 4096;
+
+/// Headers list a few dozen segments at most; anything longer than this
+/// isn't a header.
+const MAX_HEADER: u64 = 1024 * 1024;
+
+/// Where the commands that read a single layer file find it.
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BackendKind {
+    /// Layer files are paths on the local filesystem
+    #[default]
+    Dir,
+    /// Layer files are keys in an S3 bucket, read with ranged requests
+    /// through the AWS CLI
+    S3,
+}
+
+/// The backend set for this run, and the bucket and prefix of an S3
+/// backend.
+pub enum Backend {
+    Dir,
+    S3 { bucket: String, prefix: String },
+}
+
+static BACKEND: OnceLock<Backend> = OnceLock::new();
+
+/// Filesystem types whose reads go over the network.
+const NETWORK_FILESYSTEMS: &[&str] = &[
+    "nfs",
+    "nfs4",
+    "cifs",
+    "smb3",
+    "smbfs",
+    "9p",
+    "ceph",
+    "glusterfs",
+    "lustre",
+    "gpfs",
+    "fuse.sshfs",
+    "fuse.s3fs",
+    "fuse.gcsfuse",
+];
+
+/// What the store is kept on, as far as sizing the runtime goes.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Storage {
+    /// A local disk, where each read returns quickly
+    Local,
+    /// A network filesystem such as NFS, where each read waits on a round
+    /// trip
+    Network,
+    /// An S3 bucket, read through AWS CLI processes the runtime waits on
+    /// without blocking
+    Remote,
+}
+
+/// The storage a store at `path` is kept on. Directories are looked up in
+/// /proc/mounts; where that can't be read the storage is taken as local.
+pub fn storage(kind: BackendKind, path: &Path) -> Storage {
+    if kind == BackendKind::S3 {
+        return Storage::Remote;
+    }
+    let path = match path.canonicalize() {
+        Ok(path) => path,
+        Err(_) => return Storage::Local,
+    };
+    let mounts = std::fs::read_to_string("/proc/mounts").unwrap_or_default();
+    let fs_type = mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split(' ');
+            let mount_point = fields.nth(1)?.replace("\\040", " ");
+            let fs_type = fields.next()?;
+            path.starts_with(&mount_point)
+                .then_some((mount_point.len(), fs_type))
+        })
+        .max_by_key(|(len, _)| *len)
+        .map(|(_, fs_type)| fs_type);
+    match fs_type {
+        Some(fs_type) if NETWORK_FILESYSTEMS.contains(&fs_type) => Storage::Network,
+        _ => Storage::Local,
+    }
+}
+
+impl Storage {
+    /// The default number of worker and blocking threads for the runtime.
+    /// Workers match the cores either way. Blocking file IO keeps tokio's
+    /// 512 threads on local disks and S3, whose reads don't block on the
+    /// network, and gets four times as many on a network filesystem so
+    /// enough reads are in flight to hide the round trips.
+    pub fn threads(self) -> (usize, usize) {
+        let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let blocking = match self {
+            Storage::Local | Storage::Remote => 512,
+            Storage::Network => 2048,
+        };
+        (cores, blocking)
+    }
+}
+
+/// Read layer files through the given backend for the rest of the run.
+pub fn set(backend: Backend) {
+    let _ = BACKEND.set(backend);
+}
+
+/// A layer file on whichever backend is set.
+pub enum LayerSource {
+    File(PathBuf),
+    S3 { bucket: String, key: String },
+}
+
+/// Whether a name is a bare layer name, 40 hex digits.
+fn is_layer_name(name: &str) -> bool {
+    name.len() == 40 && name.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+impl LayerSource {
+    /// The layer file a command was given. On an S3 backend, the name is
+    /// a key under the prefix; a bare layer name is laid out as a store
+    /// directory would keep it.
+    pub fn open(name: &str) -> Self {
+        match BACKEND.get().unwrap_or(&Backend::Dir) {
+            Backend::Dir => LayerSource::File(PathBuf::from(name)),
+            Backend::S3 { bucket, prefix } => {
+                let name = if is_layer_name(name) {
+                    format!("{}/{name}.larch", &name[..3])
+                } else {
+                    name.to_string()
+                };
+                let key = match prefix.trim_end_matches('/') {
+                    "" => name,
+                    prefix => format!("{prefix}/{name}"),
+                };
+                LayerSource::S3 {
+                    bucket: bucket.clone(),
+                    key,
+                }
+            }
+        }
+    }
+
+    pub fn is_remote(&self) -> bool {
+        !matches!(self, LayerSource::File(_))
+    }
+
+    /// Read a byte range of the layer file. A remote read may come back
+    /// short if the range runs past the end of the file.
+    pub async fn read_range(&self, range: Range<u64>) -> io::Result<Bytes> {
+        match self {
+            LayerSource::File(path) => {
+                let mut file = tokio::fs::File::open(path).await?;
+                file.seek(SeekFrom::Start(range.start)).await?;
+                let mut bytes = Vec::new();
+                file.take(range.end - range.start)
+                    .read_to_end(&mut bytes)
+                    .await?;
+                Ok(bytes.into())
+            }
+            LayerSource::S3 { bucket, key } => s3_range(bucket, key, range).await,
+        }
+    }
+
+    /// Read only the header of the layer file.
+    pub async fn header(&self) -> io::Result<HeaderReport> {
+        if let LayerSource::File(path) = self {
+            return HeaderReport::read(path).await;
+        }
+        let mut fetch = HEADER_FETCH;
+        loop {
+            let bytes = self.read_range(0..fetch).await?;
+            let complete = (bytes.len() as u64) < fetch;
+            match HeaderReport::from_reader(&mut Cursor::new(bytes)).await {
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof && !complete => {
+                    fetch *= 2;
+                    if fetch > MAX_HEADER {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("no header within the first {MAX_HEADER} bytes"),
+                        ));
+                    }
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Read one segment of the layer file, fetching only the header and
+    /// the segment's own bytes.
+    pub async fn segment(&self, file_type: LayerFileEnum) -> io::Result<Bytes> {
+        let header = self.header().await?;
+        let info = header
+            .segments
+            .iter()
+            .find(|s| s.segment == file_type)
+            .ok_or_else(|| absent_segment(file_type))?;
+        let start = (header.header_len + info.range.start) as u64;
+        let bytes = self
+            .read_range(start..start + info.range.len() as u64)
+            .await?;
+        if bytes.len() != info.range.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!(
+                    "layer file ends {} bytes into a {} byte segment",
+                    bytes.len(),
+                    info.range.len()
+                ),
+            ));
+        }
+
+        Ok(bytes)
+    }
+}
+
+/// Fetch a byte range of an S3 object. The AWS CLI writes the object to a
+/// file and its metadata to stdout, so the range goes through a temporary
+/// file.
+async fn s3_range(bucket: &str, key: &str, range: Range<u64>) -> io::Result<Bytes> {
+    if range.is_empty() {
+        return Ok(Bytes::new());
+    }
+    let name = key.rsplit('/').next().unwrap_or(key);
+    let tmp = atomic::temp_path(&std::env::temp_dir().join(name));
+    let result = async {
+        let output = tokio::process::Command::new("aws")
+            .args(["s3api", "get-object", "--bucket", bucket, "--key", key])
+            .arg("--range")
+            .arg(format!("bytes={}-{}", range.start, range.end - 1))
+            .arg(&tmp)
+            .output()
+            .await?;
+        if !output.status.success() {
+            let error = String::from_utf8_lossy(&output.stderr);
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("s3://{bucket}/{key}: {}", error.trim()),
+            ));
+        }
+        tokio::fs::read(&tmp).await
+    }
+    .await;
+    let _ = tokio::fs::remove_file(&tmp).await;
+
+    result.map(Bytes::from)
+}
@@ -0,0 +1,453 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{self, Read},
+    path::Path,
+    time::SystemTime,
+};
+
+use bytes::Bytes;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use terminus_store::storage::{name_to_string, string_to_name};
+
+use crate::{
+    archive::Archive,
+    atomic,
+    store::{label_path, layer_path, list_labels, list_layers, parse_label, read_label},
+    verify::verify,
+};
+
+/// The layers and labels found in a backup.
+#[derive(Default)]
+struct Backup {
+    layers: BTreeMap<[u32; 5], Bytes>,
+    labels: BTreeMap<String, Option<[u32; 5]>>,
+}
+
+impl Backup {
+    async fn from_dir(path: &Path) -> io::Result<Self> {
+        let mut backup = Self::default();
+        for (name, layer_path) in list_layers(path).await? {
+            backup
+                .layers
+                .insert(name, tokio::fs::read(layer_path).await?.into());
+        }
+        for label in list_labels(path).await? {
+            let head = read_label(path, &label).await?;
+            backup.labels.insert(label, head);
+        }
+        Ok(backup)
+    }
+
+    /// Read a tar archive of a store without unpacking it.
+    fn from_tar(path: &Path) -> io::Result<Self> {
+        let mut backup = Self::default();
+        let mut tar = tar::Archive::new(std::fs::File::open(path)?);
+        for entry in tar.entries()? {
+            let mut entry = entry?;
+            let entry_path = entry.path()?.to_path_buf();
+            let (stem, extension) = match (
+                entry_path.file_stem().and_then(|s| s.to_str()),
+                entry_path.extension().and_then(|s| s.to_str()),
+            ) {
+                (Some(stem), Some(extension)) => (stem.to_string(), extension.to_string()),
+                _ => continue,
+            };
+            let mut contents = Vec::new();
+            match extension.as_str() {
+                "larch" => {
+                    entry.read_to_end(&mut contents)?;
+                    backup
+                        .layers
+                        .insert(string_to_name(&stem)?, contents.into());
+                }
+                "label" => {
+                    entry.read_to_end(&mut contents)?;
+                    let head = parse_label(&String::from_utf8_lossy(&contents))?;
+                    backup.labels.insert(stem, head);
+                }
+                _ => {}
+            }
+        }
+        Ok(backup)
+    }
+}
+
+/// HMAC-SHA256 of a message.
+fn hmac_sha256(key: &[u8], message: &[u8]) -> String {
+    const BLOCK: usize = 64;
+    let mut key = if key.len() > BLOCK {
+        Sha256::digest(key).to_vec()
+    } else {
+        key.to_vec()
+    };
+    key.resize(BLOCK, 0);
+    let pad = |byte: u8| key.iter().map(|k| k ^ byte).collect::<Vec<_>>();
+    let mut inner = Sha256::new();
+    inner.update(pad(0x36));
+    inner.update(message);
+    let mut outer = Sha256::new();
+    outer.update(pad(0x5c));
+    outer.update(inner.finalize());
+    format!("{:x}", outer.finalize())
+}
+
+/// Verify an offline backup, either a store directory or a tar of one.
+///
+/// Every layer gets the quick verification, and every label's chain must be
+/// complete within the backup. The result is a JSON summary including the
+/// checksum of every layer. With a key, the summary carries an HMAC-SHA256
+/// signature over its other contents, so it can't be altered unnoticed.
+pub async fn verify_backup(path: &Path, key: Option<&[u8]>) -> io::Result<Value> {
+    let backup = if path.is_dir() {
+        Backup::from_dir(path).await?
+    } else {
+        Backup::from_tar(path)?
+    };
+
+    let mut failures = Vec::new();
+    let mut layers = BTreeMap::new();
+    for (name, contents) in backup.layers.iter() {
+        let name_string = name_to_string(*name);
+        layers.insert(
+            name_string.clone(),
+            format!("{:x}", Sha256::digest(contents)),
+        );
+        let findings = match Archive::parse(contents.clone()).await {
+            Ok(archive) => verify(&archive, true)
+                .await
+                .into_iter()
+                .map(|f| f.to_string())
+                .collect(),
+            Err(e) => vec![format!("could not parse header: {e}")],
+        };
+        for finding in findings {
+            failures.push(json!({"layer": name_string, "finding": finding}));
+        }
+    }
+
+    for (label, head) in backup.labels.iter() {
+        let mut current = *head;
+        while let Some(name) = current {
+            current = match backup.layers.get(&name) {
+                None => {
+                    failures.push(json!({
+                        "label": label,
+                        "finding": format!("layer {} is missing", name_to_string(name)),
+                    }));
+                    None
+                }
+                Some(contents) => match Archive::parse(contents.clone()).await {
+                    Ok(archive) => archive.parent().unwrap_or(None),
+                    // already reported above
+                    Err(_) => None,
+                },
+            };
+        }
+    }
+
+    let labels: serde_json::Map<_, _> = backup
+        .labels
+        .iter()
+        .map(|(label, head)| (label.clone(), json!(head.map(name_to_string))))
+        .collect();
+    let mut summary = json!({
+        "backup": path.display().to_string(),
+        "verified": humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+        "ok": failures.is_empty(),
+        "labels": labels,
+        "layers": layers,
+        "failures": failures,
+    });
+    if let Some(key) = key {
+        let signature = hmac_sha256(key, summary.to_string().as_bytes());
+        summary["signature"] = json!(signature);
+    }
+
+    Ok(summary)
+}
+
+/// Name of the manifest inside a backup tar. It's always the first entry.
+const MANIFEST_ENTRY: &str = "manifest.json";
+
+/// The state of a store as captured by a backup: every label's head and
+/// the checksum of every layer. An incremental backup only archives the
+/// layers listed in `archived`, and names the manifest it builds on by
+/// its checksum in `base`.
+pub struct Manifest {
+    pub base: Option<String>,
+    pub labels: BTreeMap<String, Option<String>>,
+    pub layers: BTreeMap<String, String>,
+    pub archived: Vec<String>,
+}
+
+impl Manifest {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "created": humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+            "base": self.base,
+            "labels": self.labels,
+            "layers": self.layers,
+            "archived": self.archived,
+        })
+    }
+
+    pub fn parse(contents: &[u8]) -> io::Result<Self> {
+        let value: Value = serde_json::from_slice(contents)?;
+        let strings = |key: &str| -> BTreeMap<String, String> {
+            value[key]
+                .as_object()
+                .into_iter()
+                .flatten()
+                .filter_map(|(k, v)| v.as_str().map(|v| (k.clone(), v.to_string())))
+                .collect()
+        };
+        Ok(Self {
+            base: value["base"].as_str().map(|s| s.to_string()),
+            labels: value["labels"]
+                .as_object()
+                .into_iter()
+                .flatten()
+                .map(|(k, v)| (k.clone(), v.as_str().map(|v| v.to_string())))
+                .collect(),
+            layers: strings("layers"),
+            archived: value["archived"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|v| v.as_str().map(|v| v.to_string()))
+                .collect(),
+        })
+    }
+
+    pub async fn read(path: &Path) -> io::Result<(Self, String)> {
+        let contents = tokio::fs::read(path).await?;
+        Ok((
+            Self::parse(&contents)?,
+            format!("{:x}", Sha256::digest(&contents)),
+        ))
+    }
+}
+
+/// The differences between two manifests, one line each: layers added
+/// (`+`), removed (`-`) or changed (`~`), and labels whose head moved.
+pub fn manifest_diff(old: &Manifest, new: &Manifest) -> Vec<String> {
+    let mut result = Vec::new();
+    for (name, sha256) in new.layers.iter() {
+        match old.layers.get(name) {
+            None => result.push(format!("+ {name}")),
+            Some(old_sha256) if old_sha256 != sha256 => result.push(format!("~ {name}")),
+            Some(_) => {}
+        }
+    }
+    for name in old.layers.keys() {
+        if !new.layers.contains_key(name) {
+            result.push(format!("- {name}"));
+        }
+    }
+    let show = |head: Option<&Option<String>>| match head {
+        None => "(absent)".to_string(),
+        Some(None) => "(no head)".to_string(),
+        Some(Some(head)) => head.clone(),
+    };
+    let labels: BTreeSet<_> = old.labels.keys().chain(new.labels.keys()).collect();
+    for label in labels {
+        let (old_head, new_head) = (old.labels.get(label), new.labels.get(label));
+        if old_head != new_head {
+            result.push(format!("{label}: {} -> {}", show(old_head), show(new_head)));
+        }
+    }
+
+    result
+}
+
+/// Write a tar backup of a store, with its manifest as the first entry
+/// and a copy of the manifest next to the tar. With `since`, only layers
+/// that aren't in that earlier manifest (or that changed) are archived.
+pub async fn backup(store: &Path, output: &Path, since: Option<&Path>) -> io::Result<Manifest> {
+    let base = match since {
+        Some(since) => Some(Manifest::read(since).await?),
+        None => None,
+    };
+    let layers = list_layers(store).await?;
+    let mut manifest = Manifest {
+        base: base.as_ref().map(|(_, sha256)| sha256.clone()),
+        labels: BTreeMap::new(),
+        layers: BTreeMap::new(),
+        archived: Vec::new(),
+    };
+    let mut archived = Vec::new();
+    for (name, path) in layers {
+        let name = name_to_string(name);
+        let contents = tokio::fs::read(&path).await?;
+        let sha256 = format!("{:x}", Sha256::digest(&contents));
+        let unchanged = base
+            .as_ref()
+            .and_then(|(base, _)| base.layers.get(&name))
+            .map(|base_sha256| *base_sha256 == sha256)
+            .unwrap_or(false);
+        if !unchanged {
+            manifest.archived.push(name.clone());
+            archived.push((format!("{}/{name}.larch", &name[..3]), contents));
+        }
+        manifest.layers.insert(name, sha256);
+    }
+    let mut labels = Vec::new();
+    for label in list_labels(store).await? {
+        let head = read_label(store, &label).await?;
+        manifest
+            .labels
+            .insert(label.clone(), head.map(name_to_string));
+        let contents = tokio::fs::read(label_path(store, &label)).await?;
+        labels.push((format!("{label}.label"), contents));
+    }
+
+    let manifest_json = serde_json::to_vec_pretty(&manifest.to_json())?;
+    write_tar(output, &manifest_json, archived.iter().chain(labels.iter())).await?;
+
+    Ok(manifest)
+}
+
+/// Write a tar with the manifest as its first entry followed by the given
+/// files, and a copy of the manifest next to it.
+async fn write_tar<'a>(
+    output: &Path,
+    manifest_json: &[u8],
+    files: impl Iterator<Item = &'a (String, Vec<u8>)>,
+) -> io::Result<()> {
+    let mut out = Vec::new();
+    {
+        let mut builder = tar::Builder::new(&mut out);
+        let mut append = |path: &str, contents: &[u8]| {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, path, contents)
+        };
+        append(MANIFEST_ENTRY, manifest_json)?;
+        for (path, contents) in files {
+            append(path, contents)?;
+        }
+        builder.finish()?;
+    }
+    atomic::write(output, out).await?;
+    let mut manifest_path = output.as_os_str().to_owned();
+    manifest_path.push(".manifest.json");
+    atomic::write(manifest_path, manifest_json).await
+}
+
+/// Package the layers of a chain between `from` and `to` into a tar laid
+/// out like a store, with a manifest listing them oldest first, so they
+/// can be unpacked into a replica that stops at `from`. Neither end is
+/// included unless asked for. `from` must be an ancestor of `to`; the
+/// layers below it are never read. Returns the packaged layers, oldest
+/// first.
+pub async fn export_range(
+    store: &Path,
+    from: [u32; 5],
+    to: [u32; 5],
+    include_from: bool,
+    include_to: bool,
+    output: &Path,
+) -> io::Result<Vec<[u32; 5]>> {
+    let mut range = Vec::new();
+    let mut current = Some(to);
+    loop {
+        let name = current.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} is not an ancestor of {}",
+                    name_to_string(from),
+                    name_to_string(to)
+                ),
+            )
+        })?;
+        if name == from {
+            if include_from {
+                range.push(name);
+            }
+            break;
+        }
+        if name != to || include_to {
+            range.push(name);
+        }
+        current = Archive::open(layer_path(store, name)).await?.parent()?;
+    }
+    range.reverse();
+
+    let mut files = Vec::new();
+    let mut layers = Vec::new();
+    for name in range.iter() {
+        let contents = tokio::fs::read(layer_path(store, *name)).await?;
+        let name = name_to_string(*name);
+        layers.push(json!({
+            "layer": name,
+            "sha256": format!("{:x}", Sha256::digest(&contents)),
+        }));
+        files.push((format!("{}/{name}.larch", &name[..3]), contents));
+    }
+    let manifest = json!({
+        "created": humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+        "from": name_to_string(from),
+        "to": name_to_string(to),
+        "layers": layers,
+    });
+    write_tar(output, &serde_json::to_vec_pretty(&manifest)?, files.iter()).await?;
+
+    Ok(range)
+}
+
+/// Restore a chain of backups, a full one followed by incremental ones in
+/// the order they were taken, into an empty directory. Each incremental
+/// backup must build on the manifest of the one before it. Labels end up
+/// as in the last backup, and every layer its manifest lists must be
+/// restored intact.
+pub fn restore(backups: &[&Path], output: &Path) -> io::Result<()> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let mut previous: Option<String> = None;
+    let mut last = None;
+    for path in backups {
+        let mut tar = tar::Archive::new(std::fs::File::open(path)?);
+        let mut entries = tar.entries()?;
+        let mut manifest_json = Vec::new();
+        match entries.next() {
+            Some(entry) => {
+                let mut entry = entry?;
+                if entry.path()?.to_str() != Some(MANIFEST_ENTRY) {
+                    return Err(invalid(format!("{} has no manifest", path.display())));
+                }
+                entry.read_to_end(&mut manifest_json)?;
+            }
+            None => return Err(invalid(format!("{} is empty", path.display()))),
+        }
+        let manifest = Manifest::parse(&manifest_json)?;
+        if manifest.base != previous {
+            return Err(invalid(format!(
+                "{} doesn't build on the backup before it",
+                path.display()
+            )));
+        }
+        previous = Some(format!("{:x}", Sha256::digest(&manifest_json)));
+        for entry in entries {
+            entry?.unpack_in(output)?;
+        }
+        last = Some(manifest);
+    }
+
+    if let Some(manifest) = last {
+        for (name, sha256) in manifest.layers.iter() {
+            let layer_path = output.join(&name[..3]).join(format!("{name}.larch"));
+            let contents = std::fs::read(&layer_path)
+                .map_err(|e| invalid(format!("layer {name} wasn't restored: {e}")))?;
+            if format!("{:x}", Sha256::digest(&contents)) != *sha256 {
+                return Err(invalid(format!(
+                    "restored layer {name} doesn't match its checksum"
+                )));
+            }
+        }
+    }
+
+    Ok(())
+}
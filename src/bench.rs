@@ -0,0 +1,413 @@
+use std::{
+    io,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use serde_json::json;
+use terminus_store::structure::Datatype;
+
+use crate::{
+    archive::Archive,
+    dict::{self, DictType},
+    dump::walk_triples,
+    output::{human_bytes, ndjson, paint, Color, OutputFormat},
+    query::{walk_pattern, Pattern, QueryIndex},
+};
+
+/// How many entries, ids and triples the lookup and query workloads use.
+const SAMPLES: usize = 300;
+
+/// A difference in means counts as real when Welch's t is beyond this,
+/// roughly a 95% confidence for the iteration counts a bench runs.
+const SIGNIFICANT_T: f64 = 2.0;
+
+const DICTS: [DictType; 3] = [DictType::Nodes, DictType::Predicates, DictType::Values];
+
+/// One operation the bench times on both layers.
+#[derive(Clone, Copy)]
+enum Workload {
+    /// Read the file and parse its header
+    Open,
+    /// Decode every entry of the three dictionaries
+    DictScan,
+    /// Find the ids of sampled dictionary entries
+    DictLookup,
+    /// Decode the entries of sampled ids
+    DictEntry,
+    /// Walk every triple the layer adds
+    Triples,
+    /// Match sampled subjects through the adjacency lists
+    SubjectQuery,
+    /// Match sampled predicates through the wavelet tree
+    PredicateQuery,
+    /// Match sampled objects through the object index
+    ObjectQuery,
+}
+
+const WORKLOADS: [Workload; 8] = [
+    Workload::Open,
+    Workload::DictScan,
+    Workload::DictLookup,
+    Workload::DictEntry,
+    Workload::Triples,
+    Workload::SubjectQuery,
+    Workload::PredicateQuery,
+    Workload::ObjectQuery,
+];
+
+impl Workload {
+    fn name(self) -> &'static str {
+        match self {
+            Workload::Open => "open",
+            Workload::DictScan => "dict-scan",
+            Workload::DictLookup => "dict-lookup",
+            Workload::DictEntry => "dict-entry",
+            Workload::Triples => "triples",
+            Workload::SubjectQuery => "subject-query",
+            Workload::PredicateQuery => "predicate-query",
+            Workload::ObjectQuery => "object-query",
+        }
+    }
+}
+
+/// The entries, ids and triples the sampled workloads ask for, taken from
+/// the baseline so that both layers answer the same questions.
+struct Sample {
+    entries: Vec<(DictType, Option<Datatype>, Bytes)>,
+    ids: Vec<(DictType, u64)>,
+    triples: Vec<(u64, u64, u64)>,
+}
+
+/// Up to `n` items spread evenly over `items`.
+fn spread<T: Clone>(items: &[T], n: usize) -> Vec<T> {
+    let step = (items.len() / n.max(1)).max(1);
+    items.iter().step_by(step).take(n).cloned().collect()
+}
+
+impl Sample {
+    async fn take(archive: &Archive) -> io::Result<Self> {
+        let mut entries = Vec::new();
+        let mut ids = Vec::new();
+        for t in DICTS {
+            let all = dict::read_typed_entries(archive, t).await?;
+            let all_ids: Vec<u64> = (1..=all.len() as u64).collect();
+            entries.extend(
+                spread(&all, SAMPLES / DICTS.len())
+                    .into_iter()
+                    .map(|(datatype, e)| (t, datatype, e)),
+            );
+            ids.extend(
+                spread(&all_ids, SAMPLES / DICTS.len())
+                    .into_iter()
+                    .map(|id| (t, id)),
+            );
+        }
+        let mut count = 0;
+        walk_triples(archive, false, |_, _, _| {
+            count += 1;
+            Ok(())
+        })?;
+        let step = (count / SAMPLES).max(1);
+        let mut triples = Vec::new();
+        let mut i = 0;
+        walk_triples(archive, false, |s, p, o| {
+            if i % step == 0 && triples.len() < SAMPLES {
+                triples.push((s, p, o));
+            }
+            i += 1;
+            Ok(())
+        })?;
+
+        Ok(Sample {
+            entries,
+            ids,
+            triples,
+        })
+    }
+}
+
+/// Run a workload once, returning how long it took and an answer that
+/// summarizes what it found, which both layers should agree on.
+async fn run(
+    workload: Workload,
+    path: &Path,
+    archive: &Archive,
+    sample: &Sample,
+) -> io::Result<(Duration, u64)> {
+    let start = Instant::now();
+    let mut answer = 0;
+    match workload {
+        Workload::Open => answer = Archive::open(path).await?.segments().len() as u64,
+        Workload::DictScan => {
+            for t in DICTS {
+                answer += dict::count_entries(archive, t).await?;
+            }
+        }
+        Workload::DictLookup => {
+            for (t, datatype, entry) in sample.entries.iter() {
+                answer += dict::lookup(archive, *t, *datatype, entry)
+                    .await?
+                    .unwrap_or(0);
+            }
+        }
+        Workload::DictEntry => {
+            for (t, id) in sample.ids.iter() {
+                let entry = dict::entry(archive, *t, *id).await?;
+                answer += entry.map(|(_, e)| e.len() as u64).unwrap_or(0);
+            }
+        }
+        Workload::Triples => walk_triples(archive, false, |_, _, _| {
+            answer += 1;
+            Ok(())
+        })?,
+        Workload::SubjectQuery | Workload::PredicateQuery | Workload::ObjectQuery => {
+            for (s, p, o) in sample.triples.iter() {
+                let (pattern, index) = match workload {
+                    Workload::SubjectQuery => (
+                        Pattern {
+                            subject: Some(*s),
+                            ..Default::default()
+                        },
+                        QueryIndex::Pos,
+                    ),
+                    Workload::PredicateQuery => (
+                        Pattern {
+                            predicate: Some(*p),
+                            ..Default::default()
+                        },
+                        QueryIndex::Predicates,
+                    ),
+                    _ => (
+                        Pattern {
+                            object: Some(*o),
+                            ..Default::default()
+                        },
+                        QueryIndex::Objects,
+                    ),
+                };
+                walk_pattern(archive, &pattern, index, false, |_, _, _| {
+                    answer += 1;
+                    Ok(())
+                })?;
+            }
+        }
+    }
+    let elapsed = start.elapsed();
+
+    Ok((elapsed, answer))
+}
+
+/// Mean, standard deviation and median of a series of timings, in
+/// seconds.
+pub struct Summary {
+    pub mean: f64,
+    pub stddev: f64,
+    pub median: f64,
+}
+
+impl Summary {
+    fn of(times: &[f64]) -> Self {
+        let n = times.len() as f64;
+        let mean = times.iter().sum::<f64>() / n;
+        let variance = if times.len() > 1 {
+            times.iter().map(|t| (t - mean).powi(2)).sum::<f64>() / (n - 1.0)
+        } else {
+            0.0
+        };
+        let mut sorted = times.to_vec();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let mid = sorted.len() / 2;
+        let median = if sorted.len() % 2 == 0 {
+            (sorted[mid - 1] + sorted[mid]) / 2.0
+        } else {
+            sorted[mid]
+        };
+
+        Summary {
+            mean,
+            stddev: variance.sqrt(),
+            median,
+        }
+    }
+}
+
+/// How one workload fared on the two layers.
+pub struct Comparison {
+    pub workload: &'static str,
+    pub baseline: Summary,
+    pub candidate: Summary,
+    /// Welch's t for the difference in means, positive when the candidate
+    /// is slower.
+    pub t: f64,
+    /// The answers the workload gave on each layer. They differ when the
+    /// layers don't hold the same data, which makes the timings moot.
+    pub answers: (u64, u64),
+}
+
+impl Comparison {
+    /// The change in mean time from baseline to candidate, as a fraction.
+    pub fn change(&self) -> f64 {
+        self.candidate.mean / self.baseline.mean - 1.0
+    }
+
+    pub fn significant(&self) -> bool {
+        self.t.abs() > SIGNIFICANT_T
+    }
+}
+
+fn welch_t(a: &[f64], b: &[f64]) -> f64 {
+    let (sa, sb) = (Summary::of(a), Summary::of(b));
+    let error = (sa.stddev.powi(2) / a.len() as f64 + sb.stddev.powi(2) / b.len() as f64).sqrt();
+    if error > 0.0 {
+        (sb.mean - sa.mean) / error
+    } else if sa.mean == sb.mean {
+        0.0
+    } else {
+        f64::INFINITY.copysign(sb.mean - sa.mean)
+    }
+}
+
+/// The sizes of the two layer files and how each workload compares.
+pub struct BenchReport {
+    pub sizes: (u64, u64),
+    pub comparisons: Vec<Comparison>,
+}
+
+/// Time every workload on two encodings of the same layer. Both archives
+/// are held in memory, so only `open` touches the disk. Each workload runs
+/// `warmup` untimed rounds and then `iterations` timed ones, alternating
+/// which layer goes first so that neither always gets the warmer cache.
+pub async fn compare(
+    baseline: &Path,
+    candidate: &Path,
+    iterations: usize,
+    warmup: usize,
+) -> io::Result<BenchReport> {
+    let paths = [baseline, candidate];
+    let archives = [
+        Archive::open(baseline).await?,
+        Archive::open(candidate).await?,
+    ];
+    let sample = Sample::take(&archives[0]).await?;
+
+    let mut comparisons = Vec::new();
+    for workload in WORKLOADS {
+        let mut times = [Vec::new(), Vec::new()];
+        let mut answers = [0, 0];
+        for round in 0..warmup + iterations {
+            let order = if round % 2 == 0 { [0, 1] } else { [1, 0] };
+            for i in order {
+                let (elapsed, answer) = run(workload, paths[i], &archives[i], &sample).await?;
+                answers[i] = answer;
+                if round >= warmup {
+                    times[i].push(elapsed.as_secs_f64());
+                }
+            }
+        }
+        comparisons.push(Comparison {
+            workload: workload.name(),
+            baseline: Summary::of(&times[0]),
+            candidate: Summary::of(&times[1]),
+            t: welch_t(&times[0], &times[1]),
+            answers: (answers[0], answers[1]),
+        });
+    }
+
+    Ok(BenchReport {
+        sizes: (
+            archives[0].contents().len() as u64,
+            archives[1].contents().len() as u64,
+        ),
+        comparisons,
+    })
+}
+
+fn human_seconds(seconds: f64) -> String {
+    if seconds >= 1.0 {
+        format!("{seconds:.2} s")
+    } else if seconds >= 1e-3 {
+        format!("{:.2} ms", seconds * 1e3)
+    } else {
+        format!("{:.1} µs", seconds * 1e6)
+    }
+}
+
+pub fn print_report(report: &BenchReport, format: OutputFormat) {
+    let (baseline, candidate) = report.sizes;
+    match format {
+        OutputFormat::Ndjson => println!(
+            "{}",
+            ndjson(json!({
+                "kind": "size",
+                "baseline": baseline,
+                "candidate": candidate,
+            }))
+        ),
+        OutputFormat::Pretty => println!(
+            "{:<16} {:>21} {:>21}\n{:<16} {:>21} {:>21}",
+            "",
+            "baseline",
+            "candidate",
+            "size",
+            human_bytes(baseline as usize),
+            human_bytes(candidate as usize)
+        ),
+        OutputFormat::Text => println!("size {baseline} {candidate}"),
+    }
+    for c in report.comparisons.iter() {
+        if c.answers.0 != c.answers.1 {
+            eprintln!(
+                "warning: {} answered {} on the baseline but {} on the candidate; the layers may not hold the same data",
+                c.workload, c.answers.0, c.answers.1
+            );
+        }
+        match format {
+            OutputFormat::Ndjson => println!(
+                "{}",
+                ndjson(json!({
+                    "kind": "workload",
+                    "workload": c.workload,
+                    "baseline_mean": c.baseline.mean,
+                    "baseline_stddev": c.baseline.stddev,
+                    "baseline_median": c.baseline.median,
+                    "candidate_mean": c.candidate.mean,
+                    "candidate_stddev": c.candidate.stddev,
+                    "candidate_median": c.candidate.median,
+                    "change": c.change(),
+                    "t": if c.t.is_finite() { Some(c.t) } else { None },
+                    "significant": c.significant(),
+                    "answers_match": c.answers.0 == c.answers.1,
+                }))
+            ),
+            OutputFormat::Pretty => {
+                let timing = |s: &Summary| {
+                    format!("{} ± {}", human_seconds(s.mean), human_seconds(s.stddev))
+                };
+                let change = format!("{:+.1}%", c.change() * 100.0);
+                let verdict = match (c.significant(), c.t > 0.0) {
+                    (false, _) => paint("no significant difference", Color::Dim),
+                    (true, true) => paint("slower", Color::Red),
+                    (true, false) => paint("faster", Color::Green),
+                };
+                println!(
+                    "{:<16} {:>21} {:>21} {change:>8}  {verdict}",
+                    c.workload,
+                    timing(&c.baseline),
+                    timing(&c.candidate)
+                );
+            }
+            OutputFormat::Text => println!(
+                "{} {} {} {} {} {:.4} {:.2}",
+                c.workload,
+                c.baseline.mean,
+                c.baseline.stddev,
+                c.candidate.mean,
+                c.candidate.stddev,
+                c.change(),
+                c.t
+            ),
+        }
+    }
+}
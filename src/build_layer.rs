@@ -0,0 +1,370 @@
+use std::{
+    io::{self, BufRead, BufReader},
+    path::Path,
+};
+
+use rug::Integer;
+use terminus_store::{
+    layer::{ObjectType, ValueTriple},
+    store::sync::open_sync_archive_store,
+    structure::{Decimal, LangString, TdbDataType, TypedDictEntry},
+    Layer,
+};
+
+use crate::{atomic, store::layer_path, values::Decoded};
+
+const XSD: &str = "http://www.w3.org/2001/XMLSchema#";
+
+/// What building a layer from N-Triples produced.
+pub struct BuildSummary {
+    pub triples: usize,
+    /// Literals of datatypes the store has no encoding for here, kept as
+    /// strings as allowed by `allow_string_fallback`.
+    pub as_strings: usize,
+    /// Name of the new base layer.
+    pub name: [u32; 5],
+}
+
+/// Undo the escapes of an N-Triples IRI or string. The `\u{...}` and `\0`
+/// escapes older `export-all` output used are accepted too.
+fn unescape(s: &str) -> Result<String, String> {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        let escaped = chars.next().ok_or("dangling \\")?;
+        let hex = |chars: &mut std::str::Chars, n| -> Result<char, String> {
+            let digits: String = chars.take(n).collect();
+            u32::from_str_radix(&digits, 16)
+                .ok()
+                .and_then(char::from_u32)
+                .ok_or_else(|| format!("bad escape \\{escaped}{digits}"))
+        };
+        result.push(match escaped {
+            't' => '\t',
+            'b' => '\u{8}',
+            'n' => '\n',
+            'r' => '\r',
+            'f' => '\u{c}',
+            '0' => '\0',
+            '"' | '\'' | '\\' => escaped,
+            'u' if chars.as_str().starts_with('{') => {
+                let end = chars.as_str().find('}').ok_or("unterminated \\u{")?;
+                chars.next();
+                let c = hex(&mut chars, end - 1)?;
+                chars.next();
+                c
+            }
+            'u' => hex(&mut chars, 4)?,
+            'U' => hex(&mut chars, 8)?,
+            other => return Err(format!("unknown escape \\{other}")),
+        });
+    }
+
+    Ok(result)
+}
+
+/// Parse an `<iri>` at the start of `s`, returning it and what follows.
+fn parse_iri(s: &str) -> Result<(String, &str), String> {
+    if s.starts_with("_:") {
+        return Err("blank nodes aren't supported; give them IRIs first".to_string());
+    }
+    if !s.starts_with('<') {
+        return Err(format!(
+            "expected <iri>, found {}",
+            s.split_whitespace().next().unwrap_or("end of line")
+        ));
+    }
+    let end = s.find('>').ok_or("unterminated <iri>")?;
+
+    Ok((unescape(&s[1..end])?, s[end + 1..].trim_start()))
+}
+
+/// Turn a literal into a stored value. Returns whether it had to be kept
+/// as a string because its datatype has no encoding here. Datatypes are
+/// given as XML schema local names, or as the stored datatype names
+/// older `export-all` output used.
+pub fn typed_value(lexical: &str, datatype: &str) -> Result<(TypedDictEntry, bool), String> {
+    let invalid = || format!("{lexical:?} is not a valid {datatype}");
+    let entry = match datatype {
+        "string" | "String" => String::make_entry(&lexical.to_string()),
+        "boolean" | "Boolean" => bool::make_entry(&match lexical {
+            "true" | "1" => true,
+            "false" | "0" => false,
+            _ => return Err(invalid()),
+        }),
+        "int" | "Int32" => i32::make_entry(&lexical.parse::<i32>().map_err(|_| invalid())?),
+        "unsignedInt" | "UInt32" => {
+            u32::make_entry(&lexical.parse::<u32>().map_err(|_| invalid())?)
+        }
+        "long" | "Int64" => i64::make_entry(&lexical.parse::<i64>().map_err(|_| invalid())?),
+        "unsignedLong" | "UInt64" => {
+            u64::make_entry(&lexical.parse::<u64>().map_err(|_| invalid())?)
+        }
+        "float" | "Float32" => {
+            f32::make_entry(&parse_float(lexical).map_err(|_| invalid())? as f32)
+        }
+        "double" | "Float64" => f64::make_entry(&parse_float(lexical).map_err(|_| invalid())?),
+        "integer" | "BigInt" => {
+            Integer::make_entry(&lexical.parse::<Integer>().map_err(|_| invalid())?)
+        }
+        "decimal" | "Decimal" => {
+            Decoded::parse(lexical, "Decimal").ok_or_else(invalid)?;
+            Decimal::make_entry(&Decimal(lexical.to_string()))
+        }
+        "LangString" => LangString::make_entry(&lexical.to_string()),
+        _ => return Ok((String::make_entry(&lexical.to_string()), true)),
+    };
+
+    Ok((entry, false))
+}
+
+/// Parse a float, accepting the `INF` and `-INF` of XML schema.
+fn parse_float(lexical: &str) -> Result<f64, std::num::ParseFloatError> {
+    match lexical {
+        "INF" => Ok(f64::INFINITY),
+        "-INF" => Ok(f64::NEG_INFINITY),
+        _ => lexical.parse(),
+    }
+}
+
+/// Parse a `"literal"` with its language tag or datatype at the start of
+/// `s`, returning its value, whether it was kept as a string, and what
+/// follows.
+fn parse_literal(s: &str) -> Result<(TypedDictEntry, bool, &str), String> {
+    let mut escaped = false;
+    let end = s[1..]
+        .find(|c| {
+            let close = c == '"' && !escaped;
+            escaped = c == '\\' && !escaped;
+            close
+        })
+        .ok_or("unterminated string")?
+        + 1;
+    let lexical = unescape(&s[1..end])?;
+    let rest = &s[end + 1..];
+    if let Some(rest) = rest.strip_prefix('@') {
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-'))
+            .unwrap_or(rest.len());
+        let entry = LangString::make_entry(&format!("{}@{lexical}", &rest[..len]));
+        return Ok((entry, false, rest[len..].trim_start()));
+    }
+    if let Some(rest) = rest.strip_prefix("^^") {
+        let (datatype, rest) = if rest.starts_with('<') {
+            let (datatype, rest) = parse_iri(rest)?;
+            match datatype.strip_prefix(XSD) {
+                Some(local) => (local.to_string(), rest),
+                None => (datatype, rest),
+            }
+        } else {
+            let len = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            (rest[..len].to_string(), rest[len..].trim_start())
+        };
+        let (entry, as_string) = typed_value(&lexical, &datatype)?;
+        return Ok((entry, as_string, rest));
+    }
+
+    Ok((String::make_entry(&lexical), false, rest.trim_start()))
+}
+
+/// Parse one line of N-Triples. Blank lines and comments give None.
+/// Returns the triple and whether its object was kept as a string.
+pub fn parse_line(line: &str) -> Result<Option<(ValueTriple, bool)>, String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+    let (subject, rest) = parse_iri(line)?;
+    let (predicate, rest) = parse_iri(rest)?;
+    let (object, as_string, rest) = if rest.starts_with('"') {
+        let (entry, as_string, rest) = parse_literal(rest)?;
+        (ObjectType::Value(entry), as_string, rest)
+    } else {
+        let (node, rest) = parse_iri(rest)?;
+        (ObjectType::Node(node), false, rest)
+    };
+    let rest = rest
+        .strip_prefix('.')
+        .ok_or("expected . at the end of the triple")?;
+    let rest = rest.trim_start();
+    if !rest.is_empty() && !rest.starts_with('#') {
+        return Err(format!("unexpected {rest} after the triple"));
+    }
+
+    Ok(Some((
+        ValueTriple {
+            subject,
+            predicate,
+            object,
+        },
+        as_string,
+    )))
+}
+
+/// Build a standalone base layer archive from an N-Triples file, as
+/// `export`, `export-all` or another tool writes them. The layer is built
+/// in a scratch store next to the output, which is removed afterwards, so
+/// its dictionaries, adjacency lists and indexes all come from the store
+/// itself. Any line that doesn't parse stops the build, as does a literal
+/// of a datatype without an encoding here unless `allow_string_fallback`
+/// is given, in which case it is stored as a string.
+pub async fn build_layer(
+    input: &Path,
+    output: &Path,
+    allow_string_fallback: bool,
+) -> io::Result<BuildSummary> {
+    let scratch = atomic::temp_path(output);
+    std::fs::create_dir_all(&scratch)?;
+    let result: io::Result<BuildSummary> = async {
+        let scratch_store = open_sync_archive_store(&scratch, 512);
+        let builder = scratch_store.create_base_layer()?;
+        let mut triples = 0;
+        let mut as_strings = 0;
+        let reader = BufReader::new(std::fs::File::open(input)?);
+        for (i, line) in reader.lines().enumerate() {
+            let invalid =
+                |e| io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {e}", i + 1));
+            let parsed = parse_line(&line?).map_err(invalid)?;
+            if let Some((triple, as_string)) = parsed {
+                if as_string && !allow_string_fallback {
+                    return Err(invalid(
+                        "the literal's datatype has no encoding here; give \
+                         --allow-string-fallback to store it as a string"
+                            .to_string(),
+                    ));
+                }
+                builder.add_value_triple(triple)?;
+                triples += 1;
+                as_strings += as_string as usize;
+            }
+        }
+        let layer = builder.commit()?;
+        let contents = tokio::fs::read(layer_path(&scratch, layer.name())).await?;
+        atomic::write(output, contents).await?;
+
+        Ok(BuildSummary {
+            triples,
+            as_strings,
+            name: layer.name(),
+        })
+    }
+    .await;
+    let _ = tokio::fs::remove_dir_all(&scratch).await;
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rdf::{ntriples_line, quoted};
+
+    fn value(object: &ObjectType) -> (String, Vec<u8>) {
+        match object {
+            ObjectType::Value(value) => {
+                (format!("{:?}", value.datatype()), value.to_bytes().to_vec())
+            }
+            ObjectType::Node(node) => panic!("expected a value, found node {node}"),
+        }
+    }
+
+    #[test]
+    fn unescape_undoes_ntriples_escapes() {
+        assert_eq!(unescape(r#"a\tb\nc\"d\\e"#).unwrap(), "a\tb\nc\"d\\e");
+        assert_eq!(unescape(r"é\U0001F600").unwrap(), "\u{e9}\u{1F600}");
+        assert_eq!(unescape("plain").unwrap(), "plain");
+    }
+
+    #[test]
+    fn unescape_accepts_older_export_escapes() {
+        assert_eq!(unescape(r"\u{e9}\0").unwrap(), "\u{e9}\0");
+    }
+
+    #[test]
+    fn unescape_rejects_bad_escapes() {
+        assert!(unescape(r"trailing\").is_err());
+        assert!(unescape(r"\q").is_err());
+        assert!(unescape(r"\uZZZZ").is_err());
+        assert!(unescape(r"\uD800").is_err());
+        assert!(unescape(r"\u{e9").is_err());
+    }
+
+    #[test]
+    fn unescape_reverses_quoting() {
+        let text = "line\nbreak \"quoted\" back\\slash \u{1} caf\u{e9}";
+        let quoted = quoted(text);
+        assert_eq!(unescape(&quoted[1..quoted.len() - 1]).unwrap(), text);
+    }
+
+    #[test]
+    fn blank_lines_and_comments_parse_to_nothing() {
+        assert!(parse_line("").unwrap().is_none());
+        assert!(parse_line("   # a comment").unwrap().is_none());
+    }
+
+    #[test]
+    fn node_triples_parse() {
+        let (triple, as_string) = parse_line("<http://a> <http://p> <http://b> .")
+            .unwrap()
+            .unwrap();
+        assert_eq!(triple.subject, "http://a");
+        assert_eq!(triple.predicate, "http://p");
+        assert!(matches!(triple.object, ObjectType::Node(ref node) if node == "http://b"));
+        assert!(!as_string);
+    }
+
+    #[test]
+    fn literals_parse_with_their_datatypes() {
+        let parse = |line: &str| {
+            let (triple, as_string) = parse_line(line).unwrap().unwrap();
+            assert!(!as_string);
+            value(&triple.object)
+        };
+        let plain = parse(r#"<http://a> <http://p> "café" ."#);
+        assert_eq!(
+            plain,
+            value(&ObjectType::Value(String::make_entry(
+                &"caf\u{e9}".to_string()
+            )))
+        );
+        let lang = parse(r#"<http://a> <http://p> "chat"@fr ."#);
+        assert_eq!(lang.0, "LangString");
+        let integer =
+            parse("<http://a> <http://p> \"42\"^^<http://www.w3.org/2001/XMLSchema#int> .");
+        assert_eq!(integer, value(&ObjectType::Value(i32::make_entry(&42))));
+        let big = parse("<http://a> <http://p> \"123456789012345678901234567890\"^^BigInt .");
+        assert_eq!(big.0, "BigInt");
+    }
+
+    #[test]
+    fn unknown_datatypes_are_kept_as_strings() {
+        let (triple, as_string) =
+            parse_line("<http://a> <http://p> \"x\"^^<http://example.com/t> .")
+                .unwrap()
+                .unwrap();
+        assert!(as_string);
+        assert_eq!(value(&triple.object).0, "String");
+    }
+
+    #[test]
+    fn malformed_lines_are_rejected() {
+        assert!(parse_line("_:b <http://p> <http://o> .").is_err());
+        assert!(parse_line("<http://a> <http://p> <http://o>").is_err());
+        assert!(parse_line("<http://a> <http://p> \"open .").is_err());
+        assert!(parse_line("<http://a> <http://p> <http://o> . extra").is_err());
+        assert!(parse_line("<http://a> <http://p> \"x\"^^Int32 .").is_err());
+    }
+
+    #[test]
+    fn exported_lines_parse_back() {
+        let triple = ValueTriple::new_string_value("http://a", "http://p", "tab\there \"q\"");
+        let (parsed, _) = parse_line(&ntriples_line(&triple)).unwrap().unwrap();
+        assert_eq!(parsed.subject, triple.subject);
+        assert_eq!(parsed.predicate, triple.predicate);
+        assert_eq!(value(&parsed.object), value(&triple.object));
+    }
+}
@@ -0,0 +1,93 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::atomic;
+
+/// A local directory of archives fetched from elsewhere, each kept under
+/// its own file name. A file's modification time records when it was last
+/// used, and when the cache grows past its size limit the least recently
+/// used files are evicted.
+pub struct LayerCache {
+    dir: PathBuf,
+    max_size: Option<u64>,
+}
+
+fn sha256_of(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+impl LayerCache {
+    pub fn new(dir: PathBuf, max_size: Option<u64>) -> Self {
+        Self { dir, max_size }
+    }
+
+    /// The cached copy of `source`, which must hash to `sha256`. A cached
+    /// copy is verified every time it's reused, and fetched again if it
+    /// doesn't match.
+    pub fn get(&self, source: &Path, sha256: &str) -> io::Result<PathBuf> {
+        let mut cached = self.dir.clone();
+        cached.push(source.file_name().unwrap_or_default());
+        if cached.exists() {
+            if sha256_of(&cached)? == sha256 {
+                fs::File::options()
+                    .append(true)
+                    .open(&cached)?
+                    .set_modified(SystemTime::now())?;
+                return Ok(cached);
+            }
+            fs::remove_file(&cached)?;
+        }
+
+        fs::create_dir_all(&self.dir)?;
+        let tmp = atomic::temp_path(&cached);
+        fs::copy(source, &tmp)?;
+        if sha256_of(&tmp)? != sha256 {
+            let _ = fs::remove_file(&tmp);
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} doesn't match its expected sha256", source.display()),
+            ));
+        }
+        fs::rename(&tmp, &cached)?;
+        self.evict(&cached)?;
+
+        Ok(cached)
+    }
+
+    /// Remove the least recently used files until the cache fits its
+    /// limit. The file just fetched is kept even if it alone exceeds it.
+    fn evict(&self, keep: &Path) -> io::Result<()> {
+        let max_size = match self.max_size {
+            Some(max_size) => max_size,
+            None => return Ok(()),
+        };
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_file() {
+                files.push((metadata.modified()?, metadata.len(), entry.path()));
+            }
+        }
+        let mut total: u64 = files.iter().map(|(_, size, _)| size).sum();
+        files.sort();
+        for (_, size, path) in files {
+            if total <= max_size {
+                break;
+            }
+            if path != keep {
+                fs::remove_file(&path)?;
+                total -= size;
+            }
+        }
+
+        Ok(())
+    }
+}
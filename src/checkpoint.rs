@@ -0,0 +1,120 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::atomic::temp_path;
+
+/// How far the export of one label got.
+#[derive(Clone)]
+pub struct Position {
+    /// The layer being exported. Progress on a different head is useless.
+    pub head: String,
+    pub triples: u64,
+    /// Length of the output file when the position was recorded.
+    pub bytes: u64,
+    /// Hash of the first `bytes` bytes of the output file.
+    pub sha256: String,
+    pub complete: bool,
+}
+
+/// Export progress per label, kept in a file so an interrupted export can
+/// pick up where it left off. Each line is `label head triples bytes
+/// sha256 complete|partial`.
+pub struct Checkpoint {
+    path: PathBuf,
+    positions: Mutex<BTreeMap<String, Position>>,
+}
+
+impl Checkpoint {
+    /// Read a checkpoint file, or start an empty one if it doesn't exist
+    /// yet.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        let mut positions = BTreeMap::new();
+        for line in contents.lines() {
+            // malformed lines are simply exported again
+            let fields: Vec<_> = line.rsplitn(6, ' ').collect();
+            if let [status, sha256, bytes, triples, head, label] = fields[..] {
+                if let (Ok(triples), Ok(bytes)) = (triples.parse(), bytes.parse()) {
+                    positions.insert(
+                        label.to_string(),
+                        Position {
+                            head: head.to_string(),
+                            triples,
+                            bytes,
+                            sha256: sha256.to_string(),
+                            complete: status == "complete",
+                        },
+                    );
+                }
+            }
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            positions: Mutex::new(positions),
+        })
+    }
+
+    pub fn get(&self, label: &str) -> Option<Position> {
+        self.positions.lock().unwrap().get(label).cloned()
+    }
+
+    /// Record a label's progress and rewrite the checkpoint file. The file
+    /// is replaced by renaming, so a crash leaves either the old or the new
+    /// checkpoint.
+    pub fn record(&self, label: &str, position: Position) -> io::Result<()> {
+        let mut positions = self.positions.lock().unwrap();
+        positions.insert(label.to_string(), position);
+        let mut out = String::new();
+        for (label, p) in positions.iter() {
+            let status = if p.complete { "complete" } else { "partial" };
+            out.push_str(&format!(
+                "{label} {} {} {} {} {status}\n",
+                p.head, p.triples, p.bytes, p.sha256
+            ));
+        }
+        let tmp = temp_path(&self.path);
+        std::fs::write(&tmp, out)?;
+        std::fs::rename(&tmp, &self.path)
+    }
+}
+
+/// Check that an output file still starts with what a position says was
+/// written to it. Returns the hash state over those bytes, to continue
+/// from, or None if the file is shorter or differs.
+pub fn verify_prefix(path: &Path, position: &Position) -> io::Result<Option<Sha256>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    if file.metadata()?.len() < position.bytes {
+        return Ok(None);
+    }
+    let mut hasher = Sha256::new();
+    let mut prefix = file.take(position.bytes);
+    let mut buf = vec![0; 1 << 20];
+    loop {
+        let n = prefix.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    if format!("{:x}", hasher.clone().finalize()) != position.sha256 {
+        return Ok(None);
+    }
+
+    Ok(Some(hasher))
+}
@@ -0,0 +1,99 @@
+use crate::{
+    adjacency,
+    archive::Archive,
+    checksum::verify_checksums,
+    codes::FindingCode,
+    validate::{validate_archive, Finding},
+};
+
+/// A check that fsck and watch can run on every layer archive.
+///
+/// Besides the built-in checks, organizations can compile in their own by
+/// enabling the `custom-checks` feature and listing them in
+/// `custom_checks::checks`.
+pub trait Check: Send + Sync {
+    /// The name by which the check is selected with `--checks`.
+    fn name(&self) -> &'static str;
+    fn run(&self, archive: &Archive) -> Vec<Finding>;
+}
+
+/// Segment bounds and the fixed-layout segments.
+struct Structure;
+
+impl Check for Structure {
+    fn name(&self) -> &'static str {
+        "structure"
+    }
+
+    fn run(&self, archive: &Archive) -> Vec<Finding> {
+        validate_archive(archive)
+    }
+}
+
+/// Segment contents against the checksum trailer, if there is one.
+struct Checksums;
+
+impl Check for Checksums {
+    fn name(&self) -> &'static str {
+        "checksums"
+    }
+
+    fn run(&self, archive: &Archive) -> Vec<Finding> {
+        verify_checksums(archive)
+    }
+}
+
+/// The invariants of every adjacency list.
+struct Adjacency;
+
+impl Check for Adjacency {
+    fn name(&self) -> &'static str {
+        "adjacency"
+    }
+
+    fn run(&self, archive: &Archive) -> Vec<Finding> {
+        adjacency::check_archive(archive)
+            .into_iter()
+            .flat_map(|(list, findings)| {
+                findings.into_iter().map(move |message| Finding {
+                    code: FindingCode::AdjacencyInvalid,
+                    segment: None,
+                    message: format!("{list}AdjacencyList: {message}"),
+                })
+            })
+            .collect()
+    }
+}
+
+/// All checks that can be selected.
+pub fn available() -> Vec<Box<dyn Check>> {
+    #[allow(unused_mut)]
+    let mut checks: Vec<Box<dyn Check>> = vec![
+        Box::new(Structure),
+        Box::new(Checksums),
+        Box::new(Adjacency),
+    ];
+    #[cfg(feature = "custom-checks")]
+    checks.extend(crate::custom_checks::checks());
+    checks
+}
+
+/// Look up checks by name.
+pub fn select(names: &[String]) -> Result<Vec<Box<dyn Check>>, String> {
+    let mut available = available();
+    let mut result = Vec::new();
+    for name in names {
+        match available.iter().position(|c| c.name() == name) {
+            Some(ix) => result.push(available.remove(ix)),
+            None => {
+                let known: Vec<_> = self::available().iter().map(|c| c.name()).collect();
+                return Err(format!(
+                    "unknown check {name}, expected one of {}",
+                    known.join(", ")
+                ));
+            }
+        }
+    }
+
+    Ok(result)
+}
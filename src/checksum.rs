@@ -0,0 +1,221 @@
+use std::io;
+
+use bytes::Bytes;
+use num::FromPrimitive;
+use sha2::{Digest, Sha256};
+use terminus_store::storage::consts::LayerFileEnum;
+
+use crate::{archive::Archive, codes::FindingCode, validate::Finding};
+
+/// Marks the end of a checksum trailer.
+const TRAILER_MAGIC: &[u8; 8] = b"SRGYSUM1";
+
+/// Size of a single trailer entry: a segment type and its sha256.
+const ENTRY_SIZE: usize = 1 + 32;
+
+/// Per-segment checksums stored after the last segment of an archive.
+///
+/// The trailer lies outside every range the header lists, so readers that
+/// don't know about it are unaffected. It consists of one entry per
+/// segment, a big-endian u32 entry count and `TRAILER_MAGIC`.
+pub struct ChecksumTrailer {
+    pub checksums: Vec<(LayerFileEnum, [u8; 32])>,
+}
+
+impl ChecksumTrailer {
+    /// Compute the checksums of all segments of an archive.
+    pub fn compute(archive: &Archive) -> io::Result<Self> {
+        let mut checksums = Vec::new();
+        for (file_type, _) in archive.segments() {
+            let contents = archive.segment(file_type)?.unwrap();
+            checksums.push((file_type, Sha256::digest(&contents).into()));
+        }
+
+        Ok(Self { checksums })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut result = Vec::with_capacity(self.checksums.len() * ENTRY_SIZE + 12);
+        for (file_type, checksum) in self.checksums.iter() {
+            result.push(*file_type as u8);
+            result.extend_from_slice(checksum);
+        }
+        result.extend_from_slice(&(self.checksums.len() as u32).to_be_bytes());
+        result.extend_from_slice(TRAILER_MAGIC);
+        result
+    }
+
+    /// Parse the trailer of an archive. Returns None if the archive has
+    /// nothing after its last segment.
+    pub fn read(archive: &Archive) -> io::Result<Option<Self>> {
+        Self::parse(&archive.trailer())
+    }
+
+    /// Parse the bytes following the last segment of an archive.
+    pub fn parse(trailer: &[u8]) -> io::Result<Option<Self>> {
+        if trailer.is_empty() {
+            return Ok(None);
+        }
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        if !trailer.ends_with(TRAILER_MAGIC) || trailer.len() < 12 {
+            return Err(invalid(format!(
+                "{} unknown bytes after the last segment",
+                trailer.len()
+            )));
+        }
+        let count_start = trailer.len() - 12;
+        let count = u32::from_be_bytes(trailer[count_start..count_start + 4].try_into().unwrap());
+        if count as usize * ENTRY_SIZE != count_start {
+            return Err(invalid(format!(
+                "checksum trailer claims {count} entries but holds {count_start} bytes of them"
+            )));
+        }
+        let mut checksums = Vec::with_capacity(count as usize);
+        for entry in trailer[..count_start].chunks(ENTRY_SIZE) {
+            let file_type = LayerFileEnum::from_usize(entry[0] as usize)
+                .ok_or_else(|| invalid(format!("unknown segment type {}", entry[0])))?;
+            checksums.push((file_type, entry[1..].try_into().unwrap()));
+        }
+
+        Ok(Some(Self { checksums }))
+    }
+
+    /// The checksum recorded for a segment, if any.
+    pub fn get(&self, file_type: LayerFileEnum) -> Option<&[u8; 32]> {
+        self.checksums
+            .iter()
+            .find(|(t, _)| *t == file_type)
+            .map(|(_, checksum)| checksum)
+    }
+}
+
+/// Compare the segments of an archive with its checksum trailer, if it has
+/// one.
+pub fn verify_checksums(archive: &Archive) -> Vec<Finding> {
+    let trailer = match ChecksumTrailer::read(archive) {
+        Ok(Some(trailer)) => trailer,
+        Ok(None) => return Vec::new(),
+        Err(e) => {
+            return vec![Finding {
+                code: FindingCode::ChecksumTrailerInvalid,
+                segment: None,
+                message: e.to_string(),
+            }]
+        }
+    };
+    let actual = match ChecksumTrailer::compute(archive) {
+        Ok(actual) => actual,
+        // out of bounds segments are reported by the structural checks
+        Err(_) => return Vec::new(),
+    };
+
+    let mut findings = Vec::new();
+    for (file_type, checksum) in actual.checksums.iter() {
+        let (code, message) = match trailer.checksums.iter().find(|(t, _)| t == file_type) {
+            None => (FindingCode::ChecksumMissing, "segment has no checksum"),
+            Some((_, expected)) if expected != checksum => {
+                (FindingCode::ChecksumMismatch, "checksum mismatch")
+            }
+            Some(_) => continue,
+        };
+        findings.push(Finding {
+            code,
+            segment: Some(*file_type),
+            message: message.to_string(),
+        });
+    }
+    for (file_type, _) in trailer.checksums.iter() {
+        if archive.header.range_for(*file_type).is_none() {
+            findings.push(Finding {
+                code: FindingCode::ChecksumOrphaned,
+                segment: Some(*file_type),
+                message: "checksum for a segment that isn't present".to_string(),
+            });
+        }
+    }
+
+    findings
+}
+
+/// Write an archive with nothing after its last segment, optionally
+/// followed by a fresh checksum trailer.
+pub fn canonicalize(archive: &Archive, with_checksums: bool) -> io::Result<Bytes> {
+    let mut result = archive.contents()[..archive.segments_end()].to_vec();
+    if with_checksums {
+        result.extend(ChecksumTrailer::compute(archive)?.to_bytes());
+    }
+
+    Ok(result.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::tests::archive;
+
+    const PARENT: &[u8] = &[7; 20];
+
+    #[tokio::test]
+    async fn canonicalizing_strips_what_follows_the_last_segment() {
+        let original = archive(&[(LayerFileEnum::Parent, PARENT)], b"junk").await;
+        let canonical = canonicalize(&original, false).unwrap();
+        assert_eq!(canonical.len(), original.contents().len() - 4);
+        assert!(Archive::parse(canonical)
+            .await
+            .unwrap()
+            .trailer()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn canonical_checksums_verify_and_are_stable() {
+        let original = archive(&[(LayerFileEnum::Parent, PARENT)], b"junk").await;
+        let canonical = Archive::parse(canonicalize(&original, true).unwrap())
+            .await
+            .unwrap();
+        assert!(verify_checksums(&canonical).is_empty());
+        assert_eq!(
+            &canonicalize(&canonical, true).unwrap(),
+            canonical.contents()
+        );
+    }
+
+    #[tokio::test]
+    async fn a_changed_segment_fails_its_checksum() {
+        let trailer =
+            ChecksumTrailer::compute(&archive(&[(LayerFileEnum::Parent, PARENT)], &[]).await)
+                .unwrap()
+                .to_bytes();
+        let changed = archive(&[(LayerFileEnum::Parent, &[8; 20][..])], &trailer).await;
+        assert!(!verify_checksums(&changed).is_empty());
+    }
+
+    #[test]
+    fn trailers_round_trip() {
+        let trailer = ChecksumTrailer {
+            checksums: vec![
+                (LayerFileEnum::Parent, [1; 32]),
+                (LayerFileEnum::Rollup, [2; 32]),
+            ],
+        };
+        let parsed = ChecksumTrailer::parse(&trailer.to_bytes())
+            .unwrap()
+            .unwrap();
+        assert_eq!(parsed.get(LayerFileEnum::Parent), Some(&[1; 32]));
+        assert_eq!(parsed.get(LayerFileEnum::Rollup), Some(&[2; 32]));
+        assert!(ChecksumTrailer::parse(&[]).unwrap().is_none());
+    }
+
+    #[test]
+    fn trailers_with_the_wrong_entry_count_are_rejected() {
+        let mut bytes = ChecksumTrailer {
+            checksums: vec![(LayerFileEnum::Parent, [1; 32])],
+        }
+        .to_bytes();
+        bytes[ENTRY_SIZE + 3] = 2;
+        let e = ChecksumTrailer::parse(&bytes).err().unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        let e = ChecksumTrailer::parse(b"junk").err().unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,241 @@
+use std::fmt;
+
+/// A stable code for each kind of finding the validators report, so
+/// findings can be looked up with `explain` and matched on in scripts.
+/// Codes are never reused or renumbered.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FindingCode {
+    HeaderUnreadable,
+    SegmentOutOfBounds,
+    LogArrayInvalid,
+    BitArrayInvalid,
+    ParentInvalid,
+    ChecksumTrailerInvalid,
+    ChecksumMissing,
+    ChecksumMismatch,
+    ChecksumOrphaned,
+    DictionaryInvalid,
+    BitIndexLength,
+    BitIndexBlockMismatch,
+    BitIndexSBlockMismatch,
+    AdjacencyInvalid,
+    ValueTruncated,
+    Custom,
+}
+
+/// How badly a finding affects a layer.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Severity {
+    /// The layer's data may be lost or unreadable.
+    Critical,
+    /// Part of the layer is damaged but can be rebuilt or worked around.
+    Error,
+    /// Something is inconsistent without the data being affected.
+    Warning,
+}
+
+impl Severity {
+    pub const ALL: [Severity; 3] = [Severity::Critical, Severity::Error, Severity::Warning];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Critical => "critical",
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+
+    /// How much of a layer's health a finding of this severity costs.
+    pub fn weight(self) -> f64 {
+        match self {
+            Severity::Critical => 1.0,
+            Severity::Error => 0.5,
+            Severity::Warning => 0.1,
+        }
+    }
+}
+
+/// What a finding code means and what to do about it.
+pub struct Explanation {
+    pub title: &'static str,
+    pub meaning: &'static str,
+    pub causes: &'static str,
+    pub repair: &'static str,
+}
+
+impl FindingCode {
+    pub const ALL: [FindingCode; 16] = [
+        FindingCode::HeaderUnreadable,
+        FindingCode::SegmentOutOfBounds,
+        FindingCode::LogArrayInvalid,
+        FindingCode::BitArrayInvalid,
+        FindingCode::ParentInvalid,
+        FindingCode::ChecksumTrailerInvalid,
+        FindingCode::ChecksumMissing,
+        FindingCode::ChecksumMismatch,
+        FindingCode::ChecksumOrphaned,
+        FindingCode::DictionaryInvalid,
+        FindingCode::BitIndexLength,
+        FindingCode::BitIndexBlockMismatch,
+        FindingCode::BitIndexSBlockMismatch,
+        FindingCode::AdjacencyInvalid,
+        FindingCode::ValueTruncated,
+        FindingCode::Custom,
+    ];
+
+    pub fn code(self) -> &'static str {
+        match self {
+            FindingCode::HeaderUnreadable => "SURG-E000",
+            FindingCode::SegmentOutOfBounds => "SURG-E001",
+            FindingCode::LogArrayInvalid => "SURG-E002",
+            FindingCode::BitArrayInvalid => "SURG-E003",
+            FindingCode::ParentInvalid => "SURG-E004",
+            FindingCode::ChecksumTrailerInvalid => "SURG-E005",
+            FindingCode::ChecksumMissing => "SURG-E006",
+            FindingCode::ChecksumMismatch => "SURG-E007",
+            FindingCode::ChecksumOrphaned => "SURG-E008",
+            FindingCode::DictionaryInvalid => "SURG-E009",
+            FindingCode::BitIndexLength => "SURG-E010",
+            FindingCode::BitIndexBlockMismatch => "SURG-E011",
+            FindingCode::BitIndexSBlockMismatch => "SURG-E012",
+            FindingCode::AdjacencyInvalid => "SURG-E013",
+            FindingCode::ValueTruncated => "SURG-E014",
+            FindingCode::Custom => "SURG-E900",
+        }
+    }
+
+    pub fn severity(self) -> Severity {
+        match self {
+            FindingCode::HeaderUnreadable
+            | FindingCode::SegmentOutOfBounds
+            | FindingCode::ParentInvalid
+            | FindingCode::ChecksumMismatch
+            | FindingCode::DictionaryInvalid
+            | FindingCode::ValueTruncated => Severity::Critical,
+            FindingCode::LogArrayInvalid
+            | FindingCode::BitArrayInvalid
+            | FindingCode::BitIndexLength
+            | FindingCode::BitIndexBlockMismatch
+            | FindingCode::BitIndexSBlockMismatch
+            | FindingCode::AdjacencyInvalid
+            | FindingCode::Custom => Severity::Error,
+            FindingCode::ChecksumTrailerInvalid
+            | FindingCode::ChecksumMissing
+            | FindingCode::ChecksumOrphaned => Severity::Warning,
+        }
+    }
+
+    /// Look up a code, ignoring case. The `SURG-` prefix may be left off.
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.to_uppercase();
+        let s = s.strip_prefix("SURG-").unwrap_or(&s);
+        Self::ALL.into_iter().find(|c| &c.code()[5..] == s)
+    }
+
+    pub fn explanation(self) -> Explanation {
+        match self {
+            FindingCode::HeaderUnreadable => Explanation {
+                title: "archive header unreadable",
+                meaning: "The file is too short to hold an archive header, or the header's segment sizes can't be read.",
+                causes: "A truncated copy, a file that isn't a layer archive, or damage to the first bytes of the file.",
+                repair: "rebuild-header <file> -o <output> reconstructs a header from the segments that follow it.",
+            },
+            FindingCode::SegmentOutOfBounds => Explanation {
+                title: "segment extends past the end of the archive",
+                meaning: "The header describes a segment that ends beyond the end of the file.",
+                causes: "A truncated archive, usually from an interrupted copy or a full disk, or a corrupted size in the header.",
+                repair: "Restore the layer from a backup. Otherwise carve out the intact segments with carve and rebuild-header.",
+            },
+            FindingCode::LogArrayInvalid => Explanation {
+                title: "logarray control word invalid",
+                meaning: "A logarray segment's length and width don't match its size, or its control word is malformed.",
+                causes: "Bit flips in the control word at the end of the segment, or a segment boundary in the header that is off.",
+                repair: "print-segment shows what can be decoded. Index logarrays can be rebuilt with build-object-index, build-predicate-index or build-subject-index.",
+            },
+            FindingCode::BitArrayInvalid => Explanation {
+                title: "bitarray control word invalid",
+                meaning: "A bitarray segment's bit count doesn't match its size.",
+                causes: "Bit flips in the control word, or a wrong segment boundary in the header.",
+                repair: "Restore the layer from a backup. If only a bitindex is affected, rebuild it with build-subject-index.",
+            },
+            FindingCode::ParentInvalid => Explanation {
+                title: "parent reference invalid",
+                meaning: "The parent segment doesn't hold a 20 byte layer name.",
+                causes: "Damage to the parent segment or a wrong segment boundary in the header.",
+                repair: "meta set <file> parent <layer> writes a new parent reference once the right parent is known.",
+            },
+            FindingCode::ChecksumTrailerInvalid => Explanation {
+                title: "checksum trailer unreadable",
+                meaning: "The archive ends in something that looks like a checksum trailer but can't be read.",
+                causes: "Bytes appended after the last segment, or damage to the trailer itself.",
+                repair: "canonicalize --with-checksums rewrites the trailer, after verifying the segments some other way.",
+            },
+            FindingCode::ChecksumMissing => Explanation {
+                title: "segment has no checksum",
+                meaning: "The checksum trailer has no entry for a segment in the header.",
+                causes: "A segment was added after the trailer was written, or the trailer was written by an older tool.",
+                repair: "canonicalize --with-checksums recomputes the trailer.",
+            },
+            FindingCode::ChecksumMismatch => Explanation {
+                title: "segment checksum mismatch",
+                meaning: "A segment's contents no longer hash to the checksum recorded for it.",
+                causes: "Silent corruption on disk or in transit, or a repair that changed the segment without updating the trailer.",
+                repair: "Restore the layer from a backup. If the change was a deliberate repair, canonicalize --with-checksums records the new checksums.",
+            },
+            FindingCode::ChecksumOrphaned => Explanation {
+                title: "checksum for an absent segment",
+                meaning: "The checksum trailer lists a segment that the header doesn't have.",
+                causes: "A header that lost a segment, or a trailer copied from a different archive.",
+                repair: "Check the header with parse-header. If it is right, canonicalize --with-checksums rewrites the trailer.",
+            },
+            FindingCode::DictionaryInvalid => Explanation {
+                title: "dictionary undecodable or unsorted",
+                meaning: "Dictionary blocks can't be decoded, or their entries aren't in strictly increasing order.",
+                causes: "Corruption in the blocks segment, or offsets that don't point at block boundaries.",
+                repair: "salvage-dict recovers every readable entry. dump-dict-blocks shows which blocks are damaged.",
+            },
+            FindingCode::BitIndexLength => Explanation {
+                title: "bitindex size mismatch",
+                meaning: "A bitindex has a different number of blocks or superblocks than its bits require.",
+                causes: "A bitindex built for a different bit array, or a truncated segment.",
+                repair: "Rebuild the bitindex from the bits, for example with build-subject-index.",
+            },
+            FindingCode::BitIndexBlockMismatch => Explanation {
+                title: "bitindex block mismatch",
+                meaning: "A bitindex block doesn't count the ones from its 64 bit word to the end of its superblock.",
+                causes: "Corruption of the blocks segment or of the bits.",
+                repair: "Rebuild the bitindex from the bits, for example with build-subject-index.",
+            },
+            FindingCode::BitIndexSBlockMismatch => Explanation {
+                title: "bitindex sblock mismatch",
+                meaning: "A bitindex superblock's running count doesn't match the ones in the words it covers.",
+                causes: "Corruption of the sblocks segment or of the bits.",
+                repair: "Rebuild the bitindex from the bits, for example with build-subject-index.",
+            },
+            FindingCode::AdjacencyInvalid => Explanation {
+                title: "adjacency list invariant broken",
+                meaning: "An adjacency list's nums and bits disagree, or a group isn't sorted.",
+                causes: "Corruption of the nums or bits segments, or an index rebuilt from the wrong input.",
+                repair: "check-adjacency gives details. Object indexes can be rebuilt with build-object-index.",
+            },
+            FindingCode::ValueTruncated => Explanation {
+                title: "value entry truncated",
+                meaning: "A value dictionary entry's length prefix disagrees with the bytes its block holds, or its text ends partway through a UTF-8 character.",
+                causes: "A value cut short when it was written, or corruption of the length prefixes in a block.",
+                repair: "salvage-dict recovers the entries that can be read. The finding names the block and entry, which print-dict shows.",
+            },
+            FindingCode::Custom => Explanation {
+                title: "site-specific check failed",
+                meaning: "A check compiled in with the custom-checks feature reported a problem.",
+                causes: "See the check's own documentation.",
+                repair: "See the check's own documentation.",
+            },
+        }
+    }
+}
+
+impl fmt::Display for FindingCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.code())
+    }
+}
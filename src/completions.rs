@@ -0,0 +1,92 @@
+use std::{io, path::Path};
+
+use clap::{Command, ValueEnum};
+use clap_complete::Shell;
+use terminus_store::storage::name_to_string;
+
+use crate::store::{list_labels, list_layers};
+
+/// What the dynamic completion helper should list.
+#[derive(ValueEnum, Clone, Copy)]
+pub enum CompletionKind {
+    Labels,
+    Layers,
+}
+
+const BASH_DYNAMIC: &str = r#"
+_terminusdb_surgery_dynamic() {
+    local cur prev store i
+    cur="${COMP_WORDS[COMP_CWORD]}"
+    prev="${COMP_WORDS[COMP_CWORD-1]}"
+    store="."
+    for ((i=1; i < COMP_CWORD; i++)); do
+        if [[ "${COMP_WORDS[i]}" == "-s" || "${COMP_WORDS[i]}" == "--store" ]]; then
+            store="${COMP_WORDS[i+1]}"
+        fi
+    done
+    case "$prev" in
+        -g|--label)
+            COMPREPLY=($(terminusdb-surgery complete labels --store "$store" "$cur" 2>/dev/null))
+            return 0
+            ;;
+        -l|--layer|--new-parent)
+            COMPREPLY=($(terminusdb-surgery complete layers --store "$store" "$cur" 2>/dev/null))
+            return 0
+            ;;
+    esac
+    _terminusdb-surgery "$@"
+}
+complete -F _terminusdb_surgery_dynamic -o nosort -o bashdefault -o default terminusdb-surgery
+"#;
+
+const FISH_DYNAMIC: &str = r#"
+function __terminusdb_surgery_store
+    set -l args (commandline -opc)
+    for i in (seq (count $args))
+        if contains -- $args[$i] -s --store
+            echo $args[(math $i + 1)]
+            return
+        end
+    end
+    echo .
+end
+complete -c terminusdb-surgery -s g -l label -x -a '(terminusdb-surgery complete labels --store (__terminusdb_surgery_store))'
+complete -c terminusdb-surgery -s l -l layer -x -a '(terminusdb-surgery complete layers --store (__terminusdb_surgery_store))'
+complete -c terminusdb-surgery -l new-parent -x -a '(terminusdb-surgery complete layers --store (__terminusdb_surgery_store))'
+"#;
+
+/// Write a completion script for the given shell. Bash and fish scripts
+/// also complete label names and layer ids from the store given with
+/// `--store`.
+pub fn generate(shell: Shell, mut command: Command) {
+    let name = command.get_name().to_string();
+    clap_complete::generate(shell, &mut command, name, &mut io::stdout());
+    match shell {
+        Shell::Bash => print!("{BASH_DYNAMIC}"),
+        Shell::Fish => print!("{FISH_DYNAMIC}"),
+        _ => {}
+    }
+}
+
+/// Print the labels or layer ids of a store that start with a prefix.
+pub async fn complete(store: &Path, kind: CompletionKind, prefix: &str) -> io::Result<()> {
+    match kind {
+        CompletionKind::Labels => {
+            for label in list_labels(store).await? {
+                if label.starts_with(prefix) {
+                    println!("{label}");
+                }
+            }
+        }
+        CompletionKind::Layers => {
+            for (name, _) in list_layers(store).await? {
+                let name = name_to_string(name);
+                if name.starts_with(prefix) {
+                    println!("{name}");
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,86 @@
+use std::{
+    io::{self, BufRead, IsTerminal, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    output::{human_bytes, paint, Color},
+    store::{chain, list_labels, read_label},
+};
+
+/// The name to type to confirm an operation on a store: its directory's
+/// name.
+fn store_name(store: &Path) -> String {
+    let store = store.canonicalize().unwrap_or_else(|_| store.to_path_buf());
+    store
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| store.display().to_string())
+}
+
+/// Total size of some layer files, for previews. Files that can't be read
+/// count as empty.
+pub fn total_size(files: &[PathBuf]) -> String {
+    let total: u64 = files
+        .iter()
+        .filter_map(|f| std::fs::metadata(f).ok())
+        .map(|m| m.len())
+        .sum();
+    human_bytes(total as usize)
+}
+
+/// The labels whose head's chain includes `layer`. Labels with a broken
+/// chain are left out.
+pub async fn labels_reaching(store: &Path, layer: [u32; 5]) -> io::Result<Vec<String>> {
+    let mut result = Vec::new();
+    for label in list_labels(store).await? {
+        if let Ok(Some(head)) = read_label(store, &label).await {
+            if let Ok(layers) = chain(store, head).await {
+                if layers.iter().any(|(name, _)| *name == layer) {
+                    result.push(label);
+                }
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+/// Print a preview of what a destructive command is about to do and ask
+/// for the store's name to be typed back before going ahead. `yes` skips
+/// the question. Without a terminal to ask on, the command is refused
+/// unless `yes` is given.
+pub fn confirm(command: &str, store: &Path, preview: &[String], yes: bool) -> io::Result<()> {
+    let mut stderr = io::stderr().lock();
+    writeln!(
+        stderr,
+        "{}",
+        paint(&format!("{command} will:"), Color::Yellow)
+    )?;
+    for line in preview {
+        writeln!(stderr, "  {line}")?;
+    }
+    if yes {
+        return Ok(());
+    }
+    if !io::stdin().is_terminal() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{command} needs confirmation; give --yes to go ahead without a terminal"),
+        ));
+    }
+
+    let name = store_name(store);
+    write!(stderr, "Type the store name ({name}) to go ahead: ")?;
+    stderr.flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    if answer.trim() != name {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{command} cancelled"),
+        ));
+    }
+
+    Ok(())
+}
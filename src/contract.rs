@@ -0,0 +1,443 @@
+use serde_json::{json, Value};
+
+use crate::output::FORMAT_VERSION;
+
+/// Commands that support `--format ndjson` or `--json`, as given to
+/// `schema`.
+pub const COMMANDS: [&str; 34] = [
+    "assert",
+    "bench compare",
+    "centrality",
+    "check-collation",
+    "check-counts",
+    "check-required",
+    "dangling-objects",
+    "databases",
+    "diagnose",
+    "dict-entry",
+    "dict-lookup",
+    "dump-dict-blocks",
+    "extract-all",
+    "fsck",
+    "grep",
+    "has-triple",
+    "id-node",
+    "lang-stats",
+    "node-count",
+    "node-id",
+    "parse-header",
+    "print-dict",
+    "scan",
+    "scan-store",
+    "search-values",
+    "show-subject",
+    "smoke",
+    "stats",
+    "stats-index show",
+    "triple-count",
+    "triples",
+    "validate-dict",
+    "validate-layer",
+    "validate-store",
+];
+
+/// An object as `object_json` writes it: either a node or a typed value.
+fn object_schema() -> Value {
+    json!({
+        "oneOf": [
+            {
+                "type": "object",
+                "properties": { "node": { "type": "string" } },
+                "required": ["node"],
+            },
+            {
+                "type": "object",
+                "properties": {
+                    "value": { "type": "string" },
+                    "datatype": { "type": "string" },
+                },
+                "required": ["value", "datatype"],
+            },
+        ]
+    })
+}
+
+/// A record with the given fields, all of them required, plus the format
+/// version every record carries.
+fn record(fields: Value) -> Value {
+    let mut properties = fields;
+    let mut required: Vec<String> = properties.as_object().unwrap().keys().cloned().collect();
+    properties.as_object_mut().unwrap().insert(
+        "format_version".to_string(),
+        json!({ "const": FORMAT_VERSION }),
+    );
+    required.push("format_version".to_string());
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+    })
+}
+
+/// The fields of the records a command prints with `--format ndjson`.
+fn records(command: &str) -> Option<Vec<Value>> {
+    let string = json!({ "type": "string" });
+    let integer = json!({ "type": "integer", "minimum": 0 });
+    let triple = || {
+        json!({
+            "subject": string,
+            "predicate": string,
+            "object": object_schema(),
+        })
+    };
+    let with = |mut fields: Value, name: &str, schema: Value| {
+        fields
+            .as_object_mut()
+            .unwrap()
+            .insert(name.to_string(), schema);
+        fields
+    };
+    Some(match command {
+        "triples" => vec![record(triple())],
+        "show-subject" => vec![record(with(
+            triple(),
+            "layer",
+            json!({ "type": ["string", "null"] }),
+        ))],
+        "grep" => vec![record(with(triple(), "label", string.clone()))],
+        "has-triple" => vec![record(json!({ "found": { "type": "boolean" } }))],
+        "centrality" => vec![record(json!({
+            "node": string,
+            "id": integer,
+            "in": integer,
+            "out": integer,
+            "total": integer,
+        }))],
+        "dangling-objects" => vec![record(json!({ "node": string, "references": integer }))],
+        "check-required" => vec![record(json!({
+            "class": string,
+            "subject": string,
+            "missing": { "type": "array", "items": string },
+        }))],
+        "search-values" => vec![record(json!({
+            "id": integer,
+            "datatype": string,
+            "value": string,
+        }))],
+        "diagnose" => vec![record(json!({
+            "layer": string,
+            "findings": { "type": "object", "additionalProperties": integer },
+            "causes": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "cause": {
+                            "enum": [
+                                "truncated upload",
+                                "interrupted index build",
+                                "bitflip",
+                                "version mismatch",
+                            ],
+                        },
+                        "confidence": { "type": "number", "minimum": 0, "maximum": 1 },
+                        "evidence": { "type": "array", "items": string },
+                        "next": { "type": "array", "items": string },
+                    },
+                    "required": ["cause", "confidence", "evidence", "next"],
+                },
+            },
+        }))],
+        "smoke" => vec![record(json!({
+            "check": string,
+            "status": { "enum": ["pass", "fail"] },
+            "cases": integer,
+            "failed": integer,
+            "failures": { "type": "array", "items": string },
+        }))],
+        "lang-stats" => vec![record(json!({ "lang": string, "count": integer }))],
+        "dump-dict-blocks" => vec![record(json!({
+            "block": integer,
+            "offset": integer,
+            "size": integer,
+            "entries": integer,
+            "entry_bytes": integer,
+            "head": string,
+        }))],
+        "parse-header" => vec![record(json!({
+            "segment": string,
+            "start": integer,
+            "end": integer,
+            "size": integer,
+        }))],
+        "fsck" => vec![
+            record(json!({ "layer": string, "status": { "const": "ok" } })),
+            record(json!({
+                "layer": string,
+                "status": { "const": "fail" },
+                "finding": string,
+            })),
+            record(json!({
+                "checked": integer,
+                "skipped": integer,
+                "failed": integer,
+                "remaining": integer,
+                "health": { "type": "number", "minimum": 0, "maximum": 100 },
+                "findings": { "type": "object", "additionalProperties": integer },
+            })),
+        ],
+        "validate-layer" => vec![record(json!({
+            "segment": string,
+            "start": integer,
+            "end": integer,
+            "status": { "enum": ["pass", "fail"] },
+            "offset": { "type": ["integer", "null"], "minimum": 0 },
+            "message": { "type": ["string", "null"] },
+        }))],
+        "validate-store" => vec![
+            record(json!({
+                "kind": { "const": "failure" },
+                "layer": string,
+                "segment": { "type": ["string", "null"] },
+                "offset": { "type": ["integer", "null"], "minimum": 0 },
+                "message": string,
+            })),
+            record(json!({
+                "kind": { "const": "summary" },
+                "validated": integer,
+                "resumed": integer,
+                "failed": integer,
+            })),
+        ],
+        "scan" => vec![
+            record(json!({
+                "store": string,
+                "status": { "enum": ["ok", "damaged", "dead", "empty"] },
+                "health": { "type": "number", "minimum": 0, "maximum": 100 },
+                "layers": integer,
+                "failed": integer,
+                "labels": integer,
+            })),
+            record(json!({
+                "stores": integer,
+                "ok": integer,
+                "damaged": integer,
+                "dead": integer,
+                "empty": integer,
+            })),
+        ],
+        "bench compare" => {
+            let number = json!({ "type": "number" });
+            vec![
+                record(json!({
+                    "kind": { "const": "size" },
+                    "baseline": integer,
+                    "candidate": integer,
+                })),
+                record(json!({
+                    "kind": { "const": "workload" },
+                    "workload": string,
+                    "baseline_mean": number,
+                    "baseline_stddev": number,
+                    "baseline_median": number,
+                    "candidate_mean": number,
+                    "candidate_stddev": number,
+                    "candidate_median": number,
+                    "change": number,
+                    "t": { "type": ["number", "null"] },
+                    "significant": { "type": "boolean" },
+                    "answers_match": { "type": "boolean" },
+                })),
+            ]
+        }
+        "stats" => {
+            let number = json!({ "type": "number" });
+            let optional = json!({ "type": ["integer", "null"] });
+            vec![
+                record(json!({
+                    "kind": { "const": "segment" },
+                    "segment": string,
+                    "size": integer,
+                    "len": optional,
+                    "width": optional,
+                    "ones": optional,
+                })),
+                record(json!({
+                    "kind": { "const": "dictionary" },
+                    "dictionary": { "enum": ["nodes", "predicates", "values"] },
+                    "entries": integer,
+                    "blocks": integer,
+                    "size": integer,
+                    "entry_bytes": integer,
+                    "average_len": number,
+                    "compression": number,
+                })),
+                record(json!({
+                    "kind": { "const": "adjacency" },
+                    "list": string,
+                    "groups": integer,
+                    "entries": integer,
+                    "density": number,
+                    "max_group": integer,
+                    "empty_groups": integer,
+                })),
+                record(json!({
+                    "kind": { "const": "summary" },
+                    "size": integer,
+                    "header_len": integer,
+                    "added": integer,
+                    "removed": integer,
+                    "bits_per_triple": number,
+                    "index_compression": number,
+                })),
+            ]
+        }
+        "stats-index show" => vec![record(json!({
+            "layer": string,
+            "size": integer,
+            "parent": { "type": ["string", "null"] },
+            "nodes": integer,
+            "predicates": integer,
+            "values": integer,
+            "added": integer,
+            "removed": integer,
+        }))],
+        "extract-all" => vec![record(json!({
+            "segment": string,
+            "file": string,
+            "size": integer,
+            "checksum": { "type": ["boolean", "null"] },
+        }))],
+        "databases" => {
+            let segment = json!({ "type": ["string", "null"] });
+            vec![record(json!({
+                "label": string,
+                "organization": segment,
+                "database": segment,
+                "repository": segment,
+                "branch": segment,
+                "recognized": { "type": "boolean" },
+                "head": segment,
+                "chain": integer,
+                "size": integer,
+            }))]
+        }
+        "assert" => vec![record(json!({
+            "line": integer,
+            "assertion": string,
+            "status": { "enum": ["pass", "fail"] },
+            "actual": integer,
+        }))],
+        "scan-store" => vec![
+            record(json!({
+                "kind": { "const": "unreachable" },
+                "layer": string,
+                "path": string,
+                "size": integer,
+            })),
+            record(json!({
+                "kind": { "const": "dangling" },
+                "from": string,
+                "reference": { "enum": ["head", "pin", "parent", "rollup"] },
+                "missing": string,
+            })),
+            record(json!({
+                "kind": { "const": "broken" },
+                "layer": string,
+                "path": string,
+                "reason": string,
+            })),
+            record(json!({
+                "kind": { "const": "database" },
+                "label": string,
+                "database": { "type": ["string", "null"] },
+                "layers": integer,
+                "size": integer,
+            })),
+            record(json!({
+                "kind": { "const": "summary" },
+                "layers": integer,
+                "size": integer,
+                "unreachable": integer,
+                "unreachable_size": integer,
+                "dangling": integer,
+                "broken": integer,
+            })),
+        ],
+        "node-id" => vec![record(json!({
+            "node": string,
+            "id": { "type": ["integer", "null"], "minimum": 0 },
+        }))],
+        "id-node" => vec![record(json!({
+            "id": string,
+            "node": { "type": ["string", "null"] },
+        }))],
+        "node-count" => vec![record(json!({
+            "nodes": { "type": ["integer", "null"], "minimum": 0 },
+        }))],
+        "triple-count" => vec![
+            record(json!({ "triples": { "type": "integer" } })),
+            record(json!({ "predicate": string, "triples": integer })),
+            record(json!({
+                "layer": string,
+                "added": integer,
+                "removed": integer,
+                "total": { "type": "integer" },
+            })),
+        ],
+        "print-dict" => vec![
+            record(json!({ "id": integer, "entry": string })),
+            record(json!({ "id": integer, "value": string, "datatype": string })),
+        ],
+        "dict-lookup" | "dict-entry" => {
+            vec![record(json!({ "id": integer, "entry": string }))]
+        }
+        "validate-dict" => vec![record(json!({
+            "status": { "const": "ok" },
+            "entries": integer,
+        }))],
+        "check-collation" => vec![
+            record(json!({
+                "dict": { "enum": ["nodes", "predicates", "values"] },
+                "id": integer,
+                "entry": string,
+                "found": { "type": ["integer", "null"], "minimum": 0 },
+                "reason": string,
+            })),
+            record(json!({ "unfindable": { "const": 0 } })),
+        ],
+        "check-counts" => vec![record(json!({
+            "side": { "enum": ["additions", "removals"] },
+            "quantity": { "enum": ["triples", "s_p pairs"] },
+            "counts": { "type": "object", "additionalProperties": integer },
+            "errors": { "type": "object", "additionalProperties": string },
+            "agree": { "type": "boolean" },
+        }))],
+        _ => return None,
+    })
+}
+
+/// The JSON schema of each line a command prints with `--format ndjson`,
+/// or None if the command has no such output. Commands that print several
+/// kinds of record accept any one of them per line.
+pub fn output_schema(command: &str) -> Option<Value> {
+    let mut records = records(command)?;
+    let mut schema = if records.len() == 1 {
+        records.pop().unwrap()
+    } else {
+        json!({ "oneOf": records })
+    };
+    let fields = schema.as_object_mut().unwrap();
+    fields.insert(
+        "$schema".to_string(),
+        json!("https://json-schema.org/draft/2020-12/schema"),
+    );
+    fields.insert(
+        "title".to_string(),
+        json!(format!(
+            "{command} --format ndjson, version {FORMAT_VERSION}"
+        )),
+    );
+
+    Some(schema)
+}
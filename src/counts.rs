@@ -0,0 +1,174 @@
+use serde_json::json;
+use terminus_store::{
+    storage::consts::LayerFileEnum,
+    structure::{bitarray::BitArray, LogArray},
+};
+
+use crate::{
+    archive::{all_segment_types, Archive},
+    output::{ndjson, paint, Color, OutputFormat},
+};
+
+/// One quantity of a layer as derived from each structure that encodes it.
+pub struct CountCheck {
+    /// `additions` or `removals`.
+    pub side: &'static str,
+    /// `triples` or `s_p pairs`.
+    pub quantity: &'static str,
+    /// Each source with the count it gives, or why it can't be read.
+    pub counts: Vec<(&'static str, Result<u64, String>)>,
+}
+
+impl CountCheck {
+    pub fn agrees(&self) -> bool {
+        let mut counts = self.counts.iter().map(|(_, count)| count);
+        match counts.next() {
+            Some(Ok(first)) => counts.all(|count| count.as_ref() == Ok(first)),
+            Some(Err(_)) => false,
+            None => true,
+        }
+    }
+}
+
+fn find_segment(name: &str) -> Option<LayerFileEnum> {
+    all_segment_types().find(|t| format!("{t:?}") == name)
+}
+
+fn logarray(archive: &Archive, name: &str) -> Option<Result<LogArray, String>> {
+    match archive.segment(find_segment(name)?) {
+        Err(e) => Some(Err(e.to_string())),
+        Ok(None) => None,
+        Ok(Some(contents)) => Some(LogArray::parse(contents).map_err(|e| e.to_string())),
+    }
+}
+
+fn bitarray(archive: &Archive, name: &str) -> Option<Result<BitArray, String>> {
+    match archive.segment(find_segment(name)?) {
+        Err(e) => Some(Err(e.to_string())),
+        Ok(None) => None,
+        Ok(Some(contents)) => Some(BitArray::from_bits(contents).map_err(|e| e.to_string())),
+    }
+}
+
+/// Derive the triple count of each side of a layer independently from the
+/// SpO nums and bits, and from the OPs object index; and its number of
+/// subject-predicate pairs from the S_P nums, the groups of the SpO bits
+/// and the predicate wavelet tree, whose bits hold one layer per bit of
+/// the S_P nums width. Sources whose segments are absent are left out.
+pub fn check_counts(archive: &Archive) -> Vec<CountCheck> {
+    let mut result = Vec::new();
+    for (prefix, side) in [("Pos", "additions"), ("Neg", "removals")] {
+        let name = |suffix: &str| format!("{prefix}{suffix}");
+        let sp_o_bits = bitarray(archive, &name("SpOAdjacencyListBits"));
+        let s_p_nums = logarray(archive, &name("SPAdjacencyListNums"));
+
+        let mut triples = Vec::new();
+        let logarray_len =
+            |suffix: &str| logarray(archive, &name(suffix)).map(|l| l.map(|l| l.len() as u64));
+        let bitarray_len =
+            |suffix: &str| bitarray(archive, &name(suffix)).map(|b| b.map(|b| b.len() as u64));
+        let sources = [
+            ("sp_o nums", logarray_len("SpOAdjacencyListNums")),
+            ("sp_o bits", bitarray_len("SpOAdjacencyListBits")),
+            ("o_ps nums", logarray_len("OPsAdjacencyListNums")),
+            ("o_ps bits", bitarray_len("OPsAdjacencyListBits")),
+        ];
+        for (source, count) in sources {
+            if let Some(count) = count {
+                triples.push((source, count));
+            }
+        }
+
+        let mut pairs = Vec::new();
+        if let Some(nums) = s_p_nums.as_ref() {
+            pairs.push((
+                "s_p nums",
+                nums.as_ref().map(|n| n.len() as u64).map_err(Clone::clone),
+            ));
+        }
+        if let Some(bits) = sp_o_bits.as_ref() {
+            let groups = bits
+                .as_ref()
+                .map(|b| (0..b.len()).filter(|i| b.get(*i)).count() as u64)
+                .map_err(Clone::clone);
+            pairs.push(("sp_o groups", groups));
+        }
+        if let (Some(wavelet), Some(Ok(nums))) = (
+            bitarray(archive, &name("PredicateWaveletTreeBits")),
+            s_p_nums.as_ref(),
+        ) {
+            let count = wavelet.and_then(|w| match nums.width() as u64 {
+                0 => Err("s_p nums have width 0".to_string()),
+                width if w.len() as u64 % width != 0 => Err(format!(
+                    "{} bits don't divide into layers of {width}",
+                    w.len()
+                )),
+                width => Ok(w.len() as u64 / width),
+            });
+            pairs.push(("wavelet", count));
+        }
+
+        for (quantity, counts) in [("triples", triples), ("s_p pairs", pairs)] {
+            if !counts.is_empty() {
+                result.push(CountCheck {
+                    side,
+                    quantity,
+                    counts,
+                });
+            }
+        }
+    }
+
+    result
+}
+
+/// Print each count with the sources it was derived from, marking those
+/// where the sources disagree.
+pub fn print_counts(checks: &[CountCheck], format: OutputFormat) {
+    for check in checks {
+        let agrees = check.agrees();
+        match format {
+            OutputFormat::Ndjson => {
+                let counts: serde_json::Map<_, _> = check
+                    .counts
+                    .iter()
+                    .filter_map(|(source, count)| {
+                        count.as_ref().ok().map(|c| (source.to_string(), json!(c)))
+                    })
+                    .collect();
+                let errors: serde_json::Map<_, _> = check
+                    .counts
+                    .iter()
+                    .filter_map(|(source, count)| {
+                        count.as_ref().err().map(|e| (source.to_string(), json!(e)))
+                    })
+                    .collect();
+                println!(
+                    "{}",
+                    ndjson(json!({
+                        "side": check.side,
+                        "quantity": check.quantity,
+                        "counts": counts,
+                        "errors": errors,
+                        "agree": agrees,
+                    }))
+                );
+            }
+            _ => {
+                let status = match (agrees, format) {
+                    (true, OutputFormat::Pretty) => paint("agree", Color::Green),
+                    (false, OutputFormat::Pretty) => paint("DISAGREE", Color::Red),
+                    (true, _) => "agree".to_string(),
+                    (false, _) => "DISAGREE".to_string(),
+                };
+                println!("{} {}: {status}", check.side, check.quantity);
+                for (source, count) in check.counts.iter() {
+                    match count {
+                        Ok(count) => println!("  {source:<12} {count}"),
+                        Err(e) => println!("  {source:<12} unreadable: {e}"),
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,11 @@
+//! Site-specific checks, compiled in with the `custom-checks` feature.
+//!
+//! Add a type implementing `Check` here and return it from `checks` to make
+//! it available to `fsck --checks` and `watch --checks`. Its findings
+//! should carry `FindingCode::Custom`.
+
+use crate::checks::Check;
+
+pub fn checks() -> Vec<Box<dyn Check>> {
+    Vec::new()
+}
@@ -0,0 +1,177 @@
+use std::{io, path::Path};
+
+use serde_json::json;
+use terminus_store::storage::name_to_string;
+
+use crate::{
+    output::{human_bytes, ndjson, paint, Color, OutputFormat},
+    store::{chain, list_labels, read_label},
+};
+
+/// The label the server keeps its system database under.
+const SYSTEM_LABEL: &str = "terminusdb";
+
+/// What a label stands for, decoded from its name.
+#[derive(Default)]
+pub struct LabelPath {
+    pub organization: Option<String>,
+    pub database: Option<String>,
+    pub repository: Option<String>,
+    pub branch: Option<String>,
+    /// Set for labels that aren't named the way the server names them.
+    pub unrecognized: bool,
+}
+
+impl LabelPath {
+    /// The path as the server writes it, such as `admin/people`.
+    pub fn path(&self) -> Option<String> {
+        let parts: Vec<_> = [
+            &self.organization,
+            &self.database,
+            &self.repository,
+            &self.branch,
+        ]
+        .into_iter()
+        .flatten()
+        .cloned()
+        .collect();
+        (!parts.is_empty()).then(|| parts.join("/"))
+    }
+}
+
+/// Undo the percent encoding of a path segment.
+fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            result.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            result.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(result).ok()
+}
+
+/// Decode a label name the way the server builds them: the system database
+/// is `terminusdb`, and a database is its organization and name, each
+/// percent encoded, joined by `|`. Repositories and branches normally live
+/// in a database's own graphs, but labels naming them as further `|`
+/// separated segments are decoded too.
+pub fn decode_label(label: &str) -> LabelPath {
+    if label == SYSTEM_LABEL {
+        return LabelPath {
+            database: Some("_system".to_string()),
+            ..Default::default()
+        };
+    }
+    let segments: Option<Vec<_>> = label.split('|').map(percent_decode).collect();
+    match segments {
+        Some(segments) if (2..=4).contains(&segments.len()) => {
+            let mut segments = segments.into_iter();
+            LabelPath {
+                organization: segments.next(),
+                database: segments.next(),
+                repository: segments.next(),
+                branch: segments.next(),
+                unrecognized: false,
+            }
+        }
+        _ => LabelPath {
+            unrecognized: true,
+            ..Default::default()
+        },
+    }
+}
+
+/// A label of a store with what it stands for and what its head holds.
+pub struct DatabaseSummary {
+    pub label: String,
+    pub path: LabelPath,
+    pub head: Option<[u32; 5]>,
+    /// Layers in the head's chain.
+    pub chain: usize,
+    /// Total size of the chain's layer files.
+    pub size: u64,
+}
+
+/// Summarize every label of a store. A label whose head or chain can't be
+/// read is reported and listed without them.
+pub async fn databases(store: &Path) -> io::Result<Vec<DatabaseSummary>> {
+    let mut result = Vec::new();
+    for label in list_labels(store).await? {
+        let mut summary = DatabaseSummary {
+            path: decode_label(&label),
+            label,
+            head: None,
+            chain: 0,
+            size: 0,
+        };
+        match read_label(store, &summary.label).await {
+            Ok(head) => summary.head = head,
+            Err(e) => eprintln!("{}: {e}", summary.label),
+        }
+        if let Some(head) = summary.head {
+            match chain(store, head).await {
+                Ok(layers) => {
+                    summary.chain = layers.len();
+                    summary.size = layers
+                        .iter()
+                        .filter_map(|(_, path)| std::fs::metadata(path).ok())
+                        .map(|m| m.len())
+                        .sum();
+                }
+                Err(e) => eprintln!("{}: {e}", summary.label),
+            }
+        }
+        result.push(summary);
+    }
+
+    Ok(result)
+}
+
+pub fn print_database(summary: &DatabaseSummary, format: OutputFormat) {
+    let head = summary.head.map(name_to_string);
+    let path = summary.path.path();
+    match format {
+        OutputFormat::Pretty => {
+            let path = match &path {
+                Some(path) => path.clone(),
+                None => paint("?", Color::Dim),
+            };
+            println!(
+                "{path:<40} {:<40} {:>5} layers {:>10}  {}",
+                head.as_deref().unwrap_or("-"),
+                summary.chain,
+                human_bytes(summary.size as usize),
+                paint(&summary.label, Color::Dim)
+            );
+        }
+        OutputFormat::Text => println!(
+            "{} {} {} {} {}",
+            path.as_deref().unwrap_or("?"),
+            head.as_deref().unwrap_or("-"),
+            summary.chain,
+            summary.size,
+            summary.label
+        ),
+        OutputFormat::Ndjson => println!(
+            "{}",
+            ndjson(json!({
+                "label": summary.label,
+                "organization": summary.path.organization,
+                "database": summary.path.database,
+                "repository": summary.path.repository,
+                "branch": summary.path.branch,
+                "recognized": !summary.path.unrecognized,
+                "head": head,
+                "chain": summary.chain,
+                "size": summary.size,
+            }))
+        ),
+    }
+}
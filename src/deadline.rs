@@ -0,0 +1,40 @@
+use std::{
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+/// When the command started and when it has to stop, if it was given a
+/// time budget.
+static DEADLINE: OnceLock<(Instant, Instant)> = OnceLock::new();
+
+/// Give long analyses until `timeout` from now to finish. Those that
+/// support it stop at the deadline and report what they got through.
+pub fn set(timeout: Duration) {
+    let now = Instant::now();
+    let _ = DEADLINE.set((now, now + timeout));
+}
+
+pub fn expired() -> bool {
+    DEADLINE
+        .get()
+        .map(|(_, deadline)| Instant::now() >= *deadline)
+        .unwrap_or(false)
+}
+
+/// Describe where an analysis stopped at the deadline, estimating how much
+/// longer the rest would take at the rate it went so far.
+pub fn stopped(done: usize, remaining: usize, unit: &str) -> String {
+    let elapsed = DEADLINE
+        .get()
+        .map(|(start, _)| start.elapsed())
+        .unwrap_or_default();
+    let estimate = match done {
+        0 => "unknown".to_string(),
+        _ => {
+            let per_item = elapsed.as_secs_f64() / done as f64;
+            let secs = (per_item * remaining as f64).ceil() as u64;
+            humantime::format_duration(Duration::from_secs(secs)).to_string()
+        }
+    };
+    format!("stopped at the deadline after {done} {unit}, {remaining} left (about {estimate} more)")
+}
@@ -0,0 +1,72 @@
+use std::{collections::HashSet, io, path::Path};
+
+use clap::ValueEnum;
+
+use crate::{
+    archive::Archive,
+    dict::{read_entries, DictType},
+    store::chain,
+};
+
+#[derive(Default)]
+struct DictUsage {
+    entries: usize,
+    repeated_entries: usize,
+    entry_bytes: usize,
+    repeated_bytes: usize,
+    segment_bytes: usize,
+}
+
+/// Measure how much dictionary content is interned more than once across
+/// the chain ending at `head`, and estimate the dictionary size after a
+/// squash.
+///
+/// The estimate assumes the squashed dictionary compresses unique entries
+/// as well as the chain's dictionaries compress all of theirs.
+pub async fn dedup_estimate(store: &Path, head: [u32; 5]) -> io::Result<()> {
+    let mut layers = chain(store, head).await?;
+    layers.reverse();
+
+    for t in [DictType::Nodes, DictType::Predicates, DictType::Values] {
+        let mut usage = DictUsage::default();
+        let mut seen = HashSet::new();
+        for (_, path) in layers.iter() {
+            let archive = Archive::open(path).await?;
+            usage.segment_bytes += archive
+                .segment(t.blocks_segment())?
+                .map(|b| b.len())
+                .unwrap_or(0);
+            for entry in read_entries(&archive, t).await? {
+                usage.entries += 1;
+                usage.entry_bytes += entry.len();
+                if !seen.insert(entry.clone()) {
+                    usage.repeated_entries += 1;
+                    usage.repeated_bytes += entry.len();
+                }
+            }
+        }
+
+        let unique_bytes = usage.entry_bytes - usage.repeated_bytes;
+        let estimate = if usage.entry_bytes == 0 {
+            0
+        } else {
+            (usage.segment_bytes as u128 * unique_bytes as u128 / usage.entry_bytes as u128)
+                as usize
+        };
+        println!(
+            "{:>10}: {} entries over {} layers, {} repeated ({} of {} bytes); \
+             {} bytes on disk, ~{} after squash (saves ~{})",
+            t.to_possible_value().unwrap().get_name(),
+            usage.entries,
+            layers.len(),
+            usage.repeated_entries,
+            usage.repeated_bytes,
+            usage.entry_bytes,
+            usage.segment_bytes,
+            estimate,
+            usage.segment_bytes - estimate,
+        );
+    }
+
+    Ok(())
+}
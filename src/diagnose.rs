@@ -0,0 +1,335 @@
+use std::{collections::BTreeMap, fmt, io, path::Path};
+
+use serde_json::json;
+use terminus_store::storage::consts::LayerFileEnum;
+
+use crate::{
+    archive::{all_segment_types, Archive},
+    codes::FindingCode,
+    init::STORAGE_VERSION,
+    output::{ndjson, paint, Color, OutputFormat},
+    validate::Finding,
+    verify::verify,
+};
+
+/// What most likely went wrong with a damaged layer.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Cause {
+    TruncatedUpload,
+    InterruptedIndexBuild,
+    BitFlip,
+    VersionMismatch,
+}
+
+impl Cause {
+    pub fn name(self) -> &'static str {
+        match self {
+            Cause::TruncatedUpload => "truncated upload",
+            Cause::InterruptedIndexBuild => "interrupted index build",
+            Cause::BitFlip => "bitflip",
+            Cause::VersionMismatch => "version mismatch",
+        }
+    }
+
+    /// Commands to run next, for the layer at `file`.
+    pub fn next_commands(self, file: &str) -> Vec<String> {
+        match self {
+            Cause::TruncatedUpload => vec![
+                format!("parse-header {file}"),
+                format!("rebuild-header {file}"),
+                "restore the layer from a backup, or copy it again from its source".to_string(),
+            ],
+            Cause::InterruptedIndexBuild => vec![
+                format!("check-adjacency --layer-file {file}"),
+                format!("extract {file} <segment>, then build-object-index or build-subject-index"),
+            ],
+            Cause::BitFlip => vec![
+                format!("verify {file}"),
+                format!("salvage-dict {file} <dict-type>"),
+                "restore the layer from a backup".to_string(),
+            ],
+            Cause::VersionMismatch => vec![
+                format!("parse-header {file}"),
+                "open the store with the TerminusDB version that wrote it".to_string(),
+            ],
+        }
+    }
+}
+
+impl fmt::Display for Cause {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// A likely cause, with the share of the evidence pointing at it.
+pub struct Suspect {
+    pub cause: Cause,
+    pub confidence: f64,
+    pub evidence: Vec<String>,
+}
+
+/// Findings of the cheap validators, grouped by code, and the causes they
+/// point at, most likely first.
+pub struct Diagnosis {
+    pub clusters: BTreeMap<String, usize>,
+    pub suspects: Vec<Suspect>,
+}
+
+/// Segments the layer builder derives from the others after writing them.
+/// A build that stops partway leaves these missing or inconsistent.
+fn is_index(file_type: LayerFileEnum) -> bool {
+    let name = format!("{file_type:?}");
+    name.contains("BitIndex")
+        || name.contains("OPs")
+        || name.contains("Wavelet")
+        || name.ends_with("Objects")
+}
+
+/// The STORAGE_VERSION of the store holding a layer file, if the file sits
+/// in a store directory.
+fn store_version(path: &Path) -> Option<String> {
+    let store = path.parent()?.parent()?;
+    let version = std::fs::read_to_string(store.join("STORAGE_VERSION")).ok()?;
+    Some(version.trim().to_string())
+}
+
+#[derive(Default)]
+struct Evidence {
+    scores: BTreeMap<Cause, (u32, Vec<String>)>,
+}
+
+impl Evidence {
+    fn add(&mut self, cause: Cause, weight: u32, reason: String) {
+        let entry = self.scores.entry(cause).or_default();
+        entry.0 += weight;
+        entry.1.push(reason);
+    }
+
+    fn suspects(self) -> Vec<Suspect> {
+        let total: u32 = self.scores.values().map(|(score, _)| score).sum();
+        let mut suspects: Vec<Suspect> = self
+            .scores
+            .into_iter()
+            .map(|(cause, (score, evidence))| Suspect {
+                cause,
+                confidence: score as f64 / total as f64,
+                evidence,
+            })
+            .collect();
+        suspects.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+        suspects
+    }
+}
+
+fn weigh_findings(archive: &Archive, findings: &[Finding], evidence: &mut Evidence) {
+    let short = archive
+        .segments_end()
+        .saturating_sub(archive.contents().len());
+    if short > 0 {
+        evidence.add(
+            Cause::TruncatedUpload,
+            4,
+            format!("the file ends {short} bytes before its last segment"),
+        );
+    }
+
+    let count = |code| findings.iter().filter(|f| f.code == code).count();
+    let mismatches = count(FindingCode::ChecksumMismatch);
+    if (1..=2).contains(&mismatches) {
+        evidence.add(
+            Cause::BitFlip,
+            3,
+            format!("{mismatches} segment(s) no longer match their checksum"),
+        );
+    }
+    let blocks = count(FindingCode::BitIndexBlockMismatch);
+    if (1..=2).contains(&blocks) && count(FindingCode::BitIndexLength) == 0 {
+        evidence.add(
+            Cause::BitFlip,
+            2,
+            format!("{blocks} isolated bitindex block(s) disagree with their bits"),
+        );
+    }
+    for finding in findings {
+        let control_word = matches!(
+            finding.code,
+            FindingCode::LogArrayInvalid | FindingCode::BitArrayInvalid
+        );
+        match finding.segment {
+            Some(segment) if is_index(segment) => evidence.add(
+                Cause::InterruptedIndexBuild,
+                1,
+                format!("{} in index segment {segment:?}", finding.code),
+            ),
+            Some(segment) if control_word && short == 0 => evidence.add(
+                Cause::BitFlip,
+                1,
+                format!("control word of {segment:?} is invalid in a complete file"),
+            ),
+            _ => {}
+        }
+    }
+
+    let present: Vec<_> = archive.segments().into_iter().map(|(t, _)| t).collect();
+    for prefix in ["Pos", "Neg"] {
+        let has_triples = present
+            .iter()
+            .any(|t| format!("{t:?}") == format!("{prefix}SpOAdjacencyListNums"));
+        if !has_triples {
+            continue;
+        }
+        let missing: Vec<_> = all_segment_types()
+            .filter(|t| is_index(*t) && format!("{t:?}").starts_with(prefix))
+            .filter(|t| !present.contains(t))
+            .collect();
+        if !missing.is_empty() {
+            evidence.add(
+                Cause::InterruptedIndexBuild,
+                3,
+                format!("triples are present but index segments {missing:?} are not"),
+            );
+        }
+    }
+}
+
+/// Run the cheap validators on a layer and guess at the root cause of what
+/// they find. The guess weighs simple patterns, such as a file shorter than
+/// its header says or damage confined to index segments, so it is meant for
+/// triage rather than as a verdict.
+pub async fn diagnose(path: &Path) -> io::Result<Diagnosis> {
+    let mut evidence = Evidence::default();
+    if let Some(version) = store_version(path) {
+        if version != STORAGE_VERSION {
+            evidence.add(
+                Cause::VersionMismatch,
+                4,
+                format!(
+                    "the store has storage version {version}, this tool reads {STORAGE_VERSION}"
+                ),
+            );
+        }
+    }
+
+    let findings = match Archive::open(path).await {
+        Ok(archive) => {
+            let findings = verify(&archive, true).await;
+            weigh_findings(&archive, &findings, &mut evidence);
+            findings
+        }
+        Err(e) => {
+            if e.kind() == io::ErrorKind::UnexpectedEof {
+                evidence.add(
+                    Cause::TruncatedUpload,
+                    3,
+                    "the file ends inside its header".to_string(),
+                );
+            } else if e.kind() != io::ErrorKind::NotFound {
+                evidence.add(
+                    Cause::VersionMismatch,
+                    2,
+                    format!("the header doesn't parse as a known archive layout: {e}"),
+                );
+                evidence.add(Cause::BitFlip, 1, "the header may be damaged".to_string());
+            } else {
+                return Err(e);
+            }
+            vec![Finding::unreadable(e)]
+        }
+    };
+
+    let mut clusters = BTreeMap::new();
+    for finding in findings.iter() {
+        let key = match finding.segment {
+            Some(segment) => format!("{} {segment:?}", finding.code),
+            None => finding.code.to_string(),
+        };
+        *clusters.entry(key).or_default() += 1;
+    }
+
+    Ok(Diagnosis {
+        clusters,
+        suspects: evidence.suspects(),
+    })
+}
+
+/// Print a diagnosis. The most likely cause comes with its evidence and the
+/// commands to run next; the other candidates are only listed.
+pub fn print_diagnosis(layer: &str, diagnosis: &Diagnosis, format: OutputFormat) {
+    if format == OutputFormat::Ndjson {
+        let suspects: Vec<_> = diagnosis
+            .suspects
+            .iter()
+            .map(|s| {
+                json!({
+                    "cause": s.cause.name(),
+                    "confidence": s.confidence,
+                    "evidence": s.evidence,
+                    "next": s.cause.next_commands(layer),
+                })
+            })
+            .collect();
+        println!(
+            "{}",
+            ndjson(json!({
+                "layer": layer,
+                "findings": diagnosis.clusters,
+                "causes": suspects,
+            }))
+        );
+        return;
+    }
+
+    let pretty = format == OutputFormat::Pretty;
+    for (cluster, count) in diagnosis.clusters.iter() {
+        println!("{count:>5}  {cluster}");
+    }
+    let (first, rest) = match diagnosis.suspects.split_first() {
+        Some(split) => split,
+        None if diagnosis.clusters.is_empty() => {
+            let ok = "no damage found by the quick checks";
+            println!(
+                "{}",
+                if pretty {
+                    paint(ok, Color::Green)
+                } else {
+                    ok.to_string()
+                }
+            );
+            return;
+        }
+        None => {
+            println!("no known pattern matches these findings");
+            return;
+        }
+    };
+    let headline = format!(
+        "most likely: {} ({:.0}%)",
+        first.cause,
+        first.confidence * 100.0
+    );
+    println!();
+    println!(
+        "{}",
+        if pretty {
+            paint(&headline, Color::Red)
+        } else {
+            headline
+        }
+    );
+    for reason in first.evidence.iter() {
+        println!("  - {reason}");
+    }
+    for other in rest {
+        println!(
+            "also possible: {} ({:.0}%)",
+            other.cause,
+            other.confidence * 100.0
+        );
+    }
+    println!();
+    println!("next:");
+    for command in first.cause.next_commands(layer) {
+        println!("  {command}");
+    }
+}
@@ -0,0 +1,937 @@
+use std::{
+    io::{self, Cursor},
+    ops::Range,
+    path::Path,
+};
+
+use bytes::Bytes;
+use clap::ValueEnum;
+use futures::{Stream, StreamExt};
+use num::FromPrimitive;
+use terminus_store::{
+    layer::ObjectType,
+    storage::consts::LayerFileEnum,
+    structure::{stream::TfcDictStream, Datatype, LogArray},
+    Layer,
+};
+use tokio::io::AsyncRead;
+
+use crate::{
+    archive::{open_slice, Archive},
+    backend::LayerSource,
+    ids::open_file_layer,
+    values,
+};
+
+/// Maximum number of entries in a dictionary block.
+pub const BLOCK_SIZE: usize = 8;
+
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum DictType {
+    Nodes,
+    Predicates,
+    Values,
+}
+
+impl DictType {
+    /// The archive segment holding this dictionary's blocks.
+    pub fn blocks_segment(self) -> LayerFileEnum {
+        match self {
+            DictType::Nodes => LayerFileEnum::NodeDictionaryBlocks,
+            DictType::Predicates => LayerFileEnum::PredicateDictionaryBlocks,
+            DictType::Values => LayerFileEnum::ValueDictionaryBlocks,
+        }
+    }
+
+    /// The archive segment holding the offsets of this dictionary's blocks.
+    pub fn offsets_segment(self) -> LayerFileEnum {
+        match self {
+            DictType::Nodes => LayerFileEnum::NodeDictionaryOffsets,
+            DictType::Predicates => LayerFileEnum::PredicateDictionaryOffsets,
+            DictType::Values => LayerFileEnum::ValueDictionaryOffsets,
+        }
+    }
+}
+
+/// Decode all entries of a dictionary in an archive. An absent dictionary
+/// has no entries.
+pub async fn read_entries(archive: &Archive, t: DictType) -> io::Result<Vec<Bytes>> {
+    let blocks = archive.segment(t.blocks_segment())?.unwrap_or_default();
+    let mut stream = TfcDictStream::new(Cursor::new(blocks));
+    let mut result = Vec::new();
+    while let Some(element) = stream.next().await {
+        let (element, _) = element.map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        result.push(element.to_bytes());
+    }
+
+    Ok(result)
+}
+
+/// One entry of a dictionary.
+pub struct DictEntry {
+    /// Ids count from 1 within the layer's own dictionary.
+    pub id: u64,
+    pub bytes: Bytes,
+}
+
+/// A dictionary entry as `list_entries` hands it out.
+pub struct ListedEntry {
+    pub id: u64,
+    pub bytes: Bytes,
+    /// The entry as a literal and the name of its datatype, when values
+    /// are decoded. The literal notes if the store can't resolve the id.
+    pub typed: Option<(String, String)>,
+}
+
+/// Hand the entries of a dictionary with ids in `range` to `on_entry` in
+/// id order. Values are decoded with their datatypes, looked up through
+/// the store the layer file lies in, unless `raw` is set; a remote layer
+/// has no such store and can only be listed raw.
+pub async fn list_entries(
+    file_name: &Path,
+    t: DictType,
+    raw: bool,
+    range: Option<(u64, u64)>,
+    mut on_entry: impl FnMut(ListedEntry),
+) -> io::Result<()> {
+    let source = LayerSource::open(&file_name.to_string_lossy());
+    let typed = match t {
+        DictType::Values if !raw && source.is_remote() => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "values of a remote layer can only be printed --raw, as their datatypes are looked up through a store",
+            ))
+        }
+        DictType::Values if !raw => Some(open_file_layer(file_name).await?),
+        _ => None,
+    };
+    let (start, end) = range.unwrap_or((0, u64::MAX));
+    let mut entries: Box<dyn Stream<Item = io::Result<DictEntry>> + Unpin> = if source.is_remote() {
+        let blocks = source.segment(t.blocks_segment()).await?;
+        Box::new(stream_blocks(Cursor::new(blocks)))
+    } else {
+        Box::new(stream_entries(file_name, t).await?)
+    };
+    while let Some(entry) = entries.next().await {
+        let entry = entry?;
+        if entry.id >= end {
+            break;
+        }
+        if entry.id < start {
+            continue;
+        }
+        let typed = typed
+            .as_ref()
+            .map(|(layer, first)| match layer.id_object(first + entry.id) {
+                Some(ObjectType::Value(value)) => {
+                    let datatype = format!("{:?}", value.datatype());
+                    let literal = values::typed_literal(&datatype, &value.to_bytes());
+                    (literal, values::xsd_name(&datatype))
+                }
+                _ => (
+                    format!("{:?} (unresolved)", entry.bytes),
+                    "unknown".to_string(),
+                ),
+            });
+        on_entry(ListedEntry {
+            id: entry.id,
+            bytes: entry.bytes,
+            typed,
+        });
+    }
+
+    Ok(())
+}
+
+/// Stream the entries of a dictionary straight from a layer file, without
+/// loading the rest of the archive.
+pub async fn stream_entries<P: AsRef<Path>>(
+    path: P,
+    t: DictType,
+) -> io::Result<impl Stream<Item = io::Result<DictEntry>> + Unpin> {
+    let reader = open_slice(path, t.blocks_segment()).await?;
+
+    Ok(stream_blocks(reader))
+}
+
+/// Stream the entries of a dictionary from a reader over its blocks
+/// segment.
+pub fn stream_blocks<R: AsyncRead + Unpin>(
+    reader: R,
+) -> impl Stream<Item = io::Result<DictEntry>> + Unpin {
+    TfcDictStream::new(reader).enumerate().map(|(ix, element)| {
+        let (element, _) = element.map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        Ok(DictEntry {
+            id: ix as u64 + 1,
+            bytes: element.to_bytes(),
+        })
+    })
+}
+
+/// Decode all entries of a dictionary in an archive along with their
+/// datatypes, which only values have.
+pub async fn read_typed_entries(
+    archive: &Archive,
+    t: DictType,
+) -> io::Result<Vec<(Option<Datatype>, Bytes)>> {
+    let blocks = archive.segment(t.blocks_segment())?.unwrap_or_default();
+    let offsets = block_offsets(archive, t)?;
+    let mut result = Vec::new();
+    for section in sections(archive, t).await? {
+        for ix in section.blocks.clone() {
+            let entries = decode_block(&blocks, &offsets, ix, usize::MAX).await?;
+            result.extend(entries.into_iter().map(|e| (section.datatype, e)));
+        }
+    }
+
+    Ok(result)
+}
+
+/// Count the entries of a dictionary in an archive without keeping them.
+pub async fn count_entries(archive: &Archive, t: DictType) -> io::Result<u64> {
+    let blocks = archive.segment(t.blocks_segment())?.unwrap_or_default();
+    let mut stream = TfcDictStream::new(Cursor::new(blocks));
+    let mut count = 0;
+    while let Some(element) = stream.next().await {
+        element.map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+/// Layout information about a single dictionary block.
+pub struct BlockStats {
+    pub offset: usize,
+    pub size: usize,
+    pub entries: usize,
+    /// Total size of the entries once decoded.
+    pub entry_bytes: usize,
+    pub head: Bytes,
+}
+
+/// Start offsets of all blocks of a dictionary. The offsets segment lists
+/// where every block but the first starts.
+pub fn block_offsets(archive: &Archive, t: DictType) -> io::Result<Vec<usize>> {
+    let blocks_len = match archive.segment(t.blocks_segment())? {
+        None => return Ok(Vec::new()),
+        Some(blocks) if blocks.is_empty() => return Ok(Vec::new()),
+        Some(blocks) => blocks.len(),
+    };
+    let mut result = vec![0];
+    if let Some(offsets) = archive.segment(t.offsets_segment())? {
+        let offsets =
+            LogArray::parse(offsets).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        result.extend(offsets.iter().map(|o| o as usize));
+    }
+    if let Some(offset) = result.iter().find(|o| **o > blocks_len) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "block offset {offset} lies past the end of the dictionary ({blocks_len} bytes)"
+            ),
+        ));
+    }
+
+    Ok(result)
+}
+
+/// Decode every block of a dictionary separately and report its layout.
+pub async fn block_stats(archive: &Archive, t: DictType) -> io::Result<Vec<BlockStats>> {
+    let blocks = archive.segment(t.blocks_segment())?.unwrap_or_default();
+    let offsets = block_offsets(archive, t)?;
+    let mut result = Vec::with_capacity(offsets.len());
+    for (ix, offset) in offsets.iter().enumerate() {
+        let end = offsets.get(ix + 1).copied().unwrap_or(blocks.len());
+        let mut stream = TfcDictStream::new(Cursor::new(blocks.slice(*offset..end)));
+        let mut stats = BlockStats {
+            offset: *offset,
+            size: end - offset,
+            entries: 0,
+            entry_bytes: 0,
+            head: Bytes::new(),
+        };
+        while let Some(element) = stream.next().await {
+            let (element, _) = element.map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("block {ix} at offset {offset}: {e}"),
+                )
+            })?;
+            let entry = element.to_bytes();
+            if stats.entries == 0 {
+                stats.head = entry.clone();
+            }
+            stats.entries += 1;
+            stats.entry_bytes += entry.len();
+        }
+        result.push(stats);
+    }
+
+    Ok(result)
+}
+
+/// Decode the entries of one block, or only its first `limit` entries.
+pub async fn decode_block(
+    blocks: &Bytes,
+    offsets: &[usize],
+    ix: usize,
+    limit: usize,
+) -> io::Result<Vec<Bytes>> {
+    let end = offsets.get(ix + 1).copied().unwrap_or(blocks.len());
+    let mut stream =
+        TfcDictStream::new(Cursor::new(blocks.slice(offsets[ix]..end.max(offsets[ix]))))
+            .take(limit);
+    let mut result = Vec::new();
+    while let Some(element) = stream.next().await {
+        let (element, _) = element.map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("block {ix} at offset {}: {e}", offsets[ix]),
+            )
+        })?;
+        result.push(element.to_bytes());
+    }
+
+    Ok(result)
+}
+
+/// A run of dictionary blocks holding the entries of one datatype. A value
+/// dictionary holds one sorted section per datatype present, as listed by
+/// its types present and type offsets segments. Node and predicate
+/// dictionaries are a single untyped section.
+pub struct Section {
+    /// None for nodes and predicates.
+    pub datatype: Option<Datatype>,
+    /// Indexes of the section's blocks.
+    pub blocks: Range<usize>,
+    /// Entries in earlier sections. The section's first entry has the id
+    /// after it.
+    pub id_offset: u64,
+}
+
+impl Section {
+    /// The id of entry `i` of block `block`, which must lie in this
+    /// section.
+    pub fn id(&self, block: usize, i: usize) -> u64 {
+        self.id_offset + ((block - self.blocks.start) * BLOCK_SIZE + i) as u64 + 1
+    }
+
+    /// The block and index within it of the entry with the given id, if
+    /// the id lies in this section's blocks.
+    pub fn position(&self, id: u64) -> Option<(usize, usize)> {
+        let n = id.checked_sub(self.id_offset + 1)? as usize;
+        let block = self.blocks.start + n / BLOCK_SIZE;
+        self.blocks
+            .contains(&block)
+            .then_some((block, n % BLOCK_SIZE))
+    }
+}
+
+/// Split a dictionary into its sections. Every block of a section but its
+/// last is full, while the last may hold fewer entries, which shifts the
+/// ids of the sections after it; the last block of each section is
+/// decoded to count them. A value dictionary without a types present
+/// segment is a single untyped section.
+pub async fn sections(archive: &Archive, t: DictType) -> io::Result<Vec<Section>> {
+    let offsets = block_offsets(archive, t)?;
+    let untyped = Section {
+        datatype: None,
+        blocks: 0..offsets.len(),
+        id_offset: 0,
+    };
+    let types = match archive.segment(LayerFileEnum::ValueDictionaryTypesPresent)? {
+        Some(types) if t == DictType::Values => types,
+        _ => return Ok(vec![untyped]),
+    };
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let types = LogArray::parse(types).map_err(|e| invalid(format!("types present: {e}")))?;
+    if types.len() == 0 {
+        return match offsets.len() {
+            0 => Ok(Vec::new()),
+            n => Err(invalid(format!("{n} value blocks but no types present"))),
+        };
+    }
+    let mut bounds = vec![0];
+    if let Some(type_offsets) = archive.segment(LayerFileEnum::ValueDictionaryTypeOffsets)? {
+        let type_offsets =
+            LogArray::parse(type_offsets).map_err(|e| invalid(format!("type offsets: {e}")))?;
+        // each offset is the last block of the type before
+        bounds.extend(type_offsets.iter().map(|o| o as usize + 1));
+    }
+    if bounds.len() != types.len() {
+        return Err(invalid(format!(
+            "{} type offsets for {} types present",
+            bounds.len() - 1,
+            types.len()
+        )));
+    }
+    bounds.push(offsets.len());
+    if bounds.windows(2).any(|w| w[0] >= w[1]) {
+        return Err(invalid(format!(
+            "type offsets don't split the {} value blocks into nonempty sections",
+            offsets.len()
+        )));
+    }
+
+    let blocks = archive.segment(t.blocks_segment())?.unwrap_or_default();
+    let mut result = Vec::with_capacity(types.len());
+    let mut id_offset = 0;
+    for (k, w) in bounds.windows(2).enumerate() {
+        let datatype = Datatype::from_u64(types.entry(k))
+            .ok_or_else(|| invalid(format!("unknown datatype {}", types.entry(k))))?;
+        let last = decode_block(&blocks, &offsets, w[1] - 1, usize::MAX).await?;
+        result.push(Section {
+            datatype: Some(datatype),
+            blocks: w[0]..w[1],
+            id_offset,
+        });
+        id_offset += ((w[1] - w[0] - 1) * BLOCK_SIZE + last.len()) as u64;
+    }
+
+    Ok(result)
+}
+
+/// The section holding entries of the given datatype. Node and predicate
+/// dictionaries take no datatype, and a typed value dictionary needs one.
+fn find_section(sections: &[Section], datatype: Option<Datatype>) -> io::Result<Option<&Section>> {
+    let typed = sections.iter().any(|s| s.datatype.is_some());
+    if typed && datatype.is_none() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "values are typed; give the datatype of the entry",
+        ));
+    }
+
+    Ok(sections
+        .iter()
+        .find(|s| s.datatype.is_none() || s.datatype == datatype))
+}
+
+/// Find the id an entry has within a dictionary, without decoding more
+/// than the block heads a binary search passes and the one block that can
+/// hold the entry. Values are searched for in the section of their
+/// datatype. Ids are local to the archive and start at 1.
+pub async fn lookup(
+    archive: &Archive,
+    t: DictType,
+    datatype: Option<Datatype>,
+    entry: &[u8],
+) -> io::Result<Option<u64>> {
+    let blocks = archive.segment(t.blocks_segment())?.unwrap_or_default();
+    let offsets = block_offsets(archive, t)?;
+    let sections = sections(archive, t).await?;
+    let section = match find_section(&sections, datatype)? {
+        Some(section) => section,
+        None => return Ok(None),
+    };
+    // find the last block of the section whose head is not greater than
+    // the entry
+    let (mut low, mut high) = (section.blocks.start, section.blocks.end);
+    while low < high {
+        let mid = (low + high) / 2;
+        let head = decode_block(&blocks, &offsets, mid, 1).await?;
+        if head.first().map(|h| &h[..] <= entry) == Some(true) {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    if low == section.blocks.start {
+        return Ok(None);
+    }
+    let block = low - 1;
+    let entries = decode_block(&blocks, &offsets, block, BLOCK_SIZE).await?;
+
+    Ok(entries
+        .iter()
+        .position(|e| &e[..] == entry)
+        .map(|i| section.id(block, i)))
+}
+
+/// Decode the entry with the given id, seeking straight to its block
+/// through the offsets, along with its datatype if it is a value. Returns
+/// None if the dictionary has no such id.
+pub async fn entry(
+    archive: &Archive,
+    t: DictType,
+    id: u64,
+) -> io::Result<Option<(Option<Datatype>, Bytes)>> {
+    let blocks = archive.segment(t.blocks_segment())?.unwrap_or_default();
+    let offsets = block_offsets(archive, t)?;
+    let sections = sections(archive, t).await?;
+    // the section holding the id is the last that starts before it
+    let section = match sections.iter().rev().find(|s| s.id_offset < id) {
+        Some(section) => section,
+        None => return Ok(None),
+    };
+    let (block, index) = match section.position(id) {
+        Some(position) => position,
+        None => return Ok(None),
+    };
+    let mut entries = decode_block(&blocks, &offsets, block, index + 1).await?;
+    if entries.len() <= index {
+        return Ok(None);
+    }
+
+    Ok(entries.pop().map(|entry| (section.datatype, entry)))
+}
+
+/// Decode a run of whole blocks within one section, checking that the
+/// entries are strictly increasing. Returns the first and last entry and
+/// the number of entries.
+async fn check_chunk(
+    blocks: Bytes,
+    first_id: u64,
+) -> Result<Option<(Bytes, Bytes, usize)>, String> {
+    let mut stream = TfcDictStream::new(Cursor::new(blocks));
+    let mut result: Option<(Bytes, Bytes, usize)> = None;
+    while let Some(element) = stream.next().await {
+        let index = first_id + result.as_ref().map(|r| r.2).unwrap_or(0) as u64;
+        let (element, _) = element.map_err(|e| format!("entry {index}: {e}"))?;
+        let entry = element.to_bytes();
+        result = match result {
+            None => Some((entry.clone(), entry, 1)),
+            Some((_, last, _)) if entry <= last => {
+                return Err(format!("entry {index} is not greater than the one before"))
+            }
+            Some((first, _, count)) => Some((first, entry, count + 1)),
+        };
+    }
+
+    Ok(result)
+}
+
+/// Decode a whole dictionary and check that it is sorted, each datatype
+/// section of a value dictionary on its own. The blocks of a section are
+/// split into one chunk per core which are decoded concurrently; the
+/// ordering across chunks is checked by comparing the last entry of each
+/// chunk with the first of the next. Returns the number of entries.
+pub async fn validate_dict(archive: &Archive, t: DictType) -> Result<usize, String> {
+    let blocks = archive
+        .segment(t.blocks_segment())
+        .map_err(|e| e.to_string())?
+        .unwrap_or_default();
+    let offsets = block_offsets(archive, t).map_err(|e| e.to_string())?;
+    if offsets.windows(2).any(|w| w[0] >= w[1]) {
+        return Err("block offsets are not increasing".to_string());
+    }
+    let sections = sections(archive, t).await.map_err(|e| e.to_string())?;
+    let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let mut count = 0;
+    for section in sections {
+        let chunk_blocks = section.blocks.len().div_ceil(cores).max(1);
+        let mut tasks = Vec::new();
+        for start in section.blocks.clone().step_by(chunk_blocks) {
+            let end = offsets
+                .get((start + chunk_blocks).min(section.blocks.end))
+                .copied()
+                .unwrap_or(blocks.len());
+            let first_id = section.id(start, 0);
+            let chunk = blocks.slice(offsets[start]..end);
+            tasks.push((first_id, tokio::spawn(check_chunk(chunk, first_id))));
+        }
+
+        let mut last: Option<Bytes> = None;
+        for (first_id, task) in tasks {
+            if let Some((first, chunk_last, chunk_count)) = task.await.unwrap()? {
+                if last.map(|last| first <= last).unwrap_or(false) {
+                    return Err(format!(
+                        "entry {first_id} is not greater than the one before"
+                    ));
+                }
+                last = Some(chunk_last);
+                count += chunk_count;
+            }
+        }
+    }
+
+    Ok(count)
+}
+
+/// An entry that is in a dictionary but that a lookup wouldn't find.
+pub struct Unfindable {
+    pub id: u64,
+    pub entry: Bytes,
+    /// The id a lookup of the entry gives instead, if any.
+    pub found: Option<u64>,
+    pub reason: String,
+}
+
+/// The block a lookup searches for an entry: the last one whose head is
+/// not greater than it, as `lookup` finds it.
+fn landing_block(decoded: &[Vec<Bytes>], entry: &[u8]) -> Option<usize> {
+    let (mut low, mut high) = (0, decoded.len());
+    while low < high {
+        let mid = (low + high) / 2;
+        if decoded[mid].first().map(|h| &h[..] <= entry) == Some(true) {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    low.checked_sub(1)
+}
+
+/// Explain why an entry sorting before `later` in the file breaks byte
+/// order with it, naming the collation the writer seems to have used.
+fn misorder(earlier: &[u8], later: &[u8], neighbour: u64) -> String {
+    let utf16 = match (std::str::from_utf8(earlier), std::str::from_utf8(later)) {
+        (Ok(a), Ok(b)) => Some(a.encode_utf16().cmp(b.encode_utf16())),
+        _ => None,
+    };
+    let signed = earlier
+        .iter()
+        .map(|b| *b as i8)
+        .cmp(later.iter().map(|b| *b as i8));
+    if earlier == later {
+        format!("duplicate of entry {neighbour}")
+    } else if utf16 == Some(std::cmp::Ordering::Less) {
+        format!("out of byte order with entry {neighbour}, as if sorted by UTF-16 code units")
+    } else if signed == std::cmp::Ordering::Less {
+        format!(
+            "out of byte order with entry {neighbour}, as if sorted by signed bytes, \
+             which puts multi-byte UTF-8 before ASCII"
+        )
+    } else {
+        format!("out of byte order with entry {neighbour}")
+    }
+}
+
+/// Look up every entry of a dictionary the way `lookup` does, comparing
+/// raw bytes within the entry's datatype section, and report those that
+/// aren't found at their own id. An entry out of order with its
+/// neighbours, or a block head out of order, sends the binary search to
+/// the wrong block even though the entry is there. Each is reported with
+/// the likeliest reason.
+pub async fn check_collation(archive: &Archive, t: DictType) -> io::Result<Vec<Unfindable>> {
+    let blocks = archive.segment(t.blocks_segment())?.unwrap_or_default();
+    let offsets = block_offsets(archive, t)?;
+    let mut result = Vec::new();
+    for section in sections(archive, t).await? {
+        let section = &section;
+        let first = section.blocks.start;
+        let mut decoded = Vec::with_capacity(section.blocks.len());
+        for ix in section.blocks.clone() {
+            decoded.push(decode_block(&blocks, &offsets, ix, usize::MAX).await?);
+        }
+        let all: Vec<(u64, &Bytes)> = decoded
+            .iter()
+            .enumerate()
+            .flat_map(|(k, entries)| {
+                entries
+                    .iter()
+                    .enumerate()
+                    .map(move |(i, entry)| (section.id(first + k, i), entry))
+            })
+            .collect();
+
+        for (ix, (id, entry)) in all.iter().enumerate() {
+            let found = landing_block(&decoded, entry).and_then(|k| {
+                decoded[k]
+                    .iter()
+                    .position(|e| e == *entry)
+                    .map(|i| section.id(first + k, i))
+            });
+            if found == Some(*id) {
+                continue;
+            }
+            let before = ix.checked_sub(1).map(|i| all[i]);
+            let after = all.get(ix + 1).copied();
+            let reason = match (before, after) {
+                (Some((n, previous)), _) if previous >= *entry => misorder(previous, entry, n),
+                (_, Some((n, next))) if next <= *entry => misorder(entry, next, n),
+                _ => match landing_block(&decoded, entry) {
+                    Some(k) => format!(
+                        "block heads out of order send the search to block {}, not {}",
+                        first + k,
+                        section
+                            .position(*id)
+                            .map(|(block, _)| block)
+                            .unwrap_or(first)
+                    ),
+                    None => "block heads out of order send the search past every block".to_string(),
+                },
+            };
+            result.push(Unfindable {
+                id: *id,
+                entry: (*entry).clone(),
+                found,
+                reason,
+            });
+        }
+    }
+
+    Ok(result)
+}
+
+/// A dictionary entry whose encoding looks cut short.
+pub struct Truncation {
+    pub block: usize,
+    /// The entry's number within the whole dictionary, starting at 1.
+    pub entry: usize,
+    pub message: String,
+}
+
+/// Decode a variable-byte integer at `pos`, advancing past it. Each byte
+/// holds seven bits, least significant first, and the high bit marks the
+/// last byte.
+fn read_vbyte(bytes: &[u8], pos: &mut usize) -> Option<u64> {
+    let mut result = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *bytes.get(*pos)?;
+        *pos += 1;
+        result |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 != 0 {
+            return Some(result);
+        }
+    }
+    None
+}
+
+/// The number of bytes taken by the block at the start of `data`, as
+/// declared by its entry count and lengths, or None if the block does not
+/// fit. See `check_block` for the layout.
+pub fn block_len(data: &[u8]) -> Option<usize> {
+    let count = *data.first()? as usize;
+    if count < 1 || count > BLOCK_SIZE {
+        return None;
+    }
+    let mut pos = 1;
+    let head_len = read_vbyte(data, &mut pos)? as usize;
+    pos = pos.checked_add(head_len)?;
+    let mut suffixes: usize = 0;
+    for _ in 1..count {
+        read_vbyte(data, &mut pos)?;
+        suffixes = suffixes.checked_add(read_vbyte(data, &mut pos)? as usize)?;
+    }
+    let end = pos.checked_add(suffixes)?;
+
+    (end <= data.len()).then_some(end)
+}
+
+/// Whether some bytes are UTF-8 apart from an incomplete character at the
+/// end, as left behind when text is cut off mid-codepoint.
+fn ends_mid_codepoint(bytes: &[u8]) -> bool {
+    matches!(std::str::from_utf8(bytes), Err(e) if e.error_len().is_none())
+}
+
+/// Check the entries of one block against the lengths it declares. A
+/// block starts with its entry count, the length and bytes of its first
+/// entry, then a shared prefix length and suffix length for each further
+/// entry, followed by the suffixes.
+fn check_block(block: usize, data: &[u8], last: bool, result: &mut Vec<Truncation>) {
+    let first_entry = block * BLOCK_SIZE + 1;
+    let mut report = |entry: usize, message: String| {
+        result.push(Truncation {
+            block,
+            entry,
+            message,
+        })
+    };
+    let count = match data.first() {
+        Some(&count) if count as usize >= 1 && count as usize <= BLOCK_SIZE => count as usize,
+        Some(&count) => return report(first_entry, format!("block declares {count} entries")),
+        None => return report(first_entry, "block is empty".to_string()),
+    };
+    let mut pos = 1;
+    let head_len = match read_vbyte(data, &mut pos) {
+        Some(len) => len as usize,
+        None => return report(first_entry, "length prefix runs past the block".to_string()),
+    };
+    if pos + head_len > data.len() {
+        return report(
+            first_entry,
+            format!(
+                "length prefix declares {head_len} bytes, the block holds {}",
+                data.len() - pos
+            ),
+        );
+    }
+    let mut entry = data[pos..pos + head_len].to_vec();
+    pos += head_len;
+    let mut lengths = Vec::with_capacity(count - 1);
+    for i in 1..count {
+        match (read_vbyte(data, &mut pos), read_vbyte(data, &mut pos)) {
+            (Some(shared), Some(suffix)) => lengths.push((shared as usize, suffix as usize)),
+            _ => {
+                return report(
+                    first_entry + i,
+                    "length prefix runs past the block".to_string(),
+                )
+            }
+        }
+    }
+
+    if ends_mid_codepoint(&entry) {
+        report(first_entry, "entry ends mid-codepoint".to_string());
+    }
+    for (i, (shared, suffix)) in lengths.into_iter().enumerate() {
+        let index = first_entry + i + 1;
+        if shared > entry.len() {
+            return report(
+                index,
+                format!(
+                    "shares {shared} bytes with an entry of {} bytes",
+                    entry.len()
+                ),
+            );
+        }
+        if pos + suffix > data.len() {
+            return report(
+                index,
+                format!(
+                    "length prefix declares {suffix} bytes, the block holds {}",
+                    data.len() - pos
+                ),
+            );
+        }
+        entry.truncate(shared);
+        entry.extend_from_slice(&data[pos..pos + suffix]);
+        pos += suffix;
+        if ends_mid_codepoint(&entry) {
+            report(index, "entry ends mid-codepoint".to_string());
+        }
+    }
+    // the segment may be padded after its last block
+    if pos < data.len() && !last {
+        report(
+            first_entry + count - 1,
+            format!(
+                "entries declare {pos} bytes, the block holds {}",
+                data.len()
+            ),
+        );
+    }
+}
+
+/// Walk the blocks of a dictionary, decoding every entry by hand, and
+/// report entries whose length prefix disagrees with what the block holds
+/// or whose text ends partway through a character.
+pub fn find_truncations(archive: &Archive, t: DictType) -> io::Result<Vec<Truncation>> {
+    let blocks = archive.segment(t.blocks_segment())?.unwrap_or_default();
+    let offsets = block_offsets(archive, t)?;
+    let mut result = Vec::new();
+    for (ix, offset) in offsets.iter().enumerate() {
+        let end = offsets.get(ix + 1).copied().unwrap_or(blocks.len());
+        let data = &blocks[*offset..end.max(*offset)];
+        check_block(ix, data, ix + 1 == offsets.len(), &mut result);
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vbyte(mut n: usize) -> Vec<u8> {
+        let mut result = Vec::new();
+        loop {
+            let byte = (n & 0x7f) as u8;
+            n >>= 7;
+            if n == 0 {
+                result.push(byte | 0x80);
+                return result;
+            }
+            result.push(byte);
+        }
+    }
+
+    /// Encode a block the way `check_block` reads it: the entry count, the
+    /// head entry with its length, a shared prefix and suffix length for
+    /// each further entry, then the suffixes.
+    fn block(entries: &[&[u8]]) -> Vec<u8> {
+        let mut result = vec![entries.len() as u8];
+        result.extend(vbyte(entries[0].len()));
+        result.extend_from_slice(entries[0]);
+        let mut suffixes = Vec::new();
+        for pair in entries.windows(2) {
+            let shared = pair[0]
+                .iter()
+                .zip(pair[1])
+                .take_while(|(a, b)| a == b)
+                .count();
+            result.extend(vbyte(shared));
+            result.extend(vbyte(pair[1].len() - shared));
+            suffixes.extend_from_slice(&pair[1][shared..]);
+        }
+        result.extend(suffixes);
+        result
+    }
+
+    fn check(data: &[u8], last: bool) -> Vec<(usize, String)> {
+        let mut result = Vec::new();
+        check_block(1, data, last, &mut result);
+        result.into_iter().map(|t| (t.entry, t.message)).collect()
+    }
+
+    #[test]
+    fn vbytes_read_back() {
+        for n in [0, 1, 127, 128, 300, 1 << 20] {
+            let bytes = vbyte(n);
+            let mut pos = 0;
+            assert_eq!(read_vbyte(&bytes, &mut pos), Some(n as u64));
+            assert_eq!(pos, bytes.len());
+        }
+        assert_eq!(read_vbyte(&[0x01], &mut 0), None);
+    }
+
+    #[test]
+    fn a_well_formed_block_passes() {
+        let data = block(&[b"apple", b"applesauce", b"banana"]);
+        assert!(check(&data, false).is_empty());
+        assert_eq!(block_len(&data), Some(data.len()));
+    }
+
+    #[test]
+    fn block_len_stops_at_the_end_of_the_block() {
+        let data = block(&[b"apple", b"apricot"]);
+        let mut padded = data.clone();
+        padded.extend_from_slice(&[0; 5]);
+        assert_eq!(block_len(&padded), Some(data.len()));
+        assert!(check(&padded, true).is_empty());
+    }
+
+    #[test]
+    fn a_cut_suffix_is_reported_at_its_entry() {
+        let data = block(&[b"apple", b"applesauce", b"banana"]);
+        let cut = &data[..data.len() - 1];
+        let found = check(cut, false);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].0, BLOCK_SIZE + 3);
+        assert!(found[0].1.starts_with("length prefix declares 6 bytes"));
+        assert_eq!(block_len(cut), None);
+    }
+
+    #[test]
+    fn bytes_after_the_entries_are_reported_unless_the_block_is_last() {
+        let mut data = block(&[b"apple"]);
+        data.push(0);
+        assert_eq!(check(&data, false).len(), 1);
+        assert!(check(&data, true).is_empty());
+    }
+
+    #[test]
+    fn entry_counts_outside_a_block_are_reported() {
+        for count in [0, BLOCK_SIZE as u8 + 1] {
+            let found = check(&[count, 0x80], false);
+            assert_eq!(
+                found,
+                vec![(BLOCK_SIZE + 1, format!("block declares {count} entries"))]
+            );
+            assert_eq!(block_len(&[count, 0x80]), None);
+        }
+        assert_eq!(check(&[], false)[0].1, "block is empty");
+    }
+
+    #[test]
+    fn an_entry_cut_mid_codepoint_is_reported() {
+        let data = block(&["caf\u{e9}".as_bytes(), &"caf\u{e9}s".as_bytes()[..4]]);
+        let found = check(&data, false);
+        assert_eq!(
+            found,
+            vec![(BLOCK_SIZE + 2, "entry ends mid-codepoint".to_string())]
+        );
+    }
+}
@@ -0,0 +1,300 @@
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+use bytes::Bytes;
+use clap::ValueEnum;
+use terminus_store::{
+    storage::consts::LayerFileEnum,
+    structure::{bitarray::BitArray, Datatype, LogArray},
+};
+
+use crate::{
+    archive::Archive,
+    dict::{read_entries, read_typed_entries, DictType},
+    ids::{cumulative_counts, IdCounts},
+    rdf::{iri, literal, ntriples_literal},
+};
+
+/// How `dump-triples` renders triples.
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum DumpFormat {
+    /// N-Triples lines, with ids outside the layer's own dictionaries
+    /// written as `urn:surgery:` IRIs
+    Ntriples,
+    /// Subject, predicate and object separated by tabs
+    Tsv,
+    /// Raw subject, predicate and object ids
+    Ids,
+}
+
+pub fn parse_logarray(archive: &Archive, file_type: LayerFileEnum) -> io::Result<Option<LogArray>> {
+    match archive.segment(file_type)? {
+        None => Ok(None),
+        Some(bytes) => LogArray::parse(bytes)
+            .map(Some)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{file_type:?}: {e}"))),
+    }
+}
+
+pub fn parse_bitarray(archive: &Archive, file_type: LayerFileEnum) -> io::Result<BitArray> {
+    let bytes = archive.segment(file_type)?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{file_type:?} is missing"),
+        )
+    })?;
+    BitArray::from_bits(bytes)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{file_type:?}: {e}")))
+}
+
+/// Walk the s_p and sp_o adjacency lists of an archive, calling `f` with
+/// the ids of every triple it adds, or with `removals`, every triple it
+/// removes. Each s_p group belongs to the subject at the same position in
+/// the subjects logarray, or in a layer without one, to the subject whose
+/// id is the group number. Each s_p entry has an sp_o group of objects.
+pub fn walk_triples(
+    archive: &Archive,
+    removals: bool,
+    mut f: impl FnMut(u64, u64, u64) -> io::Result<()>,
+) -> io::Result<()> {
+    let (subjects, sp_nums, sp_bits, spo_nums, spo_bits) = if removals {
+        (
+            LayerFileEnum::NegSubjects,
+            LayerFileEnum::NegSPAdjacencyListNums,
+            LayerFileEnum::NegSPAdjacencyListBits,
+            LayerFileEnum::NegSpOAdjacencyListNums,
+            LayerFileEnum::NegSpOAdjacencyListBits,
+        )
+    } else {
+        (
+            LayerFileEnum::PosSubjects,
+            LayerFileEnum::PosSPAdjacencyListNums,
+            LayerFileEnum::PosSPAdjacencyListBits,
+            LayerFileEnum::PosSpOAdjacencyListNums,
+            LayerFileEnum::PosSpOAdjacencyListBits,
+        )
+    };
+    let sp_nums = match parse_logarray(archive, sp_nums)? {
+        Some(nums) => nums,
+        None => return Ok(()),
+    };
+    let sp_bits = parse_bitarray(archive, sp_bits)?;
+    let spo_nums = parse_logarray(archive, spo_nums)?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{spo_nums:?} is missing"),
+        )
+    })?;
+    let spo_bits = parse_bitarray(archive, spo_bits)?;
+    let subjects = parse_logarray(archive, subjects)?;
+
+    // writers differ in whether an empty s_p group gets an sp_o group, so
+    // go by whichever matches the number of sp_o groups
+    let spo_groups = (0..spo_bits.len()).filter(|&i| spo_bits.get(i)).count();
+    let skip_empty = spo_groups != sp_nums.len();
+
+    let mut group = 0;
+    let mut spo_pos = 0;
+    for ix in 0..sp_nums.len() {
+        let predicate = sp_nums.entry(ix);
+        let subject = match &subjects {
+            Some(subjects) if group < subjects.len() => subjects.entry(group),
+            Some(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("s_p group {group} has no subject"),
+                ))
+            }
+            None => group as u64 + 1,
+        };
+        if !(skip_empty && predicate == 0) {
+            loop {
+                if spo_pos >= spo_nums.len() || spo_pos >= spo_bits.len() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("sp_o list ends before the objects of s_p entry {ix}"),
+                    ));
+                }
+                let object = spo_nums.entry(spo_pos);
+                let last = spo_bits.get(spo_pos);
+                spo_pos += 1;
+                if predicate != 0 && object != 0 {
+                    f(subject, predicate, object)?;
+                }
+                if last {
+                    break;
+                }
+            }
+        }
+        if sp_bits.get(ix) {
+            group += 1;
+        }
+    }
+
+    Ok(())
+}
+
+/// The dictionaries of one archive, and the ids of its ancestors that
+/// come before its own.
+pub struct Terms {
+    offsets: IdCounts,
+    nodes: Vec<Bytes>,
+    predicates: Vec<Bytes>,
+    /// Values with their datatype, None if the dictionary is untyped.
+    values: Vec<(Option<Datatype>, Bytes)>,
+}
+
+enum Term<'a> {
+    Node(&'a [u8]),
+    Value(Option<Datatype>, &'a [u8]),
+    Unknown(u64),
+}
+
+/// The stored datatype name of a value, untyped values being strings.
+fn datatype_name(datatype: Option<Datatype>) -> String {
+    datatype
+        .map(|d| format!("{d:?}"))
+        .unwrap_or_else(|| "String".to_string())
+}
+
+impl Terms {
+    pub async fn load(archive: &Archive, offsets: IdCounts) -> io::Result<Self> {
+        Ok(Self {
+            offsets,
+            nodes: read_entries(archive, DictType::Nodes).await?,
+            predicates: read_entries(archive, DictType::Predicates).await?,
+            values: read_typed_entries(archive, DictType::Values).await?,
+        })
+    }
+
+    /// Load the dictionaries of an archive, numbering them after its
+    /// ancestors' ids as found in `store`. Without a store, the ids of a
+    /// child layer are taken to be local to it.
+    pub async fn load_for(archive: &Archive, store: Option<&Path>) -> io::Result<Self> {
+        let offsets = match (archive.parent()?, store) {
+            (Some(parent), Some(store)) => cumulative_counts(store, parent).await?,
+            (Some(_), None) => {
+                eprintln!("child layer without --store: ids are taken to be local to the layer");
+                IdCounts::default()
+            }
+            (None, _) => IdCounts::default(),
+        };
+        Self::load(archive, offsets).await
+    }
+
+    fn node_or_value(&self, id: u64) -> Term<'_> {
+        let local = id.wrapping_sub(self.offsets.nodes_values + 1) as usize;
+        if id <= self.offsets.nodes_values {
+            Term::Unknown(id)
+        } else if local < self.nodes.len() {
+            Term::Node(&self.nodes[local])
+        } else if local - self.nodes.len() < self.values.len() {
+            let (datatype, value) = &self.values[local - self.nodes.len()];
+            Term::Value(*datatype, value)
+        } else {
+            Term::Unknown(id)
+        }
+    }
+
+    fn predicate(&self, id: u64) -> Option<&[u8]> {
+        let local = id.checked_sub(self.offsets.predicates + 1)?;
+        self.predicates.get(local as usize).map(|p| &p[..])
+    }
+
+    /// The id of a node in this archive's dictionary.
+    pub fn node_id(&self, node: &[u8]) -> Option<u64> {
+        let local = self.nodes.binary_search_by(|n| n[..].cmp(node)).ok()?;
+        Some(self.offsets.nodes_values + local as u64 + 1)
+    }
+
+    /// The id of a value in this archive's dictionary, compared on its
+    /// stored bytes within the section of its datatype.
+    pub fn value_id(&self, datatype: Option<Datatype>, value: &[u8]) -> Option<u64> {
+        let start = self.values.iter().position(|(d, _)| *d == datatype)?;
+        let len = self.values[start..]
+            .iter()
+            .take_while(|(d, _)| *d == datatype)
+            .count();
+        let local = start
+            + self.values[start..start + len]
+                .binary_search_by(|(_, v)| v[..].cmp(value))
+                .ok()?;
+        Some(self.offsets.nodes_values + (self.nodes.len() + local) as u64 + 1)
+    }
+
+    pub fn predicate_id(&self, predicate: &[u8]) -> Option<u64> {
+        let local = self
+            .predicates
+            .binary_search_by(|p| p[..].cmp(predicate))
+            .ok()?;
+        Some(self.offsets.predicates + local as u64 + 1)
+    }
+
+    /// A triple rendered as `format` does, without the line break.
+    pub fn format(&self, s: u64, p: u64, o: u64, format: DumpFormat) -> String {
+        match format {
+            DumpFormat::Ids => format!("{s} {p} {o}"),
+            DumpFormat::Ntriples => self.ntriples(s, p, o),
+            DumpFormat::Tsv => {
+                let term = |id| match self.node_or_value(id) {
+                    Term::Node(term) => String::from_utf8_lossy(term).to_string(),
+                    Term::Value(datatype, value) => {
+                        literal(&datatype_name(datatype), value).lexical
+                    }
+                    Term::Unknown(id) => id.to_string(),
+                };
+                let predicate = self
+                    .predicate(p)
+                    .map(|p| String::from_utf8_lossy(p).to_string())
+                    .unwrap_or_else(|| p.to_string());
+                format!("{}\t{predicate}\t{}", term(s), term(o))
+            }
+        }
+    }
+
+    /// A triple as an N-Triples line, without the line break.
+    pub fn ntriples(&self, s: u64, p: u64, o: u64) -> String {
+        let node = |id| match self.node_or_value(id) {
+            Term::Node(node) => iri(&String::from_utf8_lossy(node)),
+            _ => iri(&format!("urn:surgery:id:{id}")),
+        };
+        let predicate = match self.predicate(p) {
+            Some(predicate) => iri(&String::from_utf8_lossy(predicate)),
+            None => iri(&format!("urn:surgery:predicate:{p}")),
+        };
+        let object = match self.node_or_value(o) {
+            Term::Value(datatype, value) => {
+                ntriples_literal(&literal(&datatype_name(datatype), value))
+            }
+            _ => node(o),
+        };
+        format!("{} {predicate} {object} .", node(s))
+    }
+}
+
+/// Print the triples of a single archive, resolving ids against its own
+/// dictionaries. Ids that belong to ancestor layers can't be resolved
+/// from the archive alone. Given the store, they are at least numbered
+/// correctly. Returns the number of triples printed.
+pub async fn dump_triples(
+    path: &Path,
+    store: Option<&Path>,
+    removals: bool,
+    format: DumpFormat,
+) -> io::Result<u64> {
+    let archive = Archive::open(path).await?;
+    let terms = Terms::load_for(&archive, store).await?;
+
+    let stdout = io::stdout();
+    let mut out = io::BufWriter::new(stdout.lock());
+    let mut count = 0;
+    walk_triples(&archive, removals, |s, p, o| {
+        count += 1;
+        writeln!(out, "{}", terms.format(s, p, o, format))
+    })?;
+    out.flush()?;
+
+    Ok(count)
+}
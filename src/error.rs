@@ -0,0 +1,110 @@
+use std::{fmt, io};
+
+/// Exit code of a command that found problems in what it checked.
+pub const EXIT_PROBLEMS: i32 = 1;
+/// Exit code for arguments that don't make sense, as clap uses, and for a
+/// destructive command that wasn't confirmed.
+pub const EXIT_USAGE: i32 = 2;
+/// Exit code for a file, layer, label, segment or id that isn't there.
+pub const EXIT_NOT_FOUND: i32 = 3;
+/// Exit code for data that doesn't parse as what it should be.
+pub const EXIT_CORRUPT: i32 = 4;
+/// Exit code for any other failure to read or write.
+pub const EXIT_IO: i32 = 5;
+
+/// Why a command stopped. Each kind has its own exit code, so scripts can
+/// tell a missing layer from a corrupt one from a full disk.
+#[derive(Debug)]
+pub enum Error {
+    NotFound(String),
+    Corrupt(String),
+    Usage(String),
+    Io(io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn not_found(what: impl fmt::Display) -> Self {
+        Error::NotFound(what.to_string())
+    }
+
+    pub fn corrupt(what: impl fmt::Display) -> Self {
+        Error::Corrupt(what.to_string())
+    }
+
+    pub fn usage(what: impl fmt::Display) -> Self {
+        Error::Usage(what.to_string())
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::NotFound(_) => EXIT_NOT_FOUND,
+            Error::Corrupt(_) => EXIT_CORRUPT,
+            Error::Usage(_) => EXIT_USAGE,
+            Error::Io(_) => EXIT_IO,
+        }
+    }
+
+    /// Name what the error happened to, such as the file or segment,
+    /// keeping its kind.
+    pub fn context(self, what: impl fmt::Display) -> Self {
+        match self {
+            Error::NotFound(e) => Error::NotFound(format!("{what}: {e}")),
+            Error::Corrupt(e) => Error::Corrupt(format!("{what}: {e}")),
+            Error::Usage(e) => Error::Usage(format!("{what}: {e}")),
+            Error::Io(e) => Error::Io(io::Error::new(e.kind(), format!("{what}: {e}"))),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::NotFound(e) | Error::Corrupt(e) | Error::Usage(e) => write!(f, "{e}"),
+            Error::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Sort an IO error by its kind: the layer code reports absent files and
+/// segments as NotFound, and data that doesn't parse as InvalidData or
+/// as running out of bytes early.
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::NotFound => Error::NotFound(e.to_string()),
+            io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => {
+                Error::Corrupt(e.to_string())
+            }
+            io::ErrorKind::InvalidInput => Error::Usage(e.to_string()),
+            _ => Error::Io(e),
+        }
+    }
+}
+
+/// Attach what an error happened to on the way out of a command.
+pub trait Context<T> {
+    fn context(self, what: impl fmt::Display) -> Result<T>;
+}
+
+impl<T> Context<T> for io::Result<T> {
+    fn context(self, what: impl fmt::Display) -> Result<T> {
+        self.map_err(|e| Error::from(e).context(what))
+    }
+}
+
+impl<T> Context<T> for Result<T> {
+    fn context(self, what: impl fmt::Display) -> Result<T> {
+        self.map_err(|e| e.context(what))
+    }
+}
+
+/// A missing value is something not found.
+impl<T> Context<T> for Option<T> {
+    fn context(self, what: impl fmt::Display) -> Result<T> {
+        self.ok_or_else(|| Error::not_found(what))
+    }
+}
@@ -0,0 +1,498 @@
+use std::{
+    collections::BTreeSet,
+    fmt,
+    fs::OpenOptions,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+    sync::{mpsc::sync_channel, Arc},
+    time::{Duration, Instant},
+};
+
+use clap::ValueEnum;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use terminus_store::{
+    layer::{IdTriple, ValueTriple},
+    storage::name_to_string,
+    store::sync::{open_sync_archive_store, SyncStoreLayer},
+    Layer,
+};
+use tokio::sync::Semaphore;
+
+use crate::{
+    archive::Archive,
+    atomic,
+    checkpoint::{verify_prefix, Checkpoint, Position},
+    dict::{read_entries, DictType},
+    limits,
+    rdf::ntriples_line,
+    store::{chain, list_labels, read_label},
+};
+
+/// File formats that a whole layer can be exported to.
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Ntriples,
+}
+
+/// Number of triples passed between pipeline stages at a time.
+const BATCH_SIZE: usize = 4096;
+
+/// The smallest batch `--max-memory` may shrink batches to.
+const MIN_BATCH_SIZE: usize = 64;
+
+/// Number of batches each pipeline stage may run ahead of the next.
+const PIPELINE_DEPTH: usize = 4;
+
+/// Number of batches a pipeline holds at most: those queued between its
+/// four stages, and one in each stage.
+const BATCHES_IN_FLIGHT: usize = 3 * PIPELINE_DEPTH + 4;
+
+/// Rough memory taken by one triple in a batch, once resolved to strings.
+const TRIPLE_MEMORY: usize = 256;
+
+/// Files one export keeps open: its output and checkpoint, and the layer
+/// archives the store opens as it reads the chain.
+const EXPORT_FILES: usize = 8;
+
+/// Number of batches written between checkpoints.
+const CHECKPOINT_BATCHES: u64 = 64;
+
+/// How much work one stage of an export pipeline did. Time spent waiting
+/// on the stages before and after it isn't counted as busy.
+pub struct StageMetrics {
+    pub name: &'static str,
+    pub batches: u64,
+    pub busy: Duration,
+}
+
+impl fmt::Display for StageMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {:.2}s in {} batches",
+            self.name,
+            self.busy.as_secs_f64(),
+            self.batches
+        )
+    }
+}
+
+/// Time a pipeline stage, passing each batch it produces to `send` until
+/// that fails because the next stage has gone.
+fn stage<T>(
+    name: &'static str,
+    mut produce: impl FnMut() -> Option<T>,
+    mut send: impl FnMut(T) -> bool,
+) -> StageMetrics {
+    let mut metrics = StageMetrics {
+        name,
+        batches: 0,
+        busy: Duration::ZERO,
+    };
+    loop {
+        let start = Instant::now();
+        let batch = produce();
+        metrics.busy += start.elapsed();
+        match batch {
+            Some(batch) if send(batch) => metrics.batches += 1,
+            _ => return metrics,
+        }
+    }
+}
+
+/// Write the triples of a layer as N-Triples, leaving out the first
+/// `skip`, and return how many were written and what each stage did.
+/// Reading the triples, resolving their ids to strings, serializing them
+/// and writing them out each run on their own thread, connected by bounded
+/// channels, so that a slow stage doesn't hold up the others. Triples are
+/// passed on `batch_size` at a time. `progress`
+/// is called with the output after each batch is written, with the number
+/// of triples and the text in the batch.
+pub fn write_ntriples<W: Write>(
+    layer: &SyncStoreLayer,
+    out: &mut W,
+    skip: u64,
+    batch_size: usize,
+    mut progress: impl FnMut(&mut W, u64, &[u8]) -> io::Result<()>,
+) -> io::Result<(u64, Vec<StageMetrics>)> {
+    let (id_send, id_receive) = sync_channel::<Vec<IdTriple>>(PIPELINE_DEPTH);
+    let (resolved_send, resolved_receive) =
+        sync_channel::<io::Result<Vec<ValueTriple>>>(PIPELINE_DEPTH);
+    let (text_send, text_receive) = sync_channel::<io::Result<(Vec<u8>, u64)>>(PIPELINE_DEPTH);
+
+    std::thread::scope(|scope| {
+        let read = scope.spawn(move || {
+            let mut triples = layer.triples().skip(skip as usize);
+            stage(
+                "read",
+                || {
+                    let batch: Vec<_> = triples.by_ref().take(batch_size).collect();
+                    (!batch.is_empty()).then_some(batch)
+                },
+                |batch| id_send.send(batch).is_ok(),
+            )
+        });
+        let resolve = scope.spawn(move || {
+            stage(
+                "resolve",
+                || {
+                    let batch = id_receive.recv().ok()?;
+                    Some(
+                        batch
+                            .iter()
+                            .map(|triple| {
+                                layer.id_triple_to_string(triple).ok_or_else(|| {
+                                    io::Error::new(
+                                        io::ErrorKind::InvalidData,
+                                        format!("triple {triple:?} does not resolve"),
+                                    )
+                                })
+                            })
+                            .collect(),
+                    )
+                },
+                |batch| resolved_send.send(batch).is_ok(),
+            )
+        });
+        let serialize = scope.spawn(move || {
+            stage(
+                "serialize",
+                || {
+                    let batch = resolved_receive.recv().ok()?;
+                    Some(batch.map(|triples| {
+                        let mut text = Vec::new();
+                        for triple in triples.iter() {
+                            text.extend_from_slice(ntriples_line(triple).as_bytes());
+                            text.push(b'\n');
+                        }
+                        (text, triples.len() as u64)
+                    }))
+                },
+                |batch| text_send.send(batch).is_ok(),
+            )
+        });
+
+        // writing happens on this thread. An error stops the pipeline by
+        // dropping the receiver, which makes the other stages wind down.
+        let mut count = 0;
+        let mut result = Ok(());
+        let write = stage(
+            "write",
+            || {
+                let batch = text_receive.recv().ok()?;
+                Some(batch.and_then(|(text, n)| {
+                    out.write_all(&text)?;
+                    progress(out, n, &text)?;
+                    Ok(n)
+                }))
+            },
+            |written| match written {
+                Ok(n) => {
+                    count += n;
+                    true
+                }
+                Err(e) => {
+                    result = Err(e);
+                    false
+                }
+            },
+        );
+        drop(text_receive);
+        let metrics = vec![
+            read.join().unwrap(),
+            resolve.join().unwrap(),
+            serialize.join().unwrap(),
+            write,
+        ];
+        result?;
+        out.flush()?;
+
+        Ok((count, metrics))
+    })
+}
+
+/// Export a layer to a file. With a checkpoint, progress is recorded as
+/// the export goes, and an earlier partial export of the same layer is
+/// continued if the file still holds exactly what was recorded as
+/// written. Anything after that is cut off before appending.
+fn export_layer(
+    store: &Path,
+    label: &str,
+    head: [u32; 5],
+    path: &Path,
+    checkpoint: Option<&Checkpoint>,
+    batch_size: usize,
+) -> io::Result<(u64, Vec<StageMetrics>)> {
+    let head_name = name_to_string(head);
+    let mut position = Position {
+        head: head_name.clone(),
+        triples: 0,
+        bytes: 0,
+        sha256: String::new(),
+        complete: false,
+    };
+    let mut hasher = Sha256::new();
+    let previous = checkpoint
+        .and_then(|c| c.get(label))
+        .filter(|p| p.head == head_name);
+    if let Some(previous) = previous {
+        match verify_prefix(path, &previous)? {
+            Some(_) if previous.complete => return Ok((previous.triples, Vec::new())),
+            Some(prefix) => {
+                hasher = prefix;
+                position = previous;
+            }
+            None => eprintln!("{label}: output differs from the checkpoint, starting over"),
+        }
+    }
+
+    let store = open_sync_archive_store(store, 512);
+    let layer = store.get_layer_from_id(head)?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("layer {head_name} not found"),
+        )
+    })?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .open(path)?;
+    file.set_len(position.bytes)?;
+    file.seek(SeekFrom::End(0))?;
+    let mut out = BufWriter::new(file);
+    let skip = position.triples;
+    let mut batches = 0;
+    let (count, metrics) = write_ntriples(&layer, &mut out, skip, batch_size, |out, n, text| {
+        position.triples += n;
+        position.bytes += text.len() as u64;
+        hasher.update(text);
+        batches += 1;
+        match checkpoint {
+            Some(checkpoint) if batches % CHECKPOINT_BATCHES == 0 => {
+                // only record what is known to be in the file
+                out.flush()?;
+                position.sha256 = format!("{:x}", hasher.clone().finalize());
+                checkpoint.record(label, position.clone())
+            }
+            _ => Ok(()),
+        }
+    })?;
+    if let Some(checkpoint) = checkpoint {
+        position.sha256 = format!("{:x}", hasher.finalize());
+        position.complete = true;
+        checkpoint.record(label, position)?;
+    }
+
+    Ok((skip + count, metrics))
+}
+
+/// Export the head of every label in the store to its own file, running up
+/// to `jobs` exports at once. Labels without a head are skipped. A
+/// `manifest.json` listing the exported files is written last. With a
+/// checkpoint file, a rerun after an interruption continues each export
+/// where it stopped.
+pub async fn export_all(
+    store: &Path,
+    output: &Path,
+    format: ExportFormat,
+    jobs: usize,
+    checkpoint: Option<&Path>,
+) -> io::Result<()> {
+    let extension = match format {
+        ExportFormat::Ntriples => "nt",
+    };
+    tokio::fs::create_dir_all(output).await?;
+    let checkpoint = match checkpoint {
+        Some(path) => Some(Arc::new(Checkpoint::load(path)?)),
+        None => None,
+    };
+    let memory = (BATCHES_IN_FLIGHT * MIN_BATCH_SIZE * TRIPLE_MEMORY) as u64;
+    let jobs = limits::jobs(jobs, EXPORT_FILES, memory);
+    let batch_size =
+        limits::batch_size(BATCH_SIZE, TRIPLE_MEMORY, BATCHES_IN_FLIGHT, jobs).max(MIN_BATCH_SIZE);
+    let semaphore = Arc::new(Semaphore::new(jobs));
+    let mut tasks = Vec::new();
+    for label in list_labels(store).await? {
+        let head = match read_label(store, &label).await? {
+            Some(head) => head,
+            None => continue,
+        };
+        let file_name = format!("{}.{extension}", label.replace('/', "_"));
+        let path = output.join(&file_name);
+        let permit = semaphore.clone().acquire_owned().await.unwrap();
+        let store = store.to_path_buf();
+        let checkpoint = checkpoint.clone();
+        let task_label = label.clone();
+        let task = tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            export_layer(
+                &store,
+                &task_label,
+                head,
+                &path,
+                checkpoint.as_deref(),
+                batch_size,
+            )
+        });
+        tasks.push((label, head, file_name, task));
+    }
+
+    let mut labels = Vec::new();
+    for (label, head, file_name, task) in tasks {
+        let (triples, metrics) = task.await.unwrap()?;
+        if metrics.is_empty() {
+            eprintln!("{label}: {triples} triples, already exported");
+        } else {
+            let metrics: Vec<_> = metrics.iter().map(|m| m.to_string()).collect();
+            eprintln!("{label}: {triples} triples ({})", metrics.join(", "));
+        }
+        labels.push(json!({
+            "label": label,
+            "layer": name_to_string(head),
+            "file": file_name,
+            "triples": triples,
+        }));
+    }
+    let manifest = json!({
+        "store": store.display().to_string(),
+        "labels": labels,
+    });
+    tokio::fs::write(
+        output.join("manifest.json"),
+        serde_json::to_string_pretty(&manifest)?,
+    )
+    .await
+}
+
+/// Escape a dictionary entry so it fits on one line: backslashes and line
+/// breaks are escaped, and bytes that aren't UTF-8 are replaced.
+fn escape_line(entry: &[u8]) -> String {
+    String::from_utf8_lossy(entry)
+        .replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+/// Write the strings each layer of a chain added to its dictionaries, as
+/// `<layer>.nodes`, `<layer>.predicates` and `<layer>.values` with one
+/// entry per line. `layers.tsv` lists the layers base first with the
+/// number of entries each added, so vocabulary growth can be followed
+/// over time. Returns the number of layers.
+pub async fn export_dict_deltas(store: &Path, head: [u32; 5], output: &Path) -> io::Result<usize> {
+    tokio::fs::create_dir_all(output).await?;
+    let mut layers = chain(store, head).await?;
+    layers.reverse();
+    let mut index = String::from("position\tlayer\tnodes\tpredicates\tvalues\n");
+    for (position, (name, path)) in layers.iter().enumerate() {
+        let archive = Archive::open(path).await?;
+        let name = name_to_string(*name);
+        let mut counts = Vec::new();
+        for (t, extension) in [
+            (DictType::Nodes, "nodes"),
+            (DictType::Predicates, "predicates"),
+            (DictType::Values, "values"),
+        ] {
+            let entries = read_entries(&archive, t).await?;
+            let mut contents = String::new();
+            for entry in entries.iter() {
+                contents.push_str(&escape_line(entry));
+                contents.push('\n');
+            }
+            atomic::write(output.join(format!("{name}.{extension}")), contents).await?;
+            counts.push(entries.len().to_string());
+        }
+        index.push_str(&format!("{position}\t{name}\t{}\n", counts.join("\t")));
+    }
+    atomic::write(output.join("layers.tsv"), index).await?;
+
+    Ok(layers.len())
+}
+
+/// The net changes between an ancestor and a head.
+pub struct Delta {
+    pub layers: usize,
+    pub added: usize,
+    pub removed: usize,
+}
+
+/// Write triples as N-Triples to a temporary file, renamed over `path`
+/// once complete.
+fn write_triples(
+    layer: &SyncStoreLayer,
+    triples: &BTreeSet<(u64, u64, u64)>,
+    path: &Path,
+) -> io::Result<()> {
+    let tmp = atomic::temp_path(path);
+    let result = (|| {
+        let mut out = BufWriter::new(std::fs::File::create(&tmp)?);
+        for (s, p, o) in triples.iter() {
+            let triple = IdTriple::new(*s, *p, *o);
+            let triple = layer.id_triple_to_string(&triple).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("triple {triple:?} does not resolve"),
+                )
+            })?;
+            writeln!(out, "{}", ntriples_line(&triple))?;
+        }
+        out.flush()?;
+        std::fs::rename(&tmp, path)
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+
+    result
+}
+
+/// Write the net changes from the ancestor `since` to `head` as
+/// `removed.nt` and `added.nt` in `output`. Removing the one and adding
+/// the other on top of `since` gives the head. A triple added and removed
+/// again in between, or removed and added back, doesn't appear at all.
+pub fn export_delta(head: &SyncStoreLayer, since: [u32; 5], output: &Path) -> io::Result<Delta> {
+    let mut layers = Vec::new();
+    let mut current = head.clone();
+    while current.name() != since {
+        let parent = current.parent()?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "{} is not an ancestor of {}",
+                    name_to_string(since),
+                    name_to_string(head.name())
+                ),
+            )
+        })?;
+        layers.push(current);
+        current = parent;
+    }
+
+    let mut added = BTreeSet::new();
+    let mut removed = BTreeSet::new();
+    for layer in layers.iter().rev() {
+        for t in layer.triple_additions() {
+            let t = (t.subject, t.predicate, t.object);
+            if !removed.remove(&t) {
+                added.insert(t);
+            }
+        }
+        for t in layer.triple_removals() {
+            let t = (t.subject, t.predicate, t.object);
+            if !added.remove(&t) {
+                removed.insert(t);
+            }
+        }
+    }
+
+    std::fs::create_dir_all(output)?;
+    write_triples(head, &removed, &output.join("removed.nt"))?;
+    write_triples(head, &added, &output.join("added.nt"))?;
+
+    Ok(Delta {
+        layers: layers.len(),
+        added: added.len(),
+        removed: removed.len(),
+    })
+}
@@ -0,0 +1,160 @@
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use sha2::{Digest, Sha256};
+use terminus_store::storage::consts::LayerFileEnum;
+use tokio::{
+    io::{AsyncReadExt, AsyncSeekExt},
+    sync::Semaphore,
+};
+
+use crate::{
+    atomic,
+    checksum::ChecksumTrailer,
+    header::{segment_file_name, HeaderReport},
+    limits,
+};
+
+/// Memory one segment copy needs for its buffers.
+const COPY_MEMORY: u64 = 64 * 1024;
+
+/// A segment written out by `extract_all`.
+pub struct ExtractedSegment {
+    pub segment: LayerFileEnum,
+    pub path: PathBuf,
+    pub size: u64,
+    /// Whether the written file matches the archive's checksum trailer.
+    /// None if it wasn't checked, because verification wasn't asked for
+    /// or the archive records no checksum for the segment.
+    pub checksum: Option<bool>,
+}
+
+/// Copy `len` bytes at `start` of a layer file into `path`, through a file
+/// handle of its own so that segments can be copied side by side.
+fn extract_segment(layer_file: &Path, start: u64, len: u64, path: &Path) -> io::Result<()> {
+    let tmp = atomic::temp_path(path);
+    let result = (|| {
+        let mut file = File::open(layer_file)?;
+        file.seek(SeekFrom::Start(start))?;
+        let mut out = File::create(&tmp)?;
+        let copied = io::copy(&mut file.take(len), &mut out)?;
+        if copied != len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("layer file ends {copied} bytes into a {len} byte segment"),
+            ));
+        }
+        out.sync_all()?;
+        std::fs::rename(&tmp, path)
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+
+    result
+}
+
+/// Read back a written segment, checking its size and, if one is given,
+/// its sha256. Returns whether the checksum matched.
+fn verify_segment(path: &Path, size: u64, checksum: Option<[u8; 32]>) -> io::Result<Option<bool>> {
+    let written = std::fs::metadata(path)?.len();
+    if written != size {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} holds {written} bytes but the segment has {size}",
+                path.display()
+            ),
+        ));
+    }
+    match checksum {
+        None => Ok(None),
+        Some(expected) => {
+            let mut hasher = Sha256::new();
+            io::copy(&mut File::open(path)?, &mut hasher)?;
+            Ok(Some(<[u8; 32]>::from(hasher.finalize()) == expected))
+        }
+    }
+}
+
+/// Read the checksum trailer after the last segment of a layer file
+/// without reading the segments. A trailer that can't be parsed is
+/// reported and otherwise ignored.
+async fn read_trailer(layer_file: &Path, segments_end: u64) -> io::Result<Option<ChecksumTrailer>> {
+    let mut file = tokio::fs::File::open(layer_file).await?;
+    file.seek(SeekFrom::Start(segments_end)).await?;
+    let mut trailer = Vec::new();
+    file.read_to_end(&mut trailer).await?;
+    match ChecksumTrailer::parse(&trailer) {
+        Ok(trailer) => Ok(trailer),
+        Err(e) => {
+            eprintln!("not checking checksums: {e}");
+            Ok(None)
+        }
+    }
+}
+
+/// Extract every segment of a layer file into `output`, named as for
+/// `extract`, copying up to `jobs` segments at once. With `verify`, each
+/// written file is read back and checked against the segment's size and,
+/// if the archive has a checksum trailer, its checksum. Each copy keeps
+/// two files open, which `--max-open-files` may limit `jobs` by.
+pub async fn extract_all(
+    layer_file: &Path,
+    output: &Path,
+    jobs: usize,
+    verify: bool,
+) -> io::Result<Vec<ExtractedSegment>> {
+    let report = HeaderReport::read(layer_file).await?;
+    tokio::fs::create_dir_all(output).await?;
+    let trailer = if verify {
+        let end = report.segments.iter().map(|s| s.range.end).max();
+        let segments_end = (report.header_len + end.unwrap_or(0)) as u64;
+        read_trailer(layer_file, segments_end).await?
+    } else {
+        None
+    };
+
+    let jobs = limits::jobs(jobs, 2, COPY_MEMORY);
+    let semaphore = Arc::new(Semaphore::new(jobs));
+    let mut tasks = Vec::new();
+    for info in report.segments.iter() {
+        let name = segment_file_name(info.segment)
+            .map(str::to_string)
+            .unwrap_or_else(|| info.name());
+        let path = output.join(name);
+        let start = (report.header_len + info.range.start) as u64;
+        let size = info.range.len() as u64;
+        let checksum = trailer.as_ref().and_then(|t| t.get(info.segment)).copied();
+        let permit = semaphore.clone().acquire_owned().await.unwrap();
+        let layer_file = layer_file.to_path_buf();
+        let task_path = path.clone();
+        let task = tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            extract_segment(&layer_file, start, size, &task_path)?;
+            if verify {
+                verify_segment(&task_path, size, checksum)
+            } else {
+                Ok(None)
+            }
+        });
+        tasks.push((info.segment, path, size, task));
+    }
+
+    let mut result = Vec::new();
+    for (segment, path, size, task) in tasks {
+        let checksum = task.await.unwrap()?;
+        result.push(ExtractedSegment {
+            segment,
+            path,
+            size,
+            checksum,
+        });
+    }
+
+    Ok(result)
+}
@@ -0,0 +1,129 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+
+use crate::atomic;
+
+/// Where a layer is fetched from.
+pub enum Source {
+    File(PathBuf),
+    /// An `http://` or `https://` URL, downloaded with curl.
+    Http(String),
+    /// An `s3://bucket/key` URL, downloaded with the AWS CLI.
+    S3(String),
+}
+
+impl Source {
+    pub fn parse(s: &str) -> Self {
+        if s.starts_with("http://") || s.starts_with("https://") {
+            Source::Http(s.to_string())
+        } else if s.starts_with("s3://") {
+            Source::S3(s.to_string())
+        } else {
+            Source::File(PathBuf::from(s.strip_prefix("file://").unwrap_or(s)))
+        }
+    }
+}
+
+/// Check that a digest is 64 hex digits, and lowercase it.
+pub fn parse_sha256(s: &str) -> Result<String, String> {
+    if s.len() == 64 && s.chars().all(|c| c.is_ascii_hexdigit()) {
+        Ok(s.to_lowercase())
+    } else {
+        Err(format!("{s} is not a sha256 digest of 64 hex digits"))
+    }
+}
+
+/// Copy everything from `reader` to `out`, hashing it on the way. Returns
+/// the number of bytes and their sha256 in hex.
+async fn copy_hashed(
+    mut reader: impl AsyncRead + Unpin,
+    out: &mut tokio::fs::File,
+) -> io::Result<(u64, String)> {
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 64 * 1024];
+    let mut size = 0;
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        out.write_all(&buf[..n]).await?;
+        size += n as u64;
+    }
+    out.sync_all().await?;
+
+    Ok((size, format!("{:x}", hasher.finalize())))
+}
+
+/// Run a command that writes a download to stdout, copying it to `out`.
+async fn run(
+    mut command: tokio::process::Command,
+    out: &mut tokio::fs::File,
+) -> io::Result<(u64, String)> {
+    let mut child = command.stdout(Stdio::piped()).spawn()?;
+    let copied = copy_hashed(child.stdout.take().unwrap(), out).await;
+    let status = child.wait().await?;
+    if !status.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("download exited with {status}"),
+        ));
+    }
+
+    copied
+}
+
+async fn download(source: &Source, out: &mut tokio::fs::File) -> io::Result<(u64, String)> {
+    match source {
+        Source::File(path) => copy_hashed(tokio::fs::File::open(path).await?, out).await,
+        Source::Http(url) => {
+            let mut command = tokio::process::Command::new("curl");
+            command.args([
+                "--fail",
+                "--silent",
+                "--show-error",
+                "--location",
+                url.as_str(),
+            ]);
+            run(command, out).await
+        }
+        Source::S3(url) => {
+            let mut command = tokio::process::Command::new("aws");
+            command.args(["s3", "cp", "--only-show-errors", url.as_str(), "-"]);
+            run(command, out).await
+        }
+    }
+}
+
+/// Fetch a layer to `output`, hashing it as it streams in. The file is
+/// written under a temporary name and only renamed to `output` if its
+/// sha256 is `expected`; otherwise it is removed. Returns its size.
+pub async fn fetch(source: &str, expected: &str, output: &Path) -> io::Result<u64> {
+    let source = Source::parse(source);
+    let tmp = atomic::temp_path(output);
+    let result = async {
+        let mut out = tokio::fs::File::create(&tmp).await?;
+        let (size, digest) = download(&source, &mut out).await?;
+        if digest != expected {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("sha256 is {digest}, expected {expected}; not keeping the file"),
+            ));
+        }
+        tokio::fs::rename(&tmp, output).await?;
+        Ok(size)
+    }
+    .await;
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(&tmp).await;
+    }
+
+    result
+}
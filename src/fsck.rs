@@ -0,0 +1,263 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt, io,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use serde_json::json;
+use terminus_store::storage::name_to_string;
+
+use crate::{
+    archive::Archive,
+    atomic,
+    checks::Check,
+    deadline,
+    health::Health,
+    output::{ndjson, paint, Color, OutputFormat},
+    store::list_layers,
+    validate::Finding,
+};
+
+/// Where fsck keeps its per-layer results unless told otherwise.
+pub fn default_cache_path(store: &Path) -> PathBuf {
+    let mut path = store.to_path_buf();
+    path.push(".surgery");
+    path.push("fsck-cache");
+    path
+}
+
+/// Identifies the exact file contents a cached result was computed for.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct FileStamp {
+    pub mtime: u64,
+    pub size: u64,
+}
+
+impl FileStamp {
+    pub async fn of(path: &Path) -> io::Result<Self> {
+        let metadata = tokio::fs::metadata(path).await?;
+        let mtime = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        Ok(Self {
+            mtime,
+            size: metadata.len(),
+        })
+    }
+}
+
+/// Whether a layer passed, the file it was checked as, and the checks
+/// that were run on it.
+pub struct CacheEntry {
+    pub stamp: FileStamp,
+    pub ok: bool,
+    /// Names of the checks run, empty if they weren't recorded.
+    pub checks: BTreeSet<String>,
+}
+
+impl CacheEntry {
+    /// The line recording this entry in a results cache.
+    pub fn line(&self, name: &str) -> String {
+        let status = if self.ok { "ok" } else { "fail" };
+        let mut line = format!("{name} {} {} {status}", self.stamp.mtime, self.stamp.size);
+        if !self.checks.is_empty() {
+            let checks: Vec<_> = self.checks.iter().map(String::as_str).collect();
+            line.push(' ');
+            line.push_str(&checks.join(","));
+        }
+        line.push('\n');
+        line
+    }
+
+    /// Whether the entry records a pass of the same file with at least the
+    /// given checks.
+    pub fn covers(&self, stamp: FileStamp, checks: &[Box<dyn Check>]) -> bool {
+        self.ok
+            && self.stamp == stamp
+            && checks
+                .iter()
+                .all(|check| self.checks.contains(check.name()))
+    }
+}
+
+/// Read a results cache, one layer per line. Later lines for a layer
+/// replace earlier ones, so results can be appended as they come in.
+pub async fn load_cache(path: &Path) -> io::Result<HashMap<String, CacheEntry>> {
+    let contents = match tokio::fs::read_to_string(path).await {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(e),
+    };
+    let mut cache = HashMap::new();
+    for line in contents.lines() {
+        // malformed lines are simply re-verified
+        let fields: Vec<_> = line.split(' ').collect();
+        let (name, mtime, size, status, checks) = match fields[..] {
+            [name, mtime, size, status] => (name, mtime, size, status, ""),
+            [name, mtime, size, status, checks] => (name, mtime, size, status, checks),
+            _ => continue,
+        };
+        if let (Ok(mtime), Ok(size)) = (mtime.parse(), size.parse()) {
+            cache.insert(
+                name.to_string(),
+                CacheEntry {
+                    stamp: FileStamp { mtime, size },
+                    ok: status == "ok",
+                    checks: checks
+                        .split(',')
+                        .filter(|c| !c.is_empty())
+                        .map(String::from)
+                        .collect(),
+                },
+            );
+        }
+    }
+
+    Ok(cache)
+}
+
+async fn save_cache(path: &Path, cache: &HashMap<String, CacheEntry>) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    let mut names: Vec<_> = cache.keys().collect();
+    names.sort();
+    let mut out = String::new();
+    for name in names {
+        out.push_str(&cache[name].line(name));
+    }
+    atomic::write(path, out).await
+}
+
+/// Run checks on a single layer archive, returning every problem found.
+pub async fn check_layer(path: &Path, checks: &[Box<dyn Check>]) -> Vec<Finding> {
+    match Archive::open(path).await {
+        Ok(archive) => checks
+            .iter()
+            .flat_map(|check| check.run(&archive))
+            .collect(),
+        Err(e) => vec![Finding::unreadable(e)],
+    }
+}
+
+/// Print the outcome of checking a single layer.
+pub fn print_result<F: fmt::Display>(layer: &str, findings: &[F], format: OutputFormat) {
+    match format {
+        OutputFormat::Pretty if findings.is_empty() => {
+            println!("{}  {layer}", paint("  OK", Color::Green))
+        }
+        OutputFormat::Pretty => {
+            for finding in findings {
+                println!("{}  {layer}  {finding}", paint("FAIL", Color::Red));
+            }
+        }
+        OutputFormat::Text if findings.is_empty() => println!("OK {layer}"),
+        OutputFormat::Text => {
+            for finding in findings {
+                println!("FAIL {layer}: {finding}");
+            }
+        }
+        OutputFormat::Ndjson if findings.is_empty() => {
+            println!("{}", ndjson(json!({"layer": layer, "status": "ok"})))
+        }
+        OutputFormat::Ndjson => {
+            for finding in findings {
+                println!(
+                    "{}",
+                    ndjson(
+                        json!({"layer": layer, "status": "fail", "finding": finding.to_string()})
+                    )
+                );
+            }
+        }
+    }
+}
+
+/// The outcome of checking a whole store.
+pub struct FsckReport {
+    pub checked: usize,
+    /// Layers skipped as unchanged since they last passed.
+    pub skipped: usize,
+    pub failed: usize,
+    /// Layers not reached before the deadline.
+    pub remaining: usize,
+    /// The store's health score out of 100.
+    pub health: f64,
+    /// Findings by severity.
+    pub findings: BTreeMap<&'static str, usize>,
+}
+
+/// Check every layer in the store, recording results in the cache and
+/// handing each checked layer's findings to `on_result` as it goes. With
+/// `incremental`, layers that passed the same or a wider set of checks
+/// before and are unchanged on disk are skipped. The report includes the
+/// store's health score, which is also written to `prometheus` as metrics
+/// for the node exporter's textfile collector. Past the deadline, the
+/// remaining layers are left unchecked and counted in the report, and
+/// lower the score's coverage.
+pub async fn fsck(
+    store: &Path,
+    cache_path: &Path,
+    incremental: bool,
+    checks: &[Box<dyn Check>],
+    prometheus: Option<&Path>,
+    mut on_result: impl FnMut(&str, &[Finding]),
+) -> io::Result<FsckReport> {
+    let mut cache = load_cache(cache_path).await?;
+    let mut health = Health::new(checks.len());
+    let mut checked = 0;
+    let mut skipped = 0;
+    let mut failed = 0;
+    let layers = list_layers(store).await?;
+    let mut remaining = 0;
+    for (i, (name, path)) in layers.iter().enumerate() {
+        if deadline::expired() {
+            remaining = layers.len() - i;
+            health.record_unreached(remaining);
+            break;
+        }
+        let name = name_to_string(*name);
+        let stamp = FileStamp::of(path).await?;
+        if incremental {
+            if let Some(entry) = cache.get(&name) {
+                if entry.covers(stamp, checks) {
+                    skipped += 1;
+                    health.record(&[]);
+                    continue;
+                }
+            }
+        }
+
+        checked += 1;
+        let findings = check_layer(path, checks).await;
+        on_result(&name, &findings);
+        health.record(&findings);
+        if !findings.is_empty() {
+            failed += 1;
+        }
+        cache.insert(
+            name,
+            CacheEntry {
+                stamp,
+                ok: findings.is_empty(),
+                checks: checks.iter().map(|c| c.name().to_string()).collect(),
+            },
+        );
+    }
+    save_cache(cache_path, &cache).await?;
+    if let Some(prometheus) = prometheus {
+        atomic::write(prometheus, health.prometheus(store)).await?;
+    }
+
+    Ok(FsckReport {
+        checked,
+        skipped,
+        failed,
+        remaining,
+        health: health.score(),
+        findings: health.counts(),
+    })
+}
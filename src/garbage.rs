@@ -0,0 +1,330 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{self, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use serde_json::json;
+use terminus_store::storage::{consts::LayerFileEnum, name_to_string};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use crate::{
+    archive::{parse_layer_name, parse_rollup},
+    databases::decode_label,
+    header::HeaderReport,
+    output::{human_bytes, ndjson, paint, Color, OutputFormat},
+    pins,
+    store::{list_labels, list_layers, read_label},
+};
+
+/// A reference to a layer the store doesn't have.
+pub struct Dangling {
+    /// The label, pin or layer holding the reference.
+    pub from: String,
+    /// `head`, `pin`, `parent` or `rollup`.
+    pub kind: &'static str,
+    pub missing: [u32; 5],
+}
+
+/// A layer file whose references can't be read.
+pub struct BrokenLayer {
+    pub name: [u32; 5],
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+/// The layers a label's head keeps alive, and their size on disk.
+pub struct DatabaseUsage {
+    pub label: String,
+    /// The database the label stands for, as `databases` decodes it.
+    pub database: Option<String>,
+    pub layers: usize,
+    pub size: u64,
+}
+
+/// Which layers of a store are still in use, and what's wrong with the
+/// rest.
+pub struct GarbageReport {
+    pub layers: usize,
+    pub size: u64,
+    /// Layers no label or pin reaches, with their paths and sizes.
+    pub unreachable: Vec<([u32; 5], PathBuf, u64)>,
+    pub dangling: Vec<Dangling>,
+    pub broken: Vec<BrokenLayer>,
+    /// Broken layers that labels or pins reach. Their ancestors can't be
+    /// followed, so some layers reported unreachable may be in use.
+    pub broken_reachable: usize,
+    pub usage: Vec<DatabaseUsage>,
+}
+
+async fn read_segment(
+    file: &mut tokio::fs::File,
+    report: &HeaderReport,
+    segment: LayerFileEnum,
+) -> io::Result<Option<Vec<u8>>> {
+    let info = match report.segments.iter().find(|s| s.segment == segment) {
+        Some(info) => info,
+        None => return Ok(None),
+    };
+    file.seek(SeekFrom::Start(
+        (report.header_len + info.range.start) as u64,
+    ))
+    .await?;
+    let mut bytes = vec![0; info.range.len()];
+    file.read_exact(&mut bytes).await?;
+
+    Ok(Some(bytes))
+}
+
+/// The parent and rollup a layer file refers to, reading only its header
+/// and those two segments.
+async fn references(path: &Path) -> io::Result<(Option<[u32; 5]>, Option<[u32; 5]>)> {
+    if tokio::fs::metadata(path).await?.len() == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "zero-length file",
+        ));
+    }
+    let report = HeaderReport::read(path).await?;
+    let mut file = tokio::fs::File::open(path).await?;
+    let parent = match read_segment(&mut file, &report, LayerFileEnum::Parent).await? {
+        Some(bytes) => Some(parse_layer_name(&bytes)?),
+        None => None,
+    };
+    let rollup = match read_segment(&mut file, &report, LayerFileEnum::Rollup).await? {
+        Some(bytes) => Some(parse_rollup(&bytes)?),
+        None => None,
+    };
+
+    Ok((parent, rollup))
+}
+
+/// Every layer reachable from `roots` through parents and rollups. A layer
+/// is kept alive by the layers it's a parent of and by those it's rolled
+/// up into, as readers may switch to the rollup.
+fn reach(
+    roots: impl IntoIterator<Item = [u32; 5]>,
+    refs: &BTreeMap<[u32; 5], (Option<[u32; 5]>, Option<[u32; 5]>)>,
+) -> BTreeSet<[u32; 5]> {
+    let mut reached = BTreeSet::new();
+    let mut pending: Vec<_> = roots.into_iter().collect();
+    while let Some(name) = pending.pop() {
+        if !reached.insert(name) {
+            continue;
+        }
+        if let Some((parent, rollup)) = refs.get(&name) {
+            pending.extend(parent.iter().chain(rollup.iter()).copied());
+        }
+    }
+
+    reached
+}
+
+/// Find the layers of a store no label or pin reaches, every reference to
+/// a layer the store lacks, the layer files that can't be read, and how
+/// much disk each label's chain takes. Only the header and the parent and
+/// rollup segments of each layer are read.
+pub async fn garbage_report(store: &Path) -> io::Result<GarbageReport> {
+    let layers = list_layers(store).await?;
+    let sizes: BTreeMap<_, _> = layers
+        .iter()
+        .map(|(name, path)| {
+            let size = std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+            (*name, size)
+        })
+        .collect();
+    let mut refs = BTreeMap::new();
+    let mut broken = Vec::new();
+    for (name, path) in layers.iter() {
+        match references(path).await {
+            Ok(references) => {
+                refs.insert(*name, references);
+            }
+            Err(e) => broken.push(BrokenLayer {
+                name: *name,
+                path: path.clone(),
+                reason: e.to_string(),
+            }),
+        }
+    }
+
+    let mut dangling = Vec::new();
+    let mut roots = Vec::new();
+    let mut usage = Vec::new();
+    for label in list_labels(store).await? {
+        let head = match read_label(store, &label).await {
+            Ok(Some(head)) => head,
+            Ok(None) => continue,
+            Err(e) => {
+                eprintln!("{label}: {e}");
+                continue;
+            }
+        };
+        if !sizes.contains_key(&head) {
+            dangling.push(Dangling {
+                from: format!("label {label}"),
+                kind: "head",
+                missing: head,
+            });
+        }
+        let chain = reach([head], &refs);
+        usage.push(DatabaseUsage {
+            database: decode_label(&label).path(),
+            label,
+            layers: chain.len(),
+            size: chain.iter().filter_map(|name| sizes.get(name)).sum(),
+        });
+        roots.push(head);
+    }
+    for name in pins::load(store).await?.into_keys() {
+        if !sizes.contains_key(&name) {
+            dangling.push(Dangling {
+                from: "pins".to_string(),
+                kind: "pin",
+                missing: name,
+            });
+        }
+        roots.push(name);
+    }
+    for (name, (parent, rollup)) in refs.iter() {
+        for (kind, target) in [("parent", parent), ("rollup", rollup)] {
+            if let Some(target) = target.filter(|t| !sizes.contains_key(t)) {
+                dangling.push(Dangling {
+                    from: format!("layer {}", name_to_string(*name)),
+                    kind,
+                    missing: target,
+                });
+            }
+        }
+    }
+
+    let reachable = reach(roots, &refs);
+    let unreachable = layers
+        .iter()
+        .filter(|(name, _)| !reachable.contains(name))
+        .map(|(name, path)| (*name, path.clone(), sizes[name]))
+        .collect();
+    let broken_reachable = broken
+        .iter()
+        .filter(|b| reachable.contains(&b.name))
+        .count();
+
+    Ok(GarbageReport {
+        layers: layers.len(),
+        size: sizes.values().sum(),
+        unreachable,
+        dangling,
+        broken,
+        broken_reachable,
+        usage,
+    })
+}
+
+pub fn print_report(report: &GarbageReport, format: OutputFormat) {
+    let line = |kind: &str, text: String, color: Color| match format {
+        OutputFormat::Pretty => println!("{}  {text}", paint(&format!("{kind:<11}"), color)),
+        _ => println!("{kind} {text}"),
+    };
+    for (name, path, size) in report.unreachable.iter() {
+        let name = name_to_string(*name);
+        match format {
+            OutputFormat::Ndjson => println!(
+                "{}",
+                ndjson(json!({
+                    "kind": "unreachable",
+                    "layer": name,
+                    "path": path.display().to_string(),
+                    "size": size,
+                }))
+            ),
+            _ => line(
+                "unreachable",
+                format!("{name} {} {}", human_bytes(*size as usize), path.display()),
+                Color::Yellow,
+            ),
+        }
+    }
+    for dangling in report.dangling.iter() {
+        let missing = name_to_string(dangling.missing);
+        match format {
+            OutputFormat::Ndjson => println!(
+                "{}",
+                ndjson(json!({
+                    "kind": "dangling",
+                    "from": dangling.from,
+                    "reference": dangling.kind,
+                    "missing": missing,
+                }))
+            ),
+            _ => line(
+                "dangling",
+                format!("{} {} {missing} is missing", dangling.from, dangling.kind),
+                Color::Red,
+            ),
+        }
+    }
+    for broken in report.broken.iter() {
+        let name = name_to_string(broken.name);
+        match format {
+            OutputFormat::Ndjson => println!(
+                "{}",
+                ndjson(json!({
+                    "kind": "broken",
+                    "layer": name,
+                    "path": broken.path.display().to_string(),
+                    "reason": broken.reason,
+                }))
+            ),
+            _ => line("broken", format!("{name}: {}", broken.reason), Color::Red),
+        }
+    }
+    for usage in report.usage.iter() {
+        match format {
+            OutputFormat::Ndjson => println!(
+                "{}",
+                ndjson(json!({
+                    "kind": "database",
+                    "label": usage.label,
+                    "database": usage.database,
+                    "layers": usage.layers,
+                    "size": usage.size,
+                }))
+            ),
+            _ => line(
+                "database",
+                format!(
+                    "{} {} layers {}",
+                    usage.database.as_deref().unwrap_or(&usage.label),
+                    usage.layers,
+                    human_bytes(usage.size as usize)
+                ),
+                Color::Dim,
+            ),
+        }
+    }
+
+    let garbage: u64 = report.unreachable.iter().map(|(_, _, size)| size).sum();
+    match format {
+        OutputFormat::Ndjson => println!(
+            "{}",
+            ndjson(json!({
+                "kind": "summary",
+                "layers": report.layers,
+                "size": report.size,
+                "unreachable": report.unreachable.len(),
+                "unreachable_size": garbage,
+                "dangling": report.dangling.len(),
+                "broken": report.broken.len(),
+            }))
+        ),
+        _ => println!(
+            "{} layers ({}), {} unreachable ({}), {} dangling references, {} broken",
+            report.layers,
+            human_bytes(report.size as usize),
+            report.unreachable.len(),
+            human_bytes(garbage as usize),
+            report.dangling.len(),
+            report.broken.len()
+        ),
+    }
+}
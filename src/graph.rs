@@ -0,0 +1,134 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use clap::ValueEnum;
+use terminus_store::{layer::ObjectType, store::sync::SyncStoreLayer, Layer};
+
+/// Which degree to rank nodes by.
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum DegreeMetric {
+    In,
+    Out,
+    Total,
+}
+
+/// How often a node appears as subject (out) and as object (in).
+#[derive(Default, Clone, Copy)]
+pub struct Degree {
+    pub in_degree: u64,
+    pub out_degree: u64,
+}
+
+impl Degree {
+    pub fn get(&self, metric: DegreeMetric) -> u64 {
+        match metric {
+            DegreeMetric::In => self.in_degree,
+            DegreeMetric::Out => self.out_degree,
+            DegreeMetric::Total => self.in_degree + self.out_degree,
+        }
+    }
+}
+
+/// Whether an object id refers to a node rather than a value.
+pub fn is_node(layer: &SyncStoreLayer, id: u64) -> bool {
+    matches!(layer.id_object(id), Some(ObjectType::Node(_)))
+}
+
+/// Count the triples each node takes part in. Subjects and node objects
+/// share ids, so a node's in and out degree end up in the same entry.
+pub fn degrees(layer: &SyncStoreLayer) -> HashMap<u64, Degree> {
+    let mut result: HashMap<u64, Degree> = HashMap::new();
+    let mut objects: HashMap<u64, u64> = HashMap::new();
+    for triple in layer.triples() {
+        result.entry(triple.subject).or_default().out_degree += 1;
+        *objects.entry(triple.object).or_default() += 1;
+    }
+    for (object, count) in objects {
+        if is_node(layer, object) {
+            result.entry(object).or_default().in_degree += count;
+        }
+    }
+
+    result
+}
+
+/// The `top` nodes with the highest degree, ties broken by id.
+pub fn top_degrees(layer: &SyncStoreLayer, metric: DegreeMetric, top: usize) -> Vec<(u64, Degree)> {
+    let mut result: Vec<_> = degrees(layer).into_iter().collect();
+    result.sort_by_key(|(id, degree)| (std::cmp::Reverse(degree.get(metric)), *id));
+    result.truncate(top);
+    result
+}
+
+/// The `top` nodes with the highest degree, as `top_degrees` ranks them,
+/// with their names.
+pub fn central_nodes(
+    layer: &SyncStoreLayer,
+    metric: DegreeMetric,
+    top: usize,
+) -> Vec<(u64, String, Degree)> {
+    top_degrees(layer, metric, top)
+        .into_iter()
+        .map(|(id, degree)| {
+            let node = layer.id_subject(id).unwrap_or_else(|| id.to_string());
+            (id, node, degree)
+        })
+        .collect()
+}
+
+/// All distinct subject-object pairs connected by some triple whose object
+/// is a node, in id order.
+pub fn edges(layer: &SyncStoreLayer) -> BTreeSet<(u64, u64)> {
+    let mut nodes: HashMap<u64, bool> = HashMap::new();
+    layer
+        .triples()
+        .filter(|triple| {
+            *nodes
+                .entry(triple.object)
+                .or_insert_with(|| is_node(layer, triple.object))
+        })
+        .map(|triple| (triple.subject, triple.object))
+        .collect()
+}
+
+/// For every pair of predicates, the number of subjects that use both. A
+/// predicate paired with itself counts the subjects using it at all.
+pub fn predicate_cooccurrence(layer: &SyncStoreLayer) -> BTreeMap<(u64, u64), u64> {
+    let mut result = BTreeMap::new();
+    let mut count_subject = |predicates: &BTreeSet<u64>| {
+        for a in predicates.iter() {
+            for b in predicates.range(a..) {
+                *result.entry((*a, *b)).or_default() += 1;
+            }
+        }
+    };
+    // triples come sorted by subject
+    let mut current = None;
+    let mut predicates = BTreeSet::new();
+    for triple in layer.triples() {
+        if current != Some(triple.subject) {
+            count_subject(&predicates);
+            predicates.clear();
+            current = Some(triple.subject);
+        }
+        predicates.insert(triple.predicate);
+    }
+    count_subject(&predicates);
+
+    result
+}
+
+/// Node objects that are never used as a subject, with the number of
+/// triples referring to each, in id order.
+pub fn dangling_objects(layer: &SyncStoreLayer) -> Vec<(u64, u64)> {
+    let mut subjects = BTreeSet::new();
+    let mut objects: BTreeMap<u64, u64> = BTreeMap::new();
+    for triple in layer.triples() {
+        subjects.insert(triple.subject);
+        *objects.entry(triple.object).or_default() += 1;
+    }
+
+    objects
+        .into_iter()
+        .filter(|(object, _)| !subjects.contains(object) && is_node(layer, *object))
+        .collect()
+}
@@ -0,0 +1,86 @@
+use std::{io, ops::Range, path::Path};
+
+use terminus_store::storage::{
+    archive::ArchiveHeader,
+    consts::{LayerFileEnum, FILENAME_ENUM_MAP},
+};
+use tokio::io::{AsyncRead, AsyncSeek, AsyncSeekExt};
+
+use crate::archive::all_segment_types;
+
+/// Where one segment lies in an archive.
+pub struct SegmentInfo {
+    pub segment: LayerFileEnum,
+    /// Range relative to the end of the header.
+    pub range: Range<usize>,
+}
+
+impl SegmentInfo {
+    pub fn name(&self) -> String {
+        format!("{:?}", self.segment)
+    }
+}
+
+/// The segments an archive header lists, in header order.
+pub struct HeaderReport {
+    /// Size in bytes of the header itself.
+    pub header_len: usize,
+    pub segments: Vec<SegmentInfo>,
+}
+
+impl HeaderReport {
+    /// Read only the header of a layer file, leaving the segments unread.
+    pub async fn read<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::from_reader(&mut tokio::fs::File::open(path).await?).await
+    }
+
+    /// Parse the header at the start of a reader, leaving it positioned at
+    /// the first segment.
+    pub async fn from_reader<R: AsyncRead + AsyncSeek + Unpin>(reader: &mut R) -> io::Result<Self> {
+        let header = ArchiveHeader::parse_from_reader(reader).await?;
+        let header_len = reader.stream_position().await? as usize;
+        let segments = all_segment_types()
+            .filter_map(|segment| {
+                header
+                    .range_for(segment)
+                    .map(|range| SegmentInfo { segment, range })
+            })
+            .collect();
+
+        Ok(Self {
+            header_len,
+            segments,
+        })
+    }
+
+    /// Total size of the segments, not counting the header.
+    pub fn total(&self) -> usize {
+        self.segments.iter().map(|s| s.range.len()).sum()
+    }
+}
+
+/// Look up a segment by its file name in a layer directory, such as
+/// `node_dictionary_blocks`, or by its name in a header, such as
+/// `NodeDictionaryBlocks`.
+pub fn parse_segment(name: &str) -> io::Result<LayerFileEnum> {
+    match FILENAME_ENUM_MAP.get(name) {
+        Some(file_type) => Ok(*file_type),
+        None => all_segment_types()
+            .find(|t| format!("{t:?}").eq_ignore_ascii_case(name))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("unknown segment {name}"),
+                )
+            }),
+    }
+}
+
+/// The file name of a segment in a layer directory, as `extract` and
+/// `inject` name segments.
+pub fn segment_file_name(segment: LayerFileEnum) -> Option<&'static str> {
+    FILENAME_ENUM_MAP
+        .iter()
+        .find(|(_, t)| **t == segment)
+        .map(|(name, _)| *name)
+}
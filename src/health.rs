@@ -0,0 +1,121 @@
+use std::{collections::BTreeMap, path::Path};
+
+use crate::{checks, codes::Severity, validate::Finding};
+
+/// Accumulates the findings of a store-wide check into a single 0-100
+/// score that can be tracked over time.
+///
+/// Each layer loses health by the weight of its findings' severities, up
+/// to all of it, and the store's health is the average over its layers.
+/// A run with only some of the available checks, or one stopped before
+/// it reached every layer, can't show full health: the score is scaled to
+/// between 80% and 100% by the fraction of checks that ran times the
+/// fraction of layers that were reached.
+pub struct Health {
+    checks_run: usize,
+    layers: usize,
+    /// Layers the run stopped before reaching.
+    unreached: usize,
+    penalty: f64,
+    counts: BTreeMap<Severity, usize>,
+}
+
+impl Health {
+    pub fn new(checks_run: usize) -> Self {
+        Self {
+            checks_run,
+            layers: 0,
+            unreached: 0,
+            penalty: 0.0,
+            counts: BTreeMap::new(),
+        }
+    }
+
+    /// Record the findings of one layer, which may be none.
+    pub fn record(&mut self, findings: &[Finding]) {
+        self.layers += 1;
+        let mut penalty = 0.0;
+        for finding in findings {
+            let severity = finding.code.severity();
+            penalty += severity.weight();
+            *self.counts.entry(severity).or_default() += 1;
+        }
+        self.penalty += f64::min(penalty, 1.0);
+    }
+
+    /// Record layers that were never checked, as the run stopped first.
+    pub fn record_unreached(&mut self, layers: usize) {
+        self.unreached += layers;
+    }
+
+    fn coverage(&self) -> f64 {
+        let available = checks::available().len();
+        let checks = if available == 0 {
+            1.0
+        } else {
+            f64::min(self.checks_run as f64 / available as f64, 1.0)
+        };
+        let total = self.layers + self.unreached;
+        let layers = if total == 0 {
+            1.0
+        } else {
+            self.layers as f64 / total as f64
+        };
+        checks * layers
+    }
+
+    pub fn score(&self) -> f64 {
+        let healthy = if self.layers == 0 {
+            1.0
+        } else {
+            1.0 - self.penalty / self.layers as f64
+        };
+        100.0 * healthy * (0.8 + 0.2 * self.coverage())
+    }
+
+    /// The number of findings of each severity.
+    pub fn counts(&self) -> BTreeMap<&'static str, usize> {
+        Severity::ALL
+            .into_iter()
+            .map(|s| (s.name(), self.counts.get(&s).copied().unwrap_or(0)))
+            .collect()
+    }
+
+    /// The score and finding counts as Prometheus metrics.
+    pub fn prometheus(&self, store: &Path) -> String {
+        let store = store
+            .display()
+            .to_string()
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"");
+        let mut out = String::new();
+        out.push_str("# HELP surgery_store_health Severity-weighted health of the store, 0-100.\n");
+        out.push_str("# TYPE surgery_store_health gauge\n");
+        out.push_str(&format!(
+            "surgery_store_health{{store=\"{store}\"}} {:.2}\n",
+            self.score()
+        ));
+        out.push_str("# HELP surgery_layers_checked Layers covered by the last fsck.\n");
+        out.push_str("# TYPE surgery_layers_checked gauge\n");
+        out.push_str(&format!(
+            "surgery_layers_checked{{store=\"{store}\"}} {}\n",
+            self.layers
+        ));
+        out.push_str(
+            "# HELP surgery_layers_unreached Layers the last fsck stopped before checking.\n",
+        );
+        out.push_str("# TYPE surgery_layers_unreached gauge\n");
+        out.push_str(&format!(
+            "surgery_layers_unreached{{store=\"{store}\"}} {}\n",
+            self.unreached
+        ));
+        out.push_str("# HELP surgery_findings Findings of the last fsck by severity.\n");
+        out.push_str("# TYPE surgery_findings gauge\n");
+        for (severity, count) in self.counts() {
+            out.push_str(&format!(
+                "surgery_findings{{store=\"{store}\",severity=\"{severity}\"}} {count}\n"
+            ));
+        }
+        out
+    }
+}
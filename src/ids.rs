@@ -0,0 +1,302 @@
+use std::{io, ops::Add, path::Path};
+
+use terminus_store::{
+    layer::ObjectType,
+    storage::{consts::LayerFileEnum, name_to_string, string_to_name},
+    store::sync::{open_sync_archive_store, SyncStoreLayer},
+    structure::LogArray,
+    Layer,
+};
+
+use crate::{
+    archive::Archive,
+    dict::{count_entries, entry, DictType},
+    store::chain,
+};
+
+/// Sizes of the two id spaces of a layer. Nodes and values share one id
+/// space, with values numbered after nodes.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct IdCounts {
+    pub nodes_values: u64,
+    pub predicates: u64,
+}
+
+impl Add for IdCounts {
+    type Output = IdCounts;
+
+    fn add(self, other: IdCounts) -> IdCounts {
+        IdCounts {
+            nodes_values: self.nodes_values + other.nodes_values,
+            predicates: self.predicates + other.predicates,
+        }
+    }
+}
+
+/// The number of ids a layer's own dictionaries introduce.
+pub async fn dict_counts(archive: &Archive) -> io::Result<IdCounts> {
+    Ok(IdCounts {
+        nodes_values: count_entries(archive, DictType::Nodes).await?
+            + count_entries(archive, DictType::Values).await?,
+        predicates: count_entries(archive, DictType::Predicates).await?,
+    })
+}
+
+/// The number of ids in use at a layer, counting the dictionaries of the
+/// layer and all its ancestors.
+pub async fn cumulative_counts(store: &Path, layer: [u32; 5]) -> io::Result<IdCounts> {
+    let mut total = IdCounts::default();
+    for (_, path) in chain(store, layer).await? {
+        total = total + dict_counts(&Archive::open(&path).await?).await?;
+    }
+
+    Ok(total)
+}
+
+fn max_entry(archive: &Archive, file_type: LayerFileEnum) -> io::Result<u64> {
+    match archive.segment(file_type)? {
+        None => Ok(0),
+        Some(bytes) => {
+            let logarray = LogArray::parse(bytes)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            Ok(logarray.iter().max().unwrap_or(0))
+        }
+    }
+}
+
+/// The highest node/value and predicate ids referenced by a layer's triples.
+pub fn max_referenced_ids(archive: &Archive) -> io::Result<IdCounts> {
+    let mut nodes_values = 0;
+    for file_type in [
+        LayerFileEnum::PosSubjects,
+        LayerFileEnum::PosObjects,
+        LayerFileEnum::NegSubjects,
+        LayerFileEnum::NegObjects,
+        LayerFileEnum::PosSpOAdjacencyListNums,
+        LayerFileEnum::NegSpOAdjacencyListNums,
+    ] {
+        nodes_values = nodes_values.max(max_entry(archive, file_type)?);
+    }
+    let predicates = max_entry(archive, LayerFileEnum::PosSPAdjacencyListNums)?
+        .max(max_entry(archive, LayerFileEnum::NegSPAdjacencyListNums)?);
+
+    Ok(IdCounts {
+        nodes_values,
+        predicates,
+    })
+}
+
+/// Check that the first entry of each of a layer's dictionaries is what
+/// the store finds at the id after those of the layer's ancestors, which
+/// is where the layer's own ids must start. Returns a description of the
+/// first dictionary that doesn't line up.
+async fn first_id_mismatch(
+    archive: &Archive,
+    layer: &SyncStoreLayer,
+    parent: IdCounts,
+) -> io::Result<Option<String>> {
+    let node_id = parent.nodes_values + 1;
+    if let Some((_, node)) = entry(archive, DictType::Nodes, 1).await? {
+        let node = String::from_utf8_lossy(&node).to_string();
+        let found = layer.id_subject(node_id);
+        if found.as_ref() != Some(&node) {
+            return Ok(Some(format!(
+                "first node {node:?} should have id {node_id}, but the store has {found:?} there"
+            )));
+        }
+    }
+    let predicate_id = parent.predicates + 1;
+    if let Some((_, predicate)) = entry(archive, DictType::Predicates, 1).await? {
+        let predicate = String::from_utf8_lossy(&predicate).to_string();
+        let found = layer.id_predicate(predicate_id);
+        if found.as_ref() != Some(&predicate) {
+            return Ok(Some(format!(
+                "first predicate {predicate:?} should have id {predicate_id}, but the store has {found:?} there"
+            )));
+        }
+    }
+    let value_id = node_id + count_entries(archive, DictType::Nodes).await?;
+    if let Some((datatype, value)) = entry(archive, DictType::Values, 1).await? {
+        let found = match layer.id_object(value_id) {
+            Some(ObjectType::Value(found)) => Some(found),
+            _ => None,
+        };
+        let matches = match &found {
+            Some(found) => {
+                found.to_bytes() == value && datatype.map(|d| found.datatype() == d).unwrap_or(true)
+            }
+            None => false,
+        };
+        if !matches {
+            return Ok(Some(format!(
+                "first value {value:?} should have id {value_id}, but the store has {:?} there",
+                found.map(|found| found.to_bytes())
+            )));
+        }
+    }
+
+    Ok(None)
+}
+
+/// How a layer of a chain fared in `check_chain`.
+pub struct ChainLayer {
+    pub name: [u32; 5],
+    /// Ids interned by the layer and its ancestors.
+    pub total: IdCounts,
+    /// Why the id arithmetic breaks at this layer, if it does.
+    pub broken: Option<String>,
+}
+
+/// Walk a chain from its base layer up, checking that no layer refers to
+/// ids beyond what it and its ancestors have interned, and that each
+/// layer's own ids start right after its ancestors'. Stops at the first
+/// layer where the arithmetic breaks, which is then the last one listed.
+pub async fn check_chain(store: &Path, head: [u32; 5]) -> io::Result<Vec<ChainLayer>> {
+    let mut layers = chain(store, head).await?;
+    layers.reverse();
+
+    let sync_store = open_sync_archive_store(store, 512);
+    let mut total = IdCounts::default();
+    let mut checked = Vec::new();
+    for (name, path) in layers {
+        let archive = Archive::open(&path).await?;
+        let parent = total;
+        total = total + dict_counts(&archive).await?;
+        let referenced = max_referenced_ids(&archive)?;
+        let layer = sync_store.get_layer_from_id(name)?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("layer {} not found", name_to_string(name)),
+            )
+        })?;
+        let broken = if referenced.nodes_values > total.nodes_values
+            || referenced.predicates > total.predicates
+        {
+            Some(format!(
+                "refers to node/value id {} and predicate id {}, but only {} and {} exist",
+                referenced.nodes_values,
+                referenced.predicates,
+                total.nodes_values,
+                total.predicates
+            ))
+        } else {
+            first_id_mismatch(&archive, &layer, parent).await?
+        };
+        let stop = broken.is_some();
+        checked.push(ChainLayer {
+            name,
+            total,
+            broken,
+        });
+        if stop {
+            break;
+        }
+    }
+
+    Ok(checked)
+}
+
+/// The most violations listed in full by `check_object_ids`.
+const MAX_LISTED_VIOLATIONS: usize = 20;
+
+/// Check that an objects logarray is strictly increasing (so sorted and
+/// free of duplicates), contains no zero id and stays within `max_id` when
+/// known.
+pub fn check_object_ids(objects: &LogArray, max_id: Option<u64>) -> Result<(), String> {
+    let mut violations = Vec::new();
+    let mut previous = 0;
+    for (ix, id) in objects.iter().enumerate() {
+        if id == 0 {
+            violations.push(format!("entry {ix}: id 0 is not a valid object id"));
+        } else if ix != 0 && id == previous {
+            violations.push(format!("entry {ix}: duplicate id {id}"));
+        } else if ix != 0 && id < previous {
+            violations.push(format!("entry {ix}: id {id} follows larger id {previous}"));
+        }
+        if let Some(max_id) = max_id {
+            if id > max_id {
+                violations.push(format!(
+                    "entry {ix}: id {id} exceeds the highest object id {max_id}"
+                ));
+            }
+        }
+        previous = id;
+    }
+
+    if violations.is_empty() {
+        return Ok(());
+    }
+    let count = violations.len();
+    violations.truncate(MAX_LISTED_VIOLATIONS);
+    let mut message = format!("objects file has {count} violations:\n");
+    message.push_str(&violations.join("\n"));
+    if count > MAX_LISTED_VIOLATIONS {
+        message.push_str(&format!("\n... and {} more", count - MAX_LISTED_VIOLATIONS));
+    }
+    Err(message)
+}
+
+/// What an id of one layer is in another.
+pub struct IdMapping {
+    /// node, value or predicate.
+    pub kind: &'static str,
+    pub id: u64,
+    /// The id of the same term in the other layer, if it has the term.
+    pub other: Option<u64>,
+}
+
+/// Map every node, value and predicate id of `a` to the id its term has in
+/// `b`.
+pub fn map_ids(a: &SyncStoreLayer, b: &SyncStoreLayer) -> Vec<IdMapping> {
+    let mut result = Vec::new();
+    for id in 1..=a.node_and_value_count() as u64 {
+        let (kind, other) = match a.id_object(id) {
+            Some(ObjectType::Node(node)) => ("node", b.object_node_id(&node)),
+            Some(ObjectType::Value(value)) => ("value", b.object_value_id(&value)),
+            None => continue,
+        };
+        result.push(IdMapping { kind, id, other });
+    }
+    for id in 1..=a.predicate_count() as u64 {
+        if let Some(predicate) = a.id_predicate(id) {
+            result.push(IdMapping {
+                kind: "predicate",
+                id,
+                other: b.predicate_id(&predicate),
+            });
+        }
+    }
+
+    result
+}
+
+/// Open the layer a layer file holds through the store the file lies in,
+/// with the id its first value has there, so that the values of the file
+/// can be decoded with their datatypes.
+pub async fn open_file_layer(file_name: &Path) -> io::Result<(SyncStoreLayer, u64)> {
+    let not_in_store = || {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "{} does not lie in a store; give --raw to print its values undecoded",
+                file_name.display()
+            ),
+        )
+    };
+    let store = file_name
+        .parent()
+        .and_then(Path::parent)
+        .ok_or_else(not_in_store)?;
+    let name = file_name
+        .file_stem()
+        .and_then(|stem| string_to_name(&stem.to_string_lossy()).ok())
+        .ok_or_else(not_in_store)?;
+    let layer = open_sync_archive_store(store, 512)
+        .get_layer_from_id(name)?
+        .ok_or_else(not_in_store)?;
+    let archive = Archive::open(file_name).await?;
+    let counts = cumulative_counts(store, name).await?;
+    let values = count_entries(&archive, DictType::Values).await?;
+
+    Ok((layer, counts.nodes_values - values))
+}
@@ -0,0 +1,186 @@
+use std::{io, path::Path};
+
+use terminus_store::{
+    layer::builder::{self, build_object_index_from_direct_files},
+    storage::consts::LayerFileEnum,
+    storage::{directory::FileBackedStore, AdjacencyListFiles, BitIndexFiles, FileLoad, FileStore},
+    structure::{bitarray::BitArray, bitindex::build_bitindex, LogArray},
+};
+
+use crate::{
+    archive::{absent_segment, bitindex_segments, Archive},
+    atomic, ids,
+};
+
+/// Build the o_ps adjacency list of a layer from its sp_o adjacency list,
+/// into `o_ps_dir`. With an objects file, its ids are checked against
+/// `max_object_id` first.
+pub async fn build_object_index(
+    sp_o_nums_file: &Path,
+    sp_o_bits_file: &Path,
+    o_ps_dir: &Path,
+    objects_file: Option<&Path>,
+    max_object_id: Option<u64>,
+) -> io::Result<()> {
+    if let Some(objects_file) = objects_file {
+        let contents = tokio::fs::read(objects_file).await?;
+        let objects = LogArray::parse(contents.into())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        ids::check_object_ids(&objects, max_object_id)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    }
+
+    tokio::fs::create_dir_all(o_ps_dir).await?;
+
+    let sp_o_nums_file = FileBackedStore::new(sp_o_nums_file);
+    let sp_o_bits_file = FileBackedStore::new(sp_o_bits_file);
+    let objects_file = objects_file.map(FileBackedStore::new);
+
+    let o_ps_nums_path = o_ps_dir.join("nums");
+    let o_ps_bits_path = o_ps_dir.join("bits");
+    let o_ps_bit_index_blocks_path = o_ps_dir.join("bit_index_blocks");
+    let o_ps_bit_index_sblocks_path = o_ps_dir.join("bit_index_sblocks");
+
+    // the index is built into temporary files, renamed into place once
+    // it's complete
+    let o_ps_nums_file = FileBackedStore::new(atomic::temp_path(&o_ps_nums_path));
+    let o_ps_bits_file = FileBackedStore::new(atomic::temp_path(&o_ps_bits_path));
+    let o_ps_blocks_file = FileBackedStore::new(atomic::temp_path(&o_ps_bit_index_blocks_path));
+    let o_ps_sblocks_file = FileBackedStore::new(atomic::temp_path(&o_ps_bit_index_sblocks_path));
+    let o_ps_files = AdjacencyListFiles {
+        bitindex_files: BitIndexFiles {
+            bits_file: o_ps_bits_file,
+            blocks_file: o_ps_blocks_file,
+            sblocks_file: o_ps_sblocks_file,
+        },
+        nums_file: o_ps_nums_file,
+    };
+
+    let result = build_object_index_from_direct_files(
+        sp_o_nums_file,
+        sp_o_bits_file,
+        o_ps_files,
+        objects_file,
+    )
+    .await;
+    atomic::finish(
+        &[
+            o_ps_nums_path,
+            o_ps_bits_path,
+            o_ps_bit_index_blocks_path,
+            o_ps_bit_index_sblocks_path,
+        ],
+        result,
+    )
+    .await
+}
+
+/// Build the predicate wavelet tree of a layer from its s_p adjacency
+/// list nums, into `predicate_index_dir`.
+pub async fn build_predicate_index(
+    s_p_nums_file: &Path,
+    predicate_index_dir: &Path,
+) -> io::Result<()> {
+    tokio::fs::create_dir_all(predicate_index_dir).await?;
+
+    let s_p_nums_file = FileBackedStore::new(s_p_nums_file);
+    let wavelet_bits_path = predicate_index_dir.join("bits");
+    let wavelet_blocks_path = predicate_index_dir.join("blocks");
+    let wavelet_sblocks_path = predicate_index_dir.join("sblocks");
+
+    let wavelet_bits = FileBackedStore::new(atomic::temp_path(&wavelet_bits_path));
+    let wavelet_blocks = FileBackedStore::new(atomic::temp_path(&wavelet_blocks_path));
+    let wavelet_sblocks = FileBackedStore::new(atomic::temp_path(&wavelet_sblocks_path));
+
+    let result = builder::build_predicate_index(
+        s_p_nums_file,
+        wavelet_bits,
+        wavelet_blocks,
+        wavelet_sblocks,
+    )
+    .await;
+    atomic::finish(
+        &[wavelet_bits_path, wavelet_blocks_path, wavelet_sblocks_path],
+        result,
+    )
+    .await
+}
+
+/// Build the bitindex over a layer's s_p adjacency list bits, into
+/// `subject_index_dir`, after checking the bits against the nums.
+pub async fn build_subject_index(
+    s_p_nums_file: &Path,
+    s_p_bits_file: &Path,
+    subject_index_dir: &Path,
+) -> io::Result<()> {
+    // every num of an adjacency list has a bit marking whether it ends its group
+    let nums = LogArray::parse(tokio::fs::read(s_p_nums_file).await?.into())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let bits = BitArray::from_bits(tokio::fs::read(s_p_bits_file).await?.into())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    if nums.len() != bits.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "s_p nums has {} entries but s_p bits has {} bits",
+                nums.len(),
+                bits.len()
+            ),
+        ));
+    }
+
+    rebuild_bitindex(s_p_bits_file, subject_index_dir).await
+}
+
+/// Build the blocks and sblocks of a bitindex from its bits file, into
+/// `bit_index_blocks` and `bit_index_sblocks` in `output_dir`.
+pub async fn rebuild_bitindex(bits_file: &Path, output_dir: &Path) -> io::Result<()> {
+    BitArray::from_bits(tokio::fs::read(bits_file).await?.into())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    tokio::fs::create_dir_all(output_dir).await?;
+
+    let bits_file = FileBackedStore::new(bits_file);
+    let blocks_path = output_dir.join("bit_index_blocks");
+    let sblocks_path = output_dir.join("bit_index_sblocks");
+
+    let blocks_file = FileBackedStore::new(atomic::temp_path(&blocks_path));
+    let sblocks_file = FileBackedStore::new(atomic::temp_path(&sblocks_path));
+
+    let result = async {
+        build_bitindex(
+            bits_file.open_read().await?,
+            blocks_file.open_write().await?,
+            sblocks_file.open_write().await?,
+        )
+        .await
+    }
+    .await;
+    atomic::finish(&[blocks_path, sblocks_path], result).await
+}
+
+/// Rebuild a bitindex of a layer archive from its bits segment, which is
+/// also written to `bits` in `output_dir`. Returns the blocks and sblocks
+/// segments the results replace.
+pub async fn rebuild_archive_bitindex(
+    archive: &Archive,
+    bits: LayerFileEnum,
+    output_dir: &Path,
+) -> io::Result<(LayerFileEnum, LayerFileEnum)> {
+    let (_, blocks, sblocks) = bitindex_segments()
+        .into_iter()
+        .find(|(b, _, _)| *b == bits)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{bits:?} is not the bits of a bitindex"),
+            )
+        })?;
+    let contents = archive.segment(bits)?.ok_or_else(|| absent_segment(bits))?;
+    tokio::fs::create_dir_all(output_dir).await?;
+    let bits_file = output_dir.join("bits");
+    atomic::write(&bits_file, contents).await?;
+    rebuild_bitindex(&bits_file, output_dir).await?;
+
+    Ok((blocks, sblocks))
+}
@@ -0,0 +1,46 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use terminus_store::store::sync::open_sync_archive_store;
+
+use crate::{atomic, audit::Audit, store::label_path};
+
+/// The storage version TerminusDB expects to find in a store it opens.
+pub const STORAGE_VERSION: &str = "2";
+
+fn storage_version_path(store: &Path) -> PathBuf {
+    let mut path = store.to_path_buf();
+    path.push("STORAGE_VERSION");
+    path
+}
+
+/// Create an empty directory archive store, optionally with labels that
+/// don't point at any layer yet. The directory may exist but must be
+/// empty, apart from this tool's own `.surgery` directory.
+pub async fn init(store: &Path, labels: &[String]) -> io::Result<()> {
+    if let Ok(mut entries) = tokio::fs::read_dir(store).await {
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_name() != ".surgery" {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} is not empty", store.display()),
+                ));
+            }
+        }
+    }
+    tokio::fs::create_dir_all(store).await?;
+
+    let mut audit = Audit::begin("init");
+    audit.track(&storage_version_path(store)).await?;
+    for label in labels {
+        audit.track(&label_path(store, label)).await?;
+    }
+    atomic::write(storage_version_path(store), format!("{STORAGE_VERSION}\n")).await?;
+    let sync_store = open_sync_archive_store(store, 512);
+    for label in labels {
+        sync_store.create(label)?;
+    }
+    audit.commit(store).await
+}
@@ -0,0 +1,87 @@
+use std::io;
+
+use bytes::Bytes;
+use terminus_store::{
+    storage::consts::LayerFileEnum,
+    structure::{bitarray::BitArray, LogArray},
+};
+
+use crate::{
+    archive::{all_segment_types, encode_header, parse_layer_name, Archive},
+    checksum::ChecksumTrailer,
+    validate::{segment_kind, SegmentKind},
+};
+
+/// Check that a replacement parses as the kind of segment it replaces.
+/// Dictionary blocks are only checked by verify, after injection.
+fn check_replacement(file_type: LayerFileEnum, contents: &Bytes) -> io::Result<()> {
+    let error = match segment_kind(file_type) {
+        SegmentKind::LogArray => LogArray::parse(contents.clone())
+            .err()
+            .map(|e| e.to_string()),
+        SegmentKind::BitArray => BitArray::from_bits(contents.clone())
+            .err()
+            .map(|e| e.to_string()),
+        SegmentKind::Parent => parse_layer_name(contents).err().map(|e| e.to_string()),
+        SegmentKind::DictBlocks | SegmentKind::Rollup => None,
+    };
+    match error {
+        Some(e) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("replacement for {file_type:?} is invalid: {e}"),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Rewrite an archive with the contents of one segment replaced, or added
+/// if the header doesn't list it yet. The header is rebuilt for the new
+/// sizes and every other segment is copied unchanged. A checksum trailer
+/// is recomputed; any other bytes after the last segment are dropped. The
+/// new archive is parsed back and every segment compared against the
+/// original and the replacement before it is returned.
+pub async fn inject(
+    archive: &Archive,
+    file_type: LayerFileEnum,
+    replacement: Bytes,
+) -> io::Result<Bytes> {
+    check_replacement(file_type, &replacement)?;
+    let present: Vec<_> = archive.segments().into_iter().map(|(t, _)| t).collect();
+    let mut sizes = Vec::new();
+    let mut body = Vec::new();
+    for t in all_segment_types() {
+        let contents = if t == file_type {
+            replacement.clone()
+        } else if present.contains(&t) {
+            archive.segment(t)?.unwrap()
+        } else {
+            continue;
+        };
+        sizes.push((t, contents.len()));
+        body.extend_from_slice(&contents);
+    }
+
+    let mut result = encode_header(&sizes);
+    result.extend(body);
+    if matches!(ChecksumTrailer::read(archive), Ok(Some(_))) {
+        let injected = Archive::parse(result.clone().into()).await?;
+        result.extend(ChecksumTrailer::compute(&injected)?.to_bytes());
+    }
+
+    let injected = Archive::parse(result.clone().into()).await?;
+    for t in all_segment_types() {
+        let expected = if t == file_type {
+            Some(replacement.clone())
+        } else {
+            archive.segment(t)?
+        };
+        if injected.segment(t)? != expected {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("segment {t:?} doesn't read back the same from the injected archive"),
+            ));
+        }
+    }
+
+    Ok(result.into())
+}
@@ -0,0 +1,195 @@
+use std::{io, ops::Range};
+
+use terminus_store::storage::consts::LayerFileEnum;
+
+use crate::{
+    archive::{absent_segment, Archive},
+    output::{paint, Color},
+    validate::{segment_kind, SegmentKind},
+};
+
+/// Bytes shown per hexdump line.
+const LINE: usize = 16;
+
+/// What the control word at the end of a logarray or bitarray segment
+/// says, and what about it can't be right.
+pub struct ControlWord {
+    /// Absolute range of the control word in the archive.
+    pub range: Range<usize>,
+    pub description: String,
+    pub problems: Vec<String>,
+}
+
+/// Decode the control word of a logarray: a u32 entry count, a u8 width
+/// and three zero bytes, after the entries packed into 64-bit words.
+fn logarray_control(contents: &[u8]) -> (String, Vec<String>) {
+    let word = &contents[contents.len() - 8..];
+    let len = u32::from_be_bytes(word[..4].try_into().unwrap()) as u64;
+    let width = word[4] as u64;
+    let mut problems = Vec::new();
+    if width > 64 {
+        problems.push(format!("width {width} is more than 64 bits"));
+    }
+    if width == 0 && len > 0 {
+        problems.push(format!("width 0 for {len} entries"));
+    }
+    if word[5..] != [0, 0, 0] {
+        problems.push(format!("reserved bytes are {:02x?}, not zero", &word[5..]));
+    }
+    let expected = (len * width.min(64)).div_ceil(64) * 8 + 8;
+    if expected != contents.len() as u64 {
+        problems.push(format!(
+            "{len} entries of width {width} take {expected} bytes, but the segment has {}",
+            contents.len()
+        ));
+    }
+
+    (format!("{len} entries of width {width}"), problems)
+}
+
+/// Decode the control word of a bitarray: the u64 number of bits, after
+/// the bits packed into 64-bit words.
+fn bitarray_control(contents: &[u8]) -> (String, Vec<String>) {
+    let bits = u64::from_be_bytes(contents[contents.len() - 8..].try_into().unwrap());
+    let mut problems = Vec::new();
+    let expected = bits.div_ceil(64).saturating_mul(8).saturating_add(8);
+    if expected != contents.len() as u64 {
+        problems.push(format!(
+            "{bits} bits take {expected} bytes, but the segment has {}",
+            contents.len()
+        ));
+    }
+
+    (format!("{bits} bits"), problems)
+}
+
+/// The control word of a segment starting at absolute offset `start`, for
+/// segments that have one.
+pub fn control_word(
+    file_type: LayerFileEnum,
+    contents: &[u8],
+    start: usize,
+) -> Option<ControlWord> {
+    let decode = match segment_kind(file_type) {
+        SegmentKind::LogArray => logarray_control,
+        SegmentKind::BitArray => bitarray_control,
+        _ => return None,
+    };
+    let end = start + contents.len();
+    if contents.len() < 8 {
+        return Some(ControlWord {
+            range: start..end,
+            description: "no control word".to_string(),
+            problems: vec![format!(
+                "the segment has {} bytes, too few for an 8 byte control word",
+                contents.len()
+            )],
+        });
+    }
+    let (description, mut problems) = decode(contents);
+    if contents.len() % 8 != 0 {
+        problems.push(format!(
+            "the segment has {} bytes, not a whole number of 64-bit words",
+            contents.len()
+        ));
+    }
+
+    Some(ControlWord {
+        range: end - 8..end,
+        description,
+        problems,
+    })
+}
+
+/// Print one hexdump line of up to 16 bytes at absolute offset `offset`,
+/// highlighting the bytes that fall in `highlight`.
+fn print_line(bytes: &[u8], offset: usize, highlight: &Range<usize>) {
+    let mut hex = String::new();
+    for i in 0..LINE {
+        if i == LINE / 2 {
+            hex.push(' ');
+        }
+        let byte = match bytes.get(i) {
+            Some(byte) => format!("{byte:02x}"),
+            None => "  ".to_string(),
+        };
+        if highlight.contains(&(offset + i)) {
+            hex.push_str(&paint(&byte, Color::Yellow));
+        } else {
+            hex.push_str(&byte);
+        }
+        hex.push(' ');
+    }
+    let ascii: String = bytes
+        .iter()
+        .map(|&b| {
+            if b.is_ascii_graphic() || b == b' ' {
+                b as char
+            } else {
+                '.'
+            }
+        })
+        .collect();
+    println!("{offset:08x}  {hex} |{ascii}|");
+}
+
+/// Hexdump a segment of a layer file with absolute offsets. Logarray and
+/// bitarray segments get their control word decoded, highlighted and
+/// checked for values that can't be right. Unless `all` is given, long
+/// segments are shown by their first `head` bytes and the lines holding
+/// the control word. Returns whether the control word, if any, is sound.
+pub async fn inspect(
+    layer_file: &str,
+    file_type: LayerFileEnum,
+    head: usize,
+    all: bool,
+) -> io::Result<bool> {
+    let archive = Archive::open(layer_file).await?;
+    let range = archive
+        .absolute_range(file_type)
+        .ok_or_else(|| absent_segment(file_type))?;
+    let contents = archive.segment(file_type)?.unwrap();
+    println!(
+        "{file_type:?}: {} bytes at {}..{} ({:#x}..{:#x})",
+        contents.len(),
+        range.start,
+        range.end,
+        range.start,
+        range.end
+    );
+    let control = control_word(file_type, &contents, range.start);
+    let highlight = control
+        .as_ref()
+        .filter(|c| c.range.len() == 8)
+        .map(|c| c.range.clone())
+        .unwrap_or(0..0);
+
+    // the tail shown is whole lines covering the control word
+    let tail = contents.len().saturating_sub(8) / LINE * LINE;
+    let head = head.div_ceil(LINE) * LINE;
+    for (i, line) in contents.chunks(LINE).enumerate() {
+        let at = i * LINE;
+        if all || at < head || at >= tail {
+            print_line(line, range.start + at, &highlight);
+        } else if at == head {
+            println!(
+                "{}",
+                paint(&format!("... {} bytes not shown", tail - head), Color::Dim)
+            );
+        }
+    }
+
+    let control = match control {
+        Some(control) => control,
+        None => return Ok(true),
+    };
+    println!(
+        "control word at {}: {}",
+        control.range.start, control.description
+    );
+    for problem in control.problems.iter() {
+        println!("{} {problem}", paint("!", Color::Red));
+    }
+
+    Ok(control.problems.is_empty())
+}
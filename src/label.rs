@@ -0,0 +1,293 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use terminus_store::storage::{name_to_string, string_to_name};
+
+use crate::{
+    audit::Audit,
+    confirm::{confirm, total_size},
+    store::{
+        chain, label_path, layer_path, parse_label, parse_label_history, read_label, write_label,
+        write_label_with_history,
+    },
+};
+
+/// Where to roll a label back to.
+pub enum RollbackTarget {
+    Layer(String),
+    Back(usize),
+}
+
+/// Point a label at one of the ancestors of its current head. The target
+/// must be part of the head's chain, so no history that isn't already
+/// reachable is reintroduced. The old head is recorded in the audit log.
+/// Rolling back to the current head does nothing. Otherwise a preview is
+/// shown and confirmation asked for, unless `yes` is given.
+/// Parse a layer name given on the command line.
+fn parse_layer(layer: &str) -> io::Result<[u32; 5]> {
+    string_to_name(layer).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{layer} is not a layer name"),
+        )
+    })
+}
+
+pub async fn rollback(
+    store: &Path,
+    label: &str,
+    target: RollbackTarget,
+    yes: bool,
+) -> io::Result<()> {
+    let head = read_label(store, label).await?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("label {label} does not point at a layer"),
+        )
+    })?;
+    let ancestors = chain(store, head).await?;
+    let target = match target {
+        RollbackTarget::Layer(layer) => {
+            let layer = parse_layer(&layer)?;
+            if layer == head {
+                println!(
+                    "{label} already points at {}; nothing to do",
+                    name_to_string(head)
+                );
+                let mut audit = Audit::begin("rollback");
+                audit.note("result", "already in effect".to_string());
+                return audit.commit(store).await;
+            }
+            if !ancestors[1..].iter().any(|(name, _)| *name == layer) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "{} is not an ancestor of the head of {label}",
+                        name_to_string(layer)
+                    ),
+                ));
+            }
+            layer
+        }
+        RollbackTarget::Back(0) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "rolling back 0 layers would leave the label unchanged",
+            ))
+        }
+        RollbackTarget::Back(back) => match ancestors.get(back) {
+            Some((layer, _)) => *layer,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "the head of {label} has only {} ancestors",
+                        ancestors.len() - 1
+                    ),
+                ))
+            }
+        },
+    };
+    let dropped: Vec<PathBuf> = ancestors
+        .iter()
+        .take_while(|(name, _)| *name != target)
+        .map(|(_, path)| path.clone())
+        .collect();
+    confirm(
+        "rollback",
+        store,
+        &[
+            format!(
+                "point {label} at {} instead of {}",
+                name_to_string(target),
+                name_to_string(head)
+            ),
+            format!(
+                "take {} layers ({}) out of its history; they stay in the store",
+                dropped.len(),
+                total_size(&dropped)
+            ),
+        ],
+        yes,
+    )?;
+
+    let mut audit = Audit::begin("rollback");
+    audit.track(&label_path(store, label)).await?;
+    audit.note("old_head", name_to_string(head));
+    audit.note("new_head", name_to_string(target));
+    write_label(store, label, Some(target)).await?;
+    audit.commit(store).await?;
+    println!(
+        "{label}: {} -> {}",
+        name_to_string(head),
+        name_to_string(target)
+    );
+
+    Ok(())
+}
+
+/// The result of checking one label's recorded head history against the
+/// parent chain of its head.
+pub struct HeadCheck {
+    pub label: String,
+    pub findings: Vec<String>,
+    /// The recorded heads that are ancestors of the head, in chain order.
+    /// A repair rewrites the history to this.
+    pub relinked: Option<Vec<[u32; 5]>>,
+}
+
+/// Check that every previous head a label records is an ancestor of its
+/// current head, and that they are recorded in chain order. Labels without
+/// a recorded history pass trivially.
+pub async fn check_head(store: &Path, label: &str) -> io::Result<HeadCheck> {
+    let contents = tokio::fs::read_to_string(label_path(store, label)).await?;
+    let head = parse_label(&contents)?;
+    let history = parse_label_history(&contents)?;
+    let mut check = HeadCheck {
+        label: label.to_string(),
+        findings: Vec::new(),
+        relinked: None,
+    };
+    if history.is_empty() {
+        return Ok(check);
+    }
+    let head = match head {
+        Some(head) => head,
+        None => {
+            check.findings.push(format!(
+                "has no head but records {} previous heads",
+                history.len()
+            ));
+            return Ok(check);
+        }
+    };
+    let ancestors = match chain(store, head).await {
+        Ok(ancestors) => ancestors,
+        Err(e) => {
+            check.findings.push(format!(
+                "chain of head {} can't be followed: {e}",
+                name_to_string(head)
+            ));
+            return Ok(check);
+        }
+    };
+
+    let mut positions = Vec::new();
+    let mut last = 0;
+    for previous in history {
+        let position = ancestors[1..]
+            .iter()
+            .position(|(name, _)| *name == previous)
+            .map(|i| i + 1);
+        match position {
+            None => check.findings.push(format!(
+                "previous head {} is not an ancestor of head {}",
+                name_to_string(previous),
+                name_to_string(head)
+            )),
+            Some(i) => {
+                if i <= last {
+                    check.findings.push(format!(
+                        "previous head {} is recorded out of chain order",
+                        name_to_string(previous)
+                    ));
+                }
+                last = i;
+                positions.push(i);
+            }
+        }
+    }
+    positions.sort();
+    positions.dedup();
+    check.relinked = Some(positions.into_iter().map(|i| ancestors[i].0).collect());
+
+    Ok(check)
+}
+
+/// Rewrite a label's recorded history to the relinked one from a check,
+/// keeping its head.
+pub async fn repair_head(store: &Path, check: &HeadCheck) -> io::Result<()> {
+    let relinked = match &check.relinked {
+        Some(relinked) => relinked,
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("label {} can't be relinked", check.label),
+            ))
+        }
+    };
+    let head = read_label(store, &check.label).await?;
+    let mut audit = Audit::begin("check-heads");
+    audit.track(&label_path(store, &check.label)).await?;
+    audit.note(
+        "history",
+        relinked
+            .iter()
+            .map(|name| name_to_string(*name))
+            .collect::<Vec<_>>()
+            .join(" "),
+    );
+    write_label_with_history(store, &check.label, head, relinked).await?;
+    audit.commit(store).await
+}
+
+/// Point a label at any layer, which must be in the store unless `force`
+/// is given. Unlike `rollback`, the layer needn't be an ancestor of the
+/// current head. The old head is recorded in the audit log. A preview is
+/// shown and confirmation asked for, unless `yes` is given.
+pub async fn set(store: &Path, label: &str, layer: &str, force: bool, yes: bool) -> io::Result<()> {
+    let layer = parse_layer(layer)?;
+    if !label_path(store, label).exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("label {label} not found"),
+        ));
+    }
+    let head = read_label(store, label).await?;
+    let old = head
+        .map(name_to_string)
+        .unwrap_or_else(|| "nothing".to_string());
+    if head == Some(layer) {
+        println!("{label} already points at {old}; nothing to do");
+        let mut audit = Audit::begin("label set");
+        audit.note("result", "already in effect".to_string());
+        return audit.commit(store).await;
+    }
+    let path = layer_path(store, layer);
+    if !path.exists() {
+        if !force {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "layer {} is not in the store; give --force to point {label} at it anyway",
+                    name_to_string(layer)
+                ),
+            ));
+        }
+        eprintln!(
+            "warning: layer {} is not in the store",
+            name_to_string(layer)
+        );
+    }
+    confirm(
+        "label set",
+        store,
+        &[format!(
+            "point {label} at {} instead of {old}",
+            name_to_string(layer)
+        )],
+        yes,
+    )?;
+
+    let mut audit = Audit::begin("label set");
+    audit.track(&label_path(store, label)).await?;
+    audit.note("old_head", old.clone());
+    audit.note("new_head", name_to_string(layer));
+    write_label(store, label, Some(layer)).await?;
+    audit.commit(store).await?;
+    println!("{label}: {old} -> {}", name_to_string(layer));
+
+    Ok(())
+}
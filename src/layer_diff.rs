@@ -0,0 +1,90 @@
+use std::{collections::BTreeSet, io, path::Path};
+
+use bytes::Bytes;
+
+use crate::{
+    archive::Archive,
+    dict::{read_entries, DictType},
+    dump::{walk_triples, Terms},
+    ids::{cumulative_counts, IdCounts},
+};
+
+/// What one part of a layer gained and lost going from layer a to b.
+pub struct Changes {
+    pub name: &'static str,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl Changes {
+    fn between(name: &'static str, a: BTreeSet<String>, b: BTreeSet<String>) -> Self {
+        Self {
+            name,
+            added: b.difference(&a).cloned().collect(),
+            removed: a.difference(&b).cloned().collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+fn dict_strings(entries: Vec<Bytes>) -> BTreeSet<String> {
+    entries
+        .iter()
+        .map(|entry| format!("{:?}", String::from_utf8_lossy(entry)))
+        .collect()
+}
+
+/// A layer's dictionaries and triples, resolved to strings.
+struct Contents {
+    nodes: BTreeSet<String>,
+    predicates: BTreeSet<String>,
+    values: BTreeSet<String>,
+    additions: BTreeSet<String>,
+    removals: BTreeSet<String>,
+}
+
+async fn contents(path: &Path, store: Option<&Path>) -> io::Result<Contents> {
+    let archive = Archive::open(path).await?;
+    let offsets = match (archive.parent()?, store) {
+        (Some(parent), Some(store)) => cumulative_counts(store, parent).await?,
+        _ => IdCounts::default(),
+    };
+    let terms = Terms::load(&archive, offsets).await?;
+    let mut triples = [BTreeSet::new(), BTreeSet::new()];
+    for (removals, set) in [false, true].into_iter().zip(triples.iter_mut()) {
+        walk_triples(&archive, removals, |s, p, o| {
+            set.insert(terms.ntriples(s, p, o));
+            Ok(())
+        })?;
+    }
+    let [additions, removals] = triples;
+
+    Ok(Contents {
+        nodes: dict_strings(read_entries(&archive, DictType::Nodes).await?),
+        predicates: dict_strings(read_entries(&archive, DictType::Predicates).await?),
+        values: dict_strings(read_entries(&archive, DictType::Values).await?),
+        additions,
+        removals,
+    })
+}
+
+/// Compare two layer archives by their resolved strings rather than ids,
+/// so layers that assigned ids differently still compare equal. Terms of
+/// ancestor layers can't be resolved from an archive; with the store they
+/// are numbered consistently, which is enough when both layers have the
+/// same parent.
+pub async fn diff(a: &Path, b: &Path, store: Option<&Path>) -> io::Result<Vec<Changes>> {
+    let a = contents(a, store).await?;
+    let b = contents(b, store).await?;
+
+    Ok(vec![
+        Changes::between("node", a.nodes, b.nodes),
+        Changes::between("predicate", a.predicates, b.predicates),
+        Changes::between("value", a.values, b.values),
+        Changes::between("triple", a.additions, b.additions),
+        Changes::between("removal", a.removals, b.removals),
+    ])
+}
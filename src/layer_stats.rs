@@ -0,0 +1,412 @@
+use std::io;
+
+use serde_json::json;
+use terminus_store::{
+    storage::consts::LayerFileEnum,
+    structure::{bitarray::BitArray, LogArray},
+};
+
+use crate::{
+    archive::{all_segment_types, Archive},
+    dict::{block_stats, DictType},
+    output::{human_bytes, ndjson, paint, Color, OutputFormat},
+    stats::triple_counts,
+    validate::{segment_kind, SegmentKind},
+};
+
+/// What a single segment holds, as far as its kind tells.
+pub struct SegmentStats {
+    pub segment: LayerFileEnum,
+    pub size: usize,
+    /// Entries of a logarray or bits of a bitarray.
+    pub len: Option<u64>,
+    /// Bits per entry of a logarray.
+    pub width: Option<u8>,
+    /// Set bits of a bitarray.
+    pub ones: Option<u64>,
+}
+
+pub struct DictStats {
+    pub dict: DictType,
+    /// Size of the blocks and offsets segments together.
+    pub size: usize,
+    pub blocks: usize,
+    pub entries: u64,
+    /// Total length of the entries once decoded.
+    pub entry_bytes: u64,
+}
+
+impl DictStats {
+    pub fn average_len(&self) -> f64 {
+        ratio(self.entry_bytes, self.entries)
+    }
+
+    /// Decoded size over stored size, which front coding keeps above 1
+    /// when entries share prefixes.
+    pub fn compression(&self) -> f64 {
+        ratio(self.entry_bytes, self.size as u64)
+    }
+}
+
+/// The shape of one adjacency list, from its nums and bits.
+pub struct AdjacencyStats {
+    /// The name shared by the two segments, such as `PosSpOAdjacencyList`.
+    pub name: String,
+    pub groups: u64,
+    pub entries: u64,
+    /// Groups that only hold the 0 that marks them empty.
+    pub empty_groups: u64,
+    /// The largest number of entries in a group.
+    pub max_group: u64,
+}
+
+impl AdjacencyStats {
+    /// Entries per group.
+    pub fn density(&self) -> f64 {
+        ratio(self.entries, self.groups)
+    }
+}
+
+/// Statistics of a single layer file.
+pub struct LayerAnalysis {
+    pub size: usize,
+    pub header_len: usize,
+    pub segments: Vec<SegmentStats>,
+    pub dicts: Vec<DictStats>,
+    pub adjacency: Vec<AdjacencyStats>,
+    pub added: u64,
+    pub removed: u64,
+}
+
+impl LayerAnalysis {
+    /// File bits for each triple the layer adds or removes.
+    pub fn bits_per_triple(&self) -> f64 {
+        ratio(self.size as u64 * 8, self.added + self.removed)
+    }
+
+    /// How much smaller the adjacency lists and indexes are than the same
+    /// triples stored as three 64-bit ids each.
+    pub fn index_compression(&self) -> f64 {
+        let index: usize = self
+            .segments
+            .iter()
+            .filter(|s| {
+                !matches!(
+                    segment_kind(s.segment),
+                    SegmentKind::DictBlocks | SegmentKind::Parent | SegmentKind::Rollup
+                ) && !self
+                    .dicts
+                    .iter()
+                    .any(|d| d.dict.offsets_segment() == s.segment)
+            })
+            .map(|s| s.size)
+            .sum();
+        ratio((self.added + self.removed) * 24, index as u64)
+    }
+}
+
+fn ratio(a: u64, b: u64) -> f64 {
+    if b == 0 {
+        0.0
+    } else {
+        a as f64 / b as f64
+    }
+}
+
+fn invalid(segment: LayerFileEnum, e: impl ToString) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{segment:?}: {}", e.to_string()),
+    )
+}
+
+fn segment_stats(segment: LayerFileEnum, contents: bytes::Bytes) -> io::Result<SegmentStats> {
+    let mut stats = SegmentStats {
+        segment,
+        size: contents.len(),
+        len: None,
+        width: None,
+        ones: None,
+    };
+    if contents.is_empty() {
+        return Ok(stats);
+    }
+    match segment_kind(segment) {
+        SegmentKind::LogArray => {
+            let logarray = LogArray::parse(contents).map_err(|e| invalid(segment, e))?;
+            stats.len = Some(logarray.len() as u64);
+            stats.width = Some(logarray.width());
+        }
+        SegmentKind::BitArray => {
+            let bits = BitArray::from_bits(contents).map_err(|e| invalid(segment, e))?;
+            stats.len = Some(bits.len() as u64);
+            stats.ones = Some((0..bits.len()).filter(|ix| bits.get(*ix)).count() as u64);
+        }
+        _ => {}
+    }
+
+    Ok(stats)
+}
+
+fn adjacency_stats(
+    archive: &Archive,
+    nums: LayerFileEnum,
+    bits: LayerFileEnum,
+) -> io::Result<Option<AdjacencyStats>> {
+    let (nums_bytes, bits_bytes) = match (archive.segment(nums)?, archive.segment(bits)?) {
+        (Some(n), Some(b)) if !n.is_empty() && !b.is_empty() => (n, b),
+        _ => return Ok(None),
+    };
+    let nums_array = LogArray::parse(nums_bytes).map_err(|e| invalid(nums, e))?;
+    let bits_array = BitArray::from_bits(bits_bytes).map_err(|e| invalid(bits, e))?;
+    let name = format!("{nums:?}");
+    let mut stats = AdjacencyStats {
+        name: name.strip_suffix("Nums").unwrap_or(&name).to_string(),
+        groups: 0,
+        entries: nums_array.len() as u64,
+        empty_groups: 0,
+        max_group: 0,
+    };
+    let mut group = 0;
+    for ix in 0..nums_array.len().min(bits_array.len()) {
+        let num = nums_array.entry(ix);
+        group += 1;
+        if bits_array.get(ix) {
+            stats.groups += 1;
+            if group == 1 && num == 0 {
+                stats.empty_groups += 1;
+            }
+            stats.max_group = stats.max_group.max(group);
+            group = 0;
+        }
+    }
+
+    Ok(Some(stats))
+}
+
+/// Analyze every segment of a layer file: the shape of each logarray and
+/// bitarray, the entries of each dictionary, and the density of each
+/// adjacency list.
+pub async fn analyze(archive: &Archive) -> io::Result<LayerAnalysis> {
+    let mut segments = Vec::new();
+    for (segment, _) in archive.segments() {
+        let contents = archive.segment(segment)?.unwrap_or_default();
+        segments.push(segment_stats(segment, contents)?);
+    }
+
+    let mut dicts = Vec::new();
+    for dict in [DictType::Nodes, DictType::Predicates, DictType::Values] {
+        let blocks = block_stats(archive, dict).await?;
+        let size = [dict.blocks_segment(), dict.offsets_segment()]
+            .into_iter()
+            .filter_map(|s| segments.iter().find(|stats| stats.segment == s))
+            .map(|stats| stats.size)
+            .sum();
+        dicts.push(DictStats {
+            dict,
+            size,
+            blocks: blocks.len(),
+            entries: blocks.iter().map(|b| b.entries as u64).sum(),
+            entry_bytes: blocks.iter().map(|b| b.entry_bytes as u64).sum(),
+        });
+    }
+
+    let mut adjacency = Vec::new();
+    for nums in all_segment_types() {
+        let name = format!("{nums:?}");
+        let prefix = match name.strip_suffix("AdjacencyListNums") {
+            Some(prefix) => prefix,
+            None => continue,
+        };
+        let bits_name = format!("{prefix}AdjacencyListBits");
+        let bits = match all_segment_types().find(|t| format!("{t:?}") == bits_name) {
+            Some(bits) => bits,
+            None => continue,
+        };
+        if let Some(stats) = adjacency_stats(archive, nums, bits)? {
+            adjacency.push(stats);
+        }
+    }
+
+    let (added, removed) = triple_counts(archive)?;
+
+    Ok(LayerAnalysis {
+        size: archive.contents().len(),
+        header_len: archive.header_len(),
+        segments,
+        dicts,
+        adjacency,
+        added,
+        removed,
+    })
+}
+
+fn dict_name(dict: DictType) -> &'static str {
+    match dict {
+        DictType::Nodes => "nodes",
+        DictType::Predicates => "predicates",
+        DictType::Values => "values",
+    }
+}
+
+pub fn print_analysis(analysis: &LayerAnalysis, format: OutputFormat) {
+    let print = |record: serde_json::Value, text: String| match format {
+        OutputFormat::Ndjson => println!("{}", ndjson(record)),
+        _ => println!("{text}"),
+    };
+    let pretty = format == OutputFormat::Pretty;
+    let heading = |title: &str| {
+        if pretty {
+            println!("{}", paint(title, Color::Bold));
+        }
+    };
+
+    heading("segments");
+    for s in analysis.segments.iter() {
+        let shape = match (s.len, s.width, s.ones) {
+            (Some(len), Some(width), _) => format!("{len} entries of {width} bits"),
+            (Some(len), None, Some(ones)) => {
+                format!("{len} bits, {ones} set ({:.1}%)", ratio(ones, len) * 100.0)
+            }
+            _ => String::new(),
+        };
+        let text = if pretty {
+            format!(
+                "  {:<40} {:>10}  {shape}",
+                format!("{:?}", s.segment),
+                human_bytes(s.size)
+            )
+        } else {
+            format!(
+                "segment {:?} {} {} {} {}",
+                s.segment,
+                s.size,
+                s.len.map(|l| l.to_string()).unwrap_or("-".to_string()),
+                s.width.map(|w| w.to_string()).unwrap_or("-".to_string()),
+                s.ones.map(|o| o.to_string()).unwrap_or("-".to_string())
+            )
+        };
+        print(
+            json!({
+                "kind": "segment",
+                "segment": format!("{:?}", s.segment),
+                "size": s.size,
+                "len": s.len,
+                "width": s.width,
+                "ones": s.ones,
+            }),
+            text,
+        );
+    }
+
+    heading("dictionaries");
+    for d in analysis.dicts.iter() {
+        let text = if pretty {
+            format!(
+                "  {:<12} {:>10} entries in {:>7} blocks, {:>10}, average entry {:.1} bytes, {:.2}x decoded",
+                dict_name(d.dict),
+                d.entries,
+                d.blocks,
+                human_bytes(d.size),
+                d.average_len(),
+                d.compression()
+            )
+        } else {
+            format!(
+                "dictionary {} {} {} {} {} {:.2} {:.3}",
+                dict_name(d.dict),
+                d.entries,
+                d.blocks,
+                d.size,
+                d.entry_bytes,
+                d.average_len(),
+                d.compression()
+            )
+        };
+        print(
+            json!({
+                "kind": "dictionary",
+                "dictionary": dict_name(d.dict),
+                "entries": d.entries,
+                "blocks": d.blocks,
+                "size": d.size,
+                "entry_bytes": d.entry_bytes,
+                "average_len": d.average_len(),
+                "compression": d.compression(),
+            }),
+            text,
+        );
+    }
+
+    heading("adjacency lists");
+    for a in analysis.adjacency.iter() {
+        let text = if pretty {
+            format!(
+                "  {:<28} {:>10} groups {:>10} entries, {:.2} per group, largest {}, {} empty",
+                a.name,
+                a.groups,
+                a.entries,
+                a.density(),
+                a.max_group,
+                a.empty_groups
+            )
+        } else {
+            format!(
+                "adjacency {} {} {} {:.3} {} {}",
+                a.name,
+                a.groups,
+                a.entries,
+                a.density(),
+                a.max_group,
+                a.empty_groups
+            )
+        };
+        print(
+            json!({
+                "kind": "adjacency",
+                "list": a.name,
+                "groups": a.groups,
+                "entries": a.entries,
+                "density": a.density(),
+                "max_group": a.max_group,
+                "empty_groups": a.empty_groups,
+            }),
+            text,
+        );
+    }
+
+    let text = if pretty {
+        format!(
+            "{}\n  {} ({} header), +{} -{} triples, {:.1} bits per triple, indexes {:.2}x smaller than raw id triples",
+            paint("layer", Color::Bold),
+            human_bytes(analysis.size),
+            human_bytes(analysis.header_len),
+            analysis.added,
+            analysis.removed,
+            analysis.bits_per_triple(),
+            analysis.index_compression()
+        )
+    } else {
+        format!(
+            "layer {} {} {} {} {:.2} {:.3}",
+            analysis.size,
+            analysis.header_len,
+            analysis.added,
+            analysis.removed,
+            analysis.bits_per_triple(),
+            analysis.index_compression()
+        )
+    };
+    print(
+        json!({
+            "kind": "summary",
+            "size": analysis.size,
+            "header_len": analysis.header_len,
+            "added": analysis.added,
+            "removed": analysis.removed,
+            "bits_per_triple": analysis.bits_per_triple(),
+            "index_compression": analysis.index_compression(),
+        }),
+        text,
+    );
+}
@@ -0,0 +1,85 @@
+//! Inspection and repair of TerminusDB stores and layer archives.
+//!
+//! The `terminusdb-surgery` command line tool is a thin wrapper over this
+//! library. Most diagnostics return structured reports, such as
+//! [`header::HeaderReport`], [`validate_layer::SegmentReport`] or the
+//! findings of [`verify::verify`], so they can be embedded in other tools;
+//! the `print_*` functions render them the way the tool does.
+
+pub mod adjacency;
+pub mod ancestry;
+pub mod anonymize;
+pub mod archive;
+pub mod assertions;
+pub mod atomic;
+pub mod audit;
+pub mod backend;
+pub mod backup;
+pub mod bench;
+pub mod build_layer;
+pub mod cache;
+pub mod checkpoint;
+pub mod checks;
+pub mod checksum;
+pub mod codes;
+pub mod completions;
+pub mod confirm;
+pub mod contract;
+pub mod counts;
+#[cfg(feature = "custom-checks")]
+pub mod custom_checks;
+pub mod databases;
+pub mod deadline;
+pub mod dedup;
+pub mod diagnose;
+pub mod dict;
+pub mod dump;
+pub mod error;
+pub mod export;
+pub mod extract;
+pub mod fetch;
+pub mod fsck;
+pub mod garbage;
+pub mod graph;
+pub mod header;
+pub mod health;
+pub mod ids;
+pub mod index;
+pub mod init;
+pub mod inject;
+pub mod inspect;
+pub mod label;
+pub mod layer_diff;
+pub mod layer_stats;
+pub mod limits;
+pub mod merkle;
+pub mod meta;
+pub mod output;
+pub mod patch;
+pub mod pins;
+pub mod preflight;
+pub mod purge;
+pub mod query;
+pub mod rdf;
+pub mod rebuild;
+pub mod recode;
+pub mod rename;
+pub mod repack;
+pub mod rollup;
+pub mod salvage;
+pub mod scan;
+pub mod schema;
+pub mod selftest;
+pub mod smoke;
+pub mod squash;
+pub mod squash_check;
+pub mod stats;
+pub mod store;
+pub mod stub;
+pub mod tier;
+pub mod triples;
+pub mod validate;
+pub mod validate_layer;
+pub mod values;
+pub mod verify;
+pub mod watch;
@@ -0,0 +1,85 @@
+use std::{
+    io,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        OnceLock,
+    },
+};
+
+use crate::output::human_bytes;
+
+/// The memory a command may use, if it was given a cap.
+static MAX_MEMORY: OnceLock<u64> = OnceLock::new();
+
+/// The number of files a command may keep open, if it was given a cap.
+static MAX_OPEN_FILES: OnceLock<usize> = OnceLock::new();
+
+/// Whether a reduced number of jobs was already reported.
+static REPORTED: AtomicBool = AtomicBool::new(false);
+
+/// Cap the memory heavy commands use for archives and the batches in
+/// flight between their stages.
+pub fn set_max_memory(bytes: u64) {
+    let _ = MAX_MEMORY.set(bytes);
+}
+
+/// Cap the files commands that work on several files at once keep open.
+pub fn set_max_open_files(files: usize) {
+    let _ = MAX_OPEN_FILES.set(files);
+}
+
+/// Refuse to load a file of `size` bytes whole if that alone would exceed
+/// the memory cap, rather than be killed part way through.
+pub fn check_load(path: &Path, size: u64) -> io::Result<()> {
+    match MAX_MEMORY.get() {
+        Some(&max) if size > max => Err(io::Error::new(
+            io::ErrorKind::OutOfMemory,
+            format!(
+                "{} is {}, more than --max-memory {}",
+                path.display(),
+                human_bytes(size as usize),
+                human_bytes(max as usize)
+            ),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// The number of jobs to run at once, at most `requested`, when each keeps
+/// `files` files open and needs `memory` bytes. At least one job always
+/// runs. A reduction is reported once.
+pub fn jobs(requested: usize, files: usize, memory: u64) -> usize {
+    let by_files = MAX_OPEN_FILES
+        .get()
+        .map(|&max| max / files.max(1))
+        .unwrap_or(usize::MAX);
+    let by_memory = MAX_MEMORY
+        .get()
+        .map(|&max| (max / memory.max(1)) as usize)
+        .unwrap_or(usize::MAX);
+    let jobs = requested.min(by_files).min(by_memory).max(1);
+    if jobs < requested.max(1) && !REPORTED.swap(true, Ordering::Relaxed) {
+        let cap = if by_files <= by_memory {
+            "--max-open-files"
+        } else {
+            "--max-memory"
+        };
+        eprintln!("running {jobs} jobs at once instead of {requested} to stay within {cap}");
+    }
+
+    jobs
+}
+
+/// The number of items of `item` bytes to put in a batch, at most
+/// `default`, such that `batches` batches in flight in each of `jobs` jobs
+/// fit in the memory cap. Batches hold at least one item.
+pub fn batch_size(default: usize, item: usize, batches: usize, jobs: usize) -> usize {
+    match MAX_MEMORY.get() {
+        Some(&max) => {
+            let per_batch = max as usize / (batches * jobs * item).max(1);
+            default.min(per_batch).max(1)
+        }
+        None => default,
+    }
+}
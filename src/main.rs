@@ -1,32 +1,106 @@
 use std::{
-    io::{self, SeekFrom},
-    path::PathBuf,
+    collections::{BTreeSet, HashSet},
+    io::{self, Cursor, IsTerminal, SeekFrom},
+    path::{Path, PathBuf},
 };
 
 use bytes::Bytes;
 use clap::*;
 use futures::StreamExt;
+use surgery::{
+    adjacency, ancestry, anonymize, archive, assertions, atomic, audit, backend, backup, bench,
+    build_layer, cache, checks, checksum, codes, completions, confirm, contract, counts, databases,
+    deadline, dedup, diagnose, dict, dump, error, export, extract, fetch, fsck, garbage, graph,
+    header, ids, index, init, inject, inspect, label, layer_diff, layer_stats, limits, merkle,
+    meta, output, patch, pins, preflight, purge, rdf, rebuild, recode, rename, repack, rollup,
+    salvage, scan, schema, selftest, smoke, squash, squash_check, stats, store, tier, triples,
+    validate, validate_layer, values, verify, watch,
+};
 use terminus_store::{
-    layer::builder::{self, build_object_index_from_direct_files},
+    layer::{ObjectType, ValueTriple},
     storage::{
-        archive::{ArchiveHeader, ArchiveLayerStore, ArchiveSliceReader, DirectoryArchiveBackend},
+        archive::{ArchiveHeader, ArchiveLayerStore, DirectoryArchiveBackend},
         consts::{LayerFileEnum, FILENAME_ENUM_MAP},
-        directory::FileBackedStore,
         *,
     },
-    store::sync::{open_sync_archive_store, SyncStore, SyncStoreLayer},
-    structure::{stream::TfcDictStream, LogArray, parse_control_word},
+    store::sync::{open_sync_archive_store, SyncStoreLayer},
+    structure::{bitarray::BitArray, parse_control_word, stream::TfcDictStream, LogArray},
     Layer,
 };
 
-use num::FromPrimitive;
-use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use archive::{absent_segment, parse_layer_name, Archive};
+use audit::Audit;
+use backend::LayerSource;
+use completions::CompletionKind;
+use dict::DictType;
+use error::{Context, Error};
+use export::ExportFormat;
+use graph::DegreeMetric;
+use merkle::MerkleTree;
+use output::{csv_field, human_bytes, ndjson, paint, Abbreviator, Color, OffsetBase, OutputFormat};
+use serde_json::json;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use triples::{format_object, object_json, PageArgs};
+use validate::{segment_kind, SegmentKind};
+use values::ValueQuery;
 
 #[derive(Parser)]
 #[command(author, version, about)]
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    /// Don't color pretty output
+    #[arg(long, global = true)]
+    no_color: bool,
+    /// Print results as ndjson records. Same as `--format ndjson` for
+    /// commands that have it; see `schema` for the records of each command
+    #[arg(long, global = true)]
+    json: bool,
+    /// Safely read a store that a running server is writing to. Labels are
+    /// read once up front and layers that appear afterwards are ignored.
+    /// Commands that modify files refuse to run.
+    #[arg(long, global = true)]
+    attach: bool,
+    /// Number of async worker threads. Defaults to the number of cores.
+    #[arg(long, global = true)]
+    worker_threads: Option<usize>,
+    /// Copy layers that were moved to cold storage into this directory on
+    /// first use, and read them from there
+    #[arg(long, global = true)]
+    cache_dir: Option<String>,
+    /// Evict the least recently used layers from the cache directory once
+    /// it grows past this size, such as 20G
+    #[arg(long, global = true, value_parser = output::parse_size, requires = "cache_dir")]
+    cache_size: Option<u64>,
+    /// Maximum number of threads for blocking file IO. Raising this helps
+    /// on high-latency storage such as NFS. Defaults to 2048 when the store
+    /// is on a network filesystem and 512 otherwise.
+    #[arg(long, global = true)]
+    blocking_threads: Option<usize>,
+    /// Stop long analyses such as fsck and stats-index build after this
+    /// long, such as 2h, reporting what they got through and how much is
+    /// left
+    #[arg(long, global = true, value_parser = humantime::parse_duration)]
+    timeout: Option<std::time::Duration>,
+    /// Keep memory use within this size, such as 2G, by running fewer
+    /// jobs at once and passing smaller batches between stages. Archives
+    /// too large to load within it are refused
+    #[arg(long, global = true, value_parser = output::parse_size)]
+    max_memory: Option<u64>,
+    /// Keep at most this many files open, by running fewer jobs at once
+    #[arg(long, global = true)]
+    max_open_files: Option<usize>,
+    /// Where parse-header, extract, triple-count and print-dict read the
+    /// layer file they're given from. On s3, only the header and the byte
+    /// ranges a command needs are fetched
+    #[arg(long, global = true, value_enum, default_value_t = backend::BackendKind::Dir)]
+    backend: backend::BackendKind,
+    /// The bucket of the s3 backend
+    #[arg(long, global = true, required_if_eq("backend", "s3"))]
+    bucket: Option<String>,
+    /// Prefix of the layer file keys on the s3 backend
+    #[arg(long, global = true, default_value = "")]
+    prefix: String,
 }
 
 #[derive(Subcommand)]
@@ -39,9 +113,15 @@ enum Commands {
         layer: Option<String>,
         /// Label in which to start the lookup
         label: Option<String>,
-        /// The workdir to store mappings in
+        /// Which dictionary to look the term up in. Values are looked up
+        /// as strings.
+        #[arg(long, value_enum, default_value = "subject")]
+        position: TermPosition,
+        /// The workdir to store mappings in.
+        /// Give more than once to search further stores for layers and
+        /// labels
         #[arg(short = 's', long = "store")]
-        store: Option<String>,
+        store: Vec<String>,
     },
     /// Lookup the node for an id starting at the current layer
     IdNode {
@@ -53,9 +133,15 @@ enum Commands {
         /// Label in which to start the lookup
         #[arg(short = 'g', long = "label")]
         label: Option<String>,
-        /// The workdir to store mappings in
+        /// Where the id was found. Objects resolve to nodes or to values,
+        /// which are printed as `value^^datatype`.
+        #[arg(long, value_enum, default_value = "subject")]
+        position: TermPosition,
+        /// The workdir to store mappings in.
+        /// Give more than once to search further stores for layers and
+        /// labels
         #[arg(short = 's', long = "store")]
-        store: Option<String>,
+        store: Vec<String>,
     },
     /// Node count of layer
     NodeCount {
@@ -64,9 +150,11 @@ enum Commands {
         /// Label in which to start the lookup
         #[arg(short = 'g', long = "label")]
         label: Option<String>,
-        /// The workdir to store mappings in
+        /// The workdir to store mappings in.
+        /// Give more than once to search further stores for layers and
+        /// labels
         #[arg(short = 's', long = "store")]
-        store: Option<String>,
+        store: Vec<String>,
     },
     /// Parse a larch header from a file
     ParseHeader {
@@ -75,12 +163,217 @@ enum Commands {
         /// whether to sort by size
         #[arg(short, long)]
         sort: bool,
+        /// How to print offsets
+        #[arg(long, value_enum, default_value_t = OffsetBase::Dec)]
+        offsets: OffsetBase,
+        /// Print offsets from the start of the file rather than from the
+        /// end of the header
+        #[arg(long)]
+        absolute: bool,
+        /// Only print this segment, named as for extract
+        #[arg(long)]
+        segment: Option<String>,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Pretty)]
+        format: OutputFormat,
+    },
+    /// Analyze a layer file beyond its segment sizes: dictionary entries
+    /// and their average length, adjacency list densities, logarray
+    /// widths, triple counts, bits per triple and how well each part
+    /// compresses
+    Stats {
+        layer_file: String,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Pretty)]
+        format: OutputFormat,
+    },
+    /// Print the triples a single layer archive adds or removes, read
+    /// straight from its adjacency lists and dictionaries
+    DumpTriples {
+        layer_file: String,
+        /// Print the triples the layer removes rather than those it adds
+        #[arg(long)]
+        removals: bool,
+        /// Store holding the layer's ancestors, so ids of a child layer are
+        /// numbered correctly. Terms from ancestors are still printed as
+        /// ids.
+        #[arg(short = 's', long = "store")]
+        store: Option<String>,
+        #[arg(long, value_enum, default_value_t = dump::DumpFormat::Ntriples)]
+        format: dump::DumpFormat,
+    },
+    /// Print the triples of a single layer archive matching a pattern, as
+    /// one of its indexes gives them. Terms are looked up in the layer's
+    /// own dictionaries; give a term from an ancestor layer as `#id`.
+    Query {
+        layer_file: String,
+        #[arg(long)]
+        subject: Option<String>,
+        #[arg(long)]
+        predicate: Option<String>,
+        /// A node, or a value as its stored bytes
+        #[arg(long)]
+        object: Option<String>,
+        /// The index to walk. Defaults to the adjacency lists for a bound
+        /// subject, then the object index for a bound object, then the
+        /// predicate wavelet tree for a bound predicate.
+        #[arg(long, value_enum)]
+        index: Option<query::QueryIndex>,
+        /// Walk the indexes of the triples the layer removes
+        #[arg(long)]
+        removals: bool,
+        /// Store holding the layer's ancestors, so ids of a child layer are
+        /// numbered correctly
+        #[arg(short = 's', long = "store")]
+        store: Option<String>,
+        #[arg(long, value_enum, default_value_t = dump::DumpFormat::Ntriples)]
+        format: dump::DumpFormat,
     },
-    /// Print dicts
+    /// Print dicts. Values are printed as `value^^datatype`, which needs
+    /// the layer file to lie in a store, as its datatypes are looked up
+    /// through the layer.
     PrintDict {
         file_name: String,
         #[arg(value_enum)]
         dict_type: DictType,
+        /// Print the raw bytes of each entry instead of decoding values
+        #[arg(long)]
+        raw: bool,
+        /// Only print the entries with ids in `start..end`, end excluded
+        #[arg(long, value_parser = parse_id_range)]
+        range: Option<(u64, u64)>,
+    },
+    /// Look up the id of a dictionary entry in a single archive, by binary
+    /// search over its blocks. Exits with 1 if the entry isn't there.
+    DictLookup {
+        layer_file: String,
+        #[arg(value_enum)]
+        dict_type: DictType,
+        entry: String,
+        /// Datatype of a value, as an XML schema or stored datatype name
+        #[arg(long, default_value = "xsd:string")]
+        datatype: String,
+    },
+    /// Print the dictionary entry with the given id in a single archive,
+    /// decoding only its block. Exits with 1 if there is no such id.
+    DictEntry {
+        layer_file: String,
+        #[arg(value_enum)]
+        dict_type: DictType,
+        id: u64,
+    },
+    /// Decode a dictionary and check that its entries are sorted, using
+    /// all cores
+    ValidateDict {
+        layer_file: String,
+        #[arg(value_enum)]
+        dict_type: DictType,
+    },
+    /// Look up every entry of a dictionary by binary search, comparing raw
+    /// bytes, and print those that are there but can't be found, with the
+    /// collation they seem to have been sorted by. Checks all dictionaries
+    /// unless one is given. Exits with 1 if any entry can't be found.
+    CheckCollation {
+        layer_file: String,
+        #[arg(value_enum)]
+        dict_type: Option<DictType>,
+    },
+    /// Copy an arbitrary byte range out of a file, optionally decoding it
+    /// as a segment
+    Carve {
+        file_name: String,
+        /// First byte of the range, in decimal or 0x-prefixed hex
+        #[arg(long, value_parser = parse_offset)]
+        start: usize,
+        /// Byte just past the range, in decimal or 0x-prefixed hex
+        #[arg(long, value_parser = parse_offset)]
+        end: usize,
+        /// Where to write the bytes. Without this or --decode-as they are
+        /// written to stdout.
+        #[arg(short, long)]
+        output: Option<String>,
+        /// Decode the range as this kind of segment, named as for extract
+        #[arg(long)]
+        decode_as: Option<String>,
+    },
+    /// Reconstruct the header of a file whose header is missing or
+    /// corrupt by recognizing the segments that follow it
+    RebuildHeader {
+        file_name: String,
+        /// Offset at which the first segment starts, in decimal or
+        /// 0x-prefixed hex
+        #[arg(long, value_parser = parse_offset, default_value = "0")]
+        start: usize,
+        /// Where to write the reconstructed archive. Without it, only the
+        /// proposed layout is printed.
+        #[arg(short, long)]
+        output: Option<String>,
+        /// Take the most likely layout without asking
+        #[arg(long)]
+        yes: bool,
+        /// Go ahead even if the target filesystem looks short of space
+        #[arg(long)]
+        force: bool,
+    },
+    /// Print the decoded contents of any segment of an archive
+    PrintSegment {
+        layer_file: String,
+        /// The segment to print, named as for extract
+        file_name: String,
+        /// Succeed without printing anything if the layer doesn't have
+        /// the segment
+        #[arg(long)]
+        allow_missing: bool,
+    },
+    /// Hexdump a segment of an archive with absolute file offsets, decoding
+    /// and checking the control word of logarray and bitarray segments.
+    /// Exits with 1 if the control word can't be right
+    Inspect {
+        layer_file: String,
+        /// The segment to dump, named as for extract
+        segment_name: String,
+        /// Number of bytes to show from the start of a long segment, before
+        /// skipping to the lines holding the control word
+        #[arg(long, default_value_t = 256)]
+        head: usize,
+        /// Show every byte of the segment
+        #[arg(long)]
+        all: bool,
+    },
+    /// Check every segment of a layer archive and print PASS or FAIL for
+    /// each, with the offset of the first problem in a failing segment
+    ValidateLayer {
+        layer_file: String,
+        /// Store holding the layer's ancestors, so ids of a child layer can
+        /// be checked against the dictionaries of the whole chain
+        #[arg(short = 's', long = "store")]
+        store: Option<String>,
+        /// How to print offsets
+        #[arg(long, value_enum, default_value_t = OffsetBase::Dec)]
+        offsets: OffsetBase,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Pretty)]
+        format: OutputFormat,
+    },
+    /// Validate every layer archive of a store as validate-layer does,
+    /// several at once, with a progress bar. Exits with 1 if any fails
+    ValidateStore {
+        /// The store directory
+        #[arg(short = 's', long = "store")]
+        store: Option<String>,
+        /// Number of layers to validate at once
+        #[arg(long, default_value_t = 4)]
+        jobs: usize,
+        /// Check the ids of child layers against the dictionaries of their
+        /// ancestors, which reads every ancestor of every child layer
+        #[arg(long)]
+        check_ids: bool,
+        /// File to write the problems found to, as ndjson records
+        #[arg(long)]
+        report: Option<String>,
+        /// Skip layers an earlier, interrupted run already validated and
+        /// that haven't changed since, appending to its report
+        #[arg(long)]
+        resume: bool,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Pretty)]
+        format: OutputFormat,
     },
     /// Validate LogArray
     ValidateLogArray {
@@ -90,10 +383,56 @@ enum Commands {
         #[arg(short, long, default_value_t = false)]
         header_first: bool,
     },
-    /// Extract a file from an archive
+    /// Extract a file from an archive. A segment that is present but
+    /// empty extracts to nothing, with a note on stderr
     Extract {
         layer_file_name: String,
         file_name: String,
+        /// Succeed without extracting anything if the layer doesn't have
+        /// the segment
+        #[arg(long)]
+        allow_missing: bool,
+    },
+    /// Extract every segment of an archive into a directory, several at
+    /// once. Exits with 1 if a written segment doesn't match its checksum.
+    ExtractAll {
+        layer_file: String,
+        /// Directory to write the segments to, named as for extract
+        #[arg(short, long)]
+        output: String,
+        /// Number of segments to copy at once
+        #[arg(long, default_value_t = 4)]
+        jobs: usize,
+        /// Read each segment back after writing it, checking its size and
+        /// its checksum if the archive has a checksum trailer
+        #[arg(long)]
+        verify: bool,
+    },
+    /// Replace a segment of an archive with the contents of a file, for
+    /// example one fixed after extract
+    Inject {
+        layer_file: String,
+        /// The segment to replace, named as for extract
+        segment_name: String,
+        input_file: String,
+        /// Where to write the new archive
+        #[arg(
+            short,
+            long,
+            required_unless_present = "in_place",
+            conflicts_with = "in_place"
+        )]
+        output: Option<String>,
+        /// Replace the layer file itself
+        #[arg(long)]
+        in_place: bool,
+        /// Store whose audit log records the change. Defaults to the store
+        /// the layer file is in
+        #[arg(short = 's', long = "store")]
+        store: Option<String>,
+        /// Go ahead even if the target filesystem looks short of space
+        #[arg(long)]
+        force: bool,
     },
     /// Build an object index file set from input files
     BuildObjectIndex {
@@ -102,300 +441,4514 @@ enum Commands {
         o_ps_dir: String,
         #[arg(long)]
         objects_file: Option<String>,
+        /// Highest valid object id, used to validate the objects file
+        #[arg(long)]
+        max_object_id: Option<u64>,
+        /// Layer whose dictionaries (and those of its ancestors) determine
+        /// the highest valid object id
+        #[arg(short = 'l', long = "layer")]
+        layer: Option<String>,
+        /// Store containing the layer.
+        /// Give more than once to search further stores for layers and
+        /// labels
+        #[arg(short = 's', long = "store")]
+        store: Vec<String>,
+        /// Go ahead even if the target filesystem looks short of space
+        #[arg(long)]
+        force: bool,
     },
     /// Build a predicate index from the given s_p nums file
     BuildPredicateIndex {
         s_p_nums_file: String,
         predicate_index_dir: String,
+        /// Go ahead even if the target filesystem looks short of space
+        #[arg(long)]
+        force: bool,
+    },
+    /// Build the s_p adjacency list bitindex from the given s_p nums and bits files
+    BuildSubjectIndex {
+        s_p_nums_file: String,
+        s_p_bits_file: String,
+        subject_index_dir: String,
+        /// Go ahead even if the target filesystem looks short of space
+        #[arg(long)]
+        force: bool,
+    },
+    /// Derive the triple count of a layer from each of the structures that
+    /// encode it, and its subject-predicate pairs likewise, and report any
+    /// disagreement. Exits with 1 if the counts disagree.
+    CheckCounts {
+        layer_file: String,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Pretty)]
+        format: OutputFormat,
+    },
+    /// Rebuild the blocks and sblocks of a bitindex from its bits, as
+    /// bit_index_blocks and bit_index_sblocks in the output directory, so
+    /// they can be injected back
+    RebuildBitindex {
+        /// The bits file, or a layer archive with --segment
+        input: String,
+        output_dir: String,
+        /// Read this bits segment from the layer archive given as input,
+        /// named as for extract
+        #[arg(long)]
+        segment: Option<String>,
+        /// Go ahead even if the target filesystem looks short of space
+        #[arg(long)]
+        force: bool,
     },
     /// Return a triple count of the given layer
     TripleCount {
-        layer_file: String
-    }
-}
-
-#[derive(ValueEnum, Clone, PartialEq, Eq)]
-enum DictType {
-    Nodes,
-    Predicates,
-    Values,
-}
-
-fn open_layer_or_label(
-    store: SyncStore,
-    layer: Option<String>,
-    label: Option<String>,
-) -> Box<SyncStoreLayer> {
-    let res = match (layer, label) {
-        (None, None) => panic!("You must specify either a layer or a label"),
-        (None, Some(label_name)) => store.create(&label_name).unwrap().head(),
-        (Some(layer_name), None) => {
-            let layer = string_to_name(&layer_name).unwrap();
-            store.get_layer_from_id(layer)
-        }
-        (Some(_), Some(_)) => panic!("You must specify either a layer or a label"),
-    };
-    Box::new(res.unwrap().unwrap())
-}
-
-fn node_id(store: &str, layer: Option<String>, label: Option<String>, node: &str) -> Option<u64> {
-    let store = open_sync_archive_store(store, 512);
-    let layer = open_layer_or_label(store, layer, label);
-    layer.subject_id(node)
-}
-
-fn id_node(store: &str, layer: Option<String>, label: Option<String>, id: &str) -> Option<String> {
-    let store = open_sync_archive_store(store, 512);
-    let layer = open_layer_or_label(store, layer, label);
-    layer.id_subject(id.parse().unwrap())
-}
-
-async fn node_count(store: &str, layer: Option<String>, label: Option<String>) -> Option<u64> {
-    let backend = DirectoryArchiveBackend::new(store.into());
-    let archive_store = ArchiveLayerStore::new(backend.clone(), backend);
-    let store = open_sync_archive_store(store, 512);
-    let layer_name = open_layer_or_label(store, layer, label).name();
-    archive_store.get_node_count(layer_name).await.unwrap()
-}
-
-async fn get_triple_count(layer: String) -> io::Result<()> {
-    let mut file = tokio::fs::File::open(layer).await.unwrap();
-    let header = ArchiveHeader::parse_from_reader(&mut file).await?;
-    let range = header.range_for(LayerFileEnum::PosSpOAdjacencyListNums).unwrap();
-    let mut buf = [0;8];
-    file.seek(SeekFrom::Current(range.end as i64 -8)).await?;
-    file.read_exact(&mut buf).await?;
-    let (size, _width) = parse_control_word(&buf);
-
-    println!("{size}");
-    Ok(())
-}
-
-
-async fn open_slice(
-    file_name: PathBuf,
-    file_type: LayerFileEnum,
-) -> io::Result<ArchiveSliceReader> {
-    let mut reader = tokio::fs::File::open(file_name).await?;
-    let header = ArchiveHeader::parse_from_reader(&mut reader).await?;
-
-    let range = header.range_for(file_type).unwrap();
-    let remaining = range.len();
-    reader.seek(SeekFrom::Current((range.start) as i64)).await?;
-
-    Ok(ArchiveSliceReader::new(reader, remaining))
-}
-
-async fn print_dict(file_name: PathBuf, t: DictType) -> std::io::Result<()> {
-    let file_type = match t {
-        DictType::Nodes => LayerFileEnum::NodeDictionaryBlocks,
-        DictType::Predicates => LayerFileEnum::PredicateDictionaryBlocks,
-        DictType::Values => LayerFileEnum::ValueDictionaryBlocks,
-    };
-    let reader = open_slice(file_name, file_type).await?;
-
-    let mut stream = TfcDictStream::new(reader).enumerate();
-    while let Some((ix, element)) = stream.next().await {
-        let (element, _) =
-            element.map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
-        println!("{}: {:?}", ix + 1, element.to_bytes());
-    }
-
-    Ok(())
-}
-
-async fn validate_logarray(file_name: PathBuf, header_first: bool) -> std::io::Result<()> {
-    let mut file = tokio::fs::File::open(file_name).await?;
-    let mut contents = Vec::new();
-    file.read_to_end(&mut contents).await?;
-    let contents = Bytes::from(contents);
-
-    let _logarray = if header_first {
-        LogArray::parse_header_first(contents).unwrap().0
-    } else {
-        LogArray::parse(contents).unwrap()
-    };
-    Ok(())
-}
-
-async fn extract_file(layer_path: PathBuf, file_name: &str) -> std::io::Result<()> {
-    let mut file = tokio::fs::File::open(layer_path).await?;
-    let header = ArchiveHeader::parse_from_reader(&mut file).await?;
-    let file_type = FILENAME_ENUM_MAP[file_name];
-    if let Some(range) = header.range_for(file_type) {
-        file.seek(SeekFrom::Current(range.start as i64)).await?;
-        let mut reader = ArchiveSliceReader::new(file, range.len());
-        let mut output = tokio::io::stdout();
-        tokio::io::copy(&mut reader, &mut output).await?;
-        output.flush().await?;
-    } else {
-        panic!("layer did not contain {file_name}");
-    }
-
-    Ok(())
-}
-
-async fn build_object_index(
-    sp_o_nums_file: String,
-    sp_o_bits_file: String,
-    o_ps_dir: String,
-    objects_file: Option<String>,
-) -> io::Result<()> {
-    let o_ps_dir_path: PathBuf = o_ps_dir.into();
-    tokio::fs::create_dir_all(&o_ps_dir_path).await?;
-
-    let sp_o_nums_file = FileBackedStore::new(sp_o_nums_file);
-    let sp_o_bits_file = FileBackedStore::new(sp_o_bits_file);
-    let objects_file = objects_file.map(|p| FileBackedStore::new(p));
-
-    let mut o_ps_nums_path = o_ps_dir_path.clone();
-    o_ps_nums_path.push("nums");
-    let mut o_ps_bits_path = o_ps_dir_path.clone();
-    o_ps_bits_path.push("bits");
-    let mut o_ps_bit_index_blocks_path = o_ps_dir_path.clone();
-    o_ps_bit_index_blocks_path.push("bit_index_blocks");
-    let mut o_ps_bit_index_sblocks_path = o_ps_dir_path.clone();
-    o_ps_bit_index_sblocks_path.push("bit_index_sblocks");
-
-    let o_ps_nums_file = FileBackedStore::new(o_ps_nums_path);
-    let o_ps_bits_file = FileBackedStore::new(o_ps_bits_path);
-    let o_ps_blocks_file = FileBackedStore::new(o_ps_bit_index_blocks_path);
-    let o_ps_sblocks_file = FileBackedStore::new(o_ps_bit_index_sblocks_path);
-    let o_ps_files = AdjacencyListFiles {
-        bitindex_files: BitIndexFiles {
-            bits_file: o_ps_bits_file,
-            blocks_file: o_ps_blocks_file,
-            sblocks_file: o_ps_sblocks_file,
-        },
-        nums_file: o_ps_nums_file,
-    };
-
-    build_object_index_from_direct_files(sp_o_nums_file, sp_o_bits_file, o_ps_files, objects_file)
-        .await
-}
-
-async fn build_predicate_index(
-    s_p_nums_file: String,
-    predicate_index_dir: String,
-) -> io::Result<()> {
-    let predicate_index_dir_path: PathBuf = predicate_index_dir.into();
-    tokio::fs::create_dir_all(&predicate_index_dir_path).await?;
-
-    let s_p_nums_file = FileBackedStore::new(s_p_nums_file);
-    let mut wavelet_bits_path = predicate_index_dir_path.clone();
-    wavelet_bits_path.push("bits");
-    let mut wavelet_blocks_path = predicate_index_dir_path.clone();
-    wavelet_blocks_path.push("blocks");
-    let mut wavelet_sblocks_path = predicate_index_dir_path.clone();
-    wavelet_sblocks_path.push("sblocks");
-
-    let wavelet_bits = FileBackedStore::new(wavelet_bits_path);
-    let wavelet_blocks = FileBackedStore::new(wavelet_blocks_path);
-    let wavelet_sblocks = FileBackedStore::new(wavelet_sblocks_path);
-
-    builder::build_predicate_index(s_p_nums_file, wavelet_bits, wavelet_blocks, wavelet_sblocks)
-        .await
-}
-
-#[tokio::main]
-async fn main() {
-    let cli = Cli::parse();
-
-    match cli.command {
-        Commands::NodeId {
-            node,
-            layer,
-            label,
-            store,
-        } => {
-            let store = store.unwrap_or_else(|| ".".to_string());
-            let id_for_node = node_id(&store, layer, label, &node);
-            match id_for_node {
-                Some(id) => println!("{id}"),
-                None => println!("None"),
-            };
-        }
-        Commands::IdNode {
-            id,
-            layer,
-            label,
-            store,
-        } => {
-            let store = store.unwrap_or_else(|| ".".to_string());
-            let node_for_id = id_node(&store, layer, label, &id);
-            match node_for_id {
-                Some(id) => println!("{id}"),
-                None => println!("None"),
-            };
-        }
-        Commands::NodeCount {
-            layer,
-            label,
-            store,
-        } => {
-            let store = store.unwrap_or_else(|| ".".to_string());
-            let node_count = node_count(&store, layer, label);
-            match node_count.await {
-                Some(id) => println!("{id}"),
-                None => println!("None"),
-            };
-        }
-        Commands::ParseHeader { file_name, sort } => {
-            parse_and_print_header(file_name, sort).await;
-        }
-        Commands::PrintDict {
-            file_name,
-            dict_type,
-        } => print_dict(file_name.into(), dict_type).await.unwrap(),
-        Commands::ValidateLogArray {
-            file_name,
-            header_first,
-        } => validate_logarray(file_name.into(), header_first)
-            .await
-            .unwrap(),
-        Commands::Extract {
-            layer_file_name,
-            file_name,
-        } => extract_file(layer_file_name.into(), &file_name)
-            .await
-            .unwrap(),
-        Commands::BuildObjectIndex {
-            sp_o_nums_file,
-            sp_o_bits_file,
-            o_ps_dir,
-            objects_file,
-        } => build_object_index(sp_o_nums_file, sp_o_bits_file, o_ps_dir, objects_file)
-            .await
-            .unwrap(),
-        Commands::BuildPredicateIndex {
-            s_p_nums_file,
-            predicate_index_dir,
-        } => build_predicate_index(s_p_nums_file, predicate_index_dir)
-            .await
-            .unwrap(),
-        Commands::TripleCount { layer_file } => get_triple_count(layer_file).await.unwrap()
-    }
-}
-
-async fn parse_and_print_header<P: Into<PathBuf>>(file_name: P, sort: bool) {
-    let mut file = tokio::fs::File::open(file_name.into()).await.unwrap();
-    let header = ArchiveHeader::parse_from_reader(&mut file).await.unwrap();
-
-    let mut result = Vec::new();
-    // annoying code to loop over the segments
-    for i in 0..=(LayerFileEnum::Rollup as usize) {
-        let file_type = LayerFileEnum::from_usize(i).unwrap();
-        if let Some(range) = header.range_for(file_type) {
-            let file_name = format!("{file_type:?}");
-            result.push((file_name, range.start, range.end, range.len()));
-        }
-    }
-    if sort {
-        result.sort_by_key(|x| x.3);
-        result.reverse();
-    }
-
-    for (file_name, start, end, len) in result {
-        println!("{file_name: >50}:\t{: >10}..{: <10} ({})", start, end, len);
-    }
+        #[arg(required_unless_present = "label")]
+        layer_file: Option<String>,
+        /// Count the chain of a label instead of a single layer file
+        #[arg(short = 'g', long = "label", conflicts_with = "layer_file")]
+        label: Option<String>,
+        /// The store directory.
+        /// Give more than once to search further stores for layers and
+        /// labels
+        #[arg(short = 's', long = "store")]
+        store: Vec<String>,
+        /// Print every layer of the label's chain with its additions,
+        /// removals and the running total, base layer first
+        #[arg(long, requires = "label")]
+        cumulative: bool,
+        /// Only count the triples of this predicate, in the whole chain
+        #[arg(short = 'p', long = "predicate", conflicts_with = "cumulative")]
+        predicate: Option<String>,
+    },
+    /// Build a merkle tree over all layer segments in a store and print its root
+    Merkle {
+        /// The store directory.
+        /// Give more than once to search further stores for layers and
+        /// labels
+        #[arg(short = 's', long = "store")]
+        store: Vec<String>,
+        /// File to save the tree to
+        #[arg(short, long)]
+        output: Option<String>,
+        #[command(subcommand)]
+        action: Option<MerkleCommand>,
+    },
+    /// Validate every layer in a store
+    Fsck {
+        /// The store directory.
+        /// Give more than once to search further stores for layers and
+        /// labels
+        #[arg(short = 's', long = "store")]
+        store: Vec<String>,
+        /// Skip layers that passed before and haven't changed since
+        #[arg(long)]
+        incremental: bool,
+        /// The results cache. Defaults to .surgery/fsck-cache in the store
+        #[arg(long)]
+        cache: Option<String>,
+        /// Checks to run on every layer, separated by commas
+        #[arg(long, value_delimiter = ',', default_value = "structure")]
+        checks: Vec<String>,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Pretty)]
+        format: OutputFormat,
+        /// Also write the health score and finding counts to this file in
+        /// the Prometheus text format
+        #[arg(long)]
+        prometheus: Option<String>,
+    },
+    /// Find every store under a directory, quickly verify each and print
+    /// a table of their health. Exits with 1 if any store is damaged or
+    /// dead.
+    Scan {
+        /// Directory to search for stores
+        #[arg(long)]
+        root: String,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Pretty)]
+        format: OutputFormat,
+    },
+    /// Follow every label and pin of a store through parents and rollups,
+    /// and report the layers nothing reaches, references to missing
+    /// layers, layer files that can't be read and the disk each label's
+    /// chain takes
+    ScanStore {
+        /// The store directory
+        #[arg(short = 's', long = "store")]
+        store: Option<String>,
+        /// Delete the unreachable layer files of the store, after asking
+        #[arg(long)]
+        delete_unreachable: bool,
+        /// Delete without asking
+        #[arg(short = 'y', long, requires = "delete_unreachable")]
+        yes: bool,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Pretty)]
+        format: OutputFormat,
+    },
+    /// List the labels of a store as the databases they stand for, decoded
+    /// from the server's label names, with each head, chain length and
+    /// size
+    Databases {
+        /// The workdir to store mappings in.
+        /// Give more than once to search further stores for layers and
+        /// labels
+        #[arg(short = 's', long = "store")]
+        store: Vec<String>,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Pretty)]
+        format: OutputFormat,
+    },
+    /// Validate layers as they are written to a store
+    Watch {
+        /// The store directory
+        #[arg(short = 's', long = "store")]
+        store: Option<String>,
+        /// Seconds the store must be quiet before new layers are validated
+        #[arg(long, default_value_t = 2)]
+        settle: u64,
+        /// Shell command to run for every failing layer
+        #[arg(long)]
+        on_failure: Option<String>,
+        /// Checks to run on every layer, separated by commas
+        #[arg(long, value_delimiter = ',', default_value = "structure")]
+        checks: Vec<String>,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Pretty)]
+        format: OutputFormat,
+    },
+    /// List dictionary entries added in a child layer, and flag entries that
+    /// duplicate strings already present in its ancestors
+    DictDiff {
+        parent_file: String,
+        child_file: String,
+        #[arg(value_enum)]
+        dict_type: DictType,
+        /// Further ancestor layer files to check for duplicates
+        #[arg(long)]
+        ancestor: Vec<String>,
+    },
+    /// Compare the dictionaries and triples of two layer archives by their
+    /// strings. Exits with 1 if they differ.
+    Diff {
+        layer_a: String,
+        layer_b: String,
+        /// Only print the number of differences of each kind
+        #[arg(long)]
+        summary: bool,
+        /// Store holding the layers' ancestors, so ids of child layers are
+        /// numbered correctly
+        #[arg(short = 's', long = "store")]
+        store: Option<String>,
+    },
+    /// Estimate how much dictionary content is repeated across a label's chain
+    DedupEstimate {
+        /// Label whose chain to measure
+        #[arg(short = 'g', long = "label")]
+        label: String,
+        /// The store directory.
+        /// Give more than once to search further stores for layers and
+        /// labels
+        #[arg(short = 's', long = "store")]
+        store: Vec<String>,
+    },
+    /// Check whether a triple exists in a layer, taking additions and
+    /// removals in the whole chain into account. Exits with 1 if it doesn't.
+    HasTriple {
+        #[arg(short = 's', long = "subject")]
+        subject: String,
+        #[arg(short = 'p', long = "predicate")]
+        predicate: String,
+        #[arg(short = 'o', long = "object")]
+        object: String,
+        /// Interpret the object as a string value rather than a node
+        #[arg(long)]
+        value: bool,
+        /// Layer in which to start the lookup
+        #[arg(short = 'l', long = "layer")]
+        layer: Option<String>,
+        /// Label in which to start the lookup
+        #[arg(short = 'g', long = "label")]
+        label: Option<String>,
+        /// The workdir to store mappings in.
+        /// Give more than once to search further stores for layers and
+        /// labels
+        #[arg(long = "store")]
+        store: Vec<String>,
+        /// Evaluate against the N-th ancestor of the layer instead
+        #[arg(long, default_value_t = 0)]
+        back: usize,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Pretty)]
+        format: OutputFormat,
+    },
+    /// Print the triples of a layer, including those of its ancestors
+    Triples {
+        /// Layer whose triples to print
+        #[arg(short = 'l', long = "layer")]
+        layer: Option<String>,
+        /// Label whose triples to print
+        #[arg(short = 'g', long = "label")]
+        label: Option<String>,
+        /// The workdir to store mappings in.
+        /// Give more than once to search further stores for layers and
+        /// labels
+        #[arg(short = 's', long = "store")]
+        store: Vec<String>,
+        /// Evaluate against the N-th ancestor of the layer instead
+        #[arg(long, default_value_t = 0)]
+        back: usize,
+        /// Only print triples whose object is a string in this language
+        #[arg(long)]
+        lang: Option<String>,
+        #[command(flatten)]
+        page: PageArgs,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Pretty)]
+        format: OutputFormat,
+    },
+    /// List the nodes with the most connections in a layer
+    Centrality {
+        #[arg(short = 'l', long = "layer")]
+        layer: Option<String>,
+        #[arg(short = 'g', long = "label")]
+        label: Option<String>,
+        /// The workdir to store mappings in.
+        /// Give more than once to search further stores for layers and
+        /// labels
+        #[arg(short = 's', long = "store")]
+        store: Vec<String>,
+        /// Number of nodes to list
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+        /// Degree to rank the nodes by
+        #[arg(long, value_enum, default_value_t = DegreeMetric::Total)]
+        metric: DegreeMetric,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Pretty)]
+        format: OutputFormat,
+    },
+    /// Print the node to node edges of a layer for use in graph tools
+    ExportEdgelist {
+        #[arg(short = 'l', long = "layer")]
+        layer: Option<String>,
+        #[arg(short = 'g', long = "label")]
+        label: Option<String>,
+        /// The workdir to store mappings in.
+        /// Give more than once to search further stores for layers and
+        /// labels
+        #[arg(short = 's', long = "store")]
+        store: Vec<String>,
+        /// Print node ids instead of IRIs
+        #[arg(long)]
+        numeric_ids: bool,
+        /// Print a Matrix Market coordinate matrix, which implies numeric ids
+        #[arg(long)]
+        matrix_market: bool,
+        /// Write the IRI of every node id used to this file
+        #[arg(long)]
+        mapping: Option<String>,
+    },
+    /// Print, as CSV, how many subjects use each pair of predicates
+    PredicateCooccurrence {
+        #[arg(short = 'l', long = "layer")]
+        layer: Option<String>,
+        #[arg(short = 'g', long = "label")]
+        label: Option<String>,
+        /// The workdir to store mappings in.
+        /// Give more than once to search further stores for layers and
+        /// labels
+        #[arg(short = 's', long = "store")]
+        store: Vec<String>,
+    },
+    /// List nodes that are referred to but never described. Exits with 1
+    /// if there are any.
+    DanglingObjects {
+        #[arg(short = 'l', long = "layer")]
+        layer: Option<String>,
+        #[arg(short = 'g', long = "label")]
+        label: Option<String>,
+        /// The workdir to store mappings in.
+        /// Give more than once to search further stores for layers and
+        /// labels
+        #[arg(short = 's', long = "store")]
+        store: Vec<String>,
+        /// Schema layer whose subjects, such as classes and enum values,
+        /// are not reported
+        #[arg(long)]
+        schema: Option<String>,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Pretty)]
+        format: OutputFormat,
+    },
+    /// Evaluate a file of assertions against a layer, such as `exists S P
+    /// O`, `absent S P O`, `count S P O >= N`, `node <iri>` and `class
+    /// <iri> >= N`, with a line of output per assertion. Exits with 1 if
+    /// any fails.
+    Assert {
+        /// Layer or label to evaluate against
+        target: String,
+        /// The assertions, one per line
+        #[arg(long)]
+        file: String,
+        /// The workdir to store mappings in.
+        /// Give more than once to search further stores for layers and
+        /// labels
+        #[arg(short = 's', long = "store")]
+        store: Vec<String>,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Pretty)]
+        format: OutputFormat,
+    },
+    /// List the instances that lack fields their class requires. Exits
+    /// with 1 if there are any.
+    CheckRequired {
+        /// Layer holding the schema graph
+        #[arg(long)]
+        schema: String,
+        /// Layer holding the instance graph
+        #[arg(long)]
+        instance: String,
+        /// The workdir to store mappings in.
+        /// Give more than once to search further stores for layers and
+        /// labels
+        #[arg(short = 's', long = "store")]
+        store: Vec<String>,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Pretty)]
+        format: OutputFormat,
+    },
+    /// Derive per-class property usage, cardinalities and datatypes from
+    /// the instance data of a layer, with a suggested schema to start from
+    InferSchema {
+        /// Layer holding the instance graph
+        layer: String,
+        /// The store directory.
+        /// Give more than once to search further stores for layers and
+        /// labels
+        #[arg(short = 's', long = "store")]
+        store: Vec<String>,
+        /// Where to write the inferred schema as JSON
+        #[arg(short, long)]
+        output: String,
+    },
+    /// Search the values of a layer by their decoded value
+    SearchValues {
+        #[arg(short = 'l', long = "layer")]
+        layer: Option<String>,
+        #[arg(short = 'g', long = "label")]
+        label: Option<String>,
+        /// The workdir to store mappings in.
+        /// Give more than once to search further stores for layers and
+        /// labels
+        #[arg(short = 's', long = "store")]
+        store: Vec<String>,
+        /// Find values equal to this lexical form
+        #[arg(long, required_unless_present = "range", conflicts_with = "range")]
+        equals: Option<String>,
+        /// Find values between two bounds (inclusive), written as `A..B`
+        #[arg(long, value_parser = values::parse_range)]
+        range: Option<(String, String)>,
+        /// Only consider values of this datatype, e.g. `xsd:integer`
+        #[arg(long)]
+        datatype: Option<String>,
+        /// Only consider strings tagged with this language
+        #[arg(long)]
+        lang: Option<String>,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Pretty)]
+        format: OutputFormat,
+    },
+    /// Count the language-tagged strings of a layer per language
+    LangStats {
+        #[arg(short = 'l', long = "layer")]
+        layer: Option<String>,
+        #[arg(short = 'g', long = "label")]
+        label: Option<String>,
+        /// The workdir to store mappings in.
+        /// Give more than once to search further stores for layers and
+        /// labels
+        #[arg(short = 's', long = "store")]
+        store: Vec<String>,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Pretty)]
+        format: OutputFormat,
+    },
+    /// Write everything a label's head or a layer holds, through its
+    /// chain, as standard RDF for importing elsewhere
+    Export {
+        #[arg(short = 'l', long = "layer")]
+        layer: Option<String>,
+        #[arg(short = 'g', long = "label", required_unless_present = "layer")]
+        label: Option<String>,
+        /// The store directory.
+        /// Give more than once to search further stores for layers and
+        /// labels
+        #[arg(short = 's', long = "store")]
+        store: Vec<String>,
+        #[arg(long, value_enum, default_value_t = rdf::RdfFormat::Ntriples)]
+        format: rdf::RdfFormat,
+        /// File to write
+        #[arg(short, long)]
+        output: String,
+    },
+    /// Export the head of every label in a store to its own file
+    ExportAll {
+        /// The store directory.
+        /// Give more than once to search further stores for layers and
+        /// labels
+        #[arg(short = 's', long = "store")]
+        store: Vec<String>,
+        /// Directory to write the exports and manifest to
+        #[arg(short, long)]
+        output: String,
+        #[arg(long, value_enum, default_value_t = ExportFormat::Ntriples)]
+        format: ExportFormat,
+        /// Number of labels to export at once
+        #[arg(long, default_value_t = 4)]
+        jobs: usize,
+        /// File to record progress in. Rerunning with the same file after
+        /// an interruption continues the exports instead of restarting
+        /// them.
+        #[arg(long)]
+        checkpoint: Option<String>,
+    },
+    /// Write the triples a label's head adds and removes relative to one
+    /// of its ancestors, as `added.nt` and `removed.nt`
+    ExportDelta {
+        #[arg(short = 'g', long = "label")]
+        label: String,
+        /// The ancestor to export the changes since
+        #[arg(long)]
+        since: String,
+        /// The store directory.
+        /// Give more than once to search further stores for layers and
+        /// labels
+        #[arg(short = 's', long = "store")]
+        store: Vec<String>,
+        /// Directory to write the files to
+        #[arg(short, long)]
+        output: String,
+        #[arg(long, value_enum, default_value_t = ExportFormat::Ntriples)]
+        format: ExportFormat,
+    },
+    /// Write the strings each layer of a label's chain added to its
+    /// dictionaries, one file per layer and dictionary
+    ExportDictDeltas {
+        #[arg(short = 'g', long = "label")]
+        label: String,
+        /// The store directory.
+        /// Give more than once to search further stores for layers and
+        /// labels
+        #[arg(short = 's', long = "store")]
+        store: Vec<String>,
+        /// Directory to write the files to
+        #[arg(short, long)]
+        output: String,
+    },
+    /// Time layer reads
+    Bench {
+        #[command(subcommand)]
+        action: BenchCommand,
+    },
+    /// Maintain an index of per-layer statistics next to the store
+    StatsIndex {
+        #[command(subcommand)]
+        action: StatsIndexCommand,
+    },
+    /// Print all current triples of one subject
+    ShowSubject {
+        subject: String,
+        #[arg(short = 'l', long = "layer")]
+        layer: Option<String>,
+        #[arg(short = 'g', long = "label")]
+        label: Option<String>,
+        /// The workdir to store mappings in.
+        /// Give more than once to search further stores for layers and
+        /// labels
+        #[arg(short = 's', long = "store")]
+        store: Vec<String>,
+        /// Show which layer of the chain added each triple
+        #[arg(long)]
+        annotate: bool,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Pretty)]
+        format: OutputFormat,
+    },
+    /// Resolve many ids to strings, or strings to ids, in one go. Prints
+    /// each input with its result, or None if it doesn't resolve.
+    Resolve {
+        /// File with one id or string per line
+        #[arg(long)]
+        ids_file: String,
+        #[arg(long, value_enum)]
+        direction: ResolveDirection,
+        #[arg(long, value_enum)]
+        kind: ResolveKind,
+        #[arg(short = 'l', long = "layer")]
+        layer: Option<String>,
+        #[arg(short = 'g', long = "label")]
+        label: Option<String>,
+        /// The workdir to store mappings in.
+        /// Give more than once to search further stores for layers and
+        /// labels
+        #[arg(short = 's', long = "store")]
+        store: Vec<String>,
+    },
+    /// Print the triples whose object is a value containing a string, in
+    /// the heads of all labels or the given ones
+    Grep {
+        /// Text to look for in the value dictionaries
+        #[arg(long)]
+        object_contains: String,
+        /// Comma separated labels to search. Defaults to all labels
+        #[arg(long, value_delimiter = ',')]
+        labels: Vec<String>,
+        /// The workdir to store mappings in.
+        /// Give more than once to search further stores for layers and
+        /// labels
+        #[arg(short = 's', long = "store")]
+        store: Vec<String>,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Pretty)]
+        format: OutputFormat,
+    },
+    /// Print which id each node, value and predicate of one layer has in
+    /// another, e.g. before and after a rebuild
+    MapIds {
+        layer_a: String,
+        layer_b: String,
+        /// The workdir to store mappings in.
+        /// Give more than once to search further stores for layers and
+        /// labels
+        #[arg(short = 's', long = "store")]
+        store: Vec<String>,
+    },
+    /// Verify an offline backup of a store, given as a directory or a tar
+    /// file, and print a summary. Exits with 1 if anything is wrong.
+    VerifyBackup {
+        backup: String,
+        /// File holding a key to sign the summary with
+        #[arg(long)]
+        key_file: Option<String>,
+        /// Where to write the summary. Defaults to stdout.
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Write a patch removing every triple about or referring to the listed
+    /// subjects, and list the layers that added them
+    Forget {
+        /// File with one subject IRI per line
+        subjects_file: String,
+        #[arg(short = 'g', long = "label")]
+        label: String,
+        /// The store directory.
+        /// Give more than once to search further stores for layers and
+        /// labels
+        #[arg(short = 's', long = "store")]
+        store: Vec<String>,
+        /// Where to write the patch
+        #[arg(short, long)]
+        output: String,
+    },
+    /// Apply a removal patch made by forget as a new layer on its label
+    ApplyPatch {
+        patch_file: String,
+        /// The store directory
+        #[arg(short = 's', long = "store")]
+        store: Option<String>,
+        /// Apply even if the label has moved since the patch was made
+        #[arg(long)]
+        force: bool,
+    },
+    /// Move every triple from one predicate to another in a new layer on
+    /// top of a label's head, and move the label to it
+    RenamePredicate {
+        old_iri: String,
+        new_iri: String,
+        #[arg(short = 'g', long = "label")]
+        label: String,
+        /// The store directory
+        #[arg(short = 's', long = "store")]
+        store: Option<String>,
+        /// Rename even if the new predicate is already in use, merging the
+        /// two
+        #[arg(long)]
+        merge: bool,
+    },
+    /// Rewrite a label's whole chain into a new store with every IRI under
+    /// one prefix moved under another, keeping the chain's history
+    RewriteNamespace {
+        /// The prefix to replace
+        #[arg(long)]
+        from: String,
+        /// The prefix to replace it with
+        #[arg(long)]
+        to: String,
+        #[arg(short = 'g', long = "label")]
+        label: String,
+        /// The store directory.
+        /// Give more than once to search further stores for layers and
+        /// labels
+        #[arg(short = 's', long = "store")]
+        store: Vec<String>,
+        /// Directory of the new store to write the rewritten chain to
+        #[arg(short, long)]
+        output: String,
+        /// Go ahead even if the target filesystem looks short of space
+        #[arg(long)]
+        force: bool,
+    },
+    /// Rewrite a label's whole chain into a new store with every occurrence
+    /// of a value removed
+    PurgeValue {
+        /// The value's object id in the head layer, or its lexical form
+        value: String,
+        #[arg(short = 'g', long = "label")]
+        label: String,
+        /// The store directory.
+        /// Give more than once to search further stores for layers and
+        /// labels
+        #[arg(short = 's', long = "store")]
+        store: Vec<String>,
+        /// Directory of the new store to write the rewritten chain to
+        #[arg(short, long)]
+        output: String,
+        /// Go ahead even if the target filesystem looks short of space
+        #[arg(long)]
+        force: bool,
+        /// Go ahead without asking for the store name to be typed
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Rewrite a label's whole chain into a new store with every value of
+    /// one datatype re-encoded
+    RecodeValues {
+        #[arg(short = 'g', long = "label")]
+        label: String,
+        /// The store directory.
+        /// Give more than once to search further stores for layers and
+        /// labels
+        #[arg(short = 's', long = "store")]
+        store: Vec<String>,
+        /// The datatype whose values to re-encode, such as xsd:decimal
+        #[arg(long)]
+        datatype: String,
+        #[arg(long, value_enum)]
+        strategy: recode::RecodeStrategy,
+        /// Directory of the new store to write the rewritten chain to
+        #[arg(short, long)]
+        output: String,
+        /// Go ahead even if the target filesystem looks short of space
+        #[arg(long)]
+        force: bool,
+        /// Go ahead without asking for the store name to be typed
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Copy the chains of all labels into a new store with every node,
+    /// predicate and value replaced by a pseudonym, so the store can be
+    /// shared without its data
+    Anonymize {
+        /// The store directory
+        #[arg(short = 's', long = "store")]
+        store: Option<String>,
+        /// Directory of the new store to write the anonymized chains to
+        #[arg(short, long)]
+        output: String,
+        /// Secret mixed into the pseudonyms. The same salt gives the same
+        /// pseudonyms on every run. Defaults to a random one.
+        #[arg(long)]
+        salt: Option<String>,
+        /// Go ahead even if the target filesystem looks short of space
+        #[arg(long)]
+        force: bool,
+    },
+    /// Create an empty store, to import or copy layers into without
+    /// starting a server first
+    Init {
+        /// The store directory. It may exist, but must be empty
+        #[arg(short = 's', long = "store")]
+        store: String,
+        /// Create a label without a head. Can be given more than once
+        #[arg(short = 'g', long = "label")]
+        labels: Vec<String>,
+    },
+    /// Check that the head history recorded in label files agrees with the
+    /// parent chains of their heads
+    CheckHeads {
+        /// The store directory
+        #[arg(short = 's', long = "store")]
+        store: Option<String>,
+        /// Rewrite inconsistent histories to the recorded heads that are
+        /// ancestors of the head, in chain order
+        #[arg(long)]
+        repair: bool,
+    },
+    /// Pin a layer so that store maintenance never removes it. Without a
+    /// layer, list the pinned layers.
+    Pin {
+        layer: Option<String>,
+        /// Why the layer is being kept
+        #[arg(long, default_value = "")]
+        reason: String,
+        /// The store directory
+        #[arg(short = 's', long = "store")]
+        store: Option<String>,
+    },
+    /// Remove a layer's pin
+    Unpin {
+        layer: String,
+        /// The store directory
+        #[arg(short = 's', long = "store")]
+        store: Option<String>,
+    },
+    /// Move layers that are old and far from every label's head to a cold
+    /// storage directory, leaving stubs that point at them
+    Tier {
+        /// The store directory
+        #[arg(short = 's', long = "store")]
+        store: Option<String>,
+        /// Only move layers not modified for this long, such as 365d
+        #[arg(long, value_parser = humantime::parse_duration)]
+        older_than: std::time::Duration,
+        /// Keep this many of the most recent layers of every label
+        #[arg(long, default_value_t = 10)]
+        keep_depth: usize,
+        /// Directory to move layers to, such as a mounted cold volume
+        #[arg(long)]
+        to: String,
+        /// Only list the layers that would be moved
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Point a child layer archive at a different parent layer
+    Reparent {
+        layer_file: String,
+        /// Id of the new parent layer
+        #[arg(long)]
+        new_parent: String,
+        /// Store containing the new parent
+        #[arg(short = 's', long = "store")]
+        store: Option<String>,
+        /// Skip the id range compatibility checks
+        #[arg(long)]
+        force: bool,
+        /// Where to write the modified archive. Defaults to rewriting the
+        /// layer file in place.
+        #[arg(short, long)]
+        output: Option<String>,
+        /// Go ahead without asking for the store name to be typed
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Point a label back at one of the ancestors of its head
+    Rollback {
+        label: String,
+        /// Layer to roll back to
+        #[arg(long, required_unless_present = "back", conflicts_with = "back")]
+        to: Option<String>,
+        /// Number of layers to roll back
+        #[arg(long)]
+        back: Option<usize>,
+        /// The store directory
+        #[arg(short = 's', long = "store")]
+        store: Option<String>,
+        /// Go ahead without asking for the store name to be typed
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Rewrite an archive without any bytes after its last segment
+    Canonicalize {
+        layer_file: String,
+        /// Append per-segment checksums, which verify checks
+        #[arg(long)]
+        with_checksums: bool,
+        /// Store whose audit log records the change. Defaults to the store
+        /// the layer file is in
+        #[arg(short = 's', long = "store")]
+        store: Option<String>,
+        /// Where to write the archive. Defaults to rewriting the layer file
+        /// in place.
+        #[arg(short, long)]
+        output: Option<String>,
+        /// Go ahead even if the target filesystem looks short of space
+        #[arg(long)]
+        force: bool,
+    },
+    /// Rewrite an archive from its segments, dropping any that are
+    /// excluded and anything after the last segment, and check that every
+    /// segment reads back the same
+    Repack {
+        layer_file: String,
+        output_file: String,
+        /// A segment to leave out, named as for extract. Give more than
+        /// once to leave out several
+        #[arg(long)]
+        exclude: Vec<String>,
+        /// Store whose audit log records the change. Defaults to the store
+        /// the layer file is in
+        #[arg(short = 's', long = "store")]
+        store: Option<String>,
+        /// Go ahead even if the target filesystem looks short of space
+        #[arg(long)]
+        force: bool,
+    },
+    /// Print the block structure of a dictionary
+    DumpDictBlocks {
+        layer_file: String,
+        #[arg(value_enum)]
+        dict_type: DictType,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Pretty)]
+        format: OutputFormat,
+    },
+    /// Print all readable entries of a damaged dictionary, with a report of
+    /// the stretches that couldn't be decoded
+    SalvageDict {
+        layer_file: String,
+        #[arg(value_enum)]
+        dict_type: DictType,
+    },
+    /// Check the invariants of an adjacency list, given as nums and bits
+    /// files or as all adjacency lists of a layer archive
+    CheckAdjacency {
+        nums_file: Option<String>,
+        bits_file: Option<String>,
+        /// Check all adjacency lists in this layer archive instead
+        #[arg(long)]
+        layer_file: Option<String>,
+        /// Whether empty groups are allowed in the given nums and bits
+        #[arg(long)]
+        allow_empty: bool,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Pretty)]
+        format: OutputFormat,
+    },
+    /// Verify the contents of a layer archive
+    Verify {
+        layer_file: String,
+        /// Only check headers, control words, the first and last dictionary
+        /// blocks and a random sample of index blocks
+        #[arg(long)]
+        quick: bool,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Pretty)]
+        format: OutputFormat,
+    },
+    /// Run the quick checks on a layer archive and name the most likely
+    /// cause of any damage, with the commands to run next
+    Diagnose {
+        layer_file: String,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Pretty)]
+        format: OutputFormat,
+    },
+    /// Generate random layers in a scratch store and check that this tool
+    /// reads, verifies and canonicalizes them correctly
+    SelfTest {
+        /// Number of chains to generate
+        #[arg(long, default_value_t = 10)]
+        rounds: usize,
+        /// Number of layers in each chain
+        #[arg(long, default_value_t = 3)]
+        chain_length: usize,
+        /// Upper bound on the triples added per layer, which also sets the
+        /// number of distinct nodes and values
+        #[arg(long, default_value_t = 100)]
+        size: usize,
+        /// Seed for the generator, to reproduce an earlier run
+        #[arg(long)]
+        seed: Option<u64>,
+        /// Directory for the scratch store. Defaults to a temporary
+        /// directory that is removed afterwards.
+        #[arg(long)]
+        scratch: Option<String>,
+    },
+    /// Open a label through the store API as the server would, walk its
+    /// head and cross-check random point lookups against the raw
+    /// segments of its chain. Exits with 1 if any check fails.
+    Smoke {
+        #[arg(short = 's', long = "store")]
+        store: Option<String>,
+        #[arg(short = 'g', long = "label")]
+        label: String,
+        /// Number of triples to sample for point lookups
+        #[arg(long, default_value_t = 1000)]
+        lookups: usize,
+        /// Seed for the sampling, to reproduce an earlier run
+        #[arg(long)]
+        seed: Option<u64>,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Pretty)]
+        format: OutputFormat,
+    },
+    /// Explain a finding code, such as SURG-E012, with its likely causes and
+    /// how to repair it. Without a code, list all codes.
+    Explain { code: Option<String> },
+    /// Print the JSON schema of the lines a command prints with `--format
+    /// ndjson` or `--json`. Every line carries the `format_version` it
+    /// follows.
+    Schema {
+        /// The command, e.g. `fsck` or `stats-index show`
+        #[arg(required = true, num_args = 1..)]
+        command: Vec<String>,
+    },
+    /// Print the ancestry of a layer, head first, with each layer's triple
+    /// counts and rollup. Stops at a missing or unreadable ancestor and
+    /// exits with 1.
+    Chain {
+        #[arg(short = 'l', long = "layer", required_unless_present = "label")]
+        layer: Option<String>,
+        #[arg(short = 'g', long = "label", conflicts_with = "layer")]
+        label: Option<String>,
+        /// The store directory.
+        /// Give more than once to search further stores for layers and
+        /// labels
+        #[arg(short = 's', long = "store")]
+        store: Vec<String>,
+    },
+    /// Check that every layer in a label's chain only refers to ids that
+    /// its ancestors and itself have interned, and that its own ids start
+    /// right after its ancestors'
+    CheckChain {
+        label: String,
+        /// The store directory.
+        /// Give more than once to search further stores for layers and
+        /// labels
+        #[arg(short = 's', long = "store")]
+        store: Vec<String>,
+    },
+    /// Write the current triples of a label's whole chain into one new
+    /// standalone base layer archive, to compact a chain offline or to
+    /// salvage its net content
+    Squash {
+        #[arg(short = 'g', long = "label")]
+        label: String,
+        /// The store directory.
+        /// Give more than once to search further stores for layers and
+        /// labels
+        #[arg(short = 's', long = "store")]
+        store: Vec<String>,
+        /// The archive file to write the squashed layer to
+        #[arg(short, long)]
+        output: String,
+        /// Go ahead even if the target filesystem looks short of space
+        #[arg(long)]
+        force: bool,
+    },
+    /// Check that a squashed layer holds exactly the triples of a label's
+    /// chain, and print any triples on which they differ. Exits with 1 if
+    /// they differ.
+    CheckSquash {
+        /// Label whose head the layer was squashed from
+        #[arg(long)]
+        original: String,
+        /// The squashed layer
+        #[arg(long)]
+        squashed: String,
+        /// Number of triples from each side to look up in the other
+        #[arg(long, default_value_t = 1000)]
+        samples: usize,
+        /// The store directory.
+        /// Give more than once to search further stores for layers and
+        /// labels
+        #[arg(short = 's', long = "store")]
+        store: Vec<String>,
+    },
+    /// Build a standalone base layer archive from an N-Triples file, such
+    /// as one written by export
+    BuildLayer {
+        input: String,
+        /// The archive file to write the layer to
+        output: String,
+        /// Go ahead even if the target filesystem looks short of space
+        #[arg(long)]
+        force: bool,
+        /// Store literals of datatypes without an encoding here as strings
+        /// instead of failing
+        #[arg(long)]
+        allow_string_fallback: bool,
+    },
+    /// List, print or repoint labels by reading and writing their files
+    /// directly
+    Label {
+        #[command(subcommand)]
+        action: LabelCommand,
+    },
+    /// Inspect or edit the metadata of a layer archive
+    Meta {
+        #[command(subcommand)]
+        action: MetaCommand,
+    },
+    /// Inspect, remove or rebuild the rollup reference of a layer
+    Rollup {
+        #[command(subcommand)]
+        action: RollupCommand,
+    },
+    /// Write a tar backup of a store along with its manifest, optionally
+    /// only of the layers that are new since an earlier backup
+    Backup {
+        /// The store directory
+        #[arg(short = 's', long = "store")]
+        store: Option<String>,
+        /// The tar file to write. Its manifest is also written next to it,
+        /// with .manifest.json appended to the name.
+        #[arg(short, long)]
+        output: String,
+        /// Manifest of an earlier backup. Only layers not in it are
+        /// archived.
+        #[arg(long)]
+        since: Option<String>,
+    },
+    /// Package the layers of a chain between two of its layers into a tar,
+    /// to bring a replica that has the older one up to the newer one. The
+    /// end layers are left out unless included
+    ExportRange {
+        /// The older layer, an ancestor of --to
+        #[arg(long)]
+        from: String,
+        /// The newer layer
+        #[arg(long)]
+        to: String,
+        /// Package the --from layer too
+        #[arg(long)]
+        include_from: bool,
+        /// Package the --to layer too
+        #[arg(long)]
+        include_to: bool,
+        /// The store directory.
+        /// Give more than once to search further stores for layers and
+        /// labels
+        #[arg(short = 's', long = "store")]
+        store: Vec<String>,
+        /// The tar file to write. Its manifest is also written next to it,
+        /// with .manifest.json appended to the name.
+        #[arg(short, long)]
+        output: String,
+    },
+    /// Restore a full backup followed by incremental ones into an empty
+    /// directory
+    Restore {
+        /// Backup tar files, oldest first
+        #[arg(required = true)]
+        backups: Vec<String>,
+        #[arg(short, long)]
+        output: String,
+    },
+    /// Download a layer from a path, an http(s) URL or an s3:// URL,
+    /// keeping it only if its sha256 is the one expected. http and s3
+    /// downloads go through curl and the AWS CLI
+    Fetch {
+        source: String,
+        /// The sha256 the layer must have, in hex
+        #[arg(long, value_parser = fetch::parse_sha256)]
+        expect_sha256: String,
+        /// Where to write the layer
+        #[arg(short, long)]
+        output: String,
+    },
+    /// Compare backup manifests
+    Manifest {
+        #[command(subcommand)]
+        action: ManifestCommand,
+    },
+    /// Show the record of mutating operations performed on a store
+    Audit {
+        #[command(subcommand)]
+        action: AuditCommand,
+    },
+    /// Print a shell completion script
+    Completions {
+        #[arg(value_enum)]
+        shell: clap_complete::Shell,
+    },
+    /// List labels or layer ids for shell completion
+    #[command(hide = true)]
+    Complete {
+        #[arg(value_enum)]
+        kind: CompletionKind,
+        #[arg(default_value = "")]
+        prefix: String,
+        /// The store directory. Give more than once to search further
+        /// stores for layers and labels
+        #[arg(short = 's', long = "store")]
+        store: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum AuditCommand {
+    /// Print the audit log
+    Show {
+        /// The store directory
+        #[arg(short = 's', long = "store")]
+        store: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ManifestCommand {
+    /// List the layers and labels that differ between two manifests
+    Diff { old: String, new: String },
+}
+
+#[derive(Subcommand)]
+enum LabelCommand {
+    /// Print every label of a store with the layer it points at
+    List {
+        /// The store directory
+        #[arg(short = 's', long = "store")]
+        store: Option<String>,
+    },
+    /// Print the layer a label points at
+    Get {
+        label: String,
+        /// The store directory
+        #[arg(short = 's', long = "store")]
+        store: Option<String>,
+    },
+    /// Point a label at a layer
+    Set {
+        label: String,
+        layer: String,
+        /// The store directory
+        #[arg(short = 's', long = "store")]
+        store: Option<String>,
+        /// Point the label at the layer even if the store doesn't have it
+        #[arg(long)]
+        force: bool,
+        /// Go ahead without asking for the store name to be typed
+        #[arg(short, long)]
+        yes: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum MetaCommand {
+    /// Print the parent, rollup and file details of a layer
+    Show { layer_file: String },
+    /// Change a metadata value. Only `parent` is supported.
+    Set {
+        layer_file: String,
+        key: String,
+        value: String,
+        /// Store in which the new value must refer to an existing layer,
+        /// and whose audit log records the change. Defaults to the store
+        /// the layer file is in
+        #[arg(short = 's', long = "store")]
+        store: Option<String>,
+        /// Where to write the modified archive. Defaults to rewriting the
+        /// layer file in place.
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum RollupCommand {
+    /// Print the rollup a layer refers to and whether it can be opened
+    Show {
+        layer_file: String,
+        /// Store in which to look for the rollup layer
+        #[arg(short = 's', long = "store")]
+        store: Option<String>,
+    },
+    /// Rewrite a layer without its rollup segment, so it is opened through
+    /// its plain chain
+    Strip {
+        layer_file: String,
+        /// Where to write the stripped archive. Defaults to rewriting the
+        /// layer file in place.
+        #[arg(short, long)]
+        output: Option<String>,
+        /// Store whose audit log records the change. Defaults to the store
+        /// the layer file is in
+        #[arg(short = 's', long = "store")]
+        store: Option<String>,
+        /// Go ahead even if the target filesystem looks short of space
+        #[arg(long)]
+        force: bool,
+    },
+    /// Regenerate the rollup of a layer from its ancestor chain
+    Rebuild {
+        /// Store holding the layer and its ancestors
+        #[arg(short = 's', long = "store")]
+        store: Option<String>,
+        #[arg(short = 'l', long = "layer")]
+        layer: String,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum ResolveDirection {
+    IdToString,
+    StringToId,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum ResolveKind {
+    Node,
+    Predicate,
+    /// String values
+    Value,
+}
+
+/// The position of a term in a triple, which decides the dictionary its
+/// id belongs to.
+#[derive(ValueEnum, Clone, Copy)]
+enum TermPosition {
+    Subject,
+    Predicate,
+    /// Nodes or values in object position
+    Object,
+    /// Only values in object position
+    Value,
+}
+
+#[derive(Subcommand)]
+enum BenchCommand {
+    /// Run the same workloads against two encodings of the same data and
+    /// compare their timings
+    Compare {
+        /// The layer file to compare against
+        #[arg(long)]
+        baseline: String,
+        /// The layer file to compare
+        #[arg(long)]
+        candidate: String,
+        /// Timed runs of each workload on each layer
+        #[arg(long, default_value_t = 10)]
+        iterations: usize,
+        /// Untimed runs of each workload on each layer before timing
+        #[arg(long, default_value_t = 1)]
+        warmup: usize,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Pretty)]
+        format: OutputFormat,
+    },
+}
+
+#[derive(Subcommand)]
+enum StatsIndexCommand {
+    /// Create or refresh the index, reading only new and changed layers
+    Build {
+        #[arg(short = 's', long = "store")]
+        store: Option<String>,
+    },
+    /// Print the indexed statistics
+    Show {
+        #[arg(short = 's', long = "store")]
+        store: Option<String>,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Pretty)]
+        format: OutputFormat,
+    },
+}
+
+#[derive(Subcommand)]
+enum MerkleCommand {
+    /// Check the store against a previously saved tree
+    Verify { tree_file: String },
+    /// List the layers and segments that differ between a saved tree and
+    /// the store (or a second saved tree)
+    Diff {
+        tree_file: String,
+        new_tree_file: Option<String>,
+    },
+}
+
+impl Commands {
+    /// The `--format` of commands that have one, which `--json` overrides.
+    fn format_mut(&mut self) -> Option<&mut OutputFormat> {
+        match self {
+            Commands::ParseHeader { format, .. }
+            | Commands::Stats { format, .. }
+            | Commands::ValidateLayer { format, .. }
+            | Commands::ValidateStore { format, .. }
+            | Commands::CheckCounts { format, .. }
+            | Commands::Fsck { format, .. }
+            | Commands::Scan { format, .. }
+            | Commands::Databases { format, .. }
+            | Commands::ScanStore { format, .. }
+            | Commands::Watch { format, .. }
+            | Commands::HasTriple { format, .. }
+            | Commands::Triples { format, .. }
+            | Commands::Centrality { format, .. }
+            | Commands::DanglingObjects { format, .. }
+            | Commands::CheckRequired { format, .. }
+            | Commands::Assert { format, .. }
+            | Commands::Smoke { format, .. }
+            | Commands::SearchValues { format, .. }
+            | Commands::LangStats { format, .. }
+            | Commands::ShowSubject { format, .. }
+            | Commands::Grep { format, .. }
+            | Commands::DumpDictBlocks { format, .. }
+            | Commands::CheckAdjacency { format, .. }
+            | Commands::Verify { format, .. }
+            | Commands::Diagnose { format, .. }
+            | Commands::StatsIndex {
+                action: StatsIndexCommand::Show { format, .. },
+            }
+            | Commands::Bench {
+                action: BenchCommand::Compare { format, .. },
+            } => Some(format),
+            _ => None,
+        }
+    }
+}
+
+/// Parse a layer name given on the command line.
+fn parse_name(name: &str) -> error::Result<[u32; 5]> {
+    string_to_name(name).map_err(|_| Error::usage(format!("{name} is not a layer name")))
+}
+
+fn open_layer_or_label(
+    store_path: &str,
+    layer: Option<String>,
+    label: Option<String>,
+) -> error::Result<Box<SyncStoreLayer>> {
+    // with overlays, open whichever store has the layer or label. Its
+    // chain has to be complete within that store.
+    let store_path = match (&layer, &label) {
+        (Some(layer_name), None) => {
+            store::store_with_layer(Path::new(store_path), parse_name(layer_name)?)
+        }
+        (None, Some(label_name)) => store::store_with_label(Path::new(store_path), label_name),
+        _ => PathBuf::from(store_path),
+    };
+    let store = open_sync_archive_store(&store_path, 512);
+    let layer = match (layer, label) {
+        (None, Some(label_name)) => {
+            let head = match store::attached_label(&store_path, &label_name) {
+                Some(head) => head?
+                    .map(|head| store.get_layer_from_id(head))
+                    .transpose()?
+                    .flatten(),
+                None => store
+                    .open(&label_name)?
+                    .context(format!("label {label_name} not found"))?
+                    .head()?,
+            };
+            head.context(format!("label {label_name} has no head"))?
+        }
+        (Some(layer_name), None) => store
+            .get_layer_from_id(parse_name(&layer_name)?)?
+            .context(format!("layer {layer_name} not found"))?,
+        _ => return Err(Error::usage("specify either a layer or a label")),
+    };
+
+    Ok(Box::new(layer))
+}
+
+/// Use the first of the given stores (or the current directory), and
+/// search the others in order for layers and labels it lacks.
+fn store_search_path(stores: Vec<String>) -> String {
+    let mut stores = stores.into_iter();
+    let primary = stores.next().unwrap_or_else(|| ".".to_string());
+    store::set_overlays(stores.map(PathBuf::from).collect());
+    primary
+}
+
+/// Walk `back` steps up the chain of a layer.
+fn ancestor(mut layer: Box<SyncStoreLayer>, back: usize) -> error::Result<Box<SyncStoreLayer>> {
+    for step in 0..back {
+        match layer.parent()? {
+            Some(parent) => layer = Box::new(parent),
+            None => {
+                return Err(Error::not_found(format!(
+                    "layer has only {step} ancestors, cannot go back {back}"
+                )))
+            }
+        }
+    }
+    Ok(layer)
+}
+
+fn node_id(
+    store: &str,
+    layer: Option<String>,
+    label: Option<String>,
+    position: TermPosition,
+    node: &str,
+) -> error::Result<Option<u64>> {
+    let layer = open_layer_or_label(store, layer, label)?;
+    Ok(match position {
+        TermPosition::Subject => layer.subject_id(node),
+        TermPosition::Predicate => layer.predicate_id(node),
+        TermPosition::Object => layer.object_node_id(node),
+        TermPosition::Value => match ValueTriple::new_string_value("", "", node).object {
+            ObjectType::Value(value) => layer.object_value_id(&value),
+            ObjectType::Node(_) => None,
+        },
+    })
+}
+
+fn id_node(
+    store: &str,
+    layer: Option<String>,
+    label: Option<String>,
+    position: TermPosition,
+    id: &str,
+) -> error::Result<Option<String>> {
+    let layer = open_layer_or_label(store, layer, label)?;
+    let id = id
+        .parse()
+        .map_err(|_| Error::usage(format!("{id} is not an id")))?;
+    Ok(match position {
+        TermPosition::Subject => layer.id_subject(id),
+        TermPosition::Predicate => layer.id_predicate(id),
+        TermPosition::Object | TermPosition::Value => match layer.id_object(id) {
+            Some(ObjectType::Node(node)) if matches!(position, TermPosition::Object) => Some(node),
+            Some(ObjectType::Node(_)) | None => None,
+            Some(ObjectType::Value(value)) => Some(values::typed_literal(
+                &format!("{:?}", value.datatype()),
+                &value.to_bytes(),
+            )),
+        },
+    })
+}
+
+fn has_triple(
+    store: &str,
+    layer: Option<String>,
+    label: Option<String>,
+    back: usize,
+    triple: &ValueTriple,
+) -> error::Result<bool> {
+    let layer = ancestor(open_layer_or_label(store, layer, label)?, back)?;
+    Ok(layer.value_triple_exists(triple))
+}
+
+fn print_triples(
+    store: &str,
+    layer: Option<String>,
+    label: Option<String>,
+    back: usize,
+    lang: Option<&str>,
+    page: &PageArgs,
+    format: OutputFormat,
+) -> error::Result<()> {
+    let layer = ancestor(open_layer_or_label(store, layer, label)?, back)?;
+    let mut emitted = 0;
+    let mut last = None;
+    let mut abbreviator = Abbreviator::default();
+    let triples = layer.triples().filter(|triple| match lang {
+        Some(lang) => layer
+            .id_object(triple.object)
+            .map(|object| values::has_lang(&object, lang))
+            .unwrap_or(false),
+        None => true,
+    });
+    for triple in page.apply(triples) {
+        let resolved = triples::resolve(&layer, &triple)?;
+        match format {
+            OutputFormat::Pretty => {
+                let object = match &resolved.object {
+                    ObjectType::Node(node) => abbreviator.abbreviate(node),
+                    object => format_object(object),
+                };
+                println!(
+                    "{}  {}  {}",
+                    abbreviator.abbreviate(&resolved.subject),
+                    abbreviator.abbreviate(&resolved.predicate),
+                    object
+                );
+            }
+            OutputFormat::Text => println!("{}", rdf::ntriples_line(&resolved)),
+            OutputFormat::Ndjson => println!(
+                "{}",
+                ndjson(json!({
+                    "subject": resolved.subject,
+                    "predicate": resolved.predicate,
+                    "object": object_json(&resolved.object),
+                }))
+            ),
+        }
+        emitted += 1;
+        last = Some(triple);
+    }
+    abbreviator.print_legend();
+    page.report_cursor(emitted, last);
+
+    Ok(())
+}
+
+fn show_subject(
+    store: &str,
+    subject: &str,
+    layer: Option<String>,
+    label: Option<String>,
+    annotate: bool,
+    format: OutputFormat,
+) -> error::Result<bool> {
+    let layer = open_layer_or_label(store, layer, label)?;
+    let subject_triples = match triples::subject_triples(&layer, subject, annotate)? {
+        Some(subject_triples) => subject_triples,
+        None => return Ok(false),
+    };
+    let mut abbreviator = Abbreviator::default();
+    for (resolved, added_in) in subject_triples.iter() {
+        match format {
+            OutputFormat::Pretty => {
+                let object = match &resolved.object {
+                    ObjectType::Node(node) => abbreviator.abbreviate(node),
+                    object => format_object(object),
+                };
+                let added_in = added_in
+                    .as_ref()
+                    .map(|name| format!("  {}", paint(name, Color::Dim)))
+                    .unwrap_or_default();
+                println!(
+                    "{}  {object}{added_in}",
+                    abbreviator.abbreviate(&resolved.predicate)
+                );
+            }
+            OutputFormat::Text => println!(
+                "{}{}",
+                rdf::ntriples_line(resolved),
+                added_in
+                    .as_ref()
+                    .map(|name| format!(" # {name}"))
+                    .unwrap_or_default()
+            ),
+            OutputFormat::Ndjson => println!(
+                "{}",
+                ndjson(json!({
+                    "subject": resolved.subject,
+                    "predicate": resolved.predicate,
+                    "object": object_json(&resolved.object),
+                    "layer": added_in,
+                }))
+            ),
+        }
+    }
+    abbreviator.print_legend();
+    Ok(true)
+}
+
+fn resolve_one(
+    layer: &SyncStoreLayer,
+    direction: ResolveDirection,
+    kind: ResolveKind,
+    input: &str,
+) -> Option<String> {
+    match direction {
+        ResolveDirection::IdToString => {
+            let id = input.parse().ok()?;
+            match kind {
+                ResolveKind::Node => layer.id_subject(id),
+                ResolveKind::Predicate => layer.id_predicate(id),
+                ResolveKind::Value => match layer.id_object(id)? {
+                    ObjectType::Value(value) => {
+                        Some(String::from_utf8_lossy(&value.to_bytes()).to_string())
+                    }
+                    ObjectType::Node(_) => None,
+                },
+            }
+        }
+        ResolveDirection::StringToId => match kind {
+            ResolveKind::Node => layer.subject_id(input),
+            ResolveKind::Predicate => layer.predicate_id(input),
+            ResolveKind::Value => match ValueTriple::new_string_value("", "", input).object {
+                ObjectType::Value(value) => layer.object_value_id(&value),
+                ObjectType::Node(_) => None,
+            },
+        }
+        .map(|id| id.to_string()),
+    }
+}
+
+async fn resolve(
+    store: &str,
+    ids_file: String,
+    direction: ResolveDirection,
+    kind: ResolveKind,
+    layer: Option<String>,
+    label: Option<String>,
+) -> error::Result<()> {
+    let inputs = tokio::fs::read_to_string(&ids_file)
+        .await
+        .context(&ids_file)?;
+    let layer = open_layer_or_label(store, layer, label)?;
+    for input in inputs.lines().filter(|l| !l.is_empty()) {
+        match resolve_one(&layer, direction, kind, input) {
+            Some(result) => println!("{input}\t{result}"),
+            None => println!("{input}\tNone"),
+        }
+    }
+
+    Ok(())
+}
+
+async fn grep(
+    store: &str,
+    needle: &str,
+    labels: Vec<String>,
+    format: OutputFormat,
+) -> error::Result<usize> {
+    let labels = if labels.is_empty() {
+        store::list_labels(Path::new(store)).await?
+    } else {
+        labels
+    };
+    let mut count = 0;
+    for label in labels {
+        if store::read_label(Path::new(store), &label).await?.is_none() {
+            continue;
+        }
+        let layer = open_layer_or_label(store, None, Some(label.clone()))?;
+        for resolved in triples::with_value_containing(&layer, needle)? {
+            count += 1;
+            match format {
+                OutputFormat::Pretty => println!(
+                    "{}  {}  {}  {}",
+                    paint(&label, Color::Dim),
+                    resolved.subject,
+                    resolved.predicate,
+                    format_object(&resolved.object)
+                ),
+                OutputFormat::Text => {
+                    println!("{label}\t{}", rdf::ntriples_line(&resolved))
+                }
+                OutputFormat::Ndjson => println!(
+                    "{}",
+                    ndjson(json!({
+                        "label": label,
+                        "subject": resolved.subject,
+                        "predicate": resolved.predicate,
+                        "object": object_json(&resolved.object),
+                    }))
+                ),
+            }
+        }
+    }
+
+    Ok(count)
+}
+
+fn map_ids(store: &str, layer_a: String, layer_b: String) -> error::Result<()> {
+    let a = open_layer_or_label(store, Some(layer_a), None)?;
+    let b = open_layer_or_label(store, Some(layer_b), None)?;
+    let mut mapped = 0;
+    let mut unmapped = 0;
+    for mapping in ids::map_ids(&a, &b) {
+        match mapping.other {
+            Some(other) => {
+                mapped += 1;
+                println!("{}\t{}\t{other}", mapping.kind, mapping.id);
+            }
+            None => {
+                unmapped += 1;
+                println!("{}\t{}\t-", mapping.kind, mapping.id);
+            }
+        }
+    }
+    eprintln!("{mapped} mapped, {unmapped} without a counterpart");
+
+    Ok(())
+}
+
+fn centrality(
+    store: &str,
+    layer: Option<String>,
+    label: Option<String>,
+    top: usize,
+    metric: DegreeMetric,
+    format: OutputFormat,
+) -> error::Result<()> {
+    let layer = open_layer_or_label(store, layer, label)?;
+    let mut abbreviator = Abbreviator::default();
+    if format == OutputFormat::Pretty {
+        println!(
+            "{}",
+            paint(
+                &format!("{:>10} {:>10} {:>10}  node", "total", "in", "out"),
+                Color::Dim
+            )
+        );
+    }
+    for (id, node, degree) in graph::central_nodes(&layer, metric, top) {
+        let total = degree.get(DegreeMetric::Total);
+        match format {
+            OutputFormat::Pretty => println!(
+                "{total:>10} {:>10} {:>10}  {}",
+                degree.in_degree,
+                degree.out_degree,
+                abbreviator.abbreviate(&node)
+            ),
+            OutputFormat::Text => println!(
+                "{total}\t{}\t{}\t{node}",
+                degree.in_degree, degree.out_degree
+            ),
+            OutputFormat::Ndjson => println!(
+                "{}",
+                ndjson(json!({
+                    "node": node,
+                    "id": id,
+                    "in": degree.in_degree,
+                    "out": degree.out_degree,
+                    "total": total,
+                }))
+            ),
+        }
+    }
+    abbreviator.print_legend();
+
+    Ok(())
+}
+
+async fn export_edgelist(
+    store: &str,
+    layer: Option<String>,
+    label: Option<String>,
+    numeric_ids: bool,
+    matrix_market: bool,
+    mapping: Option<String>,
+) -> error::Result<()> {
+    let layer = open_layer_or_label(store, layer, label)?;
+    let edges = graph::edges(&layer);
+    let node = |id: u64| layer.id_subject(id).unwrap_or_else(|| id.to_string());
+    if matrix_market {
+        let size = edges.iter().map(|(s, o)| *s.max(o)).max().unwrap_or(0);
+        println!("%%MatrixMarket matrix coordinate pattern general");
+        println!("{size} {size} {}", edges.len());
+    }
+    for (subject, object) in edges.iter() {
+        if numeric_ids || matrix_market {
+            println!("{subject} {object}");
+        } else {
+            println!("{} {}", node(*subject), node(*object));
+        }
+    }
+
+    if let Some(mapping) = mapping {
+        let ids: BTreeSet<u64> = edges.iter().flat_map(|(s, o)| [*s, *o]).collect();
+        let mut out = String::new();
+        for id in ids {
+            out.push_str(&format!("{id} {}\n", node(id)));
+        }
+        tokio::fs::write(&mapping, out).await.context(&mapping)?;
+    }
+
+    Ok(())
+}
+
+fn predicate_cooccurrence(
+    store: &str,
+    layer: Option<String>,
+    label: Option<String>,
+) -> error::Result<()> {
+    let layer = open_layer_or_label(store, layer, label)?;
+    let predicate = |id: u64| csv_field(&layer.id_predicate(id).unwrap_or_else(|| id.to_string()));
+    println!("predicate_a,predicate_b,subjects");
+    for ((a, b), subjects) in graph::predicate_cooccurrence(&layer) {
+        println!("{},{},{subjects}", predicate(a), predicate(b));
+    }
+
+    Ok(())
+}
+
+fn dangling_objects(
+    store: &str,
+    layer: Option<String>,
+    label: Option<String>,
+    schema: Option<String>,
+    format: OutputFormat,
+) -> error::Result<bool> {
+    let layer = open_layer_or_label(store, layer, label)?;
+    let schema = schema
+        .map(|schema| open_layer_or_label(store, Some(schema), None))
+        .transpose()?;
+    let mut found = false;
+    let mut abbreviator = Abbreviator::default();
+    for (id, references) in graph::dangling_objects(&layer) {
+        let node = layer.id_subject(id).unwrap_or_else(|| id.to_string());
+        if let Some(schema) = schema.as_ref() {
+            if schema.subject_id(&node).is_some() {
+                continue;
+            }
+        }
+        found = true;
+        match format {
+            OutputFormat::Pretty => {
+                println!("{references:>8}  {}", abbreviator.abbreviate(&node))
+            }
+            OutputFormat::Text => println!("{node}"),
+            OutputFormat::Ndjson => {
+                println!(
+                    "{}",
+                    ndjson(json!({ "node": node, "references": references }))
+                )
+            }
+        }
+    }
+    abbreviator.print_legend();
+    Ok(found)
+}
+
+fn check_required(
+    store: &str,
+    schema: String,
+    instance: String,
+    format: OutputFormat,
+) -> error::Result<bool> {
+    let schema = open_layer_or_label(store, Some(schema), None)?;
+    let instance = open_layer_or_label(store, Some(instance), None)?;
+    let required = schema::required_fields(&schema);
+    let missing = schema::missing_fields(&instance, &required);
+    for (class, instances) in missing.iter() {
+        match format {
+            OutputFormat::Pretty => {
+                println!(
+                    "{} ({} instances)",
+                    paint(class, Color::Yellow),
+                    instances.len()
+                );
+                for (subject, fields) in instances {
+                    println!("  {subject}: missing {}", fields.join(", "));
+                }
+            }
+            OutputFormat::Text => {
+                for (subject, fields) in instances {
+                    println!("{class} {subject} {}", fields.join(" "));
+                }
+            }
+            OutputFormat::Ndjson => {
+                for (subject, fields) in instances {
+                    println!(
+                        "{}",
+                        ndjson(json!({ "class": class, "subject": subject, "missing": fields }))
+                    );
+                }
+            }
+        }
+    }
+    Ok(missing.is_empty())
+}
+
+fn search_values(
+    store: &str,
+    layer: Option<String>,
+    label: Option<String>,
+    query: &ValueQuery,
+    datatype: Option<&str>,
+    lang: Option<&str>,
+    format: OutputFormat,
+) -> error::Result<()> {
+    let layer = open_layer_or_label(store, layer, label)?;
+    for found in values::search(&layer, query, datatype, lang) {
+        match format {
+            OutputFormat::Pretty => println!(
+                "{:>10}  {}  {}",
+                found.id,
+                paint(&format!("{:<8}", found.datatype), Color::Dim),
+                found.value
+            ),
+            OutputFormat::Text => println!("{} {} {}", found.id, found.datatype, found.value),
+            OutputFormat::Ndjson => println!(
+                "{}",
+                ndjson(json!({
+                    "id": found.id,
+                    "datatype": found.datatype,
+                    "value": found.value.to_string(),
+                }))
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+fn lang_stats(
+    store: &str,
+    layer: Option<String>,
+    label: Option<String>,
+    format: OutputFormat,
+) -> error::Result<()> {
+    let layer = open_layer_or_label(store, layer, label)?;
+    for (tag, count) in values::lang_stats(&layer) {
+        match format {
+            OutputFormat::Pretty => println!("{tag:<12} {count:>10}"),
+            OutputFormat::Text => println!("{tag} {count}"),
+            OutputFormat::Ndjson => println!("{}", ndjson(json!({ "lang": tag, "count": count }))),
+        }
+    }
+
+    Ok(())
+}
+
+async fn node_count(
+    store: &str,
+    layer: Option<String>,
+    label: Option<String>,
+) -> error::Result<Option<u64>> {
+    let backend = DirectoryArchiveBackend::new(store.into());
+    let archive_store = ArchiveLayerStore::new(backend.clone(), backend);
+    let layer_name = open_layer_or_label(store, layer, label)?.name();
+    Ok(archive_store.get_node_count(layer_name).await?)
+}
+
+async fn get_triple_count(layer: String) -> io::Result<()> {
+    let layer = LayerSource::open(&layer);
+    let header = layer.header().await?;
+    let range = header
+        .segments
+        .iter()
+        .find(|s| s.segment == LayerFileEnum::PosSpOAdjacencyListNums)
+        .map(|s| s.range.clone())
+        .ok_or_else(|| absent_segment(LayerFileEnum::PosSpOAdjacencyListNums))?;
+    // the control word closes the logarray
+    let end = (header.header_len + range.end) as u64;
+    let buf = layer.read_range(end.saturating_sub(8)..end).await?;
+    if buf.len() != 8 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "layer file ends before the control word",
+        ));
+    }
+    let (size, _width) = parse_control_word(&buf);
+
+    output::emit(size, json!({"triples": size}));
+    Ok(())
+}
+
+async fn chain_triple_count(store: &Path, label: &str, cumulative: bool) -> io::Result<()> {
+    let head = label_head(store, label).await?;
+    let mut layers = store::chain(store, head).await?;
+    layers.reverse();
+    let mut total: i64 = 0;
+    for (name, path) in layers {
+        let (added, removed) = stats::triple_counts(&Archive::open(&path).await?)?;
+        total += added as i64 - removed as i64;
+        if cumulative {
+            let layer = name_to_string(name);
+            output::emit(
+                format!("{layer}  +{added} -{removed}  {total}"),
+                json!({"layer": layer, "added": added, "removed": removed, "total": total}),
+            );
+        }
+    }
+    if !cumulative {
+        output::emit(total, json!({"triples": total}));
+    }
+
+    Ok(())
+}
+
+async fn merkle(
+    store: &str,
+    output: Option<String>,
+    action: Option<MerkleCommand>,
+) -> io::Result<()> {
+    match action {
+        None => {
+            let tree = MerkleTree::build(Path::new(store)).await?;
+            println!("{}", tree.root);
+            if let Some(output) = output {
+                tree.save(Path::new(&output)).await?;
+            }
+        }
+        Some(MerkleCommand::Verify { tree_file }) => {
+            let saved = MerkleTree::load(Path::new(&tree_file)).await?;
+            let tree = MerkleTree::build(Path::new(store)).await?;
+            let changes = saved.diff(&tree);
+            if changes.is_empty() {
+                println!("OK {}", tree.root);
+            } else {
+                for change in changes {
+                    println!("{change}");
+                }
+                std::process::exit(1);
+            }
+        }
+        Some(MerkleCommand::Diff {
+            tree_file,
+            new_tree_file,
+        }) => {
+            let saved = MerkleTree::load(Path::new(&tree_file)).await?;
+            let tree = match new_tree_file {
+                Some(new_tree_file) => MerkleTree::load(Path::new(&new_tree_file)).await?,
+                None => MerkleTree::build(Path::new(store)).await?,
+            };
+            for change in saved.diff(&tree) {
+                println!("{change}");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Open the layer of a layer file from the store it lies in, along with
+/// the id its first value has in the layer's chain.
+async fn print_dict(
+    file_name: PathBuf,
+    t: DictType,
+    raw: bool,
+    range: Option<(u64, u64)>,
+) -> std::io::Result<()> {
+    dict::list_entries(&file_name, t, raw, range, |entry| match entry.typed {
+        Some((literal, datatype)) => output::emit(
+            format!("{}: {literal}", entry.id),
+            json!({"id": entry.id, "value": literal, "datatype": datatype}),
+        ),
+        None => output::emit(
+            format!("{}: {:?}", entry.id, entry.bytes),
+            json!({"id": entry.id, "entry": String::from_utf8_lossy(&entry.bytes)}),
+        ),
+    })
+    .await
+}
+
+async fn print_segment(layer_file: String, file_name: &str) -> io::Result<()> {
+    let archive = Archive::open(&layer_file).await?;
+    let file_type = header::parse_segment(file_name)?;
+    let contents = archive
+        .segment(file_type)?
+        .ok_or_else(|| absent_segment(file_type))?;
+    if contents.is_empty() {
+        println!("# {file_name} is present but empty");
+        return Ok(());
+    }
+    decode_segment(file_type, contents).await
+}
+
+/// Print the contents of a segment using the decoder for its type.
+async fn decode_segment(file_type: LayerFileEnum, contents: Bytes) -> io::Result<()> {
+    let invalid = |e| io::Error::new(io::ErrorKind::InvalidData, e);
+    match segment_kind(file_type) {
+        SegmentKind::DictBlocks => {
+            let mut stream = TfcDictStream::new(Cursor::new(contents)).enumerate();
+            while let Some((ix, element)) = stream.next().await {
+                let (element, _) = element.map_err(invalid)?;
+                println!("{}: {:?}", ix + 1, element.to_bytes());
+            }
+        }
+        SegmentKind::LogArray => {
+            let logarray = LogArray::parse(contents).map_err(invalid)?;
+            println!("# {} entries of width {}", logarray.len(), logarray.width());
+            for (ix, value) in logarray.iter().enumerate() {
+                println!("{ix}: {value}");
+            }
+        }
+        SegmentKind::BitArray => {
+            let bits = BitArray::from_bits(contents).map_err(invalid)?;
+            println!("# {} bits", bits.len());
+            let bits: Vec<_> = bits.iter().map(|b| if b { '1' } else { '0' }).collect();
+            for (ix, line) in bits.chunks(64).enumerate() {
+                println!("{:>8}: {}", ix * 64, line.iter().collect::<String>());
+            }
+        }
+        SegmentKind::Parent => println!("{}", name_to_string(parse_layer_name(&contents)?)),
+        SegmentKind::Rollup => println!("{:?}", contents),
+    }
+
+    Ok(())
+}
+
+/// Parse a range of ids written as `start..end`.
+fn parse_id_range(s: &str) -> Result<(u64, u64), String> {
+    let (start, end) = values::parse_range(s)?;
+    let parse = |n: String| n.parse::<u64>().map_err(|e| format!("{n}: {e}"));
+    Ok((parse(start)?, parse(end)?))
+}
+
+fn parse_offset(s: &str) -> Result<usize, String> {
+    match s.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+    .map_err(|e| e.to_string())
+}
+
+async fn carve(
+    file_name: String,
+    start: usize,
+    end: usize,
+    output: Option<String>,
+    decode_as: Option<String>,
+) -> io::Result<()> {
+    if end < start {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("end {end} lies before start {start}"),
+        ));
+    }
+    let mut file = tokio::fs::File::open(file_name).await?;
+    file.seek(SeekFrom::Start(start as u64)).await?;
+    let mut contents = Vec::with_capacity(end - start);
+    file.take((end - start) as u64)
+        .read_to_end(&mut contents)
+        .await?;
+    if contents.len() < end - start {
+        eprintln!("file ends {} bytes into the range", contents.len());
+    }
+
+    let contents = Bytes::from(contents);
+    match (&output, decode_as) {
+        (_, Some(segment)) => {
+            decode_segment(header::parse_segment(&segment)?, contents.clone()).await?
+        }
+        (None, None) => io::Write::write_all(&mut io::stdout(), &contents)?,
+        _ => {}
+    }
+    if let Some(output) = output {
+        atomic::write(output, contents).await?;
+    }
+
+    Ok(())
+}
+
+async fn rebuild_header(
+    file_name: String,
+    start: usize,
+    output: Option<String>,
+    yes: bool,
+) -> io::Result<()> {
+    let data = Bytes::from(tokio::fs::read(file_name).await?);
+    let interactive = !yes && io::stdin().is_terminal();
+    let segments = rebuild::scan(&data, start, interactive).await?;
+    let mut offset = start;
+    for (file_type, size) in segments.iter() {
+        println!("{file_type:?}: {offset}..{} ({size} bytes)", offset + size);
+        offset += size;
+    }
+    if offset < data.len() {
+        println!("{} trailing bytes not accounted for", data.len() - offset);
+    }
+
+    if let Some(output) = output {
+        let changed =
+            atomic::write_if_changed(&output, rebuild::assemble(&data, start, &segments)).await?;
+        if !changed {
+            println!("{output} already has this layout; nothing to do");
+        }
+    }
+
+    Ok(())
+}
+
+async fn dict_diff(
+    parent_file: String,
+    child_file: String,
+    t: DictType,
+    ancestors: Vec<String>,
+) -> error::Result<bool> {
+    let mut known = HashSet::new();
+    for ancestor in std::iter::once(parent_file).chain(ancestors) {
+        let archive = Archive::open(&ancestor).await.context(&ancestor)?;
+        known.extend(dict::read_entries(&archive, t).await.context(&ancestor)?);
+    }
+
+    let child = Archive::open(&child_file).await.context(&child_file)?;
+    let mut duplicates = 0;
+    for entry in dict::read_entries(&child, t).await.context(&child_file)? {
+        if known.contains(&entry) {
+            duplicates += 1;
+            println!("DUPLICATE {:?}", entry);
+        } else {
+            println!("+ {:?}", entry);
+        }
+    }
+    if duplicates != 0 {
+        eprintln!("{duplicates} entries of the child were already interned by an ancestor");
+    }
+
+    Ok(duplicates == 0)
+}
+
+/// Print the summary of checking a whole store.
+fn print_fsck_report(report: &fsck::FsckReport, format: OutputFormat) {
+    let fsck::FsckReport {
+        checked,
+        skipped,
+        failed,
+        remaining,
+        health,
+        ..
+    } = *report;
+    if remaining > 0 {
+        eprintln!(
+            "{}",
+            deadline::stopped(checked + skipped, remaining, "layers")
+        );
+    }
+    match format {
+        OutputFormat::Pretty => {
+            let failed = format!("{failed} failed");
+            let failed = if failed.starts_with("0 ") {
+                paint(&failed, Color::Green)
+            } else {
+                paint(&failed, Color::Red)
+            };
+            println!("\n{checked} checked, {skipped} skipped unchanged, {failed}");
+            if remaining > 0 {
+                println!("{remaining} not reached before the deadline");
+            }
+            println!("health {health:.1}/100")
+        }
+        OutputFormat::Text => {
+            println!("checked {checked}, skipped {skipped} unchanged, failed {failed}, health {health:.1}, remaining {remaining}")
+        }
+        OutputFormat::Ndjson => println!(
+            "{}",
+            ndjson(json!({
+                "checked": checked,
+                "skipped": skipped,
+                "failed": failed,
+                "remaining": remaining,
+                "health": health,
+                "findings": report.findings,
+            }))
+        ),
+    }
+}
+
+/// Print the rollup reference of a layer and whether the layer it names
+/// can be opened from `store`.
+fn print_rollup(info: &rollup::RollupInfo, store: &Path) {
+    let (size, rollup, status) = match info {
+        rollup::RollupInfo::None => {
+            println!("{:>12}: none", "rollup");
+            return;
+        }
+        rollup::RollupInfo::Unreadable { size, error } => {
+            println!("{:>12}: {size} bytes", "segment");
+            println!(
+                "{:>12}: {}",
+                "rollup",
+                paint(&format!("unreadable: {error}"), Color::Red)
+            );
+            return;
+        }
+        rollup::RollupInfo::Refers {
+            size,
+            rollup,
+            status,
+        } => (size, rollup, status),
+    };
+    println!("{:>12}: {size} bytes", "segment");
+    println!("{:>12}: {}", "rollup", name_to_string(*rollup));
+    let status = match status {
+        rollup::RollupStatus::Ok => paint("ok", Color::Green),
+        rollup::RollupStatus::Missing => {
+            paint(&format!("missing from {}", store.display()), Color::Red)
+        }
+        rollup::RollupStatus::Unreadable(e) => paint(&format!("unreadable: {e}"), Color::Red),
+    };
+    println!("{:>12}: {status}", "status");
+}
+
+fn refuse_when_attached(command: &str) -> error::Result<()> {
+    if store::is_attached() {
+        return Err(Error::usage(format!(
+            "{command} modifies files and can't be used with --attach"
+        )));
+    }
+    Ok(())
+}
+
+async fn label_head(store: &Path, label: &str) -> io::Result<[u32; 5]> {
+    store::read_label(store, label).await?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("label {label} does not point at a layer"),
+        )
+    })
+}
+
+async fn dump_dict_blocks(layer_file: String, t: DictType, format: OutputFormat) -> io::Result<()> {
+    let archive = Archive::open(&layer_file).await?;
+    let blocks = dict::block_stats(&archive, t).await?;
+    if format == OutputFormat::Pretty {
+        println!(
+            "{:>8} {:>12} {:>8} {:>7} {:>9}  head",
+            "block", "offset", "size", "entries", "savings"
+        );
+    }
+    let mut total_size = 0;
+    let mut total_entry_bytes = 0;
+    for (ix, block) in blocks.iter().enumerate() {
+        total_size += block.size;
+        total_entry_bytes += block.entry_bytes;
+        let savings = block.entry_bytes as i64 - block.size as i64;
+        match format {
+            OutputFormat::Pretty => println!(
+                "{ix:>8} {:>12} {:>8} {:>7} {savings:>9}  {:?}",
+                block.offset, block.size, block.entries, block.head
+            ),
+            OutputFormat::Text => println!(
+                "{ix} {} {} {} {savings} {:?}",
+                block.offset, block.size, block.entries, block.head
+            ),
+            OutputFormat::Ndjson => println!(
+                "{}",
+                ndjson(json!({
+                    "block": ix,
+                    "offset": block.offset,
+                    "size": block.size,
+                    "entries": block.entries,
+                    "entry_bytes": block.entry_bytes,
+                    "head": String::from_utf8_lossy(&block.head),
+                }))
+            ),
+        }
+    }
+    if format == OutputFormat::Pretty {
+        println!(
+            "{} blocks, {} in blocks holding {} of entries",
+            blocks.len(),
+            human_bytes(total_size),
+            human_bytes(total_entry_bytes)
+        );
+    }
+
+    Ok(())
+}
+
+async fn salvage_dict(layer_file: String, t: DictType) -> io::Result<()> {
+    let archive = Archive::open(&layer_file).await?;
+    let salvage = salvage::salvage(&archive, t).await?;
+    for (ix, entry) in salvage.entries.iter() {
+        println!("{ix}: {:?}", entry);
+    }
+
+    if !salvage.used_offsets {
+        eprintln!("block offsets unusable, indices after the first gap are lower bounds");
+    }
+    let show = |offset: Option<usize>| offset.map(|o| o.to_string()).unwrap_or("?".to_string());
+    for gap in salvage.gaps.iter() {
+        eprintln!(
+            "gap at bytes {}..{}, entries lost from index ~{}",
+            show(gap.start),
+            show(gap.end),
+            gap.first_index
+        );
+    }
+    eprintln!(
+        "recovered {} entries, {} gaps",
+        salvage.entries.len(),
+        salvage.gaps.len()
+    );
+
+    Ok(())
+}
+
+async fn validate_logarray(file_name: PathBuf, header_first: bool) -> std::io::Result<()> {
+    let mut file = tokio::fs::File::open(file_name).await?;
+    let mut contents = Vec::new();
+    file.read_to_end(&mut contents).await?;
+    let contents = Bytes::from(contents);
+
+    let invalid = |e| io::Error::new(io::ErrorKind::InvalidData, e);
+    let _logarray = if header_first {
+        LogArray::parse_header_first(contents).map_err(invalid)?.0
+    } else {
+        LogArray::parse(contents).map_err(invalid)?
+    };
+    Ok(())
+}
+
+async fn extract_file(layer_path: PathBuf, file_name: &str) -> std::io::Result<()> {
+    let source = LayerSource::open(&layer_path.to_string_lossy());
+    if source.is_remote() {
+        let contents = source.segment(header::parse_segment(file_name)?).await?;
+        if contents.is_empty() {
+            eprintln!("{file_name} is present but empty");
+        }
+        return io::Write::write_all(&mut io::stdout().lock(), &contents);
+    }
+    let mut file = tokio::fs::File::open(layer_path).await?;
+    let header = ArchiveHeader::parse_from_reader(&mut file).await?;
+    let file_type = header::parse_segment(file_name)?;
+    let range = header
+        .range_for(file_type)
+        .ok_or_else(|| absent_segment(file_type))?;
+    if range.is_empty() {
+        eprintln!("{file_name} is present but empty");
+        return Ok(());
+    }
+    file.seek(SeekFrom::Current(range.start as i64)).await?;
+    // std's copy lets the kernel move the data (copy_file_range or
+    // sendfile) when stdout is a file or pipe
+    let file = file.into_std().await;
+    tokio::task::spawn_blocking(move || {
+        let mut reader = std::io::Read::take(file, range.len() as u64);
+        std::io::copy(&mut reader, &mut std::io::stdout().lock()).map(|_| ())
+    })
+    .await
+    .map_err(|e| io::Error::new(io::ErrorKind::Other, e))??;
+
+    Ok(())
+}
+
+/// Fail a command over a single segment, unless the segment is only
+/// absent and `allow_missing` is given, in which case that is reported
+/// and the command succeeds.
+fn fail_unless_absent(result: io::Result<()>, allow_missing: bool) -> error::Result<()> {
+    match result {
+        Ok(()) => Ok(()),
+        Err(e) if allow_missing && archive::is_absent(&e) => {
+            eprintln!("{e}; skipping");
+            Ok(())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Runs the command, and exits with the code for the kind of error it
+/// ended with: 2 for arguments that don't fit together or a command that
+/// wasn't confirmed, 3 for something not found, 4 for corrupt data and 5
+/// for other IO errors. Commands that run through but find problems exit
+/// with 1.
+fn main() {
+    let matches = Cli::command().get_matches();
+    let cli = match Cli::from_arg_matches(&matches) {
+        Ok(cli) => cli,
+        Err(e) => e.exit(),
+    };
+    // Size the runtime for the storage of the store the command was given,
+    // or of the working directory, the store commands default to.
+    let store = matches
+        .subcommand()
+        .and_then(|(_, sub)| sub.try_get_raw("store").ok().flatten())
+        .and_then(|mut stores| stores.next())
+        .map_or_else(|| PathBuf::from("."), PathBuf::from);
+    let (workers, blocking) = backend::storage(cli.backend, &store).threads();
+    let mut runtime = tokio::runtime::Builder::new_multi_thread();
+    runtime.enable_all();
+    runtime.worker_threads(cli.worker_threads.unwrap_or(workers));
+    runtime.max_blocking_threads(cli.blocking_threads.unwrap_or(blocking));
+    let result = match runtime.build() {
+        Ok(runtime) => runtime.block_on(run(cli)),
+        Err(e) => Err(e.into()),
+    };
+    if let Err(e) = result {
+        eprintln!("{e}");
+        std::process::exit(e.exit_code());
+    }
+}
+
+async fn run(mut cli: Cli) -> error::Result<()> {
+    if cli.attach {
+        store::attach();
+    }
+    if let Some(timeout) = cli.timeout {
+        deadline::set(timeout);
+    }
+    if let Some(max) = cli.max_memory {
+        limits::set_max_memory(max);
+    }
+    if let Some(max) = cli.max_open_files {
+        limits::set_max_open_files(max);
+    }
+    if let (backend::BackendKind::S3, Some(bucket)) = (cli.backend, cli.bucket.take()) {
+        backend::set(backend::Backend::S3 {
+            bucket,
+            prefix: cli.prefix.clone(),
+        });
+    }
+    if let Some(cache_dir) = cli.cache_dir {
+        store::set_cache(cache::LayerCache::new(cache_dir.into(), cli.cache_size));
+    }
+    output::set_color(
+        !cli.no_color
+            && std::env::var_os("NO_COLOR").is_none()
+            && std::io::stdout().is_terminal(),
+    );
+    output::set_json(cli.json);
+    if cli.json {
+        if let Some(format) = cli.command.format_mut() {
+            *format = OutputFormat::Ndjson;
+        }
+    }
+
+    match cli.command {
+        Commands::NodeId {
+            node,
+            layer,
+            label,
+            position,
+            store,
+        } => {
+            let store = store_search_path(store);
+            let id_for_node = node_id(&store, layer, label, position, &node)?;
+            let text = match id_for_node {
+                Some(id) => id.to_string(),
+                None => "None".to_string(),
+            };
+            output::emit(text, json!({"node": node, "id": id_for_node}));
+        }
+        Commands::IdNode {
+            id,
+            layer,
+            label,
+            position,
+            store,
+        } => {
+            let store = store_search_path(store);
+            let node_for_id = id_node(&store, layer, label, position, &id)?;
+            let text = node_for_id.clone().unwrap_or_else(|| "None".to_string());
+            output::emit(text, json!({"id": id, "node": node_for_id}));
+        }
+        Commands::NodeCount {
+            layer,
+            label,
+            store,
+        } => {
+            let store = store_search_path(store);
+            let node_count = node_count(&store, layer, label).await?;
+            let text = match node_count {
+                Some(count) => count.to_string(),
+                None => "None".to_string(),
+            };
+            output::emit(text, json!({"nodes": node_count}));
+        }
+        Commands::ParseHeader {
+            file_name,
+            sort,
+            offsets,
+            absolute,
+            segment,
+            format,
+        } => {
+            parse_and_print_header(&file_name, sort, offsets, absolute, segment, format)
+                .await
+                .context(&file_name)?;
+        }
+        Commands::Stats { layer_file, format } => {
+            let archive = Archive::open(&layer_file).await.context(&layer_file)?;
+            let analysis = layer_stats::analyze(&archive).await.context(&layer_file)?;
+            layer_stats::print_analysis(&analysis, format);
+        }
+        Commands::DumpTriples {
+            layer_file,
+            removals,
+            store,
+            format,
+        } => {
+            let count = dump::dump_triples(
+                Path::new(&layer_file),
+                store.as_deref().map(Path::new),
+                removals,
+                format,
+            )
+            .await
+            .context(&layer_file)?;
+            eprintln!("{count} triples");
+        }
+        Commands::Query {
+            layer_file,
+            subject,
+            predicate,
+            object,
+            index,
+            removals,
+            store,
+            format,
+        } => {
+            let pattern = query::Pattern {
+                subject: subject.as_deref(),
+                predicate: predicate.as_deref(),
+                object: object.as_deref(),
+            };
+            let count = query::query(
+                Path::new(&layer_file),
+                store.as_deref().map(Path::new),
+                pattern,
+                index,
+                removals,
+                format,
+            )
+            .await
+            .context(&layer_file)?;
+            eprintln!("{count} triples");
+        }
+        Commands::PrintDict {
+            file_name,
+            dict_type,
+            raw,
+            range,
+        } => print_dict(PathBuf::from(&file_name), dict_type, raw, range)
+            .await
+            .context(&file_name)?,
+        Commands::DictLookup {
+            layer_file,
+            dict_type,
+            entry,
+            datatype,
+        } => {
+            let archive = Archive::open(&layer_file).await.context(&layer_file)?;
+            let (datatype, bytes) = if dict_type == DictType::Values {
+                let local = datatype.strip_prefix("xsd:").unwrap_or(&datatype);
+                let (value, as_string) =
+                    build_layer::typed_value(&entry, local).map_err(Error::usage)?;
+                if as_string {
+                    return Err(Error::usage(format!("no encoding for datatype {datatype}")));
+                }
+                (Some(value.datatype()), value.to_bytes())
+            } else {
+                (None, Bytes::from(entry.clone().into_bytes()))
+            };
+            match dict::lookup(&archive, dict_type, datatype, &bytes)
+                .await
+                .context(&layer_file)?
+            {
+                Some(id) => output::emit(id, json!({"entry": entry, "id": id})),
+                None => return Err(Error::not_found(format!("{entry} not found"))),
+            }
+        }
+        Commands::DictEntry {
+            layer_file,
+            dict_type,
+            id,
+        } => {
+            let archive = Archive::open(&layer_file).await.context(&layer_file)?;
+            match dict::entry(&archive, dict_type, id)
+                .await
+                .context(&layer_file)?
+            {
+                Some((None, entry)) => {
+                    let entry = String::from_utf8_lossy(&entry);
+                    output::emit(&entry, json!({"id": id, "entry": entry}))
+                }
+                Some((Some(datatype), entry)) => {
+                    let datatype = format!("{datatype:?}");
+                    let literal = rdf::literal(&datatype, &entry);
+                    output::emit(
+                        format!("{}^^{}", literal.lexical, values::xsd_name(&datatype)),
+                        json!({"id": id, "entry": literal.lexical, "datatype": datatype}),
+                    )
+                }
+                None => return Err(Error::not_found(format!("no entry {id}"))),
+            }
+        }
+        Commands::ValidateDict {
+            layer_file,
+            dict_type,
+        } => {
+            let archive = Archive::open(&layer_file).await.context(&layer_file)?;
+            match dict::validate_dict(&archive, dict_type).await {
+                Ok(count) => output::emit(
+                    format!("OK {count} entries"),
+                    json!({"status": "ok", "entries": count}),
+                ),
+                Err(e) => {
+                    eprintln!("{e}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        Commands::CheckCollation {
+            layer_file,
+            dict_type,
+        } => {
+            let archive = Archive::open(&layer_file).await.context(&layer_file)?;
+            let types = match dict_type {
+                Some(t) => vec![t],
+                None => vec![DictType::Nodes, DictType::Predicates, DictType::Values],
+            };
+            let mut unfindable = 0;
+            for t in types {
+                let name = t.to_possible_value().unwrap().get_name().to_string();
+                for entry in dict::check_collation(&archive, t)
+                    .await
+                    .context(&layer_file)?
+                {
+                    let found = match entry.found {
+                        Some(id) => format!("finds {id}"),
+                        None => "finds nothing".to_string(),
+                    };
+                    let text = String::from_utf8_lossy(&entry.entry);
+                    output::emit(
+                        format!(
+                            "{} {name} {} {text:?}: {}; lookup {found}",
+                            paint("UNFINDABLE", Color::Red),
+                            entry.id,
+                            entry.reason
+                        ),
+                        json!({
+                            "dict": name,
+                            "id": entry.id,
+                            "entry": text,
+                            "found": entry.found,
+                            "reason": entry.reason,
+                        }),
+                    );
+                    unfindable += 1;
+                }
+            }
+            if unfindable > 0 {
+                std::process::exit(1);
+            }
+            output::emit(
+                paint("all entries findable", Color::Green),
+                json!({"unfindable": 0}),
+            );
+        }
+        Commands::Carve {
+            file_name,
+            start,
+            end,
+            output,
+            decode_as,
+        } => carve(file_name.clone(), start, end, output, decode_as)
+            .await
+            .context(&file_name)?,
+        Commands::RebuildHeader {
+            file_name,
+            start,
+            output,
+            yes,
+            force,
+        } => {
+            if let Some(output) = output.as_ref() {
+                let required = preflight::total_size(&[&file_name]).await?;
+                preflight::ensure_space(Path::new(output), required, force)?;
+            }
+            rebuild_header(file_name.clone(), start, output, yes)
+                .await
+                .context(&file_name)?
+        }
+        Commands::PrintSegment {
+            layer_file,
+            file_name,
+            allow_missing,
+        } => fail_unless_absent(
+            print_segment(layer_file.clone(), &file_name).await,
+            allow_missing,
+        )
+        .context(&layer_file)?,
+        Commands::Inspect {
+            layer_file,
+            segment_name,
+            head,
+            all,
+        } => {
+            let file_type = header::parse_segment(&segment_name)?;
+            let complete = inspect::inspect(&layer_file, file_type, head, all)
+                .await
+                .context(&layer_file)?;
+            if !complete {
+                std::process::exit(1);
+            }
+        }
+        Commands::ValidateLayer {
+            layer_file,
+            store,
+            offsets,
+            format,
+        } => {
+            let store = store.as_deref().map(Path::new);
+            let layer_file = Path::new(&layer_file);
+            let validation = match validate_layer::validate_file(layer_file, store).await {
+                Ok(validation) => validation,
+                Err(corruption) => {
+                    eprintln!("FAIL header: {}", corruption.message);
+                    std::process::exit(1);
+                }
+            };
+            if let Some(reason) = validation.ids_unchecked {
+                eprintln!("{reason}: ids are not checked");
+            }
+            let reports = validation.reports;
+            validate_layer::print_reports(&reports, offsets, format);
+            if reports.iter().any(|r| r.corruption.is_some()) {
+                std::process::exit(1);
+            }
+        }
+        Commands::ValidateStore {
+            store,
+            jobs,
+            check_ids,
+            report,
+            resume,
+            format,
+        } => {
+            let store = store.unwrap_or_else(|| ".".to_string());
+            let result = validate_layer::validate_store(
+                Path::new(&store),
+                jobs,
+                check_ids,
+                report.as_deref().map(Path::new),
+                resume,
+                format,
+            )
+            .await?;
+            validate_layer::print_summary(&result, format);
+            if result.failed > 0 {
+                std::process::exit(1);
+            }
+        }
+        Commands::ValidateLogArray {
+            file_name,
+            header_first,
+        } => validate_logarray(PathBuf::from(&file_name), header_first)
+            .await
+            .context(&file_name)?,
+        Commands::Extract {
+            layer_file_name,
+            file_name,
+            allow_missing,
+        } => fail_unless_absent(
+            extract_file(PathBuf::from(&layer_file_name), &file_name).await,
+            allow_missing,
+        )
+        .context(&layer_file_name)?,
+        Commands::ExtractAll {
+            layer_file,
+            output,
+            jobs,
+            verify,
+        } => {
+            let segments =
+                extract::extract_all(Path::new(&layer_file), Path::new(&output), jobs, verify)
+                    .await
+                    .context(&layer_file)?;
+            let mut mismatched = false;
+            for segment in segments.iter() {
+                let status = match segment.checksum {
+                    Some(true) => " checksum ok",
+                    Some(false) => " CHECKSUM MISMATCH",
+                    None if segment.size == 0 => " (present but empty)",
+                    None => "",
+                };
+                mismatched |= segment.checksum == Some(false);
+                output::emit(
+                    format!("{} {} bytes{status}", segment.path.display(), segment.size),
+                    json!({
+                        "segment": format!("{:?}", segment.segment),
+                        "file": segment.path.display().to_string(),
+                        "size": segment.size,
+                        "checksum": segment.checksum,
+                    }),
+                );
+            }
+            if mismatched {
+                std::process::exit(1);
+            }
+        }
+        Commands::Inject {
+            layer_file,
+            segment_name,
+            input_file,
+            output,
+            in_place: _,
+            store,
+            force,
+        } => {
+            let store = audit::log_store(store, Path::new(&layer_file))?;
+            let output = output.unwrap_or_else(|| layer_file.clone());
+            refuse_when_attached("inject")?;
+            let file_type = match FILENAME_ENUM_MAP.get(segment_name.as_str()) {
+                Some(file_type) => *file_type,
+                None => return Err(Error::usage(format!("unknown segment {segment_name}"))),
+            };
+            let required = preflight::total_size(&[&layer_file, &input_file]).await?;
+            preflight::ensure_space(Path::new(&output), required, force)?;
+            let archive = Archive::open(&layer_file).await.context(&layer_file)?;
+            let replacement = tokio::fs::read(&input_file).await.context(&input_file)?;
+            let contents = inject::inject(&archive, file_type, replacement.into())
+                .await
+                .context(&layer_file)?;
+            let mut audit = Audit::begin("inject");
+            audit.track(Path::new(&output)).await?;
+            audit.note("segment", segment_name);
+            if !atomic::write_if_changed(&output, contents).await? {
+                println!("{output} already has that segment; nothing to do");
+                audit.note("result", "already in effect".to_string());
+            }
+            audit.commit(&store).await?
+        }
+        Commands::BuildObjectIndex {
+            sp_o_nums_file,
+            sp_o_bits_file,
+            o_ps_dir,
+            objects_file,
+            max_object_id,
+            layer,
+            store,
+            force,
+        } => {
+            let required = preflight::total_size(&[&sp_o_nums_file, &sp_o_bits_file]).await?;
+            preflight::ensure_space(Path::new(&o_ps_dir), required, force)?;
+            let max_object_id = match (max_object_id, layer) {
+                (Some(max_object_id), _) => Some(max_object_id),
+                (None, Some(layer)) => {
+                    let store = store_search_path(store);
+                    let layer = parse_name(&layer)?;
+                    let counts = ids::cumulative_counts(Path::new(&store), layer).await?;
+                    Some(counts.nodes_values)
+                }
+                (None, None) => None,
+            };
+            index::build_object_index(
+                Path::new(&sp_o_nums_file),
+                Path::new(&sp_o_bits_file),
+                Path::new(&o_ps_dir),
+                objects_file.as_deref().map(Path::new),
+                max_object_id,
+            )
+            .await?
+        }
+        Commands::BuildPredicateIndex {
+            s_p_nums_file,
+            predicate_index_dir,
+            force,
+        } => {
+            let required = preflight::total_size(&[&s_p_nums_file]).await?;
+            preflight::ensure_space(Path::new(&predicate_index_dir), required, force)?;
+            index::build_predicate_index(Path::new(&s_p_nums_file), Path::new(&predicate_index_dir))
+                .await?
+        }
+        Commands::BuildSubjectIndex {
+            s_p_nums_file,
+            s_p_bits_file,
+            subject_index_dir,
+            force,
+        } => {
+            let required = preflight::total_size(&[&s_p_nums_file, &s_p_bits_file]).await?;
+            preflight::ensure_space(Path::new(&subject_index_dir), required, force)?;
+            index::build_subject_index(
+                Path::new(&s_p_nums_file),
+                Path::new(&s_p_bits_file),
+                Path::new(&subject_index_dir),
+            )
+            .await?
+        }
+        Commands::CheckCounts { layer_file, format } => {
+            let archive = Archive::open(&layer_file).await.context(&layer_file)?;
+            let checks = counts::check_counts(&archive);
+            counts::print_counts(&checks, format);
+            if !checks.iter().all(|check| check.agrees()) {
+                std::process::exit(1);
+            }
+        }
+        Commands::RebuildBitindex {
+            input,
+            output_dir,
+            segment,
+            force,
+        } => {
+            let required = preflight::total_size(&[&input]).await?;
+            preflight::ensure_space(Path::new(&output_dir), required, force)?;
+            let output_dir = Path::new(&output_dir);
+            match segment {
+                None => index::rebuild_bitindex(Path::new(&input), output_dir).await?,
+                Some(segment) => {
+                    let bits = header::parse_segment(&segment)?;
+                    let archive = Archive::open(&input).await.context(&input)?;
+                    let (blocks, sblocks) =
+                        index::rebuild_archive_bitindex(&archive, bits, output_dir).await?;
+                    for (segment, file) in
+                        [(blocks, "bit_index_blocks"), (sblocks, "bit_index_sblocks")]
+                    {
+                        println!(
+                            "inject {input} {} {}",
+                            header::segment_file_name(segment).unwrap(),
+                            output_dir.join(file).display()
+                        );
+                    }
+                }
+            }
+        }
+        Commands::TripleCount {
+            layer_file,
+            label,
+            store,
+            predicate: Some(predicate),
+            ..
+        } => {
+            let store = store_search_path(store);
+            // a layer file is named after its layer
+            let layer = layer_file
+                .map(|file| {
+                    Path::new(&file)
+                        .file_stem()
+                        .and_then(|s| s.to_str())
+                        .map(|s| s.to_string())
+                        .context(format!("{file} is not named after a layer"))
+                })
+                .transpose()?;
+            let layer = open_layer_or_label(&store, layer, label)?;
+            let count = match layer.predicate_id(&predicate) {
+                Some(id) => layer.triples_p(id).count(),
+                None => 0,
+            };
+            output::emit(count, json!({"predicate": predicate, "triples": count}));
+        }
+        Commands::TripleCount {
+            layer_file,
+            label,
+            store,
+            cumulative,
+            predicate: None,
+        } => match layer_file {
+            Some(layer_file) => get_triple_count(layer_file.clone())
+                .await
+                .context(&layer_file)?,
+            None => {
+                let store = store.unwrap_or_else(|| ".".to_string());
+                chain_triple_count(Path::new(&store), &label.unwrap(), cumulative).await?
+            }
+        },
+        Commands::Merkle {
+            store,
+            output,
+            action,
+        } => {
+            let store = store_search_path(store);
+            merkle(&store, output, action).await?
+        }
+        Commands::Scan { root, format } => {
+            if !scan::scan(Path::new(&root), format).await? {
+                std::process::exit(1);
+            }
+        }
+        Commands::ScanStore {
+            store,
+            delete_unreachable,
+            yes,
+            format,
+        } => {
+            let store = store.unwrap_or_else(|| ".".to_string());
+            let store = Path::new(&store);
+            let report = garbage::garbage_report(store).await?;
+            garbage::print_report(&report, format);
+            if delete_unreachable {
+                refuse_when_attached("scan-store --delete-unreachable")?;
+                if report.broken_reachable > 0 {
+                    eprintln!(
+                        "not deleting: {} layers in use can't be read, so layers they need may look unreachable",
+                        report.broken_reachable
+                    );
+                    std::process::exit(1);
+                }
+                // layers reached through overlays or stubs live elsewhere
+                // and are left alone
+                let files: Vec<PathBuf> = report
+                    .unreachable
+                    .iter()
+                    .map(|(_, path, _)| path.clone())
+                    .filter(|path| {
+                        path.starts_with(store)
+                            && path.extension().map(|e| e == "larch") == Some(true)
+                    })
+                    .collect();
+                let preview = vec![format!(
+                    "delete {} unreachable layers ({})",
+                    files.len(),
+                    confirm::total_size(&files)
+                )];
+                confirm::confirm("scan-store", store, &preview, yes)?;
+                let mut audit = Audit::begin("scan-store");
+                for file in files.iter() {
+                    audit.track(file).await?;
+                }
+                for file in files.iter() {
+                    tokio::fs::remove_file(file).await.context(file.display())?;
+                }
+                audit.note("deleted", files.len().to_string());
+                audit.commit(store).await?;
+                eprintln!("deleted {} layers", files.len());
+            }
+        }
+        Commands::Databases { store, format } => {
+            let store = store_search_path(store);
+            for summary in databases::databases(Path::new(&store)).await? {
+                databases::print_database(&summary, format);
+            }
+        }
+        Commands::Fsck {
+            store,
+            incremental,
+            cache,
+            checks,
+            format,
+            prometheus,
+        } => {
+            let store: PathBuf = store_search_path(store).into();
+            let cache = cache
+                .map(PathBuf::from)
+                .unwrap_or_else(|| fsck::default_cache_path(&store));
+            let checks = checks::select(&checks).map_err(Error::usage)?;
+            let prometheus = prometheus.map(PathBuf::from);
+            let report = fsck::fsck(
+                &store,
+                &cache,
+                incremental,
+                &checks,
+                prometheus.as_deref(),
+                |layer, findings| fsck::print_result(layer, findings, format),
+            )
+            .await?;
+            print_fsck_report(&report, format);
+            if report.failed > 0 {
+                std::process::exit(1);
+            }
+        }
+        Commands::Watch {
+            store,
+            settle,
+            on_failure,
+            checks,
+            format,
+        } => {
+            let store = store.unwrap_or_else(|| ".".to_string());
+            let checks = checks::select(&checks).map_err(Error::usage)?;
+            watch::watch(
+                Path::new(&store),
+                std::time::Duration::from_secs(settle),
+                on_failure,
+                &checks,
+                format,
+            )
+            .await?
+        }
+        Commands::DictDiff {
+            parent_file,
+            child_file,
+            dict_type,
+            ancestor,
+        } => {
+            if !dict_diff(parent_file, child_file, dict_type, ancestor).await? {
+                std::process::exit(1);
+            }
+        }
+        Commands::Diff {
+            layer_a,
+            layer_b,
+            summary,
+            store,
+        } => {
+            let changes = layer_diff::diff(
+                Path::new(&layer_a),
+                Path::new(&layer_b),
+                store.as_deref().map(Path::new),
+            )
+            .await?;
+            for change in changes.iter() {
+                if summary {
+                    println!(
+                        "{}s: +{} -{}",
+                        change.name,
+                        change.added.len(),
+                        change.removed.len()
+                    );
+                    continue;
+                }
+                for added in change.added.iter() {
+                    println!("+ {} {added}", change.name);
+                }
+                for removed in change.removed.iter() {
+                    println!("- {} {removed}", change.name);
+                }
+            }
+            if changes.iter().any(|c| !c.is_empty()) {
+                std::process::exit(1);
+            }
+        }
+        Commands::DedupEstimate { label, store } => {
+            let store: PathBuf = store_search_path(store).into();
+            let head = label_head(&store, &label).await?;
+            dedup::dedup_estimate(&store, head).await?
+        }
+        Commands::HasTriple {
+            subject,
+            predicate,
+            object,
+            value,
+            layer,
+            label,
+            store,
+            back,
+            format,
+        } => {
+            let store = store_search_path(store);
+            let triple = if value {
+                ValueTriple::new_string_value(&subject, &predicate, &object)
+            } else {
+                ValueTriple::new_node(&subject, &predicate, &object)
+            };
+            let found = has_triple(&store, layer, label, back, &triple)?;
+            match format {
+                OutputFormat::Pretty if found => println!("{}", paint("found", Color::Green)),
+                OutputFormat::Pretty => println!("{}", paint("not found", Color::Red)),
+                OutputFormat::Text if found => println!("found"),
+                OutputFormat::Text => println!("not found"),
+                OutputFormat::Ndjson => println!("{}", ndjson(json!({ "found": found }))),
+            }
+            if !found {
+                std::process::exit(1);
+            }
+        }
+        Commands::Triples {
+            layer,
+            label,
+            store,
+            back,
+            lang,
+            page,
+            format,
+        } => {
+            let store = store_search_path(store);
+            print_triples(&store, layer, label, back, lang.as_deref(), &page, format)?;
+        }
+        Commands::Centrality {
+            layer,
+            label,
+            store,
+            top,
+            metric,
+            format,
+        } => {
+            let store = store_search_path(store);
+            centrality(&store, layer, label, top, metric, format)?;
+        }
+        Commands::ExportEdgelist {
+            layer,
+            label,
+            store,
+            numeric_ids,
+            matrix_market,
+            mapping,
+        } => {
+            let store = store_search_path(store);
+            export_edgelist(&store, layer, label, numeric_ids, matrix_market, mapping).await?
+        }
+        Commands::PredicateCooccurrence {
+            layer,
+            label,
+            store,
+        } => {
+            let store = store_search_path(store);
+            predicate_cooccurrence(&store, layer, label)?;
+        }
+        Commands::DanglingObjects {
+            layer,
+            label,
+            store,
+            schema,
+            format,
+        } => {
+            let store = store_search_path(store);
+            if dangling_objects(&store, layer, label, schema, format)? {
+                std::process::exit(1);
+            }
+        }
+        Commands::Assert {
+            target,
+            file,
+            store,
+            format,
+        } => {
+            let store = store_search_path(store);
+            let contents = std::fs::read_to_string(&file).context(&file)?;
+            let lines = assertions::parse(&contents).context(&file)?;
+            let layer = match string_to_name(&target) {
+                Ok(_) => open_layer_or_label(&store, Some(target), None)?,
+                Err(_) => open_layer_or_label(&store, None, Some(target))?,
+            };
+            let mut failed = 0;
+            for line in lines.iter() {
+                let (pass, actual) = assertions::evaluate(&layer, &line.assertion);
+                failed += !pass as usize;
+                assertions::print_outcome(line, pass, actual, format);
+            }
+            eprintln!(
+                "{} of {} assertions hold",
+                lines.len() - failed,
+                lines.len()
+            );
+            if failed > 0 {
+                std::process::exit(1);
+            }
+        }
+        Commands::CheckRequired {
+            schema,
+            instance,
+            store,
+            format,
+        } => {
+            let store = store_search_path(store);
+            if !check_required(&store, schema, instance, format)? {
+                std::process::exit(1);
+            }
+        }
+        Commands::InferSchema {
+            layer,
+            store,
+            output,
+        } => {
+            let store = store_search_path(store);
+            let layer = open_layer_or_label(&store, Some(layer), None)?;
+            let schema = schema::infer_schema(&layer);
+            eprintln!("inferred {} classes", schema.as_object().unwrap().len());
+            atomic::write(
+                &output,
+                serde_json::to_string_pretty(&schema).unwrap() + "\n",
+            )
+            .await?;
+        }
+        Commands::SearchValues {
+            layer,
+            label,
+            store,
+            equals,
+            range,
+            datatype,
+            lang,
+            format,
+        } => {
+            let store = store_search_path(store);
+            let query = match (equals, range) {
+                (Some(equals), _) => ValueQuery::Equals(equals),
+                (None, range) => {
+                    let (low, high) = range.unwrap();
+                    ValueQuery::Range(low, high)
+                }
+            };
+            search_values(
+                &store,
+                layer,
+                label,
+                &query,
+                datatype.as_deref(),
+                lang.as_deref(),
+                format,
+            )?;
+        }
+        Commands::LangStats {
+            layer,
+            label,
+            store,
+            format,
+        } => {
+            let store = store_search_path(store);
+            lang_stats(&store, layer, label, format)?;
+        }
+        Commands::Export {
+            layer,
+            label,
+            store,
+            format,
+            output,
+        } => {
+            let store = store_search_path(store);
+            let layer = open_layer_or_label(&store, layer, label)?;
+            let exported = rdf::export(&layer, format, Path::new(&output))?;
+            eprintln!("wrote {} triples to {output}", exported.triples);
+            if exported.undecoded > 0 {
+                eprintln!(
+                    "{}",
+                    paint(
+                        &format!(
+                            "{} values don't decode as their datatype and were written with their stored bytes; check the layer",
+                            exported.undecoded
+                        ),
+                        Color::Red
+                    )
+                );
+            }
+        }
+        Commands::ExportAll {
+            store,
+            output,
+            format,
+            jobs,
+            checkpoint,
+        } => {
+            let store = store_search_path(store);
+            export::export_all(
+                Path::new(&store),
+                Path::new(&output),
+                format,
+                jobs,
+                checkpoint.as_deref().map(Path::new),
+            )
+            .await?
+        }
+        Commands::ExportDelta {
+            label,
+            since,
+            store,
+            output,
+            format: ExportFormat::Ntriples,
+        } => {
+            let store = store_search_path(store);
+            let since = parse_name(&since)?;
+            let head = open_layer_or_label(&store, None, Some(label))?;
+            let delta = export::export_delta(&head, since, Path::new(&output))?;
+            eprintln!(
+                "{} layers: {} triples added, {} removed",
+                delta.layers, delta.added, delta.removed
+            );
+        }
+        Commands::ExportDictDeltas {
+            label,
+            store,
+            output,
+        } => {
+            let store: PathBuf = store_search_path(store).into();
+            let head = label_head(&store, &label).await?;
+            let layers = export::export_dict_deltas(&store, head, Path::new(&output)).await?;
+            eprintln!("wrote the dictionaries of {layers} layers to {output}");
+        }
+        Commands::Bench { action } => match action {
+            BenchCommand::Compare {
+                baseline,
+                candidate,
+                iterations,
+                warmup,
+                format,
+            } => {
+                if iterations == 0 {
+                    return Err(Error::usage("--iterations must be at least 1"));
+                }
+                let (baseline, candidate) = (Path::new(&baseline), Path::new(&candidate));
+                let report = bench::compare(baseline, candidate, iterations, warmup).await?;
+                bench::print_report(&report, format);
+            }
+        },
+        Commands::StatsIndex { action } => match action {
+            StatsIndexCommand::Build { store } => {
+                let store = store.unwrap_or_else(|| ".".to_string());
+                stats::build(Path::new(&store)).await?
+            }
+            StatsIndexCommand::Show { store, format } => {
+                let store = store.unwrap_or_else(|| ".".to_string());
+                stats::show(Path::new(&store), format).await?
+            }
+        },
+        Commands::ShowSubject {
+            subject,
+            layer,
+            label,
+            store,
+            annotate,
+            format,
+        } => {
+            let store = store_search_path(store);
+            if !show_subject(&store, &subject, layer, label, annotate, format)? {
+                return Err(Error::not_found(format!("subject {subject} not found")));
+            }
+        }
+        Commands::Resolve {
+            ids_file,
+            direction,
+            kind,
+            layer,
+            label,
+            store,
+        } => {
+            let store = store_search_path(store);
+            resolve(&store, ids_file, direction, kind, layer, label).await?
+        }
+        Commands::Grep {
+            object_contains,
+            labels,
+            store,
+            format,
+        } => {
+            let store = store_search_path(store);
+            let count = grep(&store, &object_contains, labels, format).await?;
+            if count == 0 {
+                std::process::exit(1);
+            }
+        }
+        Commands::MapIds {
+            layer_a,
+            layer_b,
+            store,
+        } => {
+            let store = store_search_path(store);
+            map_ids(&store, layer_a, layer_b)?;
+        }
+        Commands::VerifyBackup {
+            backup,
+            key_file,
+            output,
+        } => {
+            let key = match key_file {
+                Some(key_file) => Some(tokio::fs::read(&key_file).await.context(&key_file)?),
+                None => None,
+            };
+            let summary = backup::verify_backup(Path::new(&backup), key.as_deref())
+                .await
+                .context(&backup)?;
+            let text = serde_json::to_string_pretty(&summary).unwrap();
+            match output {
+                Some(output) => tokio::fs::write(output, text).await?,
+                None => println!("{text}"),
+            }
+            if summary["ok"] != json!(true) {
+                std::process::exit(1);
+            }
+        }
+        Commands::Forget {
+            subjects_file,
+            label,
+            store,
+            output,
+        } => {
+            let store = store_search_path(store);
+            let subjects: Vec<String> = tokio::fs::read_to_string(&subjects_file)
+                .await
+                .context(&subjects_file)?
+                .lines()
+                .filter(|l| !l.is_empty())
+                .map(|l| l.to_string())
+                .collect();
+            let layer = open_layer_or_label(&store, None, Some(label.clone()))?;
+            let patch = patch::forget(&layer, &label, &subjects);
+            tokio::fs::write(&output, patch.to_json_lines(&layer)).await?;
+            eprintln!("{} triples to remove", patch.removals.len());
+            for name in patch::affected_layers(&layer, &patch)? {
+                println!("{}", name_to_string(name));
+            }
+        }
+        Commands::ApplyPatch {
+            patch_file,
+            store,
+            force,
+        } => {
+            let store = store.unwrap_or_else(|| ".".to_string());
+            refuse_when_attached("apply-patch")?;
+            let contents = tokio::fs::read_to_string(&patch_file)
+                .await
+                .context(&patch_file)?;
+            let patch = patch::RemovalPatch::parse(&contents).context(&patch_file)?;
+            let layer = patch::apply(Path::new(&store), &patch, force).await?;
+            println!("{}", name_to_string(layer));
+        }
+        Commands::RenamePredicate {
+            old_iri,
+            new_iri,
+            label,
+            store,
+            merge,
+        } => {
+            let store = store.unwrap_or_else(|| ".".to_string());
+            refuse_when_attached("rename-predicate")?;
+            let summary =
+                rename::rename_predicate(Path::new(&store), &label, &old_iri, &new_iri, merge)
+                    .await?;
+            println!(
+                "moved {} triples, merged {}; {label} now points at {}",
+                summary.moved,
+                summary.merged,
+                name_to_string(summary.head)
+            );
+        }
+        Commands::RewriteNamespace {
+            from,
+            to,
+            label,
+            store,
+            output,
+            force,
+        } => {
+            let store = store_search_path(store);
+            let layer = open_layer_or_label(&store, None, Some(label.clone()))?;
+            let layers: Vec<_> = store::chain(Path::new(&store), layer.name())
+                .await?
+                .into_iter()
+                .map(|(_, path)| path)
+                .collect();
+            let required = preflight::total_size(&layers).await?;
+            preflight::ensure_space(Path::new(&output), required, force)?;
+            let summary =
+                rename::rewrite_namespace(&layer, &label, &from, &to, Path::new(&output))?;
+            println!(
+                "rewrote {} layers, {} triples changed; {label} now points at {}",
+                summary.layers,
+                summary.rewritten,
+                name_to_string(summary.head)
+            );
+        }
+        Commands::PurgeValue {
+            value,
+            label,
+            store,
+            output,
+            force,
+            yes,
+        } => {
+            let store = store_search_path(store);
+            let layer = open_layer_or_label(&store, None, Some(label.clone()))?;
+            let layers: Vec<_> = store::chain(Path::new(&store), layer.name())
+                .await?
+                .into_iter()
+                .map(|(_, path)| path)
+                .collect();
+            let required = preflight::total_size(&layers).await?;
+            preflight::ensure_space(Path::new(&output), required, force)?;
+            let preview = [
+                format!(
+                    "rewrite the {} layers ({}) of {label} into {output}",
+                    layers.len(),
+                    confirm::total_size(&layers)
+                ),
+                format!("leave out every triple with the value {value}"),
+            ];
+            confirm::confirm("purge-value", Path::new(&store), &preview, yes)?;
+            let target = purge::PurgeTarget::parse(&value);
+            let summary = purge::purge_value(&layer, &label, &target, Path::new(&output))?;
+            println!(
+                "rewrote {} layers, purged {} triples; {label} now points at {}",
+                summary.layers,
+                summary.purged,
+                name_to_string(summary.head)
+            );
+        }
+        Commands::RecodeValues {
+            label,
+            store,
+            datatype,
+            strategy,
+            output,
+            force,
+            yes,
+        } => {
+            let store = store_search_path(store);
+            let layer = open_layer_or_label(&store, None, Some(label.clone()))?;
+            let layers: Vec<_> = store::chain(Path::new(&store), layer.name())
+                .await?
+                .into_iter()
+                .map(|(_, path)| path)
+                .collect();
+            let required = preflight::total_size(&layers).await?;
+            preflight::ensure_space(Path::new(&output), required, force)?;
+            let preview = [
+                format!(
+                    "rewrite the {} layers ({}) of {label} into {output}",
+                    layers.len(),
+                    confirm::total_size(&layers)
+                ),
+                format!(
+                    "re-encode every {datatype} value with the {} strategy",
+                    strategy.to_possible_value().unwrap().get_name()
+                ),
+            ];
+            confirm::confirm("recode-values", Path::new(&store), &preview, yes)?;
+            let summary =
+                recode::recode_values(&layer, &label, &datatype, strategy, Path::new(&output))?;
+            println!(
+                "rewrote {} layers, recoded {} triples; {label} now points at {}",
+                summary.layers,
+                summary.recoded,
+                name_to_string(summary.head)
+            );
+        }
+        Commands::Anonymize {
+            store,
+            output,
+            salt,
+            force,
+        } => {
+            let store = store.unwrap_or_else(|| ".".to_string());
+            let layers: Vec<_> = store::list_layers(Path::new(&store))
+                .await?
+                .into_iter()
+                .map(|(_, path)| path)
+                .collect();
+            let required = preflight::total_size(&layers).await?;
+            preflight::ensure_space(Path::new(&output), required, force)?;
+            let mut heads = Vec::new();
+            for label in store::list_labels(Path::new(&store)).await? {
+                if store::read_label(Path::new(&store), &label)
+                    .await?
+                    .is_some()
+                {
+                    let head = open_layer_or_label(&store, None, Some(label.clone()))?;
+                    heads.push((label, *head));
+                }
+            }
+            let salt = salt.unwrap_or_else(|| format!("{:x}", rand::random::<u64>()));
+            let summary = anonymize::anonymize(&heads, &salt, Path::new(&output))?;
+            println!(
+                "anonymized {} layers of {} labels into {output}",
+                summary.layers, summary.labels
+            );
+        }
+        Commands::Init { store, labels } => {
+            init::init(Path::new(&store), &labels)
+                .await
+                .context(&store)?;
+            println!("initialized store {store}");
+        }
+        Commands::CheckHeads { store, repair } => {
+            let store = store.unwrap_or_else(|| ".".to_string());
+            if repair {
+                refuse_when_attached("check-heads --repair")?;
+            }
+            let store = Path::new(&store);
+            let mut unrepaired = 0;
+            for label in store::list_labels(store).await? {
+                let check = label::check_head(store, &label).await?;
+                if check.findings.is_empty() {
+                    continue;
+                }
+                for finding in check.findings.iter() {
+                    println!("{label}: {finding}");
+                }
+                if repair && check.relinked.is_some() {
+                    label::repair_head(store, &check).await?;
+                    println!("{label}: relinked");
+                } else {
+                    unrepaired += 1;
+                }
+            }
+            if unrepaired > 0 {
+                std::process::exit(1);
+            }
+        }
+        Commands::Pin {
+            layer: None, store, ..
+        } => {
+            let store = store.unwrap_or_else(|| ".".to_string());
+            for (name, pin) in pins::load(Path::new(&store)).await? {
+                println!(
+                    "{}  {} {}  {}",
+                    name_to_string(name),
+                    pin.pinned,
+                    pin.user,
+                    pin.reason
+                );
+            }
+        }
+        Commands::Pin {
+            layer: Some(layer),
+            reason,
+            store,
+        } => {
+            let store = store.unwrap_or_else(|| ".".to_string());
+            refuse_when_attached("pin")?;
+            pins::pin(Path::new(&store), &layer, &reason).await?;
+        }
+        Commands::Unpin { layer, store } => {
+            let store = store.unwrap_or_else(|| ".".to_string());
+            refuse_when_attached("unpin")?;
+            pins::unpin(Path::new(&store), &layer).await?;
+        }
+        Commands::Tier {
+            store,
+            older_than,
+            keep_depth,
+            to,
+            dry_run,
+        } => {
+            let store = store.unwrap_or_else(|| ".".to_string());
+            if !dry_run {
+                refuse_when_attached("tier")?;
+            }
+            tier::tier(
+                Path::new(&store),
+                Path::new(&to),
+                older_than,
+                keep_depth,
+                dry_run,
+            )
+            .await?;
+        }
+        Commands::Reparent {
+            layer_file,
+            new_parent,
+            store,
+            force,
+            output,
+            yes,
+        } => {
+            let store = store.unwrap_or_else(|| ".".to_string());
+            let output = output.unwrap_or_else(|| layer_file.clone());
+            refuse_when_attached("reparent")?;
+            let old_parent = Archive::open(&layer_file)
+                .await
+                .context(&layer_file)?
+                .parent()
+                .context(&layer_file)?
+                .map(name_to_string)
+                .unwrap_or_else(|| "none".to_string());
+            let mut preview = vec![format!(
+                "point {output} at parent {new_parent} instead of {old_parent}"
+            )];
+            let layer = Path::new(&layer_file)
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(|s| string_to_name(s).ok());
+            if let (Some(layer), true) = (layer, output == layer_file) {
+                let labels = confirm::labels_reaching(Path::new(&store), layer).await?;
+                preview.push(format!(
+                    "change the history of {} labels: {}",
+                    labels.len(),
+                    labels.join(", ")
+                ));
+            }
+            confirm::confirm("reparent", Path::new(&store), &preview, yes)?;
+            let mut audit = Audit::begin("reparent");
+            audit.track(Path::new(&output)).await?;
+            let changed = meta::reparent(
+                Path::new(&layer_file),
+                &new_parent,
+                Path::new(&store),
+                force,
+                Path::new(&output),
+            )
+            .await?;
+            if !changed {
+                println!("{output} already has parent {new_parent}; nothing to do");
+                audit.note("result", "already in effect".to_string());
+            }
+            audit.commit(Path::new(&store)).await?
+        }
+        Commands::Rollback {
+            label,
+            to,
+            back,
+            store,
+            yes,
+        } => {
+            let store = store.unwrap_or_else(|| ".".to_string());
+            refuse_when_attached("rollback")?;
+            let target = match (to, back) {
+                (Some(layer), _) => label::RollbackTarget::Layer(layer),
+                (None, back) => label::RollbackTarget::Back(back.unwrap()),
+            };
+            label::rollback(Path::new(&store), &label, target, yes)
+                .await
+                .context(&label)?
+        }
+        Commands::Canonicalize {
+            layer_file,
+            with_checksums,
+            store,
+            output,
+            force,
+        } => {
+            let store = audit::log_store(store, Path::new(&layer_file))?;
+            let output = output.unwrap_or_else(|| layer_file.clone());
+            refuse_when_attached("canonicalize")?;
+            let required = preflight::total_size(&[&layer_file]).await?;
+            preflight::ensure_space(Path::new(&output), required, force)?;
+            let mut audit = Audit::begin("canonicalize");
+            audit.track(Path::new(&output)).await?;
+            let archive = Archive::open(&layer_file).await.context(&layer_file)?;
+            let contents = checksum::canonicalize(&archive, with_checksums).context(&layer_file)?;
+            if !atomic::write_if_changed(&output, contents).await? {
+                println!("{output} is already canonical; nothing to do");
+                audit.note("result", "already in effect".to_string());
+            }
+            audit.commit(&store).await?
+        }
+        Commands::Repack {
+            layer_file,
+            output_file,
+            exclude,
+            store,
+            force,
+        } => {
+            let store = audit::log_store(store, Path::new(&layer_file))?;
+            refuse_when_attached("repack")?;
+            let exclude: Vec<_> = exclude
+                .iter()
+                .map(|name| header::parse_segment(name))
+                .collect::<io::Result<_>>()?;
+            let required = preflight::total_size(&[&layer_file]).await?;
+            preflight::ensure_space(Path::new(&output_file), required, force)?;
+            let archive = Archive::open(&layer_file).await.context(&layer_file)?;
+            let repacked = repack::repack(&archive, &exclude)
+                .await
+                .context(&layer_file)?;
+            let mut audit = Audit::begin("repack");
+            audit.track(Path::new(&output_file)).await?;
+            if !repacked.dropped.is_empty() {
+                audit.note(
+                    "excluded",
+                    repacked
+                        .dropped
+                        .iter()
+                        .map(|t| format!("{t:?}"))
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                );
+            }
+            let size = repacked.contents.len();
+            let changed = atomic::write_if_changed(&output_file, repacked.contents).await?;
+            if changed {
+                println!(
+                    "wrote {} segments ({}) to {output_file}, {} dropped, {} trailing bytes removed",
+                    repacked.segments,
+                    human_bytes(size),
+                    repacked.dropped.len(),
+                    repacked.trailing
+                );
+            } else {
+                println!("{output_file} is already tightly packed; nothing to do");
+                audit.note("result", "already in effect".to_string());
+            }
+            audit.commit(&store).await?
+        }
+        Commands::DumpDictBlocks {
+            layer_file,
+            dict_type,
+            format,
+        } => dump_dict_blocks(layer_file.clone(), dict_type, format)
+            .await
+            .context(&layer_file)?,
+        Commands::SalvageDict {
+            layer_file,
+            dict_type,
+        } => salvage_dict(layer_file.clone(), dict_type)
+            .await
+            .context(&layer_file)?,
+        Commands::CheckAdjacency {
+            nums_file,
+            bits_file,
+            layer_file,
+            allow_empty,
+            format,
+        } => {
+            let results = match (layer_file, nums_file, bits_file) {
+                (Some(layer_file), None, None) => {
+                    let archive = Archive::open(&layer_file).await.context(&layer_file)?;
+                    adjacency::check_archive(&archive)
+                }
+                (None, Some(nums_file), Some(bits_file)) => {
+                    let nums = tokio::fs::read(&nums_file).await.context(&nums_file)?;
+                    let bits = tokio::fs::read(&bits_file).await.context(&bits_file)?;
+                    let findings =
+                        adjacency::check_adjacency(nums.into(), bits.into(), allow_empty);
+                    vec![(nums_file, findings)]
+                }
+                _ => {
+                    return Err(Error::usage(
+                        "specify either nums and bits files or a layer file",
+                    ))
+                }
+            };
+            let mut ok = true;
+            for (name, findings) in results {
+                fsck::print_result(&name, &findings, format);
+                ok &= findings.is_empty();
+            }
+            if !ok {
+                std::process::exit(1);
+            }
+        }
+        Commands::Verify {
+            layer_file,
+            quick,
+            format,
+        } => {
+            let findings = match Archive::open(&layer_file).await {
+                Ok(archive) => verify::verify(&archive, quick).await,
+                Err(e) => vec![validate::Finding::unreadable(e)],
+            };
+            fsck::print_result(&layer_file, &findings, format);
+            if !findings.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        Commands::Diagnose { layer_file, format } => {
+            let diagnosis = diagnose::diagnose(Path::new(&layer_file))
+                .await
+                .context(&layer_file)?;
+            diagnose::print_diagnosis(&layer_file, &diagnosis, format);
+            if !diagnosis.clusters.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        Commands::Chain {
+            layer,
+            label,
+            store,
+        } => {
+            let store: PathBuf = store_search_path(store).into();
+            let head = match (layer, label) {
+                (Some(layer), _) => parse_name(&layer)?,
+                (None, label) => label_head(&store, &label.unwrap()).await?,
+            };
+            if !ancestry::print_ancestry(&store, head).await? {
+                std::process::exit(1);
+            }
+        }
+        Commands::CheckChain { label, store } => {
+            let store: PathBuf = store_search_path(store).into();
+            let head = label_head(&store, &label).await?;
+            let mut broken = false;
+            for layer in ids::check_chain(&store, head).await? {
+                let name = name_to_string(layer.name);
+                match layer.broken {
+                    Some(problem) => {
+                        println!("{} {name}: {problem}", paint("BROKEN", Color::Red));
+                        broken = true;
+                    }
+                    None => println!(
+                        "{} {name}: {} node/value ids, {} predicate ids",
+                        paint("    OK", Color::Green),
+                        layer.total.nodes_values,
+                        layer.total.predicates
+                    ),
+                }
+            }
+            if broken {
+                std::process::exit(1);
+            }
+        }
+        Commands::Squash {
+            label,
+            store,
+            output,
+            force,
+        } => {
+            let store = store_search_path(store);
+            let layer = open_layer_or_label(&store, None, Some(label.clone()))?;
+            let layers: Vec<_> = store::chain(Path::new(&store), layer.name())
+                .await?
+                .into_iter()
+                .map(|(_, path)| path)
+                .collect();
+            // the layer is built in a scratch store, then copied out
+            let required = preflight::total_size(&layers).await? * 2;
+            preflight::ensure_space(Path::new(&output), required, force)?;
+            let summary = squash::squash(&layer, Path::new(&output)).await?;
+            println!(
+                "squashed {} layers of {label} into {} with {} triples",
+                summary.layers,
+                name_to_string(summary.name),
+                summary.triples
+            );
+        }
+        Commands::BuildLayer {
+            input,
+            output,
+            force,
+            allow_string_fallback,
+        } => {
+            // the layer is built in a scratch store, then copied out
+            let required = preflight::total_size(&[&input]).await? * 2;
+            preflight::ensure_space(Path::new(&output), required, force)?;
+            let summary = build_layer::build_layer(
+                Path::new(&input),
+                Path::new(&output),
+                allow_string_fallback,
+            )
+            .await
+            .context(&input)?;
+            println!(
+                "built {} with {} triples into {output}",
+                name_to_string(summary.name),
+                summary.triples
+            );
+            if summary.as_strings > 0 {
+                eprintln!(
+                    "{} literals of datatypes without an encoding here were stored as strings",
+                    summary.as_strings
+                );
+            }
+        }
+        Commands::CheckSquash {
+            original,
+            squashed,
+            samples,
+            store,
+        } => {
+            let store = store_search_path(store);
+            let original = open_layer_or_label(&store, None, Some(original))?;
+            let squashed = open_layer_or_label(&store, Some(squashed), None)?;
+            let check = squash_check::check_squash(&original, &squashed, samples)?;
+            println!(
+                "original: {} triples, hash {}",
+                check.original_count, check.original_hash
+            );
+            println!(
+                "squashed: {} triples, hash {}",
+                check.squashed_count, check.squashed_hash
+            );
+            println!("{} spot checks", check.spot_checks);
+            for triple in check.missing.iter() {
+                println!("- {triple}");
+            }
+            for triple in check.extra.iter() {
+                println!("+ {triple}");
+            }
+            if check.is_equal() {
+                println!("{}", paint("equal", Color::Green));
+            } else {
+                println!("{}", paint("differ", Color::Red));
+                std::process::exit(1);
+            }
+        }
+        Commands::Label { action } => label_command(action).await?,
+        Commands::Meta { action } => meta_command(action).await?,
+        Commands::Rollup { action } => rollup_command(action).await?,
+        Commands::Backup {
+            store,
+            output,
+            since,
+        } => {
+            let store = store.unwrap_or_else(|| ".".to_string());
+            let manifest = backup::backup(
+                Path::new(&store),
+                Path::new(&output),
+                since.as_deref().map(Path::new),
+            )
+            .await?;
+            println!(
+                "archived {} of {} layers and {} labels",
+                manifest.archived.len(),
+                manifest.layers.len(),
+                manifest.labels.len()
+            );
+        }
+        Commands::ExportRange {
+            from,
+            to,
+            include_from,
+            include_to,
+            store,
+            output,
+        } => {
+            let store = store_search_path(store);
+            let layers = backup::export_range(
+                Path::new(&store),
+                parse_name(&from)?,
+                parse_name(&to)?,
+                include_from,
+                include_to,
+                Path::new(&output),
+            )
+            .await?;
+            for layer in layers.iter() {
+                println!("{}", name_to_string(*layer));
+            }
+            eprintln!("packaged {} layers", layers.len());
+        }
+        Commands::Restore { backups, output } => {
+            let backups: Vec<&Path> = backups.iter().map(Path::new).collect();
+            backup::restore(&backups, Path::new(&output))?;
+        }
+        Commands::Fetch {
+            source,
+            expect_sha256,
+            output,
+        } => {
+            let size = fetch::fetch(&source, &expect_sha256, Path::new(&output))
+                .await
+                .context(&source)?;
+            println!("{output}: {} verified", human_bytes(size as usize));
+        }
+        Commands::Manifest {
+            action: ManifestCommand::Diff { old, new },
+        } => {
+            let (old, _) = backup::Manifest::read(Path::new(&old))
+                .await
+                .context(&old)?;
+            let (new, _) = backup::Manifest::read(Path::new(&new))
+                .await
+                .context(&new)?;
+            for line in backup::manifest_diff(&old, &new) {
+                println!("{line}");
+            }
+        }
+        Commands::Audit {
+            action: AuditCommand::Show { store },
+        } => {
+            let store = store.unwrap_or_else(|| ".".to_string());
+            audit::show(Path::new(&store)).await?
+        }
+        Commands::Completions { shell } => completions::generate(shell, Cli::command()),
+        Commands::Explain { code: None } => {
+            for code in codes::FindingCode::ALL {
+                println!("{code}  {}", code.explanation().title);
+            }
+        }
+        Commands::Explain { code: Some(code) } => match codes::FindingCode::parse(&code) {
+            Some(code) => {
+                let explanation = code.explanation();
+                println!("{code}: {}", explanation.title);
+                println!();
+                println!("{}", explanation.meaning);
+                println!();
+                println!("Likely causes: {}", explanation.causes);
+                println!();
+                println!("Repair: {}", explanation.repair);
+            }
+            None => return Err(Error::not_found(format!("unknown finding code {code}"))),
+        },
+        Commands::Schema { command } => {
+            let command = command.join(" ");
+            match contract::output_schema(&command) {
+                Some(schema) => println!("{}", serde_json::to_string_pretty(&schema).unwrap()),
+                None => {
+                    return Err(Error::not_found(format!(
+                        "{command} has no ndjson output. Commands that do: {}",
+                        contract::COMMANDS.join(", ")
+                    )))
+                }
+            }
+        }
+        Commands::SelfTest {
+            rounds,
+            chain_length,
+            size,
+            seed,
+            scratch,
+        } => {
+            let seed = seed.unwrap_or_else(rand::random);
+            let (scratch, temporary) = match scratch {
+                Some(scratch) => (PathBuf::from(scratch), false),
+                None => (
+                    std::env::temp_dir().join(format!("surgery-self-test-{}", std::process::id())),
+                    true,
+                ),
+            };
+            let problems = selftest::self_test(&scratch, rounds, chain_length, size, seed).await;
+            if temporary {
+                let _ = tokio::fs::remove_dir_all(&scratch).await;
+            }
+            let problems = problems?;
+            for problem in problems.iter() {
+                println!("{problem}");
+            }
+            println!(
+                "{} problems in {rounds} chains of {chain_length} layers (seed {seed})",
+                problems.len()
+            );
+            if !problems.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        Commands::Smoke {
+            store,
+            label,
+            lookups,
+            seed,
+            format,
+        } => {
+            let store = store.unwrap_or_else(|| ".".to_string());
+            let seed = seed.unwrap_or_else(rand::random);
+            let checks = smoke::smoke(Path::new(&store), &label, lookups, seed).await?;
+            for check in checks.iter() {
+                smoke::print_check(check, format);
+            }
+            let failed = checks.iter().filter(|c| !c.passed()).count();
+            eprintln!("{failed} of {} checks failed (seed {seed})", checks.len());
+            if failed > 0 {
+                std::process::exit(1);
+            }
+        }
+        Commands::Complete {
+            kind,
+            prefix,
+            store,
+        } => {
+            let store = store_search_path(store);
+            // a store that can't be read simply has nothing to complete
+            let _ = completions::complete(Path::new(&store), kind, &prefix).await;
+        }
+    }
+
+    Ok(())
+}
+
+async fn label_command(action: LabelCommand) -> error::Result<()> {
+    match action {
+        LabelCommand::List { store } => {
+            let store = store.unwrap_or_else(|| ".".to_string());
+            let store = Path::new(&store);
+            for label in store::list_labels(store).await? {
+                match store::read_label(store, &label).await {
+                    Ok(head) => println!(
+                        "{label} {}",
+                        head.map(name_to_string).as_deref().unwrap_or("-")
+                    ),
+                    Err(e) => eprintln!("{label}: {e}"),
+                }
+            }
+        }
+        LabelCommand::Get { label, store } => {
+            let store = store.unwrap_or_else(|| ".".to_string());
+            let head = store::read_label(Path::new(&store), &label)
+                .await
+                .context(&label)?;
+            println!("{}", head.map(name_to_string).as_deref().unwrap_or("-"));
+        }
+        LabelCommand::Set {
+            label,
+            layer,
+            store,
+            force,
+            yes,
+        } => {
+            let store = store.unwrap_or_else(|| ".".to_string());
+            refuse_when_attached("label set")?;
+            label::set(Path::new(&store), &label, &layer, force, yes)
+                .await
+                .context(&label)?
+        }
+    }
+
+    Ok(())
+}
+
+async fn meta_command(action: MetaCommand) -> error::Result<()> {
+    match action {
+        MetaCommand::Show { layer_file } => meta::show(Path::new(&layer_file))
+            .await
+            .context(&layer_file)?,
+        MetaCommand::Set {
+            layer_file,
+            key,
+            value,
+            store,
+            output,
+        } => {
+            let output = output.unwrap_or_else(|| layer_file.clone());
+            refuse_when_attached("meta set")?;
+            let store = audit::log_store(store, Path::new(&layer_file))?;
+            let mut audit = Audit::begin("meta set");
+            audit.track(Path::new(&output)).await?;
+            let changed = meta::set(
+                Path::new(&layer_file),
+                &key,
+                &value,
+                Some(&store),
+                Path::new(&output),
+            )
+            .await?;
+            if !changed {
+                println!("{output} already has {key} {value}; nothing to do");
+                audit.note("result", "already in effect".to_string());
+            }
+            audit.commit(&store).await?
+        }
+    }
+
+    Ok(())
+}
+
+async fn rollup_command(action: RollupCommand) -> error::Result<()> {
+    match action {
+        RollupCommand::Show { layer_file, store } => {
+            let store = store.unwrap_or_else(|| ".".to_string());
+            let info = rollup::show(Path::new(&store), Path::new(&layer_file))
+                .await
+                .context(&layer_file)?;
+            print_rollup(&info, Path::new(&store));
+            if !info.usable() {
+                std::process::exit(1);
+            }
+        }
+        RollupCommand::Strip {
+            layer_file,
+            output,
+            store,
+            force,
+        } => {
+            let store = audit::log_store(store, Path::new(&layer_file))?;
+            let output = output.unwrap_or_else(|| layer_file.clone());
+            refuse_when_attached("rollup strip")?;
+            let required = preflight::total_size(&[&layer_file]).await?;
+            preflight::ensure_space(Path::new(&output), required, force)?;
+            let archive = Archive::open(&layer_file).await.context(&layer_file)?;
+            match rollup::strip(&archive).await.context(&layer_file)? {
+                Some(stripped) => {
+                    let mut audit = Audit::begin("rollup strip");
+                    audit.track(Path::new(&output)).await?;
+                    if let Ok(Some(rollup)) = archive.rollup() {
+                        audit.note("rollup", name_to_string(rollup));
+                    }
+                    if atomic::write_if_changed(&output, stripped.contents).await? {
+                        println!("wrote {output} without its rollup");
+                    } else {
+                        println!("{output} already has no rollup; nothing to do");
+                        audit.note("result", "already in effect".to_string());
+                    }
+                    audit.commit(&store).await?
+                }
+                None => println!("{layer_file} has no rollup; nothing to do"),
+            }
+        }
+        RollupCommand::Rebuild { store, layer } => {
+            let store = store.unwrap_or_else(|| ".".to_string());
+            let name = parse_name(&layer)?;
+            refuse_when_attached("rollup rebuild")?;
+            let mut audit = Audit::begin("rollup rebuild");
+            audit
+                .track(&store::layer_path(Path::new(&store), name))
+                .await?;
+            let rebuilt = rollup::rebuild(Path::new(&store), name)
+                .await
+                .context(&layer)?;
+            if rebuilt.stripped {
+                println!("stripped the previous rollup of {layer}");
+            }
+            println!(
+                "rolled up {layer} into {} ({} triples)",
+                name_to_string(rebuilt.rollup),
+                rebuilt.triples
+            );
+            audit.note("rollup", name_to_string(rebuilt.rollup));
+            audit.commit(Path::new(&store)).await?
+        }
+    }
+
+    Ok(())
+}
+
+async fn parse_and_print_header(
+    file_name: &str,
+    sort: bool,
+    offsets: OffsetBase,
+    absolute: bool,
+    segment: Option<String>,
+    format: OutputFormat,
+) -> io::Result<()> {
+    let report = LayerSource::open(file_name).header().await?;
+    let base = if absolute { report.header_len } else { 0 };
+    let wanted = segment
+        .map(|name| header::parse_segment(&name))
+        .transpose()?;
+
+    let mut result = Vec::new();
+    for info in report.segments.iter() {
+        if wanted.map(|w| w != info.segment).unwrap_or(false) {
+            continue;
+        }
+        result.push((
+            info.name(),
+            base + info.range.start,
+            base + info.range.end,
+            info.range.len(),
+        ));
+    }
+    if sort {
+        result.sort_by_key(|x| x.3);
+        result.reverse();
+    }
+
+    match format {
+        OutputFormat::Pretty => {
+            let width = result.iter().map(|x| x.0.len()).max().unwrap_or(0);
+            let offset_width = result
+                .iter()
+                .map(|x| offsets.format(x.2).len())
+                .max()
+                .unwrap_or(0)
+                .max(12);
+            let total: usize = result.iter().map(|x| x.3).sum();
+            for (file_name, start, end, len) in result {
+                println!(
+                    "{file_name:<width$}  {:>offset_width$}..{:<offset_width$} {:>10}",
+                    offsets.format(start),
+                    offsets.format(end),
+                    human_bytes(len)
+                );
+            }
+            println!(
+                "{:<width$}  {:>pad$} {}",
+                "total",
+                "",
+                paint(&format!("{:>10}", human_bytes(total)), Color::Yellow),
+                pad = offset_width * 2 + 2
+            );
+        }
+        OutputFormat::Text => {
+            for (file_name, start, end, len) in result {
+                println!(
+                    "{file_name: >50}:\t{: >10}..{: <10} ({})",
+                    offsets.format(start),
+                    offsets.format(end),
+                    len
+                );
+            }
+        }
+        OutputFormat::Ndjson => {
+            for (file_name, start, end, len) in result {
+                println!(
+                    "{}",
+                    ndjson(json!({"segment": file_name, "start": start, "end": end, "size": len}))
+                );
+            }
+        }
+    }
+
+    Ok(())
 }
@@ -0,0 +1,183 @@
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+};
+
+use sha2::{Digest, Sha256};
+use terminus_store::storage::name_to_string;
+
+use crate::{archive::Archive, atomic, fsck::FileStamp, store::list_layers};
+
+/// Where the tree of the last build is kept, so unchanged layers needn't
+/// be hashed again.
+pub fn default_cache_path(store: &Path) -> PathBuf {
+    let mut path = store.to_path_buf();
+    path.push(".surgery");
+    path.push("merkle-cache");
+    path
+}
+
+/// A two-level merkle tree over a store: segments hash into their layer,
+/// layers hash into the root.
+#[derive(Default, PartialEq, Eq)]
+pub struct MerkleTree {
+    pub root: String,
+    pub layers: BTreeMap<String, LayerNode>,
+}
+
+#[derive(Default, Clone, PartialEq, Eq)]
+pub struct LayerNode {
+    pub hash: String,
+    pub segments: BTreeMap<String, String>,
+    /// The layer file the hashes were computed from, if known.
+    pub stamp: Option<FileStamp>,
+}
+
+fn combine<'a, I: Iterator<Item = (&'a String, &'a String)>>(children: I) -> String {
+    let mut hasher = Sha256::new();
+    for (name, hash) in children {
+        hasher.update(name.as_bytes());
+        hasher.update(hash.as_bytes());
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+impl MerkleTree {
+    /// Build the tree of a store. Layers whose file is unchanged since the
+    /// cached tree was built keep their hashes from it, and only the others
+    /// are read and hashed. The cache is then replaced by the new tree.
+    pub async fn build(store: &Path) -> io::Result<Self> {
+        let cache_path = default_cache_path(store);
+        // a missing or malformed cache just means hashing everything
+        let cached = Self::load(&cache_path).await.unwrap_or_default();
+        let mut layers = BTreeMap::new();
+        for (name, path) in list_layers(store).await? {
+            let name = name_to_string(name);
+            let stamp = FileStamp::of(&path).await?;
+            if let Some(node) = cached.layers.get(&name) {
+                if node.stamp == Some(stamp) {
+                    layers.insert(name, node.clone());
+                    continue;
+                }
+            }
+            let archive = Archive::open(&path).await?;
+            let mut segments = BTreeMap::new();
+            for (file_type, _) in archive.segments() {
+                let contents = archive.segment(file_type)?.unwrap();
+                segments.insert(
+                    format!("{file_type:?}"),
+                    format!("{:x}", Sha256::digest(&contents)),
+                );
+            }
+            let hash = combine(segments.iter());
+            layers.insert(
+                name,
+                LayerNode {
+                    hash,
+                    segments,
+                    stamp: Some(stamp),
+                },
+            );
+        }
+        let root = combine(layers.iter().map(|(name, node)| (name, &node.hash)));
+        let tree = Self { root, layers };
+        if let Some(dir) = cache_path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        tree.save(&cache_path).await?;
+
+        Ok(tree)
+    }
+
+    pub async fn save(&self, path: &Path) -> io::Result<()> {
+        let mut out = format!("root {}\n", self.root);
+        for (name, node) in self.layers.iter() {
+            match node.stamp {
+                Some(stamp) => out.push_str(&format!(
+                    "layer {name} {} {} {}\n",
+                    node.hash, stamp.mtime, stamp.size
+                )),
+                None => out.push_str(&format!("layer {name} {}\n", node.hash)),
+            }
+            for (segment, hash) in node.segments.iter() {
+                out.push_str(&format!("segment {name} {segment} {hash}\n"));
+            }
+        }
+        atomic::write(path, out).await
+    }
+
+    pub async fn load(path: &Path) -> io::Result<Self> {
+        let contents = tokio::fs::read_to_string(path).await?;
+        let mut tree = Self::default();
+        for line in contents.lines() {
+            let fields: Vec<_> = line.split(' ').collect();
+            match fields[..] {
+                ["root", hash] => tree.root = hash.to_string(),
+                ["layer", name, hash] => {
+                    tree.layers.entry(name.to_string()).or_default().hash = hash.to_string();
+                }
+                ["layer", name, hash, mtime, size] => {
+                    let node = tree.layers.entry(name.to_string()).or_default();
+                    node.hash = hash.to_string();
+                    if let (Ok(mtime), Ok(size)) = (mtime.parse(), size.parse()) {
+                        node.stamp = Some(FileStamp { mtime, size });
+                    }
+                }
+                ["segment", name, segment, hash] => {
+                    tree.layers
+                        .entry(name.to_string())
+                        .or_default()
+                        .segments
+                        .insert(segment.to_string(), hash.to_string());
+                }
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("malformed merkle tree line: {line}"),
+                    ))
+                }
+            }
+        }
+
+        Ok(tree)
+    }
+
+    /// Describe every difference between this (older) tree and a newer
+    /// one. Only subtrees whose hashes differ are descended into.
+    pub fn diff(&self, newer: &MerkleTree) -> Vec<String> {
+        let mut result = Vec::new();
+        if self.root == newer.root {
+            return result;
+        }
+        for (name, node) in self.layers.iter() {
+            match newer.layers.get(name) {
+                None => result.push(format!("removed layer {name}")),
+                Some(new_node) if new_node.hash != node.hash => {
+                    for (segment, hash) in node.segments.iter() {
+                        match new_node.segments.get(segment) {
+                            None => result.push(format!("removed segment {name} {segment}")),
+                            Some(new_hash) if new_hash != hash => {
+                                result.push(format!("changed segment {name} {segment}"))
+                            }
+                            _ => {}
+                        }
+                    }
+                    for segment in new_node.segments.keys() {
+                        if !node.segments.contains_key(segment) {
+                            result.push(format!("added segment {name} {segment}"));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        for name in newer.layers.keys() {
+            if !self.layers.contains_key(name) {
+                result.push(format!("added layer {name}"));
+            }
+        }
+
+        result
+    }
+}
@@ -0,0 +1,139 @@
+use std::{io, path::Path};
+
+use bytes::BytesMut;
+use terminus_store::storage::{consts::LayerFileEnum, name_to_string, string_to_name};
+
+use crate::{
+    archive::Archive,
+    atomic,
+    ids::{cumulative_counts, dict_counts, max_referenced_ids},
+    output::human_bytes,
+    store::layer_path,
+};
+
+/// Print the metadata an archive carries about itself.
+pub async fn show(layer_file: &Path) -> io::Result<()> {
+    let archive = Archive::open(layer_file).await?;
+    let metadata = tokio::fs::metadata(layer_file).await?;
+    println!("{:>12}: {}", "file", layer_file.display());
+    println!("{:>12}: {}", "size", human_bytes(archive.contents().len()));
+    println!("{:>12}: {} bytes", "header", archive.header_len());
+    println!("{:>12}: {}", "segments", archive.segments().len());
+    if let Ok(modified) = metadata.modified() {
+        let age = modified.elapsed().map(|d| d.as_secs()).unwrap_or(0);
+        println!("{:>12}: {age}s ago", "modified");
+    }
+    match archive.parent()? {
+        Some(parent) => println!("{:>12}: {}", "parent", name_to_string(parent)),
+        None => println!("{:>12}: none (base layer)", "parent"),
+    }
+    match archive.segment(LayerFileEnum::Rollup)? {
+        Some(rollup) => println!("{:>12}: {} bytes", "rollup", rollup.len()),
+        None => println!("{:>12}: none", "rollup"),
+    }
+
+    Ok(())
+}
+
+/// Change a metadata value of an archive, writing the result to `output`.
+/// Returns false, writing nothing, if `output` already has the result.
+///
+/// Only the parent reference can be changed. It can't be added to or
+/// removed from a layer, as base and child layers have different segments.
+pub async fn set(
+    layer_file: &Path,
+    key: &str,
+    value: &str,
+    store: Option<&Path>,
+    output: &Path,
+) -> io::Result<bool> {
+    if key != "parent" {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unknown metadata key {key}, only parent can be set"),
+        ));
+    }
+    let parent = string_to_name(value)?;
+    let archive = Archive::open(layer_file).await?;
+    let range = archive.absolute_range(LayerFileEnum::Parent).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "layer is a base layer and has no parent to change",
+        )
+    })?;
+    // make sure the existing reference is a well-formed name we can overwrite
+    archive.parent()?;
+
+    let own_name = layer_file
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .and_then(|s| string_to_name(s).ok());
+    if own_name == Some(parent) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "a layer can't be its own parent",
+        ));
+    }
+    if let Some(store) = store {
+        if !layer_path(store, parent).exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("parent layer {value} is not in the store"),
+            ));
+        }
+    }
+
+    let mut contents = BytesMut::from(&archive.contents()[..]);
+    for (i, part) in parent.iter().enumerate() {
+        let start = range.start + i * 4;
+        contents[start..start + 4].copy_from_slice(&part.to_be_bytes());
+    }
+    atomic::write_if_changed(output, contents).await
+}
+
+/// Point a child layer at a different parent. Unless `force` is set, the
+/// new parent must number its ids exactly like the old one (when that is
+/// still in the store), and must leave room for every id the child refers
+/// to. Returns false if `output` already had the new parent.
+pub async fn reparent(
+    layer_file: &Path,
+    new_parent: &str,
+    store: &Path,
+    force: bool,
+    output: &Path,
+) -> io::Result<bool> {
+    let new_parent_name = string_to_name(new_parent)?;
+    if !force {
+        let archive = Archive::open(layer_file).await?;
+        let new_counts = cumulative_counts(store, new_parent_name).await?;
+        if let Some(old_parent) = archive.parent()? {
+            if layer_path(store, old_parent).exists() {
+                let old_counts = cumulative_counts(store, old_parent).await?;
+                if old_counts != new_counts {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "id ranges differ: old parent {} has {old_counts:?}, new parent has {new_counts:?}",
+                            name_to_string(old_parent)
+                        ),
+                    ));
+                }
+            }
+        }
+
+        let available = new_counts + dict_counts(&archive).await?;
+        let referenced = max_referenced_ids(&archive)?;
+        if referenced.nodes_values > available.nodes_values
+            || referenced.predicates > available.predicates
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "layer refers to ids up to {referenced:?}, but only {available:?} exist on top of the new parent"
+                ),
+            ));
+        }
+    }
+
+    set(layer_file, "parent", new_parent, Some(store), output).await
+}
@@ -0,0 +1,234 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{self, IsTerminal},
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+use clap::ValueEnum;
+use serde_json::Value;
+
+/// Version of the records commands print with `--format ndjson`. It is
+/// bumped whenever a field is removed or changes meaning, but not when
+/// fields are added. `schema` prints the records of each version.
+pub const FORMAT_VERSION: u64 = 1;
+
+/// How commands render their results.
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Aligned, colored output for people
+    Pretty,
+    /// Plain text lines
+    Text,
+    /// One JSON object per line
+    Ndjson,
+}
+
+/// Serialize a record for `--format ndjson` output, stamped with the
+/// format version.
+pub fn ndjson(mut record: Value) -> String {
+    if let Value::Object(fields) = &mut record {
+        fields.insert("format_version".to_string(), FORMAT_VERSION.into());
+    }
+    record.to_string()
+}
+
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// Make commands without `--format` print their results as ndjson records,
+/// as `--json` does.
+pub fn set_json(enabled: bool) {
+    JSON.store(enabled, Ordering::Relaxed);
+}
+
+/// Print a command's result as text, or as an ndjson record with `--json`.
+pub fn emit(text: impl fmt::Display, record: Value) {
+    if JSON.load(Ordering::Relaxed) {
+        println!("{}", ndjson(record));
+    } else {
+        println!("{text}");
+    }
+}
+
+/// Number base for printing byte offsets.
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum OffsetBase {
+    Dec,
+    Hex,
+    /// Decimal followed by hexadecimal
+    Both,
+}
+
+impl OffsetBase {
+    pub fn format(self, offset: usize) -> String {
+        match self {
+            OffsetBase::Dec => offset.to_string(),
+            OffsetBase::Hex => format!("{offset:#x}"),
+            OffsetBase::Both => format!("{offset} ({offset:#x})"),
+        }
+    }
+}
+
+static COLOR: AtomicBool = AtomicBool::new(true);
+
+/// Enable or disable ANSI colors in pretty output.
+pub fn set_color(enabled: bool) {
+    COLOR.store(enabled, Ordering::Relaxed);
+}
+
+#[derive(Clone, Copy)]
+pub enum Color {
+    Red,
+    Green,
+    Yellow,
+    Dim,
+}
+
+/// Wrap text in the escape codes for the given color, if colors are on.
+pub fn paint(text: &str, color: Color) -> String {
+    if !COLOR.load(Ordering::Relaxed) {
+        return text.to_string();
+    }
+    let code = match color {
+        Color::Red => "31",
+        Color::Green => "32",
+        Color::Yellow => "33",
+        Color::Dim => "2",
+    };
+    format!("\x1b[{code}m{text}\x1b[0m")
+}
+
+/// Render a byte count with a binary unit, e.g. `1.5 MiB`.
+pub fn human_bytes(n: usize) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = n as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{n} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+/// Parse a byte count, optionally with a binary unit suffix such as `512M`
+/// or `10GiB`.
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let digits = s.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let multiplier: u64 = match s[digits.len()..].to_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KIB" => 1 << 10,
+        "M" | "MIB" => 1 << 20,
+        "G" | "GIB" => 1 << 30,
+        "T" | "TIB" => 1 << 40,
+        unit => return Err(format!("unknown size unit {unit}")),
+    };
+    let n: u64 = digits
+        .parse()
+        .map_err(|e| format!("invalid size {s}: {e}"))?;
+    n.checked_mul(multiplier)
+        .ok_or_else(|| format!("size {s} is too large"))
+}
+
+/// Quote a CSV field if it contains a separator, quote or line break.
+pub fn csv_field(field: &str) -> String {
+    if field.contains(|c| matches!(c, ',' | '"' | '\n' | '\r')) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// IRIs longer than this are abbreviated in pretty output.
+const ABBREVIATE_ABOVE: usize = 40;
+
+/// Shortens long IRIs to `pN:local` by assigning numbered prefixes, which
+/// are listed afterwards in a legend.
+#[derive(Default)]
+pub struct Abbreviator {
+    prefixes: Vec<String>,
+    index: HashMap<String, usize>,
+}
+
+impl Abbreviator {
+    pub fn abbreviate(&mut self, iri: &str) -> String {
+        if iri.len() <= ABBREVIATE_ABOVE {
+            return format!("<{iri}>");
+        }
+        let split = match iri.rfind(|c| c == '/' || c == '#') {
+            Some(ix) => ix + 1,
+            None => return format!("<{iri}>"),
+        };
+        let (prefix, local) = iri.split_at(split);
+        let next = self.prefixes.len();
+        let ix = *self.index.entry(prefix.to_string()).or_insert(next);
+        if ix == next {
+            self.prefixes.push(prefix.to_string());
+        }
+        format!("p{ix}:{local}")
+    }
+
+    pub fn print_legend(&self) {
+        if self.prefixes.is_empty() {
+            return;
+        }
+        println!();
+        for (ix, prefix) in self.prefixes.iter().enumerate() {
+            println!("{}", paint(&format!("p{ix}: <{prefix}>"), Color::Dim));
+        }
+    }
+}
+
+/// Width in characters of the bar drawn by `Progress`.
+const PROGRESS_WIDTH: usize = 30;
+
+/// A progress bar on stderr, redrawn at most ten times a second. Nothing
+/// is drawn when stderr isn't a terminal.
+pub struct Progress {
+    total: usize,
+    unit: &'static str,
+    enabled: bool,
+    drawn: Option<Instant>,
+}
+
+impl Progress {
+    pub fn new(total: usize, unit: &'static str) -> Self {
+        Self {
+            total,
+            unit,
+            enabled: io::stderr().is_terminal(),
+            drawn: None,
+        }
+    }
+
+    /// Show `done` of the total, followed by a note such as a failure count.
+    pub fn update(&mut self, done: usize, note: &str) {
+        let due = self
+            .drawn
+            .map(|at| at.elapsed() >= Duration::from_millis(100))
+            .unwrap_or(true);
+        if !self.enabled || !(due || done == self.total) {
+            return;
+        }
+        self.drawn = Some(Instant::now());
+        let filled = PROGRESS_WIDTH * done / self.total.max(1);
+        eprint!(
+            "\r[{}{}] {done}/{} {} {note}\x1b[K",
+            "#".repeat(filled),
+            " ".repeat(PROGRESS_WIDTH - filled),
+            self.total,
+            self.unit
+        );
+    }
+
+    /// Clear the bar so that the next output starts on a clean line.
+    pub fn clear(&self) {
+        if self.enabled && self.drawn.is_some() {
+            eprint!("\r\x1b[K");
+        }
+    }
+}
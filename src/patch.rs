@@ -0,0 +1,170 @@
+use std::{collections::BTreeSet, io, path::Path};
+
+use serde_json::{json, Value};
+use terminus_store::{
+    layer::IdTriple,
+    storage::{name_to_string, string_to_name},
+    store::sync::{open_sync_archive_store, SyncStoreLayer},
+    Layer,
+};
+
+use crate::{
+    audit::Audit,
+    store::{label_path, read_label},
+    triples::object_json,
+};
+
+/// A set of triples to remove from a label's head. It's saved as JSON
+/// lines: a header naming the label and the head it was made against,
+/// then one line per triple with its ids and, for review, its strings.
+pub struct RemovalPatch {
+    pub label: String,
+    pub base: [u32; 5],
+    pub removals: BTreeSet<(u64, u64, u64)>,
+}
+
+impl RemovalPatch {
+    pub fn to_json_lines(&self, layer: &SyncStoreLayer) -> String {
+        let mut out = format!(
+            "{}\n",
+            json!({"label": self.label, "base": name_to_string(self.base)})
+        );
+        for (s, p, o) in self.removals.iter() {
+            let triple = IdTriple::new(*s, *p, *o);
+            let mut line = json!({ "remove": [s, p, o] });
+            if let Some(resolved) = layer.id_triple_to_string(&triple) {
+                line["subject"] = json!(resolved.subject);
+                line["predicate"] = json!(resolved.predicate);
+                line["object"] = object_json(&resolved.object);
+            }
+            out.push_str(&format!("{line}\n"));
+        }
+        out
+    }
+
+    pub fn parse(contents: &str) -> io::Result<Self> {
+        let invalid =
+            |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+        let mut lines = contents.lines().filter(|l| !l.is_empty());
+        let header: Value =
+            serde_json::from_str(lines.next().ok_or_else(|| invalid("empty patch"))?)?;
+        let label = header["label"]
+            .as_str()
+            .ok_or_else(|| invalid("patch header lacks a label"))?;
+        let base = header["base"]
+            .as_str()
+            .ok_or_else(|| invalid("patch header lacks a base layer"))?;
+        let mut removals = BTreeSet::new();
+        for line in lines {
+            let line: Value = serde_json::from_str(line)?;
+            let ids: Vec<u64> = line["remove"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|id| id.as_u64())
+                .collect();
+            match ids[..] {
+                [s, p, o] => removals.insert((s, p, o)),
+                _ => return Err(invalid("patch line without a remove triple")),
+            };
+        }
+
+        Ok(Self {
+            label: label.to_string(),
+            base: string_to_name(base)?,
+            removals,
+        })
+    }
+}
+
+/// Collect every triple that describes or refers to one of the subjects.
+pub fn forget(layer: &SyncStoreLayer, label: &str, subjects: &[String]) -> RemovalPatch {
+    let mut removals = BTreeSet::new();
+    for subject in subjects {
+        if let Some(id) = layer.subject_id(subject) {
+            removals.extend(
+                layer
+                    .triples_s(id)
+                    .map(|t| (t.subject, t.predicate, t.object)),
+            );
+        }
+        if let Some(id) = layer.object_node_id(subject) {
+            removals.extend(
+                layer
+                    .triples_o(id)
+                    .map(|t| (t.subject, t.predicate, t.object)),
+            );
+        }
+    }
+
+    RemovalPatch {
+        label: label.to_string(),
+        base: layer.name(),
+        removals,
+    }
+}
+
+/// The layers of the chain that added any of the triples in a patch,
+/// head first.
+pub fn affected_layers(layer: &SyncStoreLayer, patch: &RemovalPatch) -> io::Result<Vec<[u32; 5]>> {
+    let subjects: BTreeSet<u64> = patch.removals.iter().map(|t| t.0).collect();
+    let mut result = Vec::new();
+    let mut current = Some(layer.clone());
+    while let Some(layer) = current {
+        let touched = subjects.iter().any(|s| {
+            layer
+                .triple_additions_s(*s)
+                .any(|t| patch.removals.contains(&(t.subject, t.predicate, t.object)))
+        });
+        if touched {
+            result.push(layer.name());
+        }
+        current = layer.parent()?;
+    }
+    Ok(result)
+}
+
+/// Apply a removal patch as a new layer on top of the label's head, and
+/// move the label to it. The head must still be the one the patch was
+/// made against unless `force` is given.
+pub async fn apply(store: &Path, patch: &RemovalPatch, force: bool) -> io::Result<[u32; 5]> {
+    let head = read_label(store, &patch.label).await?;
+    if head != Some(patch.base) && !force {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "label {} has moved since the patch was made against {}",
+                patch.label,
+                name_to_string(patch.base)
+            ),
+        ));
+    }
+
+    let sync_store = open_sync_archive_store(store, 512);
+    let graph = sync_store.open(&patch.label)?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("label {} not found", patch.label),
+        )
+    })?;
+    let base = graph.head()?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("label {} has no head", patch.label),
+        )
+    })?;
+    let builder = base.open_write()?;
+    for (s, p, o) in patch.removals.iter() {
+        builder.remove_id_triple(IdTriple::new(*s, *p, *o))?;
+    }
+    let layer = builder.commit()?;
+
+    let mut audit = Audit::begin("apply-patch");
+    audit.track(&label_path(store, &patch.label)).await?;
+    audit.note("old_head", name_to_string(base.name()));
+    audit.note("new_head", name_to_string(layer.name()));
+    graph.set_head(&layer)?;
+    audit.commit(store).await?;
+
+    Ok(layer.name())
+}
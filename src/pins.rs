@@ -0,0 +1,105 @@
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use terminus_store::storage::{name_to_string, string_to_name};
+
+use crate::{atomic, audit::Audit};
+
+/// Where a store's pinned layers are recorded.
+pub fn pins_path(store: &Path) -> PathBuf {
+    let mut path = store.to_path_buf();
+    path.push(".surgery");
+    path.push("pins");
+    path
+}
+
+/// Why and when a layer was pinned.
+pub struct Pin {
+    pub pinned: String,
+    pub user: String,
+    pub reason: String,
+}
+
+/// Read the pinned layers of a store. The pins file has a line per layer:
+/// its name, the time it was pinned, the user who pinned it and the
+/// reason, separated by tabs.
+pub async fn load(store: &Path) -> io::Result<BTreeMap<[u32; 5], Pin>> {
+    let contents = match tokio::fs::read_to_string(pins_path(store)).await {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(e),
+    };
+    let mut result = BTreeMap::new();
+    for line in contents.lines().filter(|l| !l.is_empty()) {
+        let mut fields = line.splitn(4, '\t');
+        let name = string_to_name(fields.next().unwrap())?;
+        let mut field = || fields.next().unwrap_or("").to_string();
+        result.insert(
+            name,
+            Pin {
+                pinned: field(),
+                user: field(),
+                reason: field(),
+            },
+        );
+    }
+
+    Ok(result)
+}
+
+async fn save(store: &Path, pins: &BTreeMap<[u32; 5], Pin>) -> io::Result<()> {
+    let path = pins_path(store);
+    tokio::fs::create_dir_all(path.parent().unwrap()).await?;
+    let contents: String = pins
+        .iter()
+        .map(|(name, pin)| {
+            format!(
+                "{}\t{}\t{}\t{}\n",
+                name_to_string(*name),
+                pin.pinned,
+                pin.user,
+                pin.reason.replace(['\t', '\n'], " ")
+            )
+        })
+        .collect();
+    atomic::write(path, contents).await
+}
+
+/// Pin a layer so that store maintenance leaves it alone. Pinning an
+/// already pinned layer replaces its reason.
+pub async fn pin(store: &Path, layer: &str, reason: &str) -> io::Result<()> {
+    let name = string_to_name(layer)?;
+    let mut pins = load(store).await?;
+    let mut audit = Audit::begin("pin");
+    audit.track(&pins_path(store)).await?;
+    pins.insert(
+        name,
+        Pin {
+            pinned: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+            user: std::env::var("USER").unwrap_or_default(),
+            reason: reason.to_string(),
+        },
+    );
+    save(store, &pins).await?;
+    audit.commit(store).await
+}
+
+/// Remove a layer's pin. Fails if the layer isn't pinned.
+pub async fn unpin(store: &Path, layer: &str) -> io::Result<()> {
+    let name = string_to_name(layer)?;
+    let mut pins = load(store).await?;
+    if pins.remove(&name).is_none() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("layer {layer} is not pinned"),
+        ));
+    }
+    let mut audit = Audit::begin("unpin");
+    audit.track(&pins_path(store)).await?;
+    save(store, &pins).await?;
+    audit.commit(store).await
+}
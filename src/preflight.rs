@@ -0,0 +1,53 @@
+use std::{io, path::Path};
+
+use crate::output::human_bytes;
+
+/// Room required beyond an estimate, as a fraction of it, since estimates
+/// from input sizes are rough.
+const HEADROOM_DIVISOR: u64 = 10;
+
+/// Total size of a set of files. Operations that rewrite their inputs write
+/// about as much as they read, so this serves as their estimate.
+pub async fn total_size<P: AsRef<Path>>(files: &[P]) -> io::Result<u64> {
+    let mut total = 0;
+    for file in files {
+        total += tokio::fs::metadata(file).await?.len();
+    }
+
+    Ok(total)
+}
+
+/// Free space on the filesystem holding `target`, which need not exist yet.
+fn available_space(target: &Path) -> io::Result<u64> {
+    let existing = target
+        .ancestors()
+        .find(|p| p.exists())
+        .unwrap_or_else(|| Path::new("."));
+    fs2::available_space(existing)
+}
+
+/// Check that there's room for an operation that will write about
+/// `required` bytes at `target`, plus some headroom. With `force`, a lack
+/// of room is only warned about.
+pub fn ensure_space(target: &Path, required: u64, force: bool) -> io::Result<()> {
+    let needed = required + required / HEADROOM_DIVISOR;
+    let available = available_space(target)?;
+    if available >= needed {
+        return Ok(());
+    }
+    let message = format!(
+        "{} needs about {} but only {} is free",
+        target.display(),
+        human_bytes(needed as usize),
+        human_bytes(available as usize)
+    );
+    if force {
+        eprintln!("warning: {message}");
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("{message}; use --force to go ahead anyway"),
+        ))
+    }
+}
@@ -0,0 +1,141 @@
+use std::{io, path::Path};
+
+use terminus_store::{
+    layer::{IdTriple, ObjectType, ValueTriple},
+    store::sync::{open_sync_archive_store, SyncStoreLayer},
+    Layer,
+};
+
+use crate::values::{search, ValueQuery};
+
+/// The value to purge, given either as its object id in the head layer or
+/// as its lexical form.
+pub enum PurgeTarget {
+    Id(u64),
+    Literal(String),
+}
+
+impl PurgeTarget {
+    pub fn parse(s: &str) -> Self {
+        match s.parse() {
+            Ok(id) => PurgeTarget::Id(id),
+            Err(_) => PurgeTarget::Literal(s.to_string()),
+        }
+    }
+}
+
+/// What a purge rewrote.
+pub struct PurgeSummary {
+    pub layers: usize,
+    pub purged: usize,
+    pub head: [u32; 5],
+}
+
+/// The stored values a target refers to. A literal matches every value
+/// that decodes to it, whatever its datatype.
+fn purged_values(head: &SyncStoreLayer, target: &PurgeTarget) -> io::Result<Vec<ObjectType>> {
+    match target {
+        PurgeTarget::Id(id) => match head.id_object(*id) {
+            Some(object @ ObjectType::Value(_)) => Ok(vec![object]),
+            Some(ObjectType::Node(_)) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("object {id} is a node, not a value"),
+            )),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("object {id} does not exist in the head layer"),
+            )),
+        },
+        PurgeTarget::Literal(literal) => {
+            let values: Vec<_> = search(head, &ValueQuery::Equals(literal.clone()), None, None)
+                .into_iter()
+                .filter_map(|m| head.id_object(m.id))
+                .collect();
+            if values.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no value matches {literal}"),
+                ));
+            }
+            Ok(values)
+        }
+    }
+}
+
+/// Resolve the triples of a layer to strings, leaving out those whose
+/// object is purged. Returns the kept triples and the number left out.
+fn kept_triples<I: Iterator<Item = IdTriple>>(
+    layer: &SyncStoreLayer,
+    triples: I,
+    purged: &[ObjectType],
+) -> io::Result<(Vec<ValueTriple>, usize)> {
+    let mut kept = Vec::new();
+    let mut dropped = 0;
+    for triple in triples {
+        let resolved = layer.id_triple_to_string(&triple).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "triple {} {} {} does not resolve to strings",
+                    triple.subject, triple.predicate, triple.object
+                ),
+            )
+        })?;
+        if purged.contains(&resolved.object) {
+            dropped += 1;
+        } else {
+            kept.push(resolved);
+        }
+    }
+
+    Ok((kept, dropped))
+}
+
+/// Rewrite the chain of `head` into a fresh store at `output`, leaving out
+/// every triple whose object is the purged value. Each layer is rebuilt
+/// from strings on top of its rewritten parent, so the new dictionaries
+/// never contain the value and all ids are assigned afresh. The label is
+/// created in the new store pointing at the rewritten head.
+pub fn purge_value(
+    head: &SyncStoreLayer,
+    label: &str,
+    target: &PurgeTarget,
+    output: &Path,
+) -> io::Result<PurgeSummary> {
+    let purged = purged_values(head, target)?;
+    let mut layers = vec![head.clone()];
+    while let Some(parent) = layers.last().unwrap().parent()? {
+        layers.push(parent);
+    }
+
+    std::fs::create_dir_all(output)?;
+    let new_store = open_sync_archive_store(output, 512);
+    let mut rewritten: Option<SyncStoreLayer> = None;
+    let mut dropped = 0;
+    for layer in layers.iter().rev() {
+        let builder = match &rewritten {
+            None => new_store.create_base_layer()?,
+            Some(parent) => parent.open_write()?,
+        };
+        let (additions, n) = kept_triples(layer, layer.triple_additions(), &purged)?;
+        dropped += n;
+        for triple in additions {
+            builder.add_value_triple(triple)?;
+        }
+        let (removals, n) = kept_triples(layer, layer.triple_removals(), &purged)?;
+        dropped += n;
+        for triple in removals {
+            builder.remove_value_triple(triple)?;
+        }
+        rewritten = Some(builder.commit()?);
+    }
+
+    let rewritten = rewritten.unwrap();
+    new_store.create(label)?.set_head(&rewritten)?;
+
+    Ok(PurgeSummary {
+        layers: layers.len(),
+        purged: dropped,
+        head: rewritten.name(),
+    })
+}
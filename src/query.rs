@@ -0,0 +1,378 @@
+use std::{
+    io::{self, Write},
+    ops::Range,
+    path::Path,
+};
+
+use clap::ValueEnum;
+use terminus_store::{
+    storage::consts::LayerFileEnum,
+    structure::{bitarray::BitArray, LogArray},
+};
+
+use crate::{
+    archive::{all_segment_types, Archive},
+    dump::{parse_bitarray, parse_logarray, DumpFormat, Terms},
+};
+
+/// The index `query` walks to answer a pattern.
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum QueryIndex {
+    /// The s_p and sp_o adjacency lists of the triples the layer adds
+    Pos,
+    /// The s_p and sp_o adjacency lists of the triples the layer removes
+    Neg,
+    /// The o_ps object index
+    Objects,
+    /// The predicate wavelet tree
+    Predicates,
+}
+
+/// A triple pattern, as terms or as ids, with None for the positions left
+/// open.
+#[derive(Default, Clone, Copy)]
+pub struct Pattern<T = u64> {
+    pub subject: Option<T>,
+    pub predicate: Option<T>,
+    pub object: Option<T>,
+}
+
+impl Pattern {
+    fn matches(&self, s: u64, p: u64, o: u64) -> bool {
+        self.subject.map(|x| x == s).unwrap_or(true)
+            && self.predicate.map(|x| x == p).unwrap_or(true)
+            && self.object.map(|x| x == o).unwrap_or(true)
+    }
+
+    /// The index that answers this pattern most directly.
+    pub fn index(&self, removals: bool) -> QueryIndex {
+        match self {
+            Pattern {
+                subject: None,
+                object: Some(_),
+                ..
+            } => QueryIndex::Objects,
+            Pattern {
+                subject: None,
+                predicate: Some(_),
+                ..
+            } => QueryIndex::Predicates,
+            _ if removals => QueryIndex::Neg,
+            _ => QueryIndex::Pos,
+        }
+    }
+}
+
+fn segment(prefix: &str, suffix: &str) -> LayerFileEnum {
+    let name = format!("{prefix}{suffix}");
+    all_segment_types()
+        .find(|t| format!("{t:?}") == name)
+        .unwrap_or_else(|| panic!("no segment named {name}"))
+}
+
+/// An adjacency list, with where each of its groups starts.
+struct Adjacency {
+    nums: LogArray,
+    /// The position of the first entry of each group, followed by one
+    /// past the last entry.
+    starts: Vec<usize>,
+}
+
+impl Adjacency {
+    fn load(archive: &Archive, nums: LayerFileEnum, bits: LayerFileEnum) -> io::Result<Self> {
+        let nums = parse_logarray(archive, nums)?.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, format!("{nums:?} is missing"))
+        })?;
+        let bits = parse_bitarray(archive, bits)?;
+        let mut starts = vec![0];
+        starts.extend((0..bits.len()).filter(|&i| bits.get(i)).map(|i| i + 1));
+        if *starts.last().unwrap() != nums.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} nums but the bits end the last group at {}",
+                    nums.len(),
+                    starts.last().unwrap()
+                ),
+            ));
+        }
+
+        Ok(Self { nums, starts })
+    }
+
+    fn groups(&self) -> usize {
+        self.starts.len() - 1
+    }
+
+    /// The positions of a group's entries, counting groups from 0.
+    fn group(&self, group: usize) -> Range<usize> {
+        match self.starts.get(group + 1) {
+            Some(end) => self.starts[group]..*end,
+            None => 0..0,
+        }
+    }
+
+    /// The group holding the entry at a position.
+    fn group_of(&self, position: usize) -> Option<usize> {
+        let group = self.starts.partition_point(|&start| start <= position);
+        group.checked_sub(1).filter(|&g| g < self.groups())
+    }
+}
+
+/// The s_p and sp_o adjacency lists of one side of a layer, with the
+/// subjects their groups belong to.
+struct Side {
+    subjects: Option<LogArray>,
+    s_p: Adjacency,
+    sp_o: Adjacency,
+    /// The sp_o group of each s_p entry. Writers differ in whether an
+    /// empty s_p group gets an sp_o group, so this goes by whichever
+    /// matches the number of sp_o groups.
+    sp_o_groups: Vec<Option<usize>>,
+}
+
+impl Side {
+    fn load(archive: &Archive, prefix: &str) -> io::Result<Self> {
+        let subjects = parse_logarray(archive, segment(prefix, "Subjects"))?;
+        let s_p = Adjacency::load(
+            archive,
+            segment(prefix, "SPAdjacencyListNums"),
+            segment(prefix, "SPAdjacencyListBits"),
+        )?;
+        let sp_o = Adjacency::load(
+            archive,
+            segment(prefix, "SpOAdjacencyListNums"),
+            segment(prefix, "SpOAdjacencyListBits"),
+        )?;
+        let skip_empty = sp_o.groups() != s_p.nums.len();
+        let mut next = 0;
+        let sp_o_groups = (0..s_p.nums.len())
+            .map(|i| {
+                if skip_empty && s_p.nums.entry(i) == 0 {
+                    return None;
+                }
+                next += 1;
+                Some(next - 1)
+            })
+            .collect();
+
+        Ok(Self {
+            subjects,
+            s_p,
+            sp_o,
+            sp_o_groups,
+        })
+    }
+
+    fn subject(&self, group: usize) -> u64 {
+        match &self.subjects {
+            Some(subjects) if group < subjects.len() => subjects.entry(group),
+            Some(_) => 0,
+            None => group as u64 + 1,
+        }
+    }
+
+    fn group_of_subject(&self, subject: u64) -> Option<usize> {
+        match &self.subjects {
+            Some(subjects) => {
+                let entries: Vec<u64> = subjects.iter().collect();
+                entries.binary_search(&subject).ok()
+            }
+            None => subject.checked_sub(1).map(|s| s as usize),
+        }
+    }
+
+    /// The subject of the s_p entry at a position.
+    fn subject_at(&self, position: usize) -> Option<u64> {
+        self.s_p.group_of(position).map(|g| self.subject(g))
+    }
+
+    fn objects(&self, position: usize) -> impl Iterator<Item = u64> + '_ {
+        let group = self.sp_o_groups.get(position).copied().flatten();
+        let range = group.map(|g| self.sp_o.group(g)).unwrap_or(0..0);
+        range.map(|i| self.sp_o.nums.entry(i))
+    }
+}
+
+/// Decode the sequence a wavelet tree encodes, without its rank index.
+/// Layer k holds bit k (from the top) of every entry, with the entries
+/// ordered by their top k bits and ties kept in sequence order.
+fn decode_wavelet(bits: &BitArray, width: usize) -> io::Result<Vec<u64>> {
+    if width == 0 || bits.len() % width != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} wavelet bits don't divide into layers of {width}",
+                bits.len()
+            ),
+        ));
+    }
+    let len = bits.len() / width;
+    let mut values = vec![0u64; len];
+    let mut order: Vec<usize> = (0..len).collect();
+    for layer in 0..width {
+        for (i, &entry) in order.iter().enumerate() {
+            values[entry] = (values[entry] << 1) | bits.get(layer * len + i) as u64;
+        }
+        order.sort_by_key(|&entry| values[entry]);
+    }
+
+    Ok(values)
+}
+
+/// Walk one index of a layer for the triples matching a pattern, calling
+/// `f` with each. Every index is taken at its word: the objects of the
+/// object index and the predicates of the wavelet tree aren't checked
+/// against the adjacency lists, so disagreeing indexes give different
+/// answers.
+pub fn walk_pattern(
+    archive: &Archive,
+    pattern: &Pattern,
+    index: QueryIndex,
+    removals: bool,
+    mut f: impl FnMut(u64, u64, u64) -> io::Result<()>,
+) -> io::Result<()> {
+    let prefix = match (index, removals) {
+        (QueryIndex::Neg, _) | (_, true) => "Neg",
+        _ => "Pos",
+    };
+    let side = Side::load(archive, prefix)?;
+    match index {
+        QueryIndex::Pos | QueryIndex::Neg => {
+            let groups: Vec<usize> = match pattern.subject {
+                Some(subject) => side.group_of_subject(subject).into_iter().collect(),
+                None => (0..side.s_p.groups()).collect(),
+            };
+            for group in groups {
+                let subject = side.subject(group);
+                for position in side.s_p.group(group) {
+                    let predicate = side.s_p.nums.entry(position);
+                    for object in side.objects(position) {
+                        if predicate != 0
+                            && object != 0
+                            && pattern.matches(subject, predicate, object)
+                        {
+                            f(subject, predicate, object)?;
+                        }
+                    }
+                }
+            }
+        }
+        QueryIndex::Objects => {
+            let o_ps = Adjacency::load(
+                archive,
+                segment(prefix, "OPsAdjacencyListNums"),
+                segment(prefix, "OPsAdjacencyListBits"),
+            )?;
+            let objects = parse_logarray(archive, segment(prefix, "Objects"))?;
+            let object_at = |group: usize| match &objects {
+                Some(objects) if group < objects.len() => objects.entry(group),
+                Some(_) => 0,
+                None => group as u64 + 1,
+            };
+            let groups: Vec<usize> = match (pattern.object, &objects) {
+                (Some(object), Some(objects)) => {
+                    let entries: Vec<u64> = objects.iter().collect();
+                    entries.binary_search(&object).ok().into_iter().collect()
+                }
+                (Some(object), None) => object
+                    .checked_sub(1)
+                    .map(|o| o as usize)
+                    .into_iter()
+                    .collect(),
+                (None, _) => (0..o_ps.groups()).collect(),
+            };
+            for group in groups {
+                let object = object_at(group);
+                for i in o_ps.group(group) {
+                    // o_ps entries count s_p entries from 1
+                    let position = match o_ps.nums.entry(i).checked_sub(1) {
+                        Some(position) => position as usize,
+                        None => continue,
+                    };
+                    if position >= side.s_p.nums.len() {
+                        continue;
+                    }
+                    let predicate = side.s_p.nums.entry(position);
+                    let subject = side.subject_at(position).unwrap_or(0);
+                    if pattern.matches(subject, predicate, object) {
+                        f(subject, predicate, object)?;
+                    }
+                }
+            }
+        }
+        QueryIndex::Predicates => {
+            let bits = parse_bitarray(archive, segment(prefix, "PredicateWaveletTreeBits"))?;
+            let predicates = decode_wavelet(&bits, side.s_p.nums.width() as usize)?;
+            for (position, &predicate) in predicates.iter().enumerate() {
+                if predicate == 0 || pattern.predicate.map(|p| p != predicate).unwrap_or(false) {
+                    continue;
+                }
+                let subject = side.subject_at(position).unwrap_or(0);
+                for object in side.objects(position) {
+                    if object != 0 && pattern.matches(subject, predicate, object) {
+                        f(subject, predicate, object)?;
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Resolve a term given to `query` to an id. `#N` stands for the id N
+/// itself, for terms from ancestor layers; anything else is looked up in
+/// the archive's own dictionaries.
+fn resolve(term: &str, lookup: impl Fn(&[u8]) -> Option<u64>) -> io::Result<u64> {
+    if let Some(id) = term.strip_prefix('#').and_then(|id| id.parse().ok()) {
+        return Ok(id);
+    }
+    lookup(term.as_bytes()).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{term} is not in the layer's dictionaries; give an ancestor's term as #id"),
+        )
+    })
+}
+
+/// Print the triples of a single archive matching a pattern, as found by
+/// one of its indexes. Without an index, the one that answers the pattern
+/// most directly is used. Returns the number of triples printed.
+pub async fn query(
+    path: &Path,
+    store: Option<&Path>,
+    pattern: Pattern<&str>,
+    index: Option<QueryIndex>,
+    removals: bool,
+    format: DumpFormat,
+) -> io::Result<u64> {
+    let archive = Archive::open(path).await?;
+    let terms = Terms::load_for(&archive, store).await?;
+    let pattern = Pattern {
+        subject: pattern
+            .subject
+            .map(|s| resolve(s, |s| terms.node_id(s)))
+            .transpose()?,
+        predicate: pattern
+            .predicate
+            .map(|p| resolve(p, |p| terms.predicate_id(p)))
+            .transpose()?,
+        object: pattern
+            .object
+            .map(|o| resolve(o, |o| terms.node_id(o).or_else(|| terms.value_id(o))))
+            .transpose()?,
+    };
+    let index = index.unwrap_or_else(|| pattern.index(removals));
+
+    let stdout = io::stdout();
+    let mut out = io::BufWriter::new(stdout.lock());
+    let mut count = 0;
+    walk_pattern(&archive, &pattern, index, removals, |s, p, o| {
+        count += 1;
+        writeln!(out, "{}", terms.format(s, p, o, format))
+    })?;
+    out.flush()?;
+
+    Ok(count)
+}
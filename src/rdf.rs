@@ -0,0 +1,395 @@
+use std::{
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use clap::ValueEnum;
+use terminus_store::{
+    layer::{ObjectType, ValueTriple},
+    store::sync::SyncStoreLayer,
+    Layer,
+};
+
+use crate::{
+    atomic,
+    values::{decode, lang_tag, xsd_name, Decoded},
+};
+
+const XSD: &str = "http://www.w3.org/2001/XMLSchema#";
+const RDF: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+
+/// Datatypes whose XML schema names aren't their stored names with the
+/// first letter lowercased.
+const IRREGULAR_TYPES: [(&str, &str); 12] = [
+    ("Int8", "byte"),
+    ("UInt8", "unsignedByte"),
+    ("Int16", "short"),
+    ("UInt16", "unsignedShort"),
+    ("AnyURI", "anyURI"),
+    ("NMToken", "NMTOKEN"),
+    ("IDRef", "IDREF"),
+    ("ID", "ID"),
+    ("Name", "Name"),
+    ("NCName", "NCName"),
+    ("QName", "QName"),
+    ("Entity", "ENTITY"),
+];
+
+/// Standard RDF serializations a database can be exported to.
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum RdfFormat {
+    Ntriples,
+    Turtle,
+    RdfXml,
+}
+
+/// A stored value as an RDF literal.
+pub struct Literal {
+    pub lexical: String,
+    /// The local name of the XML schema datatype, None for a
+    /// language-tagged string.
+    pub datatype: Option<String>,
+    pub lang: Option<String>,
+    /// Whether the value could be decoded. Values that can't, which are
+    /// numbers stored with the wrong width, keep their stored bytes as
+    /// their lexical form.
+    pub decoded: bool,
+}
+
+fn xsd_local_name(datatype: &str) -> String {
+    if let Some(name) = xsd_name(datatype).strip_prefix("xsd:") {
+        return name.to_string();
+    }
+    if let Some((_, name)) = IRREGULAR_TYPES.iter().find(|(d, _)| *d == datatype) {
+        return name.to_string();
+    }
+    let mut chars = datatype.chars();
+    match chars.next() {
+        Some(first) => first.to_lowercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Turn a stored value into an RDF literal: language-tagged strings get
+/// their tag, numbers and booleans their canonical lexical form, and
+/// every other value its XML schema datatype. Arbitrary precision
+/// integers and decimals are decoded through the store's datatypes.
+pub fn literal(datatype: &str, bytes: &[u8]) -> Literal {
+    if let Some(tag) = lang_tag(datatype, bytes) {
+        return Literal {
+            lexical: String::from_utf8_lossy(&bytes[tag.len() + 1..]).to_string(),
+            datatype: None,
+            lang: Some(tag),
+            decoded: true,
+        };
+    }
+    let decoded = decode(datatype, bytes);
+    let lexical = match (datatype, &decoded) {
+        ("Boolean", Some(Decoded::Int(n))) => (*n != 0).to_string(),
+        (_, Some(Decoded::Float(n))) if n.is_infinite() => {
+            if *n > 0.0 { "INF" } else { "-INF" }.to_string()
+        }
+        (_, Some(decoded)) => decoded.to_string(),
+        (_, None) => String::from_utf8_lossy(bytes).to_string(),
+    };
+
+    Literal {
+        lexical,
+        datatype: Some(xsd_local_name(datatype)),
+        lang: None,
+        decoded: decoded.is_some(),
+    }
+}
+
+/// A literal as N-Triples writes it, with its datatype as a full IRI.
+pub fn ntriples_literal(literal: &Literal) -> String {
+    match (&literal.lang, &literal.datatype) {
+        (Some(lang), _) => format!("{}@{lang}", quoted(&literal.lexical)),
+        (None, datatype) => format!(
+            "{}^^{}",
+            quoted(&literal.lexical),
+            iri(&format!("{XSD}{}", datatype.as_deref().unwrap_or_default()))
+        ),
+    }
+}
+
+/// A literal as Turtle writes it, with its datatype under the `xsd:`
+/// prefix.
+pub fn turtle_literal(literal: &Literal) -> String {
+    match (&literal.lang, &literal.datatype) {
+        (Some(lang), _) => format!("{}@{lang}", quoted(&literal.lexical)),
+        (None, datatype) => format!(
+            "{}^^xsd:{}",
+            quoted(&literal.lexical),
+            datatype.as_deref().unwrap_or_default()
+        ),
+    }
+}
+
+/// An object as it appears in an N-Triples line.
+pub fn ntriples_object(object: &ObjectType) -> String {
+    match object {
+        ObjectType::Node(node) => iri(node),
+        ObjectType::Value(value) => ntriples_literal(&literal(
+            &format!("{:?}", value.datatype()),
+            &value.to_bytes(),
+        )),
+    }
+}
+
+/// A triple as an N-Triples line, without the line break.
+pub fn ntriples_line(triple: &ValueTriple) -> String {
+    format!(
+        "{} {} {} .",
+        iri(&triple.subject),
+        iri(&triple.predicate),
+        ntriples_object(&triple.object)
+    )
+}
+
+/// Escape an IRI for N-Triples and Turtle, which don't allow spaces,
+/// control characters or `<>"{}|^`\` in an IRI.
+pub fn iri(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() + 2);
+    result.push('<');
+    for c in s.chars() {
+        match c {
+            '<' | '>' | '"' | '{' | '}' | '|' | '^' | '`' | '\\' | '\0'..=' ' => {
+                result.push_str(&format!("\\u{:04X}", c as u32))
+            }
+            c => result.push(c),
+        }
+    }
+    result.push('>');
+    result
+}
+
+/// Quote a string for N-Triples and Turtle.
+pub fn quoted(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() + 2);
+    result.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if c.is_control() => result.push_str(&format!("\\u{:04X}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+/// Escape text for XML content and attribute values.
+fn xml_escaped(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Split a predicate into a namespace and a local name, as RDF/XML writes
+/// predicates as elements. The local name is the longest suffix that is a
+/// valid XML name.
+fn split_predicate(predicate: &str) -> io::Result<(&str, &str)> {
+    let name_char = |c: char| c.is_alphanumeric() || c == '_' || c == '-' || c == '.';
+    let mut start = predicate
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| name_char(*c))
+        .last()
+        .map(|(i, _)| i)
+        .unwrap_or(predicate.len());
+    // a name can't start with a digit, `-` or `.`
+    while let Some(c) = predicate[start..].chars().next() {
+        if c.is_alphabetic() || c == '_' {
+            break;
+        }
+        start += c.len_utf8();
+    }
+    if start == predicate.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("predicate {predicate} doesn't end in a name RDF/XML can write"),
+        ));
+    }
+
+    Ok(predicate.split_at(start))
+}
+
+/// Writes triples in one of the RDF formats. Turtle and RDF/XML group
+/// consecutive triples of the same subject, which is every triple of a
+/// subject when they come in layer order.
+pub struct RdfWriter<W: Write> {
+    out: W,
+    format: RdfFormat,
+    subject: Option<String>,
+    /// Values written with their stored bytes, as they couldn't be
+    /// decoded.
+    pub undecoded: u64,
+}
+
+impl<W: Write> RdfWriter<W> {
+    pub fn new(mut out: W, format: RdfFormat) -> io::Result<Self> {
+        match format {
+            RdfFormat::Ntriples => {}
+            RdfFormat::Turtle => writeln!(out, "@prefix xsd: <{XSD}> .\n")?,
+            RdfFormat::RdfXml => write!(
+                out,
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<rdf:RDF xmlns:rdf=\"{RDF}\">\n"
+            )?,
+        }
+
+        Ok(RdfWriter {
+            out,
+            format,
+            subject: None,
+            undecoded: 0,
+        })
+    }
+
+    fn text_object(&mut self, object: &ObjectType) -> String {
+        match object {
+            ObjectType::Node(node) => iri(node),
+            ObjectType::Value(value) => {
+                let literal = literal(&format!("{:?}", value.datatype()), &value.to_bytes());
+                self.undecoded += !literal.decoded as u64;
+                match self.format {
+                    RdfFormat::Turtle => turtle_literal(&literal),
+                    _ => ntriples_literal(&literal),
+                }
+            }
+        }
+    }
+
+    fn xml_property(&mut self, triple: &ValueTriple) -> io::Result<String> {
+        let (namespace, local) = split_predicate(&triple.predicate)?;
+        let open = format!("<p:{local} xmlns:p=\"{}\"", xml_escaped(namespace));
+        Ok(match &triple.object {
+            ObjectType::Node(node) => format!("{open} rdf:resource=\"{}\"/>", xml_escaped(node)),
+            ObjectType::Value(value) => {
+                let literal = literal(&format!("{:?}", value.datatype()), &value.to_bytes());
+                self.undecoded += !literal.decoded as u64;
+                let attribute = match (literal.lang, literal.datatype) {
+                    (Some(lang), _) => format!("xml:lang=\"{}\"", xml_escaped(&lang)),
+                    (None, datatype) => {
+                        format!("rdf:datatype=\"{XSD}{}\"", datatype.unwrap_or_default())
+                    }
+                };
+                format!(
+                    "{open} {attribute}>{}</p:{local}>",
+                    xml_escaped(&literal.lexical)
+                )
+            }
+        })
+    }
+
+    pub fn write(&mut self, triple: &ValueTriple) -> io::Result<()> {
+        let same = self.subject.as_deref() == Some(triple.subject.as_str());
+        match self.format {
+            RdfFormat::Ntriples => {
+                let object = self.text_object(&triple.object);
+                writeln!(
+                    self.out,
+                    "{} {} {object} .",
+                    iri(&triple.subject),
+                    iri(&triple.predicate)
+                )?;
+            }
+            RdfFormat::Turtle => {
+                let object = self.text_object(&triple.object);
+                if same {
+                    write!(self.out, " ;\n    {} {object}", iri(&triple.predicate))?;
+                } else {
+                    if self.subject.is_some() {
+                        writeln!(self.out, " .")?;
+                    }
+                    write!(
+                        self.out,
+                        "{} {} {object}",
+                        iri(&triple.subject),
+                        iri(&triple.predicate)
+                    )?;
+                }
+            }
+            RdfFormat::RdfXml => {
+                let property = self.xml_property(triple)?;
+                if !same {
+                    if self.subject.is_some() {
+                        writeln!(self.out, "  </rdf:Description>")?;
+                    }
+                    writeln!(
+                        self.out,
+                        "  <rdf:Description rdf:about=\"{}\">",
+                        xml_escaped(&triple.subject)
+                    )?;
+                }
+                writeln!(self.out, "    {property}")?;
+            }
+        }
+        if !same {
+            self.subject = Some(triple.subject.clone());
+        }
+
+        Ok(())
+    }
+
+    /// Close the last subject and the document, and flush.
+    pub fn finish(mut self) -> io::Result<u64> {
+        match self.format {
+            RdfFormat::Ntriples => {}
+            RdfFormat::Turtle if self.subject.is_some() => writeln!(self.out, " .")?,
+            RdfFormat::Turtle => {}
+            RdfFormat::RdfXml => {
+                if self.subject.is_some() {
+                    writeln!(self.out, "  </rdf:Description>")?;
+                }
+                writeln!(self.out, "</rdf:RDF>")?;
+            }
+        }
+        self.out.flush()?;
+
+        Ok(self.undecoded)
+    }
+}
+
+/// What `export` wrote.
+pub struct Exported {
+    pub triples: u64,
+    /// Values written with their stored bytes, as they couldn't be
+    /// decoded.
+    pub undecoded: u64,
+}
+
+/// Write every triple of a layer, as seen through its chain, to `output`
+/// in the given format. The file is written under a temporary name and
+/// renamed into place once complete.
+pub fn export(layer: &SyncStoreLayer, format: RdfFormat, output: &Path) -> io::Result<Exported> {
+    let tmp = atomic::temp_path(output);
+    let result = (|| {
+        let out = BufWriter::new(std::fs::File::create(&tmp)?);
+        let mut writer = RdfWriter::new(out, format)?;
+        let mut triples = 0;
+        for triple in layer.triples() {
+            let triple = layer.id_triple_to_string(&triple).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("triple {triple:?} does not resolve"),
+                )
+            })?;
+            writer.write(&triple)?;
+            triples += 1;
+        }
+        let undecoded = writer.finish()?;
+        std::fs::rename(&tmp, output)?;
+        Ok(Exported { triples, undecoded })
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+
+    result
+}
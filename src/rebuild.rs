@@ -0,0 +1,242 @@
+use std::{
+    collections::HashSet,
+    io::{self, BufRead, Cursor, Write},
+};
+
+use bytes::Bytes;
+use futures::StreamExt;
+use terminus_store::{
+    storage::consts::LayerFileEnum,
+    structure::{stream::TfcDictStream, LogArray},
+};
+
+use crate::{
+    archive::{all_segment_types, encode_header},
+    validate::{segment_kind, SegmentKind},
+};
+
+/// Candidate ends offered for a dictionary whose length is unknown.
+const MAX_DICT_CANDIDATES: usize = 3;
+
+/// The end of a logarray starting at `start`, found by looking for a
+/// control word (u32 length, u8 width, three zero bytes) that accounts for
+/// exactly the bytes before it.
+fn logarray_end(data: &[u8], start: usize) -> Option<usize> {
+    let mut p = start;
+    while p + 8 <= data.len() {
+        let len = u32::from_be_bytes(data[p..p + 4].try_into().unwrap()) as u64;
+        let width = data[p + 4] as u64;
+        if width <= 64 && data[p + 5..p + 8] == [0, 0, 0] {
+            let size = (len * width).div_ceil(64) * 8;
+            if size == (p - start) as u64 {
+                return Some(p + 8);
+            }
+        }
+        p += 8;
+    }
+    None
+}
+
+/// The end of a bit array starting at `start`, whose control word is the
+/// u64 number of bits.
+fn bitarray_end(data: &[u8], start: usize) -> Option<usize> {
+    let mut p = start;
+    while p + 8 <= data.len() {
+        let bits = u64::from_be_bytes(data[p..p + 8].try_into().unwrap());
+        if bits.div_ceil(64) * 8 == (p - start) as u64 {
+            return Some(p + 8);
+        }
+        p += 8;
+    }
+    None
+}
+
+async fn decodes(blocks: Bytes) -> bool {
+    let mut stream = TfcDictStream::new(Cursor::new(blocks));
+    while let Some(element) = stream.next().await {
+        if element.is_err() {
+            return false;
+        }
+    }
+    true
+}
+
+/// Possible ends of dictionary blocks starting at `start`. Dictionary blocks
+/// carry no length, but they are followed by a logarray of block offsets
+/// which must be increasing and lie within the blocks, and everything
+/// before it must decode.
+async fn dict_ends(data: &Bytes, start: usize) -> Vec<usize> {
+    let mut result = Vec::new();
+    for p in start..data.len().saturating_sub(7) {
+        let len = u32::from_be_bytes(data[p..p + 4].try_into().unwrap()) as u64;
+        let width = data[p + 4] as u64;
+        if width > 64 || data[p + 5..p + 8] != [0, 0, 0] {
+            continue;
+        }
+        let size = ((len * width).div_ceil(64) * 8) as usize;
+        let end = match p.checked_sub(size) {
+            Some(end) if end >= start => end,
+            _ => continue,
+        };
+        let offsets = match LogArray::parse(data.slice(end..p + 8)) {
+            Ok(offsets) => offsets,
+            Err(_) => continue,
+        };
+        let offsets: Vec<_> = offsets.iter().collect();
+        let increasing = offsets.windows(2).all(|w| w[0] < w[1]);
+        let within = offsets
+            .last()
+            .map(|o| (*o as usize) < end - start)
+            .unwrap_or(true);
+        if increasing && within && decodes(data.slice(start..end)).await {
+            result.push(end);
+            if result.len() == MAX_DICT_CANDIDATES {
+                break;
+            }
+        }
+    }
+    result
+}
+
+async fn candidate_ends(kind: SegmentKind, data: &Bytes, start: usize) -> Vec<usize> {
+    match kind {
+        SegmentKind::LogArray => logarray_end(data, start).into_iter().collect(),
+        SegmentKind::BitArray => bitarray_end(data, start).into_iter().collect(),
+        SegmentKind::DictBlocks => dict_ends(data, start).await,
+        SegmentKind::Parent if start + 20 <= data.len() => vec![start + 20],
+        SegmentKind::Parent => Vec::new(),
+        // rollups can only be recognized as whatever remains
+        SegmentKind::Rollup => vec![data.len()],
+    }
+}
+
+fn choose(
+    offset: usize,
+    candidates: &[(usize, LayerFileEnum, usize)],
+    interactive: bool,
+) -> io::Result<Option<usize>> {
+    if !interactive || candidates.len() == 1 {
+        return Ok(Some(0));
+    }
+    eprintln!("at offset {offset}, the next segment could be:");
+    for (ix, (_, file_type, end)) in candidates.iter().enumerate() {
+        eprintln!(
+            "  {}) {file_type:?} {offset}..{end} ({} bytes)",
+            ix + 1,
+            end - offset
+        );
+    }
+    eprint!("choice, or s to stop here [1]: ");
+    io::stderr().flush()?;
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    match line.trim() {
+        "" => Ok(Some(0)),
+        "s" => Ok(None),
+        choice => match choice.parse::<usize>() {
+            Ok(n) if (1..=candidates.len()).contains(&n) => Ok(Some(n - 1)),
+            _ => choose(offset, candidates, interactive),
+        },
+    }
+}
+
+/// Reconstruct the segment layout of archive data whose header is missing
+/// or destroyed, starting at `start`.
+///
+/// Segments are laid out in header order, so at every position the next
+/// segment is one of the types after the previous one. For every kind of
+/// segment, the earliest remaining type whose structure can be recognized
+/// at the current position is a candidate; the earliest candidate wins
+/// unless `interactive`, in which case the user picks when there is more
+/// than one.
+pub async fn scan(
+    data: &Bytes,
+    start: usize,
+    interactive: bool,
+) -> io::Result<Vec<(LayerFileEnum, usize)>> {
+    let types: Vec<_> = all_segment_types().collect();
+    let mut segments = Vec::new();
+    let mut next_type = 0;
+    let mut offset = start;
+    while offset < data.len() && next_type < types.len() {
+        let mut candidates = Vec::new();
+        let mut kinds = HashSet::new();
+        for (ix, file_type) in types.iter().enumerate().skip(next_type) {
+            let kind = segment_kind(*file_type);
+            if !kinds.insert(kind) {
+                continue;
+            }
+            for end in candidate_ends(kind, data, offset).await {
+                candidates.push((ix, *file_type, end));
+            }
+        }
+        if candidates.is_empty() {
+            eprintln!("no recognizable segment at offset {offset}, stopping");
+            break;
+        }
+        let choice = match choose(offset, &candidates, interactive)? {
+            Some(choice) => candidates[choice],
+            None => break,
+        };
+        let (ix, file_type, end) = choice;
+        segments.push((file_type, end - offset));
+        next_type = ix + 1;
+        offset = end;
+    }
+
+    Ok(segments)
+}
+
+/// Build an archive from reconstructed segments.
+pub fn assemble(data: &Bytes, start: usize, segments: &[(LayerFileEnum, usize)]) -> Vec<u8> {
+    let size: usize = segments.iter().map(|(_, size)| size).sum();
+    let mut result = encode_header(segments);
+    result.extend_from_slice(&data[start..start + size]);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::Archive;
+
+    /// Eight bytes that don't read as a control word of either kind.
+    const WORD: [u8; 8] = [7; 8];
+
+    #[test]
+    fn logarray_ends_after_the_control_word_that_accounts_for_it() {
+        // two entries of eight bits fill one word
+        let mut data = WORD.to_vec();
+        data.extend_from_slice(&[0, 0, 0, 2, 8, 0, 0, 0]);
+        data.extend_from_slice(&WORD);
+        assert_eq!(logarray_end(&data, 0), Some(16));
+        assert_eq!(logarray_end(&WORD, 0), None);
+    }
+
+    #[test]
+    fn bitarray_ends_after_its_bit_count() {
+        let mut data = WORD.to_vec();
+        data.extend_from_slice(&64u64.to_be_bytes());
+        data.extend_from_slice(&WORD);
+        assert_eq!(bitarray_end(&data, 0), Some(16));
+        assert_eq!(bitarray_end(&data, 8), None);
+    }
+
+    #[tokio::test]
+    async fn assembled_archive_lists_the_reconstructed_segments() {
+        let data = Bytes::from([[0xff; 4].as_slice(), &[7; 20], &[9; 22]].concat());
+        let segments = [(LayerFileEnum::Parent, 20), (LayerFileEnum::Rollup, 22)];
+        let archive = Archive::parse(assemble(&data, 4, &segments).into())
+            .await
+            .unwrap();
+        assert_eq!(
+            archive.segment(LayerFileEnum::Parent).unwrap().as_deref(),
+            Some(&[7; 20][..])
+        );
+        assert_eq!(
+            archive.segment(LayerFileEnum::Rollup).unwrap().as_deref(),
+            Some(&[9; 22][..])
+        );
+        assert!(archive.trailer().is_empty());
+    }
+}
@@ -0,0 +1,146 @@
+use std::{io, path::Path};
+
+use clap::ValueEnum;
+use terminus_store::{
+    layer::{IdTriple, ObjectType, ValueTriple},
+    store::sync::{open_sync_archive_store, SyncStoreLayer},
+    Layer,
+};
+
+use crate::{build_layer::typed_value, rdf::literal, values::datatype_name};
+
+/// How `recode-values` re-encodes a value.
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum RecodeStrategy {
+    /// Decode the stored value and encode its lexical form again with the
+    /// store's current encoder
+    Canonical,
+}
+
+/// What a recode rewrote.
+pub struct RecodeSummary {
+    pub layers: usize,
+    /// Triples whose value was stored differently after recoding.
+    pub recoded: usize,
+    pub head: [u32; 5],
+}
+
+/// Re-encode an object if it is a value of the given stored datatype.
+/// Returns None if it is left as it is.
+fn recode(
+    object: &ObjectType,
+    datatype: &str,
+    strategy: RecodeStrategy,
+) -> io::Result<Option<ObjectType>> {
+    let value = match object {
+        ObjectType::Value(value) if format!("{:?}", value.datatype()) == datatype => value,
+        _ => return Ok(None),
+    };
+    let bytes = value.to_bytes();
+    match strategy {
+        RecodeStrategy::Canonical => {
+            let literal = literal(datatype, &bytes);
+            if !literal.decoded {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{datatype} value {bytes:?} doesn't decode"),
+                ));
+            }
+            let lexical = match literal.lang {
+                Some(tag) => format!("{tag}@{}", literal.lexical),
+                None => literal.lexical,
+            };
+            let (entry, as_string) = typed_value(&lexical, datatype)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            if as_string {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{datatype} has no encoding here to recode values with"),
+                ));
+            }
+            Ok((entry.to_bytes() != bytes).then_some(ObjectType::Value(entry)))
+        }
+    }
+}
+
+/// Resolve the triples of a layer to strings, recoding their objects.
+/// Returns the triples and the number whose object was recoded.
+fn recoded_triples<I: Iterator<Item = IdTriple>>(
+    layer: &SyncStoreLayer,
+    triples: I,
+    datatype: &str,
+    strategy: RecodeStrategy,
+) -> io::Result<(Vec<ValueTriple>, usize)> {
+    let mut result = Vec::new();
+    let mut recoded = 0;
+    for triple in triples {
+        let mut resolved = layer.id_triple_to_string(&triple).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "triple {} {} {} does not resolve to strings",
+                    triple.subject, triple.predicate, triple.object
+                ),
+            )
+        })?;
+        if let Some(object) = recode(&resolved.object, datatype, strategy)? {
+            resolved.object = object;
+            recoded += 1;
+        }
+        result.push(resolved);
+    }
+
+    Ok((result, recoded))
+}
+
+/// Rewrite the chain of `head` into a fresh store at `output`, with every
+/// value of `datatype` re-encoded by `strategy`. As with `purge-value`,
+/// each layer is rebuilt from strings on top of its rewritten parent, so
+/// the store writes the new dictionaries and indexes. Ids are assigned
+/// afresh, but come out the same unless recoding changes the order of
+/// values. The label is created in the new store pointing at the
+/// rewritten head.
+pub fn recode_values(
+    head: &SyncStoreLayer,
+    label: &str,
+    datatype: &str,
+    strategy: RecodeStrategy,
+    output: &Path,
+) -> io::Result<RecodeSummary> {
+    let datatype = datatype_name(datatype);
+    let mut layers = vec![head.clone()];
+    while let Some(parent) = layers.last().unwrap().parent()? {
+        layers.push(parent);
+    }
+
+    std::fs::create_dir_all(output)?;
+    let new_store = open_sync_archive_store(output, 512);
+    let mut rewritten: Option<SyncStoreLayer> = None;
+    let mut recoded = 0;
+    for layer in layers.iter().rev() {
+        let builder = match &rewritten {
+            None => new_store.create_base_layer()?,
+            Some(parent) => parent.open_write()?,
+        };
+        let (additions, n) = recoded_triples(layer, layer.triple_additions(), &datatype, strategy)?;
+        recoded += n;
+        for triple in additions {
+            builder.add_value_triple(triple)?;
+        }
+        let (removals, n) = recoded_triples(layer, layer.triple_removals(), &datatype, strategy)?;
+        recoded += n;
+        for triple in removals {
+            builder.remove_value_triple(triple)?;
+        }
+        rewritten = Some(builder.commit()?);
+    }
+
+    let rewritten = rewritten.unwrap();
+    new_store.create(label)?.set_head(&rewritten)?;
+
+    Ok(RecodeSummary {
+        layers: layers.len(),
+        recoded,
+        head: rewritten.name(),
+    })
+}
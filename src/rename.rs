@@ -0,0 +1,204 @@
+use std::{io, path::Path};
+
+use terminus_store::{
+    layer::{IdTriple, ObjectType, ValueTriple},
+    storage::name_to_string,
+    store::sync::{open_sync_archive_store, SyncStoreLayer},
+    Layer,
+};
+
+use crate::{audit::Audit, store::label_path};
+
+/// What a predicate rename changed.
+pub struct RenameSummary {
+    /// Triples moved to the new predicate.
+    pub moved: usize,
+    /// Triples whose renamed form already existed, so they were only
+    /// removed.
+    pub merged: usize,
+    pub head: [u32; 5],
+}
+
+/// Move every triple with predicate `old` to predicate `new`, as a child
+/// layer of the label's head, and point the label at it. If `new` is
+/// already in use, the two predicates are only merged when `merge` is
+/// given.
+pub async fn rename_predicate(
+    store: &Path,
+    label: &str,
+    old: &str,
+    new: &str,
+    merge: bool,
+) -> io::Result<RenameSummary> {
+    if old == new {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the old and new predicates are the same",
+        ));
+    }
+    let sync_store = open_sync_archive_store(store, 512);
+    let graph = sync_store.open(label)?.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, format!("label {label} not found"))
+    })?;
+    let base = graph.head()?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("label {label} has no head"),
+        )
+    })?;
+    let old_id = base.predicate_id(old).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("predicate {old} is not in use"),
+        )
+    })?;
+    let in_use = base
+        .predicate_id(new)
+        .map(|id| base.triples_p(id).next().is_some())
+        .unwrap_or(false);
+    if in_use && !merge {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("predicate {new} is already in use; give --merge to merge {old} into it"),
+        ));
+    }
+
+    let builder = base.open_write()?;
+    let mut moved = 0;
+    let mut merged = 0;
+    for triple in base.triples_p(old_id) {
+        let resolved = base.id_triple_to_string(&triple).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "triple {} {} {} does not resolve to strings",
+                    triple.subject, triple.predicate, triple.object
+                ),
+            )
+        })?;
+        builder.remove_id_triple(triple)?;
+        let renamed = ValueTriple {
+            predicate: new.to_string(),
+            ..resolved
+        };
+        if base.value_triple_exists(&renamed) {
+            merged += 1;
+        } else {
+            builder.add_value_triple(renamed)?;
+            moved += 1;
+        }
+    }
+    let layer = builder.commit()?;
+
+    let mut audit = Audit::begin("rename-predicate");
+    audit.track(&label_path(store, label)).await?;
+    audit.note("old_head", name_to_string(base.name()));
+    audit.note("new_head", name_to_string(layer.name()));
+    audit.note("old_predicate", old.to_string());
+    audit.note("new_predicate", new.to_string());
+    graph.set_head(&layer)?;
+    audit.commit(store).await?;
+
+    Ok(RenameSummary {
+        moved,
+        merged,
+        head: layer.name(),
+    })
+}
+
+/// What a namespace rewrite changed.
+pub struct NamespaceSummary {
+    pub layers: usize,
+    /// Triples with at least one term in the old namespace.
+    pub rewritten: usize,
+    pub head: [u32; 5],
+}
+
+fn rewrite_iri(iri: &str, from: &str, to: &str) -> Option<String> {
+    iri.strip_prefix(from).map(|rest| format!("{to}{rest}"))
+}
+
+/// Resolve a triple to strings, with every subject, predicate and node
+/// object under `from` moved under `to`. Returns whether anything changed.
+fn rewrite_triple(
+    layer: &SyncStoreLayer,
+    triple: &IdTriple,
+    from: &str,
+    to: &str,
+) -> io::Result<(ValueTriple, bool)> {
+    let resolved = layer.id_triple_to_string(triple).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "triple {} {} {} does not resolve to strings",
+                triple.subject, triple.predicate, triple.object
+            ),
+        )
+    })?;
+    let subject = rewrite_iri(&resolved.subject, from, to);
+    let predicate = rewrite_iri(&resolved.predicate, from, to);
+    let object = match &resolved.object {
+        ObjectType::Node(node) => rewrite_iri(node, from, to).map(ObjectType::Node),
+        ObjectType::Value(_) => None,
+    };
+    let changed = subject.is_some() || predicate.is_some() || object.is_some();
+
+    Ok((
+        ValueTriple {
+            subject: subject.unwrap_or(resolved.subject),
+            predicate: predicate.unwrap_or(resolved.predicate),
+            object: object.unwrap_or(resolved.object),
+        },
+        changed,
+    ))
+}
+
+/// Rewrite the chain of `head` into a fresh store at `output`, moving every
+/// IRI that starts with `from` to start with `to` instead. Each layer is
+/// rebuilt from strings on top of its rewritten parent, so dictionaries,
+/// ids and indexes are all built afresh while the history of additions and
+/// removals is kept. The label is created in the new store pointing at the
+/// rewritten head.
+pub fn rewrite_namespace(
+    head: &SyncStoreLayer,
+    label: &str,
+    from: &str,
+    to: &str,
+    output: &Path,
+) -> io::Result<NamespaceSummary> {
+    let mut layers = vec![head.clone()];
+    while let Some(parent) = layers.last().unwrap().parent()? {
+        layers.push(parent);
+    }
+
+    std::fs::create_dir_all(output)?;
+    let new_store = open_sync_archive_store(output, 512);
+    let mut rewritten: Option<SyncStoreLayer> = None;
+    let mut changed = 0;
+    for layer in layers.iter().rev() {
+        let builder = match &rewritten {
+            None => new_store.create_base_layer()?,
+            Some(parent) => parent.open_write()?,
+        };
+        for triple in layer.triple_additions() {
+            let (triple, rewrote) = rewrite_triple(layer, &triple, from, to)?;
+            changed += rewrote as usize;
+            builder.add_value_triple(triple)?;
+        }
+        for triple in layer.triple_removals() {
+            let (triple, rewrote) = rewrite_triple(layer, &triple, from, to)?;
+            changed += rewrote as usize;
+            builder.remove_value_triple(triple)?;
+        }
+        rewritten = Some(builder.commit()?);
+    }
+
+    let rewritten = rewritten.unwrap();
+    new_store.create(label)?.set_head(&rewritten)?;
+
+    Ok(NamespaceSummary {
+        layers: layers.len(),
+        rewritten: changed,
+        head: rewritten.name(),
+    })
+}
@@ -0,0 +1,164 @@
+use std::io;
+
+use bytes::Bytes;
+use terminus_store::storage::consts::LayerFileEnum;
+
+use crate::{
+    archive::{encode_header, Archive},
+    checksum::ChecksumTrailer,
+};
+
+/// An archive written by `repack`.
+pub struct Repacked {
+    pub contents: Bytes,
+    pub segments: usize,
+    /// Segments left out because they were excluded.
+    pub dropped: Vec<LayerFileEnum>,
+    /// Bytes after the last segment of the original that weren't a valid
+    /// checksum trailer.
+    pub trailing: usize,
+}
+
+/// Rewrite an archive from its segments alone, leaving out the excluded
+/// ones. A header only records segment sizes, so segments are always laid
+/// out in header order, right after each other; what repacking removes is
+/// anything after the last segment. A checksum trailer is recomputed if
+/// the original had one. The new archive is parsed back and every segment
+/// compared against the original before it is returned.
+pub async fn repack(archive: &Archive, exclude: &[LayerFileEnum]) -> io::Result<Repacked> {
+    let present: Vec<_> = archive.segments().into_iter().map(|(t, _)| t).collect();
+    if let Some(missing) = exclude.iter().find(|t| !present.contains(t)) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("segment {missing:?} is not in the archive"),
+        ));
+    }
+    let kept: Vec<_> = present
+        .iter()
+        .copied()
+        .filter(|t| !exclude.contains(t))
+        .collect();
+    let mut sizes = Vec::new();
+    let mut body = Vec::new();
+    for t in kept.iter() {
+        let contents = archive.segment(*t)?.unwrap();
+        sizes.push((*t, contents.len()));
+        body.extend_from_slice(&contents);
+    }
+
+    let mut result = encode_header(&sizes);
+    result.extend(body);
+    let checksums = matches!(ChecksumTrailer::read(archive), Ok(Some(_)));
+    let trailing = if checksums {
+        0
+    } else {
+        archive.trailer().len()
+    };
+    if checksums {
+        let repacked = Archive::parse(result.clone().into()).await?;
+        result.extend(ChecksumTrailer::compute(&repacked)?.to_bytes());
+    }
+
+    let repacked = Archive::parse(result.clone().into()).await?;
+    for t in present.iter() {
+        let expected = if kept.contains(t) {
+            archive.segment(*t)?
+        } else {
+            None
+        };
+        if repacked.segment(*t)? != expected {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("segment {t:?} doesn't read back the same from the repacked archive"),
+            ));
+        }
+    }
+
+    Ok(Repacked {
+        contents: result.into(),
+        segments: kept.len(),
+        dropped: exclude.to_vec(),
+        trailing,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::tests::archive;
+
+    const PARENT: &[u8] = &[7; 20];
+    const ROLLUP: &[u8] = &[9; 22];
+
+    #[tokio::test]
+    async fn repacking_a_clean_archive_changes_nothing() {
+        let original = archive(
+            &[
+                (LayerFileEnum::Parent, PARENT),
+                (LayerFileEnum::Rollup, ROLLUP),
+            ],
+            &[],
+        )
+        .await;
+        let repacked = repack(&original, &[]).await.unwrap();
+        assert_eq!(&repacked.contents, original.contents());
+        assert_eq!(repacked.segments, 2);
+        assert_eq!(repacked.trailing, 0);
+    }
+
+    #[tokio::test]
+    async fn repacking_drops_unknown_trailing_bytes() {
+        let original = archive(&[(LayerFileEnum::Parent, PARENT)], b"junk").await;
+        let repacked = repack(&original, &[]).await.unwrap();
+        assert_eq!(repacked.trailing, 4);
+        assert_eq!(repacked.contents.len(), original.contents().len() - 4);
+    }
+
+    #[tokio::test]
+    async fn excluded_segments_are_left_out() {
+        let original = archive(
+            &[
+                (LayerFileEnum::Parent, PARENT),
+                (LayerFileEnum::Rollup, ROLLUP),
+            ],
+            &[],
+        )
+        .await;
+        let repacked = repack(&original, &[LayerFileEnum::Rollup]).await.unwrap();
+        assert_eq!(repacked.dropped, vec![LayerFileEnum::Rollup]);
+        let parsed = Archive::parse(repacked.contents).await.unwrap();
+        assert_eq!(parsed.segment(LayerFileEnum::Rollup).unwrap(), None);
+        assert_eq!(
+            parsed.segment(LayerFileEnum::Parent).unwrap().as_deref(),
+            Some(PARENT)
+        );
+    }
+
+    #[tokio::test]
+    async fn excluding_an_absent_segment_is_refused() {
+        let original = archive(&[(LayerFileEnum::Parent, PARENT)], &[]).await;
+        let e = repack(&original, &[LayerFileEnum::Rollup])
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[tokio::test]
+    async fn a_checksum_trailer_is_recomputed_for_the_kept_segments() {
+        let segments = [
+            (LayerFileEnum::Parent, PARENT),
+            (LayerFileEnum::Rollup, ROLLUP),
+        ];
+        let trailer = ChecksumTrailer::compute(&archive(&segments, &[]).await)
+            .unwrap()
+            .to_bytes();
+        let original = archive(&segments, &trailer).await;
+        let repacked = repack(&original, &[LayerFileEnum::Rollup]).await.unwrap();
+        let parsed = Archive::parse(repacked.contents).await.unwrap();
+        let checksums = ChecksumTrailer::read(&parsed).unwrap().unwrap();
+        assert_eq!(checksums.checksums.len(), 1);
+        assert!(checksums.get(LayerFileEnum::Parent).is_some());
+        assert!(checksums.get(LayerFileEnum::Rollup).is_none());
+    }
+}
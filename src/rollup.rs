@@ -0,0 +1,167 @@
+use std::{io, path::Path};
+
+use terminus_store::{
+    storage::{consts::LayerFileEnum, name_to_string},
+    store::sync::open_sync_archive_store,
+    Layer,
+};
+
+use crate::{
+    archive::Archive,
+    atomic,
+    repack::{repack, Repacked},
+    store::layer_path,
+};
+
+/// What `rebuild` did to a layer.
+pub struct Rebuilt {
+    /// Whether an existing rollup reference was stripped first.
+    pub stripped: bool,
+    /// Name of the new rollup layer.
+    pub rollup: [u32; 5],
+    /// Triples of the chain, which the rollup holds too.
+    pub triples: usize,
+}
+
+/// The rollup reference of a layer, as `show` found it.
+pub enum RollupInfo {
+    /// The layer has no rollup segment.
+    None,
+    /// The rollup segment of `size` bytes can't be decoded.
+    Unreadable { size: usize, error: String },
+    /// The rollup segment names a layer, which the store may not be able
+    /// to open.
+    Refers {
+        size: usize,
+        rollup: [u32; 5],
+        status: RollupStatus,
+    },
+}
+
+/// Whether the layer a rollup reference names can be opened.
+pub enum RollupStatus {
+    Ok,
+    Missing,
+    Unreadable(String),
+}
+
+impl RollupInfo {
+    /// False if the reference is unreadable or its layer is missing or
+    /// unreadable, any of which keeps the server from opening the layer.
+    pub fn usable(&self) -> bool {
+        matches!(
+            self,
+            RollupInfo::None
+                | RollupInfo::Refers {
+                    status: RollupStatus::Ok,
+                    ..
+                }
+        )
+    }
+}
+
+/// Read the rollup reference of a layer and whether the layer it names
+/// can be opened from the store.
+pub async fn show(store: &Path, layer_file: &Path) -> io::Result<RollupInfo> {
+    let archive = Archive::open(layer_file).await?;
+    let size = match archive.segment(LayerFileEnum::Rollup)? {
+        Some(segment) => segment.len(),
+        None => return Ok(RollupInfo::None),
+    };
+    let rollup = match archive.rollup() {
+        Ok(rollup) => rollup.expect("rollup segment is present"),
+        Err(e) => {
+            return Ok(RollupInfo::Unreadable {
+                size,
+                error: e.to_string(),
+            })
+        }
+    };
+    let path = layer_path(store, rollup);
+    let status = if !path.exists() {
+        RollupStatus::Missing
+    } else {
+        match Archive::open(&path).await {
+            Ok(_) => RollupStatus::Ok,
+            Err(e) => RollupStatus::Unreadable(e.to_string()),
+        }
+    };
+
+    Ok(RollupInfo::Refers {
+        size,
+        rollup,
+        status,
+    })
+}
+
+/// Rewrite an archive without its rollup segment, so the layer is opened
+/// through its plain chain again. Returns None if there is no rollup to
+/// strip.
+pub async fn strip(archive: &Archive) -> io::Result<Option<Repacked>> {
+    if archive.segment(LayerFileEnum::Rollup)?.is_none() {
+        return Ok(None);
+    }
+    repack(archive, &[LayerFileEnum::Rollup]).await.map(Some)
+}
+
+/// Regenerate the rollup of a layer from its ancestor chain. Any existing
+/// rollup reference is stripped first, as the store would otherwise open
+/// the layer through it. The store then rolls the chain up into a new
+/// layer and records it, and the new layer is checked to hold as many
+/// triples as the chain.
+pub async fn rebuild(store: &Path, name: [u32; 5]) -> io::Result<Rebuilt> {
+    let path = layer_path(store, name);
+    let archive = Archive::open(&path).await?;
+    if archive.parent()?.is_none() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "layer is a base layer and has no chain to roll up",
+        ));
+    }
+    let stripped = match strip(&archive).await? {
+        Some(repacked) => {
+            atomic::write(&path, repacked.contents).await?;
+            true
+        }
+        None => false,
+    };
+
+    let sync_store = open_sync_archive_store(store, 512);
+    let not_found = |name| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("layer {} not found", name_to_string(name)),
+        )
+    };
+    let layer = sync_store
+        .get_layer_from_id(name)?
+        .ok_or_else(|| not_found(name))?;
+    let triples = layer.triples().count();
+    layer.rollup()?;
+
+    let rollup = Archive::open(&path).await?.rollup()?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::Other,
+            "the store rolled the chain up but recorded no rollup",
+        )
+    })?;
+    let rolled = sync_store
+        .get_layer_from_id(rollup)?
+        .ok_or_else(|| not_found(rollup))?;
+    let rolled_triples = rolled.triples().count();
+    if rolled_triples != triples {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "rollup {} holds {rolled_triples} triples but the chain holds {triples}",
+                name_to_string(rollup)
+            ),
+        ));
+    }
+
+    Ok(Rebuilt {
+        stripped,
+        rollup,
+        triples,
+    })
+}
@@ -0,0 +1,172 @@
+use std::io::{self, Cursor};
+
+use bytes::Bytes;
+use futures::StreamExt;
+use terminus_store::structure::stream::TfcDictStream;
+
+use crate::{
+    archive::Archive,
+    dict::{block_len, block_offsets, sections, DictType, Section, BLOCK_SIZE},
+};
+
+/// A stretch of a dictionary that could not be decoded.
+pub struct Gap {
+    /// Byte offset in the blocks segment where the gap starts, if known.
+    pub start: Option<usize>,
+    /// Byte offset where decoding resumed, or None if it never did.
+    pub end: Option<usize>,
+    /// Index the first lost entry would have had.
+    pub first_index: u64,
+}
+
+#[derive(Default)]
+pub struct Salvage {
+    /// Recovered entries with their (approximate) indices.
+    pub entries: Vec<(u64, Bytes)>,
+    pub gaps: Vec<Gap>,
+    /// Whether block offsets were usable. If not, indices after the first
+    /// gap are lower bounds.
+    pub used_offsets: bool,
+}
+
+/// Decode entries until the end of some bytes or the first error. Returns
+/// the entries and whether an error occurred.
+async fn decode_entries(bytes: Bytes) -> (Vec<Bytes>, bool) {
+    let mut stream = TfcDictStream::new(Cursor::new(bytes));
+    let mut entries = Vec::new();
+    while let Some(element) = stream.next().await {
+        match element {
+            Ok((element, _)) => entries.push(element.to_bytes()),
+            Err(_) => return (entries, true),
+        }
+    }
+
+    (entries, false)
+}
+
+/// Decode whole blocks starting at a byte offset until the end of the
+/// segment or the first block that does not decode. Returns the entries,
+/// including any the failing block yielded, and the offset of that block.
+async fn decode_from(blocks: &Bytes, offset: usize) -> (Vec<Bytes>, Option<usize>) {
+    let mut entries = Vec::new();
+    let mut pos = offset;
+    // the segment may be padded after its last block
+    while blocks[pos..].iter().any(|b| *b != 0) {
+        let len = match block_len(&blocks[pos..]) {
+            Some(len) => len,
+            None => return (entries, Some(pos)),
+        };
+        let (block, failed) = decode_entries(blocks.slice(pos..pos + len)).await;
+        entries.extend(block);
+        if failed {
+            return (entries, Some(pos));
+        }
+        pos += len;
+    }
+
+    (entries, None)
+}
+
+/// Decode every block on its own, keeping the entries a failing block
+/// yields before its error. Ids are taken from the dictionary's sections
+/// if they could be read.
+async fn salvage_with_offsets(
+    blocks: &Bytes,
+    offsets: &[usize],
+    sections: Option<&[Section]>,
+) -> Salvage {
+    let mut salvage = Salvage {
+        used_offsets: true,
+        ..Default::default()
+    };
+    for (ix, offset) in offsets.iter().enumerate() {
+        let end = offsets.get(ix + 1).copied().unwrap_or(blocks.len());
+        let section =
+            sections.and_then(|sections| sections.iter().find(|s| s.blocks.contains(&ix)));
+        let id = |i: usize| match section {
+            Some(section) => section.id(ix, i),
+            None => (ix * BLOCK_SIZE + i) as u64 + 1,
+        };
+        let (entries, failed) = decode_entries(blocks.slice(*offset..end.max(*offset))).await;
+        let recovered = entries.len();
+        for (i, entry) in entries.into_iter().enumerate() {
+            salvage.entries.push((id(i), entry));
+        }
+        if failed {
+            salvage.gaps.push(Gap {
+                start: (recovered == 0).then_some(*offset),
+                end: Some(end),
+                first_index: id(recovered),
+            });
+        }
+    }
+
+    salvage
+}
+
+/// Without offsets, look for the next position at which decoding yields
+/// entries that sort after everything recovered so far. Dictionaries are
+/// sorted, so this also avoids recovering the same entries twice.
+async fn salvage_by_scanning(blocks: &Bytes) -> Salvage {
+    let mut salvage = Salvage::default();
+    let mut gap: Option<Gap> = None;
+    let mut offset = 0;
+    while offset < blocks.len() {
+        let (entries, failed_at) = decode_from(blocks, offset).await;
+        let last = salvage.entries.last().map(|(_, e)| e.clone());
+        let fresh: Vec<_> = entries
+            .into_iter()
+            .filter(|e| last.as_ref().map(|l| e > l).unwrap_or(true))
+            .collect();
+        // a single entry followed by garbage is most likely noise
+        if fresh.is_empty() || (failed_at.is_some() && fresh.len() < 2 && offset != 0) {
+            gap.get_or_insert(Gap {
+                start: Some(offset),
+                end: None,
+                first_index: salvage.entries.len() as u64 + 1,
+            });
+            offset += 1;
+            continue;
+        }
+
+        if let Some(mut gap) = gap.take() {
+            gap.end = Some(offset);
+            salvage.gaps.push(gap);
+        }
+        for entry in fresh {
+            let index = salvage.entries.len() as u64 + 1;
+            salvage.entries.push((index, entry));
+        }
+        let failed_at = match failed_at {
+            Some(failed_at) => failed_at,
+            None => return salvage,
+        };
+        // everything before the failing block decoded, so resume inside it
+        gap = Some(Gap {
+            start: Some(failed_at),
+            end: None,
+            first_index: salvage.entries.len() as u64 + 1,
+        });
+        offset = failed_at + 1;
+    }
+    salvage.gaps.extend(gap);
+
+    salvage
+}
+
+/// Recover as many entries as possible from a possibly corrupt dictionary.
+/// Block offsets are used to isolate bad blocks when they look sane;
+/// otherwise the blocks are scanned for plausible block boundaries.
+pub async fn salvage(archive: &Archive, t: DictType) -> io::Result<Salvage> {
+    let blocks = archive.segment(t.blocks_segment())?.unwrap_or_default();
+    let offsets = block_offsets(archive, t)
+        .ok()
+        .filter(|offsets| offsets.windows(2).all(|w| w[0] < w[1]));
+    Ok(match offsets {
+        Some(offsets) => {
+            let sections = sections(archive, t).await.ok();
+            salvage_with_offsets(&blocks, &offsets, sections.as_deref()).await
+        }
+        None => salvage_by_scanning(&blocks).await,
+    })
+}
@@ -0,0 +1,232 @@
+use std::{
+    collections::HashSet,
+    io,
+    path::{Path, PathBuf},
+};
+
+use serde_json::json;
+
+use crate::{
+    archive::Archive,
+    health::Health,
+    output::{ndjson, paint, Color, OutputFormat},
+    store::{list_labels, list_layers, read_label},
+    validate::Finding,
+    verify::verify,
+};
+
+/// How usable a store is as a whole.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum StoreStatus {
+    Ok,
+    /// Some layers fail, but at least one label has a sound head.
+    Damaged,
+    /// No label has a head that can be read and passes verification.
+    Dead,
+    /// The store has no labels.
+    Empty,
+}
+
+impl StoreStatus {
+    pub fn name(self) -> &'static str {
+        match self {
+            StoreStatus::Ok => "ok",
+            StoreStatus::Damaged => "damaged",
+            StoreStatus::Dead => "dead",
+            StoreStatus::Empty => "empty",
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            StoreStatus::Ok => Color::Green,
+            StoreStatus::Damaged => Color::Yellow,
+            StoreStatus::Dead => Color::Red,
+            StoreStatus::Empty => Color::Dim,
+        }
+    }
+}
+
+/// The outcome of quickly verifying one store.
+pub struct StoreSummary {
+    pub path: PathBuf,
+    pub status: StoreStatus,
+    pub labels: usize,
+    pub layers: usize,
+    pub failed: usize,
+    pub health: f64,
+}
+
+/// A directory is taken to be a store if it has a storage version file or
+/// any label.
+fn is_store(dir: &Path) -> io::Result<bool> {
+    if dir.join("STORAGE_VERSION").exists() {
+        return Ok(true);
+    }
+    for entry in std::fs::read_dir(dir)? {
+        if entry?.path().extension().map(|e| e == "label") == Some(true) {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// Find every store under a directory, sorted by path. Stores aren't
+/// searched for further stores inside them.
+pub fn find_stores(root: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut result = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        if is_store(&dir)? {
+            result.push(dir);
+            continue;
+        }
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() && entry.file_name() != ".surgery" {
+                pending.push(entry.path());
+            }
+        }
+    }
+    result.sort();
+
+    Ok(result)
+}
+
+/// Quickly verify every layer of a store, and check which labels still
+/// have a sound head.
+pub async fn scan_store(store: &Path) -> io::Result<StoreSummary> {
+    let layers = list_layers(store).await?;
+    // quick verification covers the structure and checksums checks
+    let mut health = Health::new(2);
+    let mut failed = HashSet::new();
+    for (name, path) in layers.iter() {
+        let findings = match Archive::open(path).await {
+            Ok(archive) => verify(&archive, true).await,
+            Err(e) => vec![Finding::unreadable(e)],
+        };
+        health.record(&findings);
+        if !findings.is_empty() {
+            failed.insert(*name);
+        }
+    }
+
+    let labels = list_labels(store).await?;
+    let layer_names: HashSet<_> = layers.iter().map(|(name, _)| *name).collect();
+    let mut sound_heads = 0;
+    for label in labels.iter() {
+        // an unreadable label counts as one without a sound head
+        if let Ok(Some(head)) = read_label(store, label).await {
+            if layer_names.contains(&head) && !failed.contains(&head) {
+                sound_heads += 1;
+            }
+        }
+    }
+    let status = if labels.is_empty() {
+        StoreStatus::Empty
+    } else if sound_heads == 0 {
+        StoreStatus::Dead
+    } else if !failed.is_empty() {
+        StoreStatus::Damaged
+    } else {
+        StoreStatus::Ok
+    };
+
+    Ok(StoreSummary {
+        path: store.to_path_buf(),
+        status,
+        labels: labels.len(),
+        layers: layers.len(),
+        failed: failed.len(),
+        health: health.score(),
+    })
+}
+
+/// Scan every store under `root` and print a table with a line per store
+/// and a fleet-wide summary. A store that can't be listed at all is
+/// reported as dead. Returns whether every store with labels is ok.
+pub async fn scan(root: &Path, format: OutputFormat) -> io::Result<bool> {
+    let stores = find_stores(root)?;
+    let mut summaries = Vec::new();
+    for store in stores.iter() {
+        let summary = match scan_store(store).await {
+            Ok(summary) => summary,
+            Err(e) => {
+                eprintln!("{}: {e}", store.display());
+                StoreSummary {
+                    path: store.clone(),
+                    status: StoreStatus::Dead,
+                    labels: 0,
+                    layers: 0,
+                    failed: 0,
+                    health: 0.0,
+                }
+            }
+        };
+        print_summary(&summary, format);
+        summaries.push(summary);
+    }
+
+    let count = |status| summaries.iter().filter(|s| s.status == status).count();
+    let (ok, damaged, dead, empty) = (
+        count(StoreStatus::Ok),
+        count(StoreStatus::Damaged),
+        count(StoreStatus::Dead),
+        count(StoreStatus::Empty),
+    );
+    match format {
+        OutputFormat::Pretty => println!(
+            "\n{} stores: {ok} ok, {}, {}, {empty} empty",
+            summaries.len(),
+            paint(&format!("{damaged} damaged"), Color::Yellow),
+            paint(&format!("{dead} dead"), Color::Red)
+        ),
+        OutputFormat::Text => println!(
+            "stores {}, ok {ok}, damaged {damaged}, dead {dead}, empty {empty}",
+            summaries.len()
+        ),
+        OutputFormat::Ndjson => println!(
+            "{}",
+            ndjson(json!({
+                "stores": summaries.len(),
+                "ok": ok,
+                "damaged": damaged,
+                "dead": dead,
+                "empty": empty,
+            }))
+        ),
+    }
+
+    Ok(damaged == 0 && dead == 0)
+}
+
+fn print_summary(summary: &StoreSummary, format: OutputFormat) {
+    let path = summary.path.display();
+    let status = summary.status.name();
+    match format {
+        OutputFormat::Pretty => println!(
+            "{}  {:>5.1}  {:>6} layers  {:>4} failed  {:>4} labels  {path}",
+            paint(&format!("{status:<7}"), summary.status.color()),
+            summary.health,
+            summary.layers,
+            summary.failed,
+            summary.labels
+        ),
+        OutputFormat::Text => println!(
+            "{status} {:.1} {} {} {} {path}",
+            summary.health, summary.layers, summary.failed, summary.labels
+        ),
+        OutputFormat::Ndjson => println!(
+            "{}",
+            ndjson(json!({
+                "store": path.to_string(),
+                "status": status,
+                "health": summary.health,
+                "layers": summary.layers,
+                "failed": summary.failed,
+                "labels": summary.labels,
+            }))
+        ),
+    }
+}
@@ -0,0 +1,250 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use serde_json::{json, Value};
+use terminus_store::{layer::ObjectType, store::sync::SyncStoreLayer, Layer};
+
+use crate::values::xsd_name;
+
+pub const RDF_TYPE: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#type";
+const SYS: &str = "http://terminusdb.com/schema/sys#";
+
+/// Type families whose fields may be left out of a document.
+const OPTIONAL_FAMILIES: [&str; 4] = ["Optional", "Set", "Array", "Cardinality"];
+
+fn sys(name: &str) -> String {
+    format!("{SYS}{name}")
+}
+
+/// The types a node in a layer has.
+fn types_of(layer: &SyncStoreLayer, type_id: u64, node: u64) -> Vec<String> {
+    layer
+        .triples_sp(node, type_id)
+        .filter_map(|t| match layer.id_object(t.object) {
+            Some(ObjectType::Node(node)) => Some(node),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The fields every instance of each class must have, read from a
+/// TerminusDB schema graph. Fields whose type is an optional family, such
+/// as `Optional` or `Set`, are left out, and inherited fields are included.
+pub fn required_fields(schema: &SyncStoreLayer) -> BTreeMap<String, BTreeSet<String>> {
+    let (type_id, class_id) = match (
+        schema.predicate_id(RDF_TYPE),
+        schema.object_node_id(&sys("Class")),
+    ) {
+        (Some(type_id), Some(class_id)) => (type_id, class_id),
+        _ => return BTreeMap::new(),
+    };
+    let optional: Vec<_> = OPTIONAL_FAMILIES.iter().map(|f| sys(f)).collect();
+    let inherits = sys("inherits");
+
+    let mut own: HashMap<String, BTreeSet<String>> = HashMap::new();
+    let mut parents: HashMap<String, Vec<String>> = HashMap::new();
+    for class in schema.triples_p(type_id).filter(|t| t.object == class_id) {
+        let class_name = schema.id_subject(class.subject).unwrap();
+        let fields = own.entry(class_name.clone()).or_default();
+        for triple in schema.triples_s(class.subject) {
+            let predicate = schema.id_predicate(triple.predicate).unwrap();
+            let object = schema.id_object(triple.object);
+            if predicate == inherits {
+                if let Some(ObjectType::Node(parent)) = object {
+                    parents.entry(class_name.clone()).or_default().push(parent);
+                }
+                continue;
+            }
+            if predicate == RDF_TYPE || predicate.starts_with(SYS) {
+                continue;
+            }
+            if !matches!(object, Some(ObjectType::Node(_))) {
+                continue;
+            }
+            let family = types_of(schema, type_id, triple.object);
+            if !family.iter().any(|t| optional.contains(t)) {
+                fields.insert(predicate);
+            }
+        }
+    }
+
+    let mut result = BTreeMap::new();
+    for class in own.keys() {
+        let mut fields = BTreeSet::new();
+        let mut seen = BTreeSet::new();
+        let mut todo = vec![class.clone()];
+        while let Some(class) = todo.pop() {
+            if !seen.insert(class.clone()) {
+                continue;
+            }
+            fields.extend(own.get(&class).into_iter().flatten().cloned());
+            todo.extend(parents.get(&class).into_iter().flatten().cloned());
+        }
+        result.insert(class.clone(), fields);
+    }
+
+    result
+}
+
+/// For every class, the instances lacking some of its required fields,
+/// with the fields they lack.
+pub fn missing_fields(
+    instance: &SyncStoreLayer,
+    required: &BTreeMap<String, BTreeSet<String>>,
+) -> BTreeMap<String, Vec<(String, Vec<String>)>> {
+    let mut result = BTreeMap::new();
+    let type_id = match instance.predicate_id(RDF_TYPE) {
+        Some(type_id) => type_id,
+        None => return result,
+    };
+    for (class, fields) in required.iter() {
+        let class_id = match instance.object_node_id(class) {
+            Some(class_id) if !fields.is_empty() => class_id,
+            _ => continue,
+        };
+        let field_ids: Vec<_> = fields
+            .iter()
+            .map(|f| (f, instance.predicate_id(f)))
+            .collect();
+        let mut broken = Vec::new();
+        for member in instance
+            .triples_o(class_id)
+            .filter(|t| t.predicate == type_id)
+        {
+            let missing: Vec<String> = field_ids
+                .iter()
+                .filter(|(_, id)| match id {
+                    Some(id) => instance.triples_sp(member.subject, *id).next().is_none(),
+                    None => true,
+                })
+                .map(|(field, _)| field.to_string())
+                .collect();
+            if !missing.is_empty() {
+                broken.push((instance.id_subject(member.subject).unwrap(), missing));
+            }
+        }
+        if !broken.is_empty() {
+            result.insert(class.clone(), broken);
+        }
+    }
+
+    result
+}
+
+/// How the instances of a class use one property.
+#[derive(Default)]
+struct PropertyUsage {
+    /// Number of instances with at least one value.
+    used_by: usize,
+    min: usize,
+    max: usize,
+    /// The classes of node objects, or datatypes of values, with counts.
+    ranges: BTreeMap<String, usize>,
+}
+
+impl PropertyUsage {
+    /// A field definition in the shape of a TerminusDB schema document:
+    /// the most common range, wrapped in `Optional` or `Set` unless every
+    /// instance has exactly one value.
+    fn suggested(&self) -> Value {
+        let range = self
+            .ranges
+            .iter()
+            .max_by_key(|(_, count)| **count)
+            .map(|(range, _)| range.clone())
+            .unwrap_or_default();
+        match (self.min, self.max) {
+            (1, 1) => json!(range),
+            (_, 0..=1) => json!({ "@type": "Optional", "@class": range }),
+            _ => json!({ "@type": "Set", "@class": range }),
+        }
+    }
+}
+
+/// Derive a schema from instance data: for every class, how many
+/// instances it has, and for each property they use, how many values an
+/// instance has and what classes or datatypes the values have. Each class
+/// comes with a suggested class frame to start reconstructing a lost
+/// schema from. Untyped node objects have range `untyped`.
+pub fn infer_schema(instance: &SyncStoreLayer) -> Value {
+    let type_id = match instance.predicate_id(RDF_TYPE) {
+        Some(type_id) => type_id,
+        None => return json!({}),
+    };
+    let mut members: BTreeMap<String, Vec<u64>> = BTreeMap::new();
+    for triple in instance.triples_p(type_id) {
+        if let Some(ObjectType::Node(class)) = instance.id_object(triple.object) {
+            members.entry(class).or_default().push(triple.subject);
+        }
+    }
+
+    let mut node_types: HashMap<u64, String> = HashMap::new();
+    let mut classes = serde_json::Map::new();
+    for (class, subjects) in members.iter() {
+        let mut usage: BTreeMap<String, PropertyUsage> = BTreeMap::new();
+        let mut counts: Vec<BTreeMap<String, usize>> = Vec::new();
+        for subject in subjects.iter() {
+            let mut per_subject: BTreeMap<String, usize> = BTreeMap::new();
+            for triple in instance.triples_s(*subject) {
+                if triple.predicate == type_id {
+                    continue;
+                }
+                let predicate = instance.id_predicate(triple.predicate).unwrap();
+                let range = match instance.id_object(triple.object) {
+                    Some(ObjectType::Value(value)) => xsd_name(&format!("{:?}", value.datatype())),
+                    Some(ObjectType::Node(_)) => node_types
+                        .entry(triple.object)
+                        .or_insert_with(|| {
+                            types_of(instance, type_id, triple.object)
+                                .into_iter()
+                                .next()
+                                .unwrap_or_else(|| "untyped".to_string())
+                        })
+                        .clone(),
+                    None => continue,
+                };
+                *usage
+                    .entry(predicate.clone())
+                    .or_default()
+                    .ranges
+                    .entry(range)
+                    .or_default() += 1;
+                *per_subject.entry(predicate).or_default() += 1;
+            }
+            counts.push(per_subject);
+        }
+
+        let mut properties = serde_json::Map::new();
+        let mut frame = serde_json::Map::new();
+        frame.insert("@type".to_string(), json!("Class"));
+        frame.insert("@id".to_string(), json!(class));
+        for (predicate, usage) in usage.iter_mut() {
+            let per_subject: Vec<usize> = counts
+                .iter()
+                .map(|c| c.get(predicate).copied().unwrap_or(0))
+                .collect();
+            usage.used_by = per_subject.iter().filter(|n| **n > 0).count();
+            usage.min = per_subject.iter().copied().min().unwrap_or(0);
+            usage.max = per_subject.iter().copied().max().unwrap_or(0);
+            frame.insert(predicate.clone(), usage.suggested());
+            properties.insert(
+                predicate.clone(),
+                json!({
+                    "used_by": usage.used_by,
+                    "min": usage.min,
+                    "max": usage.max,
+                    "ranges": usage.ranges,
+                }),
+            );
+        }
+        classes.insert(
+            class.clone(),
+            json!({
+                "instances": subjects.len(),
+                "properties": properties,
+                "suggested": frame,
+            }),
+        );
+    }
+
+    Value::Object(classes)
+}
@@ -0,0 +1,198 @@
+use std::{collections::BTreeSet, io, path::Path};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use terminus_store::{
+    layer::{ObjectType, ValueTriple},
+    storage::archive::ArchiveHeader,
+    store::sync::{open_sync_archive_store, SyncStore, SyncStoreLayer},
+    Layer,
+};
+
+use crate::{
+    archive::{all_segment_types, encode_header, Archive},
+    build_layer::typed_value,
+    checksum::canonicalize,
+    repack::repack,
+    stats::triple_counts,
+    store::layer_path,
+    verify::verify,
+};
+
+/// Triples as generated: subject, predicate, object and the datatype of
+/// the object, which is a node if there is none. Values are written in
+/// their canonical lexical form, so distinct triples stay distinct once
+/// encoded.
+type Triple = (String, String, String, Option<&'static str>);
+
+/// A random value of one of the datatypes the store encodes, with its
+/// lexical form as `typed_value` reads it.
+fn random_value(rng: &mut StdRng, size: usize) -> (String, &'static str) {
+    let n = rng.gen_range(0..size) as i64;
+    let signed = n - size as i64 / 2;
+    match rng.gen_range(0..9) {
+        0 => ((signed as i32).to_string(), "Int32"),
+        1 => ((n as u32).to_string(), "UInt32"),
+        2 => (signed.wrapping_mul(1_000_000_007).to_string(), "Int64"),
+        3 => ((n as f64 / 4.0).to_string(), "Float64"),
+        4 => ((n % 2 == 0).to_string(), "Boolean"),
+        5 => (format!("-98765432109876543210{n}"), "BigInt"),
+        6 => (format!("{n}.25"), "Decimal"),
+        7 => {
+            let tag = ["en", "de", "nl"][rng.gen_range(0..3)];
+            (format!("{tag}@value {n}"), "LangString")
+        }
+        _ => (format!("value {n}"), "String"),
+    }
+}
+
+fn random_triple(rng: &mut StdRng, size: usize) -> Triple {
+    let (object, datatype) = if rng.gen_bool(0.5) {
+        let (lexical, datatype) = random_value(rng, size);
+        (lexical, Some(datatype))
+    } else {
+        (format!("node{}", rng.gen_range(0..size)), None)
+    };
+    (
+        format!("node{}", rng.gen_range(0..size)),
+        format!("p{}", rng.gen_range(0..size / 8 + 1)),
+        object,
+        datatype,
+    )
+}
+
+fn value_triple((subject, predicate, object, datatype): &Triple) -> io::Result<ValueTriple> {
+    let object = match datatype {
+        None => ObjectType::Node(object.clone()),
+        Some(datatype) => {
+            let (entry, _) = typed_value(object, datatype)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            ObjectType::Value(entry)
+        }
+    };
+    Ok(ValueTriple {
+        subject: subject.clone(),
+        predicate: predicate.clone(),
+        object,
+    })
+}
+
+/// Build a layer from the given additions and removals, on top of `parent`
+/// or as a base layer.
+fn build_layer(
+    store: &SyncStore,
+    parent: Option<&SyncStoreLayer>,
+    additions: &BTreeSet<Triple>,
+    removals: &BTreeSet<Triple>,
+) -> io::Result<SyncStoreLayer> {
+    let builder = match parent {
+        None => store.create_base_layer()?,
+        Some(parent) => parent.open_write()?,
+    };
+    for triple in additions {
+        builder.add_value_triple(value_triple(triple)?)?;
+    }
+    for triple in removals {
+        builder.remove_value_triple(value_triple(triple)?)?;
+    }
+    builder.commit()
+}
+
+/// Check a freshly written layer with the tool's own readers. Verify must
+/// find nothing, the triple counts must match what was written, the
+/// header must encode to one the store parses back the same, repacking
+/// must leave the archive as the store wrote it, and canonicalizing must
+/// give an archive that verifies and canonicalizes to itself.
+async fn check_layer(
+    store_dir: &Path,
+    layer: &SyncStoreLayer,
+    expected: (u64, u64),
+) -> io::Result<Vec<String>> {
+    let mut problems = Vec::new();
+    let archive = Archive::open(layer_path(store_dir, layer.name())).await?;
+    for finding in verify(&archive, false).await {
+        problems.push(format!("verify: {finding}"));
+    }
+    let counts = triple_counts(&archive)?;
+    if counts != expected {
+        problems.push(format!(
+            "triple counts are {counts:?}, expected {expected:?}"
+        ));
+    }
+
+    let sizes: Vec<_> = archive
+        .segments()
+        .into_iter()
+        .map(|(t, range)| (t, range.len()))
+        .collect();
+    let encoded = encode_header(&sizes);
+    let header = ArchiveHeader::parse_from_reader(&mut &encoded[..]).await?;
+    for t in all_segment_types() {
+        if header.range_for(t) != archive.header.range_for(t) {
+            problems.push(format!(
+                "re-encoded header gives {t:?} range {:?}, expected {:?}",
+                header.range_for(t),
+                archive.header.range_for(t)
+            ));
+        }
+    }
+
+    match repack(&archive, &[]).await {
+        Ok(repacked) if &repacked.contents != archive.contents() => {
+            problems.push("repacking changes the archive".to_string())
+        }
+        Ok(_) => {}
+        Err(e) => problems.push(format!("repack: {e}")),
+    }
+
+    let canonical = Archive::parse(canonicalize(&archive, true)?).await?;
+    for finding in verify(&canonical, false).await {
+        problems.push(format!("verify after canonicalize: {finding}"));
+    }
+    if &canonicalize(&canonical, true)? != canonical.contents() {
+        problems.push("canonicalizing twice changes the archive".to_string());
+    }
+
+    Ok(problems)
+}
+
+/// Generate random chains of layers in a scratch store and check each
+/// layer. Returns a description of every problem found, prefixed with the
+/// round and layer it occurred in. The same seed generates the same
+/// layers.
+pub async fn self_test(
+    scratch: &Path,
+    rounds: usize,
+    chain_length: usize,
+    size: usize,
+    seed: u64,
+) -> io::Result<Vec<String>> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    tokio::fs::create_dir_all(scratch).await?;
+    let store = open_sync_archive_store(scratch, 512);
+    let mut problems = Vec::new();
+    for round in 0..rounds {
+        let mut current: BTreeSet<Triple> = BTreeSet::new();
+        let mut parent: Option<SyncStoreLayer> = None;
+        for depth in 0..chain_length {
+            let additions: BTreeSet<Triple> = (0..rng.gen_range(1..=size))
+                .map(|_| random_triple(&mut rng, size))
+                .filter(|t| !current.contains(t))
+                .collect();
+            let removals: BTreeSet<Triple> = current
+                .iter()
+                .filter(|_| parent.is_some() && rng.gen_bool(0.2))
+                .cloned()
+                .collect();
+            let layer = build_layer(&store, parent.as_ref(), &additions, &removals)?;
+            let expected = (additions.len() as u64, removals.len() as u64);
+            for problem in check_layer(scratch, &layer, expected).await? {
+                problems.push(format!("round {round}, layer {depth}: {problem}"));
+            }
+            current.retain(|t| !removals.contains(t));
+            current.extend(additions);
+            parent = Some(layer);
+        }
+    }
+
+    Ok(problems)
+}
@@ -0,0 +1,252 @@
+use std::{io, path::Path};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde_json::json;
+use terminus_store::{
+    layer::IdTriple,
+    storage::{consts::LayerFileEnum, name_to_string},
+    store::sync::{open_sync_archive_store, SyncStoreLayer},
+    Layer,
+};
+
+use crate::{
+    archive::Archive,
+    output::{ndjson, paint, Color, OutputFormat},
+    query::{walk_pattern, Pattern, QueryIndex},
+    stats::triple_counts,
+    store,
+};
+
+/// Failures listed in a check's detail; the rest are only counted.
+const SHOWN_FAILURES: usize = 5;
+
+/// The outcome of one step of the smoke test.
+pub struct Check {
+    pub name: &'static str,
+    /// How many cases the check went through.
+    pub cases: usize,
+    pub failures: Vec<String>,
+}
+
+impl Check {
+    fn new(name: &'static str, cases: usize, failures: Vec<String>) -> Self {
+        Check {
+            name,
+            cases,
+            failures,
+        }
+    }
+
+    fn failed(name: &'static str, failure: impl ToString) -> Self {
+        Check::new(name, 1, vec![failure.to_string()])
+    }
+
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Whether a triple holds at the top of a chain of archives, head first,
+/// going by their adjacency lists alone: the nearest layer that adds or
+/// removes it decides.
+fn raw_present(archives: &[Archive], triple: IdTriple) -> io::Result<bool> {
+    let pattern = Pattern {
+        subject: Some(triple.subject),
+        predicate: Some(triple.predicate),
+        object: Some(triple.object),
+    };
+    for archive in archives {
+        let removes = archive
+            .absolute_range(LayerFileEnum::NegSpOAdjacencyListNums)
+            .is_some();
+        for (index, removals) in [(QueryIndex::Neg, true), (QueryIndex::Pos, false)] {
+            if removals && !removes {
+                continue;
+            }
+            let mut found = false;
+            walk_pattern(archive, &pattern, index, removals, |_, _, _| {
+                found = true;
+                Ok(())
+            })?;
+            if found {
+                return Ok(!removals);
+            }
+        }
+    }
+
+    Ok(false)
+}
+
+/// Check that a triple the store API gave resolves to strings and back to
+/// the same ids.
+fn round_trip(layer: &SyncStoreLayer, triple: IdTriple) -> Result<(), String> {
+    let resolved = layer
+        .id_triple_to_string(&triple)
+        .ok_or_else(|| format!("{triple:?} doesn't resolve to strings"))?;
+    if !layer.value_triple_exists(&resolved) {
+        return Err(format!("{resolved:?} is not found by its strings"));
+    }
+    let ids = (
+        layer.subject_id(&resolved.subject),
+        layer.predicate_id(&resolved.predicate),
+    );
+    if ids != (Some(triple.subject), Some(triple.predicate)) {
+        return Err(format!(
+            "{resolved:?} resolves back to subject {:?} and predicate {:?}",
+            ids.0, ids.1
+        ));
+    }
+
+    Ok(())
+}
+
+/// Open a label through the store API the way the server does, walk every
+/// triple of its head, and cross-check random point lookups against the
+/// adjacency lists of the chain's layer files. Each sampled triple must
+/// resolve to strings and back, and be present by the raw segments; as
+/// many probes for triples with a random object must get the same answer
+/// from the API and the raw segments. Stops at the first step that fails
+/// in a way the later ones depend on.
+pub async fn smoke(store: &Path, label: &str, lookups: usize, seed: u64) -> io::Result<Vec<Check>> {
+    let mut checks = Vec::new();
+    let sync_store = open_sync_archive_store(store, 512);
+    let layer = match sync_store.open(label).map(|graph| graph.map(|g| g.head())) {
+        Ok(Some(Ok(Some(layer)))) => layer,
+        Ok(Some(Ok(None))) => {
+            checks.push(Check::failed("open", format!("label {label} has no head")));
+            return Ok(checks);
+        }
+        Ok(None) => {
+            checks.push(Check::failed("open", format!("label {label} not found")));
+            return Ok(checks);
+        }
+        Ok(Some(Err(e))) | Err(e) => {
+            checks.push(Check::failed("open", e));
+            return Ok(checks);
+        }
+    };
+    checks.push(Check::new("open", 1, Vec::new()));
+
+    let mut archives = Vec::new();
+    for (name, path) in store::chain(store, layer.name()).await? {
+        match Archive::open(&path).await {
+            Ok(archive) => archives.push(archive),
+            Err(e) => {
+                checks.push(Check::failed(
+                    "chain",
+                    format!("{}: {e}", name_to_string(name)),
+                ));
+                return Ok(checks);
+            }
+        }
+    }
+    checks.push(Check::new("chain", archives.len(), Vec::new()));
+
+    // walk the head, keeping a uniform sample of its triples
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut sample = Vec::new();
+    let mut materialized = 0;
+    for triple in layer.triples() {
+        materialized += 1;
+        if sample.len() < lookups {
+            sample.push(triple);
+        } else {
+            let i = rng.gen_range(0..materialized);
+            if i < lookups {
+                sample[i] = triple;
+            }
+        }
+    }
+    let mut expected: i64 = 0;
+    for archive in archives.iter() {
+        let (added, removed) = triple_counts(archive)?;
+        expected += added as i64 - removed as i64;
+    }
+    let failures = if materialized as i64 == expected {
+        Vec::new()
+    } else {
+        vec![format!(
+            "the API gives {materialized} triples but the layers add up to {expected}"
+        )]
+    };
+    checks.push(Check::new("triple count", 1, failures));
+
+    let mut failures = Vec::new();
+    for triple in sample.iter() {
+        if let Err(e) = round_trip(&layer, *triple) {
+            failures.push(e);
+        }
+    }
+    checks.push(Check::new("round trip", sample.len(), failures));
+
+    let mut failures = Vec::new();
+    for triple in sample.iter() {
+        if !raw_present(&archives, *triple)? {
+            failures.push(format!(
+                "{triple:?} is in the head but not in the layers' adjacency lists"
+            ));
+        }
+    }
+    checks.push(Check::new("raw lookup", sample.len(), failures));
+
+    let objects = layer.node_and_value_count() as u64;
+    let mut failures = Vec::new();
+    for triple in sample.iter().filter(|_| objects > 0) {
+        let probe = IdTriple::new(triple.subject, triple.predicate, rng.gen_range(1..=objects));
+        let api = layer
+            .triples_sp(probe.subject, probe.predicate)
+            .any(|t| t.object == probe.object);
+        let raw = raw_present(&archives, probe)?;
+        if api != raw {
+            failures.push(format!(
+                "{probe:?} is {} by the API but {} by the adjacency lists",
+                if api { "present" } else { "absent" },
+                if raw { "present" } else { "absent" }
+            ));
+        }
+    }
+    checks.push(Check::new("random probe", sample.len(), failures));
+
+    Ok(checks)
+}
+
+pub fn print_check(check: &Check, format: OutputFormat) {
+    let failed = check.failures.len();
+    match format {
+        OutputFormat::Ndjson => println!(
+            "{}",
+            ndjson(json!({
+                "check": check.name,
+                "status": if check.passed() { "pass" } else { "fail" },
+                "cases": check.cases,
+                "failed": failed,
+                "failures": check.failures.iter().take(SHOWN_FAILURES).collect::<Vec<_>>(),
+            }))
+        ),
+        OutputFormat::Pretty => {
+            let status = if check.passed() {
+                paint("PASS", Color::Green)
+            } else {
+                paint("FAIL", Color::Red)
+            };
+            println!(
+                "{status}  {:<14} {}",
+                check.name,
+                paint(&format!("({failed} of {} failed)", check.cases), Color::Dim)
+            );
+            for failure in check.failures.iter().take(SHOWN_FAILURES) {
+                println!("      {failure}");
+            }
+            if failed > SHOWN_FAILURES {
+                println!("      ... and {} more", failed - SHOWN_FAILURES);
+            }
+        }
+        OutputFormat::Text => {
+            let status = if check.passed() { "PASS" } else { "FAIL" };
+            println!("{status} {} {failed}/{}", check.name, check.cases);
+            for failure in check.failures.iter() {
+                println!("  {failure}");
+            }
+        }
+    }
+}
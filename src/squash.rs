@@ -0,0 +1,56 @@
+use std::{io, path::Path};
+
+use terminus_store::{
+    store::sync::{open_sync_archive_store, SyncStoreLayer},
+    Layer,
+};
+
+use crate::{atomic, squash_check::resolve, store::layer_path};
+
+/// What squashing a chain produced.
+pub struct SquashSummary {
+    /// Number of layers in the squashed chain.
+    pub layers: usize,
+    pub triples: usize,
+    /// Name of the new base layer.
+    pub name: [u32; 5],
+}
+
+/// Materialize the current triples of `head` and all its ancestors into a
+/// single new base layer, written as a standalone archive to `output`. The
+/// layer is built from strings in a scratch store next to the output,
+/// which is removed afterwards, so its dictionaries, ids and indexes are
+/// all fresh. Triples that were added and later removed don't appear.
+pub async fn squash(head: &SyncStoreLayer, output: &Path) -> io::Result<SquashSummary> {
+    let mut layers = 1;
+    let mut layer = head.clone();
+    while let Some(parent) = layer.parent()? {
+        layers += 1;
+        layer = parent;
+    }
+
+    let scratch = atomic::temp_path(output);
+    std::fs::create_dir_all(&scratch)?;
+    let result: io::Result<SquashSummary> = async {
+        let scratch_store = open_sync_archive_store(&scratch, 512);
+        let builder = scratch_store.create_base_layer()?;
+        let mut triples = 0;
+        for triple in head.triples() {
+            builder.add_value_triple(resolve(head, &triple)?)?;
+            triples += 1;
+        }
+        let squashed = builder.commit()?;
+        let contents = tokio::fs::read(layer_path(&scratch, squashed.name())).await?;
+        atomic::write(output, contents).await?;
+
+        Ok(SquashSummary {
+            layers,
+            triples,
+            name: squashed.name(),
+        })
+    }
+    .await;
+    let _ = tokio::fs::remove_dir_all(&scratch).await;
+
+    result
+}
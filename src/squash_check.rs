@@ -0,0 +1,113 @@
+use std::io;
+
+use rand::seq::IteratorRandom;
+use sha2::{Digest, Sha256};
+use terminus_store::{
+    layer::{IdTriple, ValueTriple},
+    store::sync::SyncStoreLayer,
+    Layer,
+};
+
+use crate::rdf::ntriples_line;
+
+/// How a squashed layer compares to the chain it was squashed from.
+pub struct SquashCheck {
+    pub original_hash: String,
+    pub squashed_hash: String,
+    pub original_count: u64,
+    pub squashed_count: u64,
+    /// Number of sampled triples looked up on the other side.
+    pub spot_checks: usize,
+    /// Triples of the original missing from the squashed layer.
+    pub missing: Vec<String>,
+    /// Triples of the squashed layer the original doesn't have.
+    pub extra: Vec<String>,
+}
+
+impl SquashCheck {
+    pub fn is_equal(&self) -> bool {
+        self.original_hash == self.squashed_hash && self.missing.is_empty() && self.extra.is_empty()
+    }
+}
+
+pub fn resolve(layer: &SyncStoreLayer, triple: &IdTriple) -> io::Result<ValueTriple> {
+    layer.id_triple_to_string(triple).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "triple {} {} {} does not resolve to strings",
+                triple.subject, triple.predicate, triple.object
+            ),
+        )
+    })
+}
+
+/// Hash the current triples of a layer as strings, independent of the ids
+/// they have and of the order they come in: each triple's N-Triples line
+/// is hashed on its own and the hashes are summed. Returns the hash and
+/// the number of triples.
+pub fn canonical_hash(layer: &SyncStoreLayer) -> io::Result<(String, u64)> {
+    let mut sum = [0u64; 4];
+    let mut count = 0;
+    for triple in layer.triples() {
+        let digest = Sha256::digest(ntriples_line(&resolve(layer, &triple)?).as_bytes());
+        for (lane, chunk) in sum.iter_mut().zip(digest.chunks(8)) {
+            *lane = lane.wrapping_add(u64::from_be_bytes(chunk.try_into().unwrap()));
+        }
+        count += 1;
+    }
+    let hash = sum.iter().map(|lane| format!("{lane:016x}")).collect();
+
+    Ok((hash, count))
+}
+
+/// The triples of `from` that `to` doesn't have, checking either all of
+/// them or a random sample. Returns them with the number checked.
+fn absent(
+    from: &SyncStoreLayer,
+    to: &SyncStoreLayer,
+    sample: Option<usize>,
+) -> io::Result<(Vec<String>, usize)> {
+    let triples: Vec<IdTriple> = match sample {
+        Some(n) => from.triples().choose_multiple(&mut rand::thread_rng(), n),
+        None => from.triples().collect(),
+    };
+    let mut result = Vec::new();
+    for triple in triples.iter() {
+        let resolved = resolve(from, triple)?;
+        if !to.value_triple_exists(&resolved) {
+            result.push(ntriples_line(&resolved));
+        }
+    }
+
+    Ok((result, triples.len()))
+}
+
+/// Check that a squashed layer holds exactly the triples of the original
+/// head's chain. Both sides are hashed, and a sample of triples from each
+/// is looked up in the other. If anything disagrees, every triple is
+/// looked up so all divergent triples can be reported.
+pub fn check_squash(
+    original: &SyncStoreLayer,
+    squashed: &SyncStoreLayer,
+    samples: usize,
+) -> io::Result<SquashCheck> {
+    let (original_hash, original_count) = canonical_hash(original)?;
+    let (squashed_hash, squashed_count) = canonical_hash(squashed)?;
+    let (mut missing, checked) = absent(original, squashed, Some(samples))?;
+    let (mut extra, checked_back) = absent(squashed, original, Some(samples))?;
+    if original_hash != squashed_hash || !missing.is_empty() || !extra.is_empty() {
+        missing = absent(original, squashed, None)?.0;
+        extra = absent(squashed, original, None)?.0;
+    }
+
+    Ok(SquashCheck {
+        original_hash,
+        squashed_hash,
+        original_count,
+        squashed_count,
+        spot_checks: checked + checked_back,
+        missing,
+        extra,
+    })
+}
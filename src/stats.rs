@@ -0,0 +1,204 @@
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+};
+
+use serde_json::json;
+use terminus_store::{
+    storage::{consts::LayerFileEnum, name_to_string},
+    structure::LogArray,
+};
+
+use crate::{
+    archive::Archive,
+    atomic, deadline,
+    dict::{count_entries, DictType},
+    fsck::FileStamp,
+    output::{human_bytes, ndjson, OutputFormat},
+    store::list_layers,
+};
+
+/// Where the stats index of a store is kept.
+pub fn index_path(store: &Path) -> PathBuf {
+    let mut path = store.to_path_buf();
+    path.push(".surgery");
+    path.push("stats-index");
+    path
+}
+
+/// The number of triples a layer adds and removes, which is the length of
+/// its SpO adjacency lists.
+pub fn triple_counts(archive: &Archive) -> io::Result<(u64, u64)> {
+    let count = |file_type| -> io::Result<u64> {
+        match archive.segment(file_type)? {
+            None => Ok(0),
+            Some(nums) => Ok(LogArray::parse(nums)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+                .len() as u64),
+        }
+    };
+    Ok((
+        count(LayerFileEnum::PosSpOAdjacencyListNums)?,
+        count(LayerFileEnum::NegSpOAdjacencyListNums)?,
+    ))
+}
+
+/// Statistics of a single layer, as recorded in the index.
+pub struct LayerStats {
+    stamp: FileStamp,
+    pub parent: Option<String>,
+    pub nodes: u64,
+    pub predicates: u64,
+    pub values: u64,
+    pub added: u64,
+    pub removed: u64,
+}
+
+impl LayerStats {
+    pub fn size(&self) -> u64 {
+        self.stamp.size
+    }
+
+    async fn compute(path: &Path, stamp: FileStamp) -> io::Result<Self> {
+        let archive = Archive::open(path).await?;
+        let (added, removed) = triple_counts(&archive)?;
+        Ok(Self {
+            stamp,
+            parent: archive.parent()?.map(name_to_string),
+            nodes: count_entries(&archive, DictType::Nodes).await?,
+            predicates: count_entries(&archive, DictType::Predicates).await?,
+            values: count_entries(&archive, DictType::Values).await?,
+            added,
+            removed,
+        })
+    }
+
+    fn parse(line: &str) -> Option<(String, Self)> {
+        let fields: Vec<_> = line.split(' ').collect();
+        let [name, mtime, size, parent, nodes, predicates, values, added, removed] = fields[..]
+        else {
+            return None;
+        };
+        Some((
+            name.to_string(),
+            Self {
+                stamp: FileStamp {
+                    mtime: mtime.parse().ok()?,
+                    size: size.parse().ok()?,
+                },
+                parent: (parent != "-").then(|| parent.to_string()),
+                nodes: nodes.parse().ok()?,
+                predicates: predicates.parse().ok()?,
+                values: values.parse().ok()?,
+                added: added.parse().ok()?,
+                removed: removed.parse().ok()?,
+            },
+        ))
+    }
+
+    fn to_line(&self, name: &str) -> String {
+        format!(
+            "{name} {} {} {} {} {} {} {} {}\n",
+            self.stamp.mtime,
+            self.stamp.size,
+            self.parent.as_deref().unwrap_or("-"),
+            self.nodes,
+            self.predicates,
+            self.values,
+            self.added,
+            self.removed
+        )
+    }
+}
+
+/// Load the stats index of a store. A missing index is empty, and
+/// malformed lines are ignored.
+pub async fn load(store: &Path) -> io::Result<BTreeMap<String, LayerStats>> {
+    match tokio::fs::read_to_string(index_path(store)).await {
+        Ok(contents) => Ok(contents.lines().filter_map(LayerStats::parse).collect()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Bring the stats index of a store up to date. Only layers that are new
+/// or changed on disk since the last build are read; layers that no longer
+/// exist are dropped. Past the deadline, no more layers are read, and the
+/// ones not reached keep whatever entry they had.
+pub async fn build(store: &Path) -> io::Result<()> {
+    let mut index = load(store).await?;
+    let mut updated = BTreeMap::new();
+    let mut scanned = 0;
+    let layers = list_layers(store).await?;
+    let mut reached = layers.len();
+    for (i, (name, path)) in layers.iter().enumerate() {
+        let name = name_to_string(*name);
+        if reached == layers.len() && deadline::expired() {
+            reached = i;
+            eprintln!("{}", deadline::stopped(i, layers.len() - i, "layers"));
+        }
+        if i >= reached {
+            if let Some(stats) = index.remove(&name) {
+                updated.insert(name, stats);
+            }
+            continue;
+        }
+        let stamp = FileStamp::of(path).await?;
+        let stats = match index.remove(&name) {
+            Some(stats) if stats.stamp == stamp => stats,
+            _ => {
+                scanned += 1;
+                LayerStats::compute(path, stamp).await?
+            }
+        };
+        updated.insert(name, stats);
+    }
+
+    let path = index_path(store);
+    tokio::fs::create_dir_all(path.parent().unwrap()).await?;
+    let contents: String = updated
+        .iter()
+        .map(|(name, stats)| stats.to_line(name))
+        .collect();
+    atomic::write(&path, contents).await?;
+    eprintln!(
+        "indexed {} layers, {scanned} scanned, {} dropped",
+        updated.len(),
+        index.len()
+    );
+
+    Ok(())
+}
+
+/// Print the stats index of a store without touching any layer.
+pub async fn show(store: &Path, format: OutputFormat) -> io::Result<()> {
+    for (name, stats) in load(store).await? {
+        match format {
+            OutputFormat::Pretty => println!(
+                "{name}  {:>10}  +{} -{} triples  {} nodes+values  {} predicates",
+                human_bytes(stats.size() as usize),
+                stats.added,
+                stats.removed,
+                stats.nodes + stats.values,
+                stats.predicates
+            ),
+            OutputFormat::Text => print!("{}", stats.to_line(&name)),
+            OutputFormat::Ndjson => println!(
+                "{}",
+                ndjson(json!({
+                    "layer": name,
+                    "size": stats.size(),
+                    "parent": stats.parent,
+                    "nodes": stats.nodes,
+                    "predicates": stats.predicates,
+                    "values": stats.values,
+                    "added": stats.added,
+                    "removed": stats.removed,
+                }))
+            ),
+        }
+    }
+
+    Ok(())
+}
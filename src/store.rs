@@ -0,0 +1,318 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        OnceLock,
+    },
+    time::SystemTime,
+};
+
+use terminus_store::storage::{name_to_string, string_to_name};
+
+use crate::{
+    archive::Archive,
+    atomic,
+    cache::LayerCache,
+    stub::{self, stub_path},
+};
+
+/// Whether we're attached to a store that a live server may be writing to.
+static ATTACHED: AtomicBool = AtomicBool::new(false);
+static SNAPSHOT: OnceLock<Snapshot> = OnceLock::new();
+/// Further stores searched, in order, for layers and labels that the store
+/// a command was given doesn't have.
+static OVERLAYS: OnceLock<Vec<PathBuf>> = OnceLock::new();
+/// Where archives that stubs point at are cached, if anywhere.
+static CACHE: OnceLock<LayerCache> = OnceLock::new();
+
+/// The state of a live store at the moment we first looked at it. Labels
+/// are only read once, and layers that appear later are ignored, so a
+/// whole command sees one consistent view of the store.
+struct Snapshot {
+    taken: SystemTime,
+    labels: HashMap<String, Option<[u32; 5]>>,
+}
+
+/// Treat the store as being written to concurrently. Only read-only
+/// commands should run in this mode.
+pub fn attach() {
+    ATTACHED.store(true, Ordering::Relaxed);
+}
+
+pub fn is_attached() -> bool {
+    ATTACHED.load(Ordering::Relaxed)
+}
+
+/// Search further stores for layers and labels missing from the primary
+/// store. Only read-only commands should do this, as anything written goes
+/// to the primary store.
+pub fn set_overlays(stores: Vec<PathBuf>) {
+    let _ = OVERLAYS.set(stores);
+}
+
+/// Read archives that stubs point at through a local cache, rather than
+/// from wherever they were moved to.
+pub fn set_cache(cache: LayerCache) {
+    let _ = CACHE.set(cache);
+}
+
+/// The archive a stub points at, or the stub itself if it can't be
+/// followed, so that opening it fails.
+fn follow_stub(path: PathBuf) -> PathBuf {
+    match stub::follow(&path, CACHE.get()) {
+        Ok(archive) => archive,
+        Err(e) => {
+            eprintln!("can't follow stub {}: {e}", path.display());
+            path
+        }
+    }
+}
+
+/// The primary store followed by any overlays.
+fn search_path(store: &Path) -> Vec<PathBuf> {
+    let mut result = vec![store.to_path_buf()];
+    result.extend(OVERLAYS.get().into_iter().flatten().cloned());
+    result
+}
+
+/// The first store in the search path that has the given layer, or the
+/// primary store if none has it.
+pub fn store_with_layer(store: &Path, name: [u32; 5]) -> PathBuf {
+    search_path(store)
+        .into_iter()
+        .find(|s| {
+            let path = primary_layer_path(s, name);
+            path.exists() || stub_path(&path).exists()
+        })
+        .unwrap_or_else(|| store.to_path_buf())
+}
+
+/// The first store in the search path that has the given label, or the
+/// primary store if none has it.
+pub fn store_with_label(store: &Path, label: &str) -> PathBuf {
+    search_path(store)
+        .into_iter()
+        .find(|s| label_path(s, label).exists())
+        .unwrap_or_else(|| store.to_path_buf())
+}
+
+fn snapshot(store: &Path) -> Option<&'static Snapshot> {
+    if !is_attached() {
+        return None;
+    }
+    Some(SNAPSHOT.get_or_init(|| {
+        let taken = SystemTime::now();
+        let mut labels = HashMap::new();
+        for entry in std::fs::read_dir(store).into_iter().flatten().flatten() {
+            let path = entry.path();
+            if path.extension().map(|e| e == "label") != Some(true) {
+                continue;
+            }
+            // labels being rewritten right now are skipped
+            let label = path.file_stem().and_then(|s| s.to_str());
+            let contents = std::fs::read_to_string(&path);
+            if let (Some(label), Ok(contents)) = (label, contents) {
+                if let Ok(head) = parse_label(&contents) {
+                    labels.insert(label.to_string(), head);
+                }
+            }
+        }
+
+        Snapshot { taken, labels }
+    }))
+}
+
+/// The head of a label as recorded in the snapshot of an attached store,
+/// or None when not attached.
+pub fn attached_label(store: &Path, label: &str) -> Option<io::Result<Option<[u32; 5]>>> {
+    snapshot(store).map(|snapshot| {
+        snapshot.labels.get(label).copied().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("label {label} not found"),
+            )
+        })
+    })
+}
+
+/// Path at which a directory archive store keeps the given layer. With
+/// overlays, this is the path in the first store that has the layer. A
+/// layer that was moved elsewhere is found through its stub.
+pub fn layer_path(store: &Path, name: [u32; 5]) -> PathBuf {
+    let path = primary_layer_path(&store_with_layer(store, name), name);
+    let stub = stub_path(&path);
+    if !path.exists() && stub.exists() {
+        return follow_stub(stub);
+    }
+    path
+}
+
+fn primary_layer_path(store: &Path, name: [u32; 5]) -> PathBuf {
+    let name = name_to_string(name);
+    let mut path = store.to_path_buf();
+    path.push(&name[..3]);
+    path.push(format!("{name}.larch"));
+    path
+}
+
+/// Read the layer a label points at. Label files consist of a version line
+/// followed by the layer name, which is empty for a label without a head.
+pub async fn read_label(store: &Path, label: &str) -> io::Result<Option<[u32; 5]>> {
+    if let Some(head) = attached_label(store, label) {
+        return head;
+    }
+    let store = store_with_label(store, label);
+    parse_label(&tokio::fs::read_to_string(label_path(&store, label)).await?)
+}
+
+pub fn label_path(store: &Path, label: &str) -> PathBuf {
+    let mut path = store.to_path_buf();
+    path.push(format!("{label}.label"));
+    path
+}
+
+/// Point an existing label at a different layer. The label's version is
+/// bumped, and the new contents are written to a temporary file which is
+/// then renamed over the label, so readers never see a partial label.
+pub async fn write_label(store: &Path, label: &str, head: Option<[u32; 5]>) -> io::Result<()> {
+    write_label_with_history(store, label, head, &[]).await
+}
+
+/// Like `write_label`, but also record previous heads after the head,
+/// most recent first.
+pub async fn write_label_with_history(
+    store: &Path,
+    label: &str,
+    head: Option<[u32; 5]>,
+    history: &[[u32; 5]],
+) -> io::Result<()> {
+    let path = label_path(store, label);
+    let contents = tokio::fs::read_to_string(&path).await?;
+    let version: u64 = contents
+        .lines()
+        .next()
+        .and_then(|v| v.trim().parse().ok())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("label {label} has no valid version line"),
+            )
+        })?;
+    let head = head.map(name_to_string).unwrap_or_default();
+    let mut new_contents = format!("{}\n{head}\n", version + 1);
+    for previous in history {
+        new_contents.push_str(&format!("{}\n", name_to_string(*previous)));
+    }
+    atomic::write(&path, new_contents).await
+}
+
+pub fn parse_label(contents: &str) -> io::Result<Option<[u32; 5]>> {
+    match contents.lines().nth(1) {
+        None | Some("") => Ok(None),
+        Some(layer) => Ok(Some(string_to_name(layer)?)),
+    }
+}
+
+/// The previous heads some replication tools record in a label file after
+/// the head, most recent first. Plain labels have none.
+pub fn parse_label_history(contents: &str) -> io::Result<Vec<[u32; 5]>> {
+    contents
+        .lines()
+        .skip(2)
+        .filter(|l| !l.is_empty())
+        .map(string_to_name)
+        .collect()
+}
+
+/// List the labels of a store and its overlays, sorted by name.
+pub async fn list_labels(store: &Path) -> io::Result<Vec<String>> {
+    if let Some(snapshot) = snapshot(store) {
+        let mut result: Vec<_> = snapshot.labels.keys().cloned().collect();
+        result.sort();
+        return Ok(result);
+    }
+    let mut result = Vec::new();
+    for store in search_path(store) {
+        let mut files = tokio::fs::read_dir(store).await?;
+        while let Some(file) = files.next_entry().await? {
+            let path = file.path();
+            if path.extension().map(|e| e == "label") != Some(true) {
+                continue;
+            }
+            if let Some(name) = path.file_stem().and_then(|s| s.to_str()) {
+                result.push(name.to_string());
+            }
+        }
+    }
+    result.sort();
+    result.dedup();
+
+    Ok(result)
+}
+
+/// Follow parent references from a layer down to its base layer. The
+/// result starts at the given layer.
+pub async fn chain(store: &Path, layer: [u32; 5]) -> io::Result<Vec<([u32; 5], PathBuf)>> {
+    let mut result = Vec::new();
+    let mut current = Some(layer);
+    while let Some(name) = current {
+        let path = layer_path(store, name);
+        current = Archive::open(&path).await?.parent()?;
+        result.push((name, path));
+    }
+
+    Ok(result)
+}
+
+/// List all layer archives in a directory archive store and its overlays,
+/// sorted by name. A layer present in several stores is listed once, from
+/// the first store that has it.
+///
+/// When attached to a live store, layers written after the snapshot was
+/// taken are left out, as they may still be incomplete.
+pub async fn list_layers(store: &Path) -> io::Result<Vec<([u32; 5], PathBuf)>> {
+    let snapshot = snapshot(store);
+    let mut result = Vec::new();
+    let mut seen = HashSet::new();
+    for store in search_path(store) {
+        let mut dirs = tokio::fs::read_dir(store).await?;
+        while let Some(dir) = dirs.next_entry().await? {
+            if !dir.file_type().await?.is_dir() {
+                continue;
+            }
+            let mut files = tokio::fs::read_dir(dir.path()).await?;
+            while let Some(file) = files.next_entry().await? {
+                let mut path = file.path();
+                match path.extension().and_then(|e| e.to_str()) {
+                    Some("larch") => {}
+                    // a stub stands in for a layer that was moved elsewhere
+                    Some("stub") if !path.with_extension("larch").exists() => {}
+                    _ => continue,
+                }
+                if let Some(snapshot) = snapshot {
+                    match file.metadata().await.and_then(|m| m.modified()) {
+                        Ok(modified) if modified <= snapshot.taken => {}
+                        _ => continue,
+                    }
+                }
+                if let Some(name) = path
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .and_then(|s| string_to_name(s).ok())
+                {
+                    if seen.insert(name) {
+                        if path.extension().map(|e| e == "stub") == Some(true) {
+                            path = follow_stub(path);
+                        }
+                        result.push((name, path));
+                    }
+                }
+            }
+        }
+    }
+    result.sort();
+
+    Ok(result)
+}
@@ -0,0 +1,90 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::cache::LayerCache;
+
+/// First line of every stub file.
+const STUB_MAGIC: &str = "surgery-stub 1";
+
+/// A small file left in place of a layer archive that was moved elsewhere,
+/// recording where it went and how to recognize it.
+pub struct Stub {
+    pub location: PathBuf,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// Where the stub for a layer archive lives: next to it, with a `.stub`
+/// extension in place of `.larch`.
+pub fn stub_path(layer_path: &Path) -> PathBuf {
+    layer_path.with_extension("stub")
+}
+
+impl Stub {
+    pub fn to_text(&self) -> String {
+        format!(
+            "{STUB_MAGIC}\nlocation {}\nsize {}\nsha256 {}\n",
+            self.location.display(),
+            self.size,
+            self.sha256
+        )
+    }
+
+    pub fn parse(contents: &str) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let mut lines = contents.lines();
+        if lines.next() != Some(STUB_MAGIC) {
+            return Err(invalid("not a layer stub".to_string()));
+        }
+        let (mut location, mut size, mut sha256) = (None, None, None);
+        for line in lines {
+            match line.split_once(' ') {
+                Some(("location", value)) => location = Some(PathBuf::from(value)),
+                Some(("size", value)) => {
+                    size = Some(
+                        value
+                            .parse()
+                            .map_err(|_| invalid(format!("invalid stub size {value}")))?,
+                    )
+                }
+                Some(("sha256", value)) => sha256 = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        match (location, size, sha256) {
+            (Some(location), Some(size), Some(sha256)) => Ok(Self {
+                location,
+                size,
+                sha256,
+            }),
+            _ => Err(invalid(
+                "stub is missing its location, size or sha256".to_string(),
+            )),
+        }
+    }
+}
+
+/// Follow a stub to the archive it points at. With a cache, the archive
+/// is read through it. Without one, only the size of the archive is
+/// checked.
+pub fn follow(path: &Path, cache: Option<&LayerCache>) -> io::Result<PathBuf> {
+    let stub = Stub::parse(&std::fs::read_to_string(path)?)?;
+    match cache {
+        Some(cache) => cache.get(&stub.location, &stub.sha256),
+        None => {
+            if std::fs::metadata(&stub.location)?.len() != stub.size {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "{} doesn't match its stub {}",
+                        stub.location.display(),
+                        path.display()
+                    ),
+                ));
+            }
+            Ok(stub.location)
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use std::{
+    collections::HashSet,
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use sha2::{Digest, Sha256};
+use terminus_store::storage::name_to_string;
+
+use crate::{
+    atomic,
+    audit::Audit,
+    pins,
+    store::{chain, list_labels, list_layers, read_label},
+    stub::{stub_path, Stub},
+};
+
+/// Layers that are worth keeping on fast storage: the `keep_depth` most
+/// recent layers of every label's chain, and every pinned layer.
+async fn hot_layers(store: &Path, keep_depth: usize) -> io::Result<HashSet<[u32; 5]>> {
+    let mut hot: HashSet<_> = pins::load(store).await?.into_keys().collect();
+    for label in list_labels(store).await? {
+        if let Some(head) = read_label(store, &label).await? {
+            let layers = chain(store, head).await?;
+            hot.extend(layers.into_iter().take(keep_depth).map(|(name, _)| name));
+        }
+    }
+
+    Ok(hot)
+}
+
+/// Layers that can move to cold storage: not hot, and not modified for at
+/// least `older_than`.
+pub async fn candidates(
+    store: &Path,
+    older_than: Duration,
+    keep_depth: usize,
+) -> io::Result<Vec<([u32; 5], PathBuf)>> {
+    let hot = hot_layers(store, keep_depth).await?;
+    let cutoff = SystemTime::now() - older_than;
+    let mut result = Vec::new();
+    for (name, path) in list_layers(store).await? {
+        // layers already moved are listed at their new location
+        if hot.contains(&name) || !path.starts_with(store) {
+            continue;
+        }
+        if tokio::fs::metadata(&path).await?.modified()? <= cutoff {
+            result.push((name, path));
+        }
+    }
+
+    Ok(result)
+}
+
+/// Move a layer archive into `target`, laid out like a directory archive
+/// store, and leave a stub pointing at it. The copy is read back and
+/// compared before the original is removed.
+pub async fn migrate(name: [u32; 5], path: &Path, target: &Path) -> io::Result<Stub> {
+    let contents = tokio::fs::read(path).await?;
+    let sha256 = format!("{:x}", Sha256::digest(&contents));
+    let name = name_to_string(name);
+    let mut location = target.to_path_buf();
+    location.push(&name[..3]);
+    tokio::fs::create_dir_all(&location).await?;
+    location.push(format!("{name}.larch"));
+    atomic::write(&location, &contents).await?;
+
+    let copied = tokio::fs::read(&location).await?;
+    if format!("{:x}", Sha256::digest(&copied)) != sha256 {
+        let _ = tokio::fs::remove_file(&location).await;
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("copy of {name} at {} doesn't match", location.display()),
+        ));
+    }
+
+    let stub = Stub {
+        location: location.canonicalize()?,
+        size: contents.len() as u64,
+        sha256,
+    };
+    atomic::write(stub_path(path), stub.to_text()).await?;
+    tokio::fs::remove_file(path).await?;
+
+    Ok(stub)
+}
+
+/// Move every candidate layer of a store to `target`, recording the moves
+/// in the audit log.
+pub async fn tier(
+    store: &Path,
+    target: &Path,
+    older_than: Duration,
+    keep_depth: usize,
+    dry_run: bool,
+) -> io::Result<()> {
+    if target.to_string_lossy().contains("://") {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "remote tiers aren't supported, give a directory such as a mounted cold volume",
+        ));
+    }
+    let layers = candidates(store, older_than, keep_depth).await?;
+    if dry_run {
+        for (name, _) in layers.iter() {
+            println!("{}", name_to_string(*name));
+        }
+        return Ok(());
+    }
+
+    let mut audit = Audit::begin("tier");
+    for (_, path) in layers.iter() {
+        audit.track(path).await?;
+        audit.track(&stub_path(path)).await?;
+    }
+    for (name, path) in layers.iter() {
+        let stub = migrate(*name, path, target).await?;
+        println!("{} -> {}", name_to_string(*name), stub.location.display());
+    }
+    audit.commit(store).await
+}
@@ -0,0 +1,169 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+};
+
+use clap::Args;
+use serde_json::{json, Value};
+use terminus_store::{
+    layer::{IdTriple, ObjectType, ValueTriple},
+    storage::name_to_string,
+    store::sync::SyncStoreLayer,
+    Layer,
+};
+
+use crate::{rdf::ntriples_object, values};
+
+/// Paging options shared by the commands that stream triples.
+#[derive(Args)]
+pub struct PageArgs {
+    /// Stop after this many triples
+    #[arg(long)]
+    pub limit: Option<usize>,
+    /// Skip this many triples before output starts
+    #[arg(long, default_value_t = 0)]
+    pub skip: usize,
+    /// Start after the given id triple, written as `s,p,o`
+    #[arg(long, value_parser = parse_cursor)]
+    pub after: Option<(u64, u64, u64)>,
+}
+
+fn parse_cursor(s: &str) -> Result<(u64, u64, u64), String> {
+    let ids: Vec<_> = s
+        .split(',')
+        .map(|id| id.trim().parse::<u64>().map_err(|e| e.to_string()))
+        .collect::<Result<_, _>>()?;
+    match ids[..] {
+        [s, p, o] => Ok((s, p, o)),
+        _ => Err("expected a cursor of the form s,p,o".to_string()),
+    }
+}
+
+impl PageArgs {
+    /// Restrict a stream of triples in id order to the requested page.
+    pub fn apply<'a, I: Iterator<Item = IdTriple> + 'a>(
+        &self,
+        triples: I,
+    ) -> impl Iterator<Item = IdTriple> + 'a {
+        let after = self.after;
+        triples
+            .skip_while(move |t| match after {
+                Some(cursor) => (t.subject, t.predicate, t.object) <= cursor,
+                None => false,
+            })
+            .skip(self.skip)
+            .take(self.limit.unwrap_or(usize::MAX))
+    }
+
+    /// Tell the user how to continue if the page was cut off by the limit.
+    pub fn report_cursor(&self, emitted: usize, last: Option<IdTriple>) {
+        if let (Some(limit), Some(last)) = (self.limit, last) {
+            if emitted == limit {
+                eprintln!(
+                    "resume with --after {},{},{}",
+                    last.subject, last.predicate, last.object
+                );
+            }
+        }
+    }
+}
+
+/// Render an object the way it would appear in an N-Triples line.
+pub fn format_object(object: &ObjectType) -> String {
+    ntriples_object(object)
+}
+
+/// Render an object as JSON, keeping nodes and values apart.
+pub fn object_json(object: &ObjectType) -> Value {
+    match object {
+        ObjectType::Node(node) => json!({ "node": node }),
+        ObjectType::Value(value) => json!({
+            "value": String::from_utf8_lossy(&value.to_bytes()),
+            "datatype": format!("{:?}", value.datatype()),
+        }),
+    }
+}
+
+/// Resolve a triple the layer itself gave out, which only fails if its
+/// dictionaries don't hold the ids its adjacency lists refer to.
+pub fn resolve(layer: &SyncStoreLayer, triple: &IdTriple) -> io::Result<ValueTriple> {
+    layer.id_triple_to_string(triple).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "layer {}: triple {triple:?} refers to ids missing from its dictionaries",
+                name_to_string(layer.name())
+            ),
+        )
+    })
+}
+
+/// The layer closest to the head that added each of the given triples of
+/// a subject.
+pub fn contributors(
+    layer: &SyncStoreLayer,
+    subject: u64,
+    triples: &[IdTriple],
+) -> io::Result<HashMap<(u64, u64, u64), String>> {
+    let mut wanted: HashSet<_> = triples
+        .iter()
+        .map(|t| (t.subject, t.predicate, t.object))
+        .collect();
+    let mut result = HashMap::new();
+    let mut current = Some(layer.clone());
+    while let Some(layer) = current {
+        if wanted.is_empty() {
+            break;
+        }
+        for t in layer.triple_additions_s(subject) {
+            if wanted.remove(&(t.subject, t.predicate, t.object)) {
+                result.insert(
+                    (t.subject, t.predicate, t.object),
+                    name_to_string(layer.name()),
+                );
+            }
+        }
+        current = layer.parent()?;
+    }
+    Ok(result)
+}
+
+/// The triples of a subject, each with the name of the layer that added
+/// it if `annotate` is set. None if the layer has no such subject.
+pub fn subject_triples(
+    layer: &SyncStoreLayer,
+    subject: &str,
+    annotate: bool,
+) -> io::Result<Option<Vec<(ValueTriple, Option<String>)>>> {
+    let id = match layer.subject_id(subject) {
+        Some(id) => id,
+        None => return Ok(None),
+    };
+    let triples: Vec<_> = layer.triples_s(id).collect();
+    let mut contributors = if annotate {
+        contributors(layer, id, &triples)?
+    } else {
+        HashMap::new()
+    };
+    triples
+        .iter()
+        .map(|triple| {
+            let added_in = contributors.remove(&(triple.subject, triple.predicate, triple.object));
+            Ok((resolve(layer, triple)?, added_in))
+        })
+        .collect::<io::Result<_>>()
+        .map(Some)
+}
+
+/// The triples of a layer's chain whose object is a value containing
+/// `needle`.
+pub fn with_value_containing(layer: &SyncStoreLayer, needle: &str) -> io::Result<Vec<ValueTriple>> {
+    let mut result = Vec::new();
+    for id in values::containing(layer, needle) {
+        for triple in layer.triples_o(id) {
+            result.push(resolve(layer, &triple)?);
+        }
+    }
+
+    Ok(result)
+}
@@ -0,0 +1,115 @@
+use std::{fmt, io};
+
+use terminus_store::{
+    storage::consts::LayerFileEnum,
+    structure::{bitarray::BitArray, LogArray},
+};
+
+use crate::{
+    archive::{parse_layer_name, Archive},
+    codes::FindingCode,
+};
+
+/// The encoding used by a segment, as far as validation is concerned.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum SegmentKind {
+    DictBlocks,
+    LogArray,
+    BitArray,
+    Parent,
+    Rollup,
+}
+
+/// Classify a segment by its header name. Every segment that is not a
+/// dictionary block, a bit array or a layer reference is a logarray
+/// (this includes bitindex blocks/sblocks and dictionary offsets).
+pub fn segment_kind(file_type: LayerFileEnum) -> SegmentKind {
+    let name = format!("{file_type:?}");
+    if file_type == LayerFileEnum::Parent {
+        SegmentKind::Parent
+    } else if file_type == LayerFileEnum::Rollup {
+        SegmentKind::Rollup
+    } else if name.ends_with("DictionaryBlocks") {
+        SegmentKind::DictBlocks
+    } else if name.ends_with("Bits") {
+        SegmentKind::BitArray
+    } else {
+        SegmentKind::LogArray
+    }
+}
+
+/// A problem found while validating an archive.
+pub struct Finding {
+    pub code: FindingCode,
+    pub segment: Option<LayerFileEnum>,
+    pub message: String,
+}
+
+impl Finding {
+    /// The finding for an archive whose header can't be parsed at all.
+    pub fn unreadable(e: io::Error) -> Self {
+        Self {
+            code: FindingCode::HeaderUnreadable,
+            segment: None,
+            message: format!("could not parse header: {e}"),
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.segment {
+            Some(segment) => write!(f, "{} {segment:?}: {}", self.code, self.message),
+            None => write!(f, "{} {}", self.code, self.message),
+        }
+    }
+}
+
+/// Check that every segment lies within the archive and that the
+/// fixed-layout segments parse.
+pub fn validate_archive(archive: &Archive) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for (file_type, _) in archive.segments() {
+        let contents = match archive.segment(file_type) {
+            Ok(contents) => contents.unwrap(),
+            Err(e) => {
+                findings.push(Finding {
+                    code: FindingCode::SegmentOutOfBounds,
+                    segment: Some(file_type),
+                    message: e.to_string(),
+                });
+                continue;
+            }
+        };
+        let empty = contents.is_empty();
+        let (code, message) = match segment_kind(file_type) {
+            SegmentKind::LogArray => (
+                FindingCode::LogArrayInvalid,
+                LogArray::parse(contents).err().map(|e| e.to_string()),
+            ),
+            SegmentKind::BitArray => (
+                FindingCode::BitArrayInvalid,
+                BitArray::from_bits(contents).err().map(|e| e.to_string()),
+            ),
+            SegmentKind::Parent => (
+                FindingCode::ParentInvalid,
+                parse_layer_name(&contents).err().map(|e| e.to_string()),
+            ),
+            _ => continue,
+        };
+        // the parsers can only say that a control word is missing
+        let message = match message {
+            Some(_) if empty => Some("present in the header but empty".to_string()),
+            message => message,
+        };
+        if let Some(message) = message {
+            findings.push(Finding {
+                code,
+                segment: Some(file_type),
+                message,
+            });
+        }
+    }
+
+    findings
+}
@@ -0,0 +1,550 @@
+use std::{
+    collections::HashMap,
+    io,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use bytes::Bytes;
+use futures::StreamExt;
+use serde_json::json;
+use terminus_store::{
+    storage::{consts::LayerFileEnum, name_to_string},
+    structure::{bitarray::BitArray, LogArray},
+};
+use tokio::io::AsyncWriteExt;
+
+use crate::{
+    adjacency::check_adjacency,
+    archive::{bitindex_segments, parse_layer_name, Archive},
+    dict::{block_offsets, decode_block, find_truncations, DictType},
+    fsck::{load_cache, CacheEntry, FileStamp},
+    ids::{self, IdCounts},
+    limits,
+    output::{ndjson, paint, Color, OffsetBase, OutputFormat, Progress},
+    store::list_layers,
+    validate::{segment_kind, SegmentKind},
+    verify::{bitindex_words, check_bitindex_lengths, check_sblock, BitIndexPart, SBLOCK_SIZE},
+};
+
+/// The first problem found in one segment.
+pub struct Corruption {
+    /// Absolute offset in the archive, if the problem can be pinned down.
+    pub offset: Option<usize>,
+    pub message: String,
+}
+
+/// The outcome of checking one segment.
+pub struct SegmentReport {
+    pub segment: LayerFileEnum,
+    /// Absolute range of the segment in the archive.
+    pub range: Range<usize>,
+    pub corruption: Option<Corruption>,
+}
+
+fn at(offset: usize, message: String) -> Option<Corruption> {
+    Some(Corruption {
+        offset: Some(offset),
+        message,
+    })
+}
+
+/// Bits needed to store ids up to `max`, as the layer builder sizes its
+/// logarrays.
+fn id_width(max: u64) -> u8 {
+    (64 - max.leading_zeros()).max(1) as u8
+}
+
+/// Absolute offset of the word holding a logarray entry.
+fn entry_offset(range: &Range<usize>, logarray: &LogArray, ix: usize) -> usize {
+    range.start + ix * logarray.width() as usize / 64 * 8
+}
+
+/// The highest id a segment's entries may hold: node/value ids, or
+/// predicate ids. Other logarrays hold positions or counts.
+fn max_id(segment: LayerFileEnum, ids: IdCounts) -> Option<u64> {
+    let name = format!("{segment:?}");
+    if name.ends_with("Subjects")
+        || name.ends_with("Objects")
+        || name.ends_with("SpOAdjacencyListNums")
+    {
+        Some(ids.nodes_values)
+    } else if name.ends_with("SPAdjacencyListNums") {
+        Some(ids.predicates)
+    } else {
+        None
+    }
+}
+
+fn check_logarray(
+    segment: LayerFileEnum,
+    range: &Range<usize>,
+    contents: Bytes,
+    ids: Option<IdCounts>,
+) -> Option<Corruption> {
+    let logarray = match LogArray::parse(contents) {
+        Ok(logarray) => logarray,
+        Err(e) => return at(range.end.saturating_sub(8), format!("control word: {e}")),
+    };
+    let max = max_id(segment, ids?)?;
+    // adjacency lists are sized for the whole id space
+    if format!("{segment:?}").ends_with("AdjacencyListNums") && logarray.width() != id_width(max) {
+        return at(
+            range.end - 8,
+            format!(
+                "width {} where the dictionaries' {max} ids take {} bits",
+                logarray.width(),
+                id_width(max)
+            ),
+        );
+    }
+    let ix = logarray.iter().position(|id| id > max)?;
+    at(
+        entry_offset(range, &logarray, ix),
+        format!(
+            "entry {ix} is id {}, past the last id {max}",
+            logarray.entry(ix)
+        ),
+    )
+}
+
+async fn check_dict(t: DictType, archive: &Archive, range: &Range<usize>) -> Option<Corruption> {
+    let blocks = archive.segment(t.blocks_segment()).ok()??;
+    let offsets = match block_offsets(archive, t) {
+        Ok(offsets) => offsets,
+        Err(e) => {
+            return Some(Corruption {
+                offset: None,
+                message: format!("block offsets: {e}"),
+            })
+        }
+    };
+    let mut last: Option<Bytes> = None;
+    for ix in 0..offsets.len() {
+        let start = offsets[ix];
+        let end = offsets.get(ix + 1).copied().unwrap_or(blocks.len());
+        if start >= end || end > blocks.len() {
+            return at(
+                range.start + start.min(blocks.len()),
+                format!("block {ix} spans {start}..{end} of {} bytes", blocks.len()),
+            );
+        }
+        let entries = match decode_block(&blocks, &offsets, ix, usize::MAX).await {
+            Ok(entries) => entries,
+            Err(e) => return at(range.start + start, e.to_string()),
+        };
+        for entry in entries {
+            if last.as_ref().map(|last| &entry <= last).unwrap_or(false) {
+                return at(
+                    range.start + start,
+                    format!("block {ix} breaks the sort order of entries"),
+                );
+            }
+            last = Some(entry);
+        }
+    }
+    if t == DictType::Values {
+        let truncation = find_truncations(archive, t).ok()?.into_iter().next()?;
+        return at(
+            range.start + offsets[truncation.block],
+            format!(
+                "block {} entry {}: {}",
+                truncation.block, truncation.entry, truncation.message
+            ),
+        );
+    }
+
+    None
+}
+
+fn check_bitindex(
+    archive: &Archive,
+    (bits_type, blocks_type, sblocks_type): (LayerFileEnum, LayerFileEnum, LayerFileEnum),
+    segment: LayerFileEnum,
+    range: &Range<usize>,
+) -> Option<Corruption> {
+    let bits = archive.segment(bits_type).ok()??;
+    let blocks = LogArray::parse(archive.segment(blocks_type).ok()??).ok()?;
+    let sblocks = LogArray::parse(archive.segment(sblocks_type).ok()??).ok()?;
+    if bits.len() < 8 {
+        return None;
+    }
+    let part = if segment == blocks_type {
+        BitIndexPart::Blocks
+    } else {
+        BitIndexPart::SBlocks
+    };
+    let words = bitindex_words(&bits);
+    if let Some((wrong, message)) = check_bitindex_lengths(words, &blocks, &sblocks) {
+        return if wrong == part {
+            at(range.end - 8, message)
+        } else {
+            None
+        };
+    }
+
+    let mismatch = (0..words.div_ceil(SBLOCK_SIZE))
+        .flat_map(|j| check_sblock(&bits, &blocks, &sblocks, j, None))
+        .find(|m| m.part == part)?;
+    let logarray = match part {
+        BitIndexPart::Blocks => &blocks,
+        BitIndexPart::SBlocks => &sblocks,
+    };
+    at(
+        entry_offset(range, logarray, mismatch.index),
+        mismatch.message(),
+    )
+}
+
+/// Check an adjacency list's nums against its bits, reported on the nums
+/// segment.
+fn check_adjacency_list(archive: &Archive, segment: LayerFileEnum) -> Option<Corruption> {
+    let name = format!("{segment:?}");
+    let prefix = name.strip_suffix("AdjacencyListNums")?;
+    let bits_name = format!("{prefix}AdjacencyListBits");
+    let bits_type = archive
+        .segments()
+        .into_iter()
+        .map(|(t, _)| t)
+        .find(|t| format!("{t:?}") == bits_name)?;
+    let nums = archive.segment(segment).ok()??;
+    let bits = archive.segment(bits_type).ok()??;
+    let message = check_adjacency(nums, bits, !prefix.contains("SpO"))
+        .into_iter()
+        .next()?;
+    Some(Corruption {
+        offset: None,
+        message,
+    })
+}
+
+/// Check every segment of an archive on its own and against the others:
+/// control words, dictionaries decoding in order, bitindexes counting
+/// their bits, adjacency lists agreeing with their bits, and ids lying
+/// within the dictionaries. `ids` is the size of the layer's id spaces,
+/// including those of its ancestors. Without it, ids aren't checked.
+/// Reports the first problem per segment.
+pub async fn validate_layer(archive: &Archive, ids: Option<IdCounts>) -> Vec<SegmentReport> {
+    let header_len = archive.header_len();
+    let mut reports = Vec::new();
+    for (segment, relative) in archive.segments() {
+        let range = relative.start + header_len..relative.end + header_len;
+        let corruption = match archive.segment(segment) {
+            Err(e) => at(archive.contents().len(), e.to_string()),
+            Ok(None) => None,
+            Ok(Some(contents)) => match segment_kind(segment) {
+                SegmentKind::Parent => parse_layer_name(&contents)
+                    .err()
+                    .and_then(|e| at(range.start, e.to_string())),
+                SegmentKind::Rollup => None,
+                SegmentKind::BitArray => BitArray::from_bits(contents)
+                    .err()
+                    .and_then(|e| at(range.end.saturating_sub(8), format!("control word: {e}"))),
+                SegmentKind::DictBlocks => {
+                    let t = [DictType::Nodes, DictType::Predicates, DictType::Values]
+                        .into_iter()
+                        .find(|t| t.blocks_segment() == segment)
+                        .unwrap();
+                    check_dict(t, archive, &range).await
+                }
+                SegmentKind::LogArray => {
+                    let index = bitindex_segments()
+                        .into_iter()
+                        .find(|(_, blocks, sblocks)| *blocks == segment || *sblocks == segment);
+                    check_logarray(segment, &range, contents, ids)
+                        .or_else(|| check_adjacency_list(archive, segment))
+                        .or_else(|| {
+                            index.and_then(|index| check_bitindex(archive, index, segment, &range))
+                        })
+                }
+            },
+        };
+        reports.push(SegmentReport {
+            segment,
+            range,
+            corruption,
+        });
+    }
+
+    reports
+}
+
+/// The problem with an archive whose header can't be parsed.
+pub fn unreadable(e: io::Error) -> Corruption {
+    Corruption {
+        offset: Some(0),
+        message: format!("could not parse header: {e}"),
+    }
+}
+
+/// Print one PASS or FAIL line per segment, with the offset of the first
+/// problem in each failing one.
+pub fn print_reports(reports: &[SegmentReport], offsets: OffsetBase, format: OutputFormat) {
+    for report in reports {
+        let name = format!("{:?}", report.segment);
+        let range = format!(
+            "{}..{}",
+            offsets.format(report.range.start),
+            offsets.format(report.range.end)
+        );
+        match (&report.corruption, format) {
+            (corruption, OutputFormat::Ndjson) => println!(
+                "{}",
+                ndjson(json!({
+                    "segment": name,
+                    "start": report.range.start,
+                    "end": report.range.end,
+                    "status": if corruption.is_some() { "fail" } else { "pass" },
+                    "offset": corruption.as_ref().and_then(|c| c.offset),
+                    "message": corruption.as_ref().map(|c| c.message.clone()),
+                }))
+            ),
+            (None, OutputFormat::Pretty) => {
+                println!("{}  {name:<40} {range}", paint("PASS", Color::Green))
+            }
+            (None, _) => println!("PASS {name} {range}"),
+            (Some(corruption), format) => {
+                let at = match corruption.offset {
+                    Some(offset) => format!("at {}: ", offsets.format(offset)),
+                    None => String::new(),
+                };
+                if format == OutputFormat::Pretty {
+                    println!(
+                        "{}  {name:<40} {range}  {at}{}",
+                        paint("FAIL", Color::Red),
+                        corruption.message
+                    );
+                } else {
+                    println!("FAIL {name} {range}: {at}{}", corruption.message);
+                }
+            }
+        }
+    }
+}
+
+/// A layer file checked by `validate_file`.
+pub struct FileValidation {
+    pub reports: Vec<SegmentReport>,
+    /// Why ids weren't checked against the dictionaries, if they weren't.
+    pub ids_unchecked: Option<String>,
+}
+
+/// Open a layer file and validate it. The ids of a child layer depend on
+/// the dictionaries of its ancestors, so they are only checked if a store
+/// holding its chain is given. Fails with the corruption of an archive
+/// that can't be read at all.
+pub async fn validate_file(
+    path: &Path,
+    store: Option<&Path>,
+) -> Result<FileValidation, Corruption> {
+    let archive = Archive::open(path).await.map_err(unreadable)?;
+    let own = ids::dict_counts(&archive).await.ok();
+    let (ids, ids_unchecked) = match (archive.parent(), store) {
+        (Err(e), _) => (None, Some(format!("parent unreadable: {e}"))),
+        (Ok(None), _) => (own, None),
+        (Ok(Some(parent)), Some(store)) => match ids::cumulative_counts(store, parent).await {
+            Ok(ancestors) => (own.map(|own| ancestors + own), None),
+            Err(e) => (None, Some(format!("ancestors unreadable: {e}"))),
+        },
+        (Ok(Some(_)), None) => (None, Some("child layer without --store".to_string())),
+    };
+
+    Ok(FileValidation {
+        reports: validate_layer(&archive, ids).await,
+        ids_unchecked,
+    })
+}
+
+/// Where validate-store records the layers it has validated, so that a
+/// later run can resume.
+pub fn progress_path(store: &Path) -> PathBuf {
+    let mut path = store.to_path_buf();
+    path.push(".surgery");
+    path.push("validate-store-progress");
+    path
+}
+
+/// How many layers of a store were validated, and how many failed.
+pub struct StoreValidation {
+    pub validated: usize,
+    /// Layers left alone because an earlier run validated them as they
+    /// are now.
+    pub resumed: usize,
+    pub failed: usize,
+}
+
+/// The problems of a validated layer, with the segment each is in.
+fn failures(
+    result: &Result<FileValidation, Corruption>,
+) -> Vec<(Option<LayerFileEnum>, &Corruption)> {
+    match result {
+        Err(corruption) => vec![(None, corruption)],
+        Ok(validation) => validation
+            .reports
+            .iter()
+            .filter_map(|r| r.corruption.as_ref().map(|c| (Some(r.segment), c)))
+            .collect(),
+    }
+}
+
+async fn open_log(path: &Path, resume: bool) -> io::Result<tokio::fs::File> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    tokio::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(resume)
+        .truncate(!resume)
+        .open(path)
+        .await
+}
+
+/// Validate every layer of a store as `validate_file` does, `jobs` at a
+/// time, with a progress bar on stderr. Each problem is printed, and with
+/// `report`, also written there as an ndjson record. Validated layers are
+/// recorded as they finish; with `resume`, those recorded by an earlier
+/// run that haven't changed since are skipped, and the report is appended
+/// to rather than replaced. Ids are only checked with `check_ids`, as that
+/// reads the dictionaries of every child layer's ancestors.
+pub async fn validate_store(
+    store: &Path,
+    jobs: usize,
+    check_ids: bool,
+    report: Option<&Path>,
+    resume: bool,
+    format: OutputFormat,
+) -> io::Result<StoreValidation> {
+    let progress = progress_path(store);
+    let previous = if resume {
+        load_cache(&progress).await?
+    } else {
+        HashMap::new()
+    };
+    let layers = list_layers(store).await?;
+    let mut result = StoreValidation {
+        validated: 0,
+        resumed: 0,
+        failed: 0,
+    };
+    let mut pending = Vec::new();
+    for (name, path) in layers.iter() {
+        let stamp = FileStamp::of(path).await?;
+        match previous.get(&name_to_string(*name)) {
+            Some(entry) if entry.stamp == stamp => {
+                result.resumed += 1;
+                if !entry.ok {
+                    result.failed += 1;
+                }
+            }
+            _ => pending.push((*name, path.clone(), stamp)),
+        }
+    }
+    let mut progress = open_log(&progress, resume).await?;
+    let mut report = match report {
+        Some(path) => Some(open_log(path, resume).await?),
+        None => None,
+    };
+
+    // every job holds a whole archive in memory
+    let largest = pending.iter().map(|(_, _, stamp)| stamp.size).max();
+    let jobs = limits::jobs(jobs, 1, largest.unwrap_or(0));
+    let ids_store = check_ids.then(|| store.to_path_buf());
+    let mut results = futures::stream::iter(pending)
+        .map(|(name, path, stamp)| {
+            let store = ids_store.clone();
+            tokio::task::spawn_blocking(move || {
+                let validation = tokio::runtime::Handle::current()
+                    .block_on(validate_file(&path, store.as_deref()));
+                (name, stamp, validation)
+            })
+        })
+        .buffer_unordered(jobs);
+    let mut bar = Progress::new(layers.len(), "layers");
+    while let Some(joined) = results.next().await {
+        let (name, stamp, validation) = joined.unwrap();
+        let name = name_to_string(name);
+        let failures = failures(&validation);
+        if !failures.is_empty() {
+            result.failed += 1;
+            bar.clear();
+        }
+        for (segment, corruption) in failures.iter() {
+            let segment = segment.map(|s| format!("{s:?}"));
+            let record = ndjson(json!({
+                "kind": "failure",
+                "layer": name,
+                "segment": segment,
+                "offset": corruption.offset,
+                "message": corruption.message,
+            }));
+            if let Some(report) = report.as_mut() {
+                report.write_all(format!("{record}\n").as_bytes()).await?;
+            }
+            let at = match corruption.offset {
+                Some(offset) => format!("at {offset}: "),
+                None => String::new(),
+            };
+            let segment = segment.as_deref().unwrap_or("header");
+            match format {
+                OutputFormat::Ndjson => println!("{record}"),
+                OutputFormat::Pretty => println!(
+                    "{}  {name}  {segment:<32} {at}{}",
+                    paint("FAIL", Color::Red),
+                    corruption.message
+                ),
+                OutputFormat::Text => {
+                    println!("FAIL {name} {segment}: {at}{}", corruption.message)
+                }
+            }
+        }
+        let entry = CacheEntry {
+            stamp,
+            ok: failures.is_empty(),
+            checks: Default::default(),
+        };
+        progress.write_all(entry.line(&name).as_bytes()).await?;
+        result.validated += 1;
+        bar.update(
+            result.resumed + result.validated,
+            &format!("{} failed", result.failed),
+        );
+    }
+    bar.clear();
+    progress.flush().await?;
+    if let Some(report) = report.as_mut() {
+        report.flush().await?;
+    }
+
+    Ok(result)
+}
+
+pub fn print_summary(result: &StoreValidation, format: OutputFormat) {
+    match format {
+        OutputFormat::Pretty => {
+            let failed = format!("{} failed", result.failed);
+            let failed = if result.failed == 0 {
+                paint(&failed, Color::Green)
+            } else {
+                paint(&failed, Color::Red)
+            };
+            println!(
+                "\n{} validated, {} validated by an earlier run, {failed}",
+                result.validated, result.resumed
+            );
+        }
+        OutputFormat::Text => println!(
+            "validated {}, resumed {}, failed {}",
+            result.validated, result.resumed, result.failed
+        ),
+        OutputFormat::Ndjson => println!(
+            "{}",
+            ndjson(json!({
+                "kind": "summary",
+                "validated": result.validated,
+                "resumed": result.resumed,
+                "failed": result.failed,
+            }))
+        ),
+    }
+}
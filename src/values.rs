@@ -0,0 +1,316 @@
+use std::{cmp::Ordering, collections::BTreeMap, fmt};
+
+use rug::Integer;
+use terminus_store::{
+    layer::ObjectType,
+    store::sync::SyncStoreLayer,
+    structure::{Decimal, FromLexical},
+    Layer,
+};
+
+use crate::rdf::{literal, turtle_literal};
+
+/// XML schema types and the names of the datatypes they are stored as.
+const XSD_TYPES: [(&str, &str); 10] = [
+    ("xsd:string", "String"),
+    ("xsd:boolean", "Boolean"),
+    ("xsd:int", "Int32"),
+    ("xsd:unsignedInt", "UInt32"),
+    ("xsd:long", "Int64"),
+    ("xsd:unsignedLong", "UInt64"),
+    ("xsd:float", "Float32"),
+    ("xsd:double", "Float64"),
+    ("xsd:integer", "BigInt"),
+    ("xsd:decimal", "Decimal"),
+];
+
+/// Map a datatype given as `xsd:name` (or a stored datatype name) to the
+/// name of the stored datatype.
+pub fn datatype_name(datatype: &str) -> String {
+    XSD_TYPES
+        .iter()
+        .find(|(xsd, _)| *xsd == datatype)
+        .map(|(_, name)| name.to_string())
+        .unwrap_or_else(|| datatype.to_string())
+}
+
+/// Map the name of a stored datatype back to its XML schema type, if it
+/// has one.
+pub fn xsd_name(datatype: &str) -> String {
+    XSD_TYPES
+        .iter()
+        .find(|(_, name)| *name == datatype)
+        .map(|(xsd, _)| xsd.to_string())
+        .unwrap_or_else(|| datatype.to_string())
+}
+
+/// A stored value decoded far enough to compare it.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Decoded {
+    Int(i128),
+    Float(f64),
+    /// An integer too large for `Int`, or a decimal, in decimal notation.
+    /// These compare exactly.
+    Decimal(String),
+    Text(String),
+}
+
+impl fmt::Display for Decoded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Decoded::Int(n) => write!(f, "{n}"),
+            Decoded::Float(n) => write!(f, "{n}"),
+            Decoded::Decimal(n) => write!(f, "{n}"),
+            Decoded::Text(s) => write!(f, "{s}"),
+        }
+    }
+}
+
+impl Decoded {
+    /// Parse a lexical form the way a value of the given datatype would
+    /// decode.
+    pub fn parse(lexical: &str, datatype: &str) -> Option<Self> {
+        match datatype {
+            "Int32" | "UInt32" | "Int64" | "UInt64" => lexical.parse().ok().map(Decoded::Int),
+            "BigInt" | "Decimal" => number(lexical),
+            "Float32" | "Float64" => lexical.parse().ok().map(Decoded::Float),
+            "Boolean" => Some(Decoded::Int((lexical == "true") as i128)),
+            _ => Some(Decoded::Text(lexical.to_string())),
+        }
+    }
+
+    pub fn compare(&self, other: &Decoded) -> Option<Ordering> {
+        match (self, other) {
+            (Decoded::Int(a), Decoded::Int(b)) => Some(a.cmp(b)),
+            (Decoded::Float(a), Decoded::Float(b)) => a.partial_cmp(b),
+            (Decoded::Int(a), Decoded::Float(b)) => (*a as f64).partial_cmp(b),
+            (Decoded::Float(a), Decoded::Int(b)) => a.partial_cmp(&(*b as f64)),
+            (Decoded::Decimal(a), Decoded::Decimal(b)) => decimal_cmp(a, b),
+            (Decoded::Decimal(a), Decoded::Int(b)) => decimal_cmp(a, &b.to_string()),
+            (Decoded::Int(a), Decoded::Decimal(b)) => decimal_cmp(&a.to_string(), b),
+            (Decoded::Decimal(a), Decoded::Float(b)) => a.parse::<f64>().ok()?.partial_cmp(b),
+            (Decoded::Float(a), Decoded::Decimal(b)) => a.partial_cmp(&b.parse::<f64>().ok()?),
+            (Decoded::Text(a), Decoded::Text(b)) => Some(a.cmp(b)),
+            _ => None,
+        }
+    }
+}
+
+/// A numeral as an `Int` if it fits, or else as a `Decimal`.
+fn number(lexical: &str) -> Option<Decoded> {
+    match lexical.parse() {
+        Ok(n) => Some(Decoded::Int(n)),
+        Err(_) => decimal_parts(lexical).map(|_| Decoded::Decimal(lexical.to_string())),
+    }
+}
+
+/// Split a decimal numeral into whether it is negative and its integer
+/// and fraction digits, without leading or trailing zeros.
+fn decimal_parts(lexical: &str) -> Option<(bool, &str, &str)> {
+    let (negative, digits) = match lexical.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, lexical.strip_prefix('+').unwrap_or(lexical)),
+    };
+    let (int, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    if int.is_empty() && fraction.is_empty() {
+        return None;
+    }
+    if !int
+        .chars()
+        .chain(fraction.chars())
+        .all(|c| c.is_ascii_digit())
+    {
+        return None;
+    }
+    let int = int.trim_start_matches('0');
+    let fraction = fraction.trim_end_matches('0');
+    let zero = int.is_empty() && fraction.is_empty();
+
+    Some((negative && !zero, int, fraction))
+}
+
+/// Compare two decimal numerals by value, whatever their length.
+fn decimal_cmp(a: &str, b: &str) -> Option<Ordering> {
+    let (a_negative, a_int, a_fraction) = decimal_parts(a)?;
+    let (b_negative, b_int, b_fraction) = decimal_parts(b)?;
+    let magnitude = a_int
+        .len()
+        .cmp(&b_int.len())
+        .then(a_int.cmp(b_int))
+        .then(a_fraction.cmp(b_fraction));
+    Some(match (a_negative, b_negative) {
+        (false, false) => magnitude,
+        (true, true) => magnitude.reverse(),
+        (true, false) => Ordering::Less,
+        (false, true) => Ordering::Greater,
+    })
+}
+
+/// Undo the sort-preserving encoding of a stored value. Numbers are stored
+/// big-endian, with the sign bit of signed integers flipped and floats
+/// arranged to sort by value. Arbitrary precision integers and decimals
+/// are decoded through the store's own datatypes.
+pub fn decode(datatype: &str, bytes: &[u8]) -> Option<Decoded> {
+    let int = |n: u64| Some(Decoded::Int(n as i128));
+    match (datatype, bytes.len()) {
+        ("UInt32", 4) => int(u32::from_be_bytes(bytes.try_into().unwrap()) as u64),
+        ("Int32", 4) => Some(Decoded::Int(
+            (u32::from_be_bytes(bytes.try_into().unwrap()) ^ 0x8000_0000) as i32 as i128,
+        )),
+        ("UInt64", 8) => int(u64::from_be_bytes(bytes.try_into().unwrap())),
+        ("Int64", 8) => Some(Decoded::Int(
+            (u64::from_be_bytes(bytes.try_into().unwrap()) ^ 0x8000_0000_0000_0000) as i64 as i128,
+        )),
+        ("Float32", 4) => {
+            let bits = u32::from_be_bytes(bytes.try_into().unwrap());
+            let bits = if bits & 0x8000_0000 != 0 {
+                bits ^ 0x8000_0000
+            } else {
+                !bits
+            };
+            Some(Decoded::Float(f32::from_bits(bits) as f64))
+        }
+        ("Float64", 8) => {
+            let bits = u64::from_be_bytes(bytes.try_into().unwrap());
+            let bits = if bits & 0x8000_0000_0000_0000 != 0 {
+                bits ^ 0x8000_0000_0000_0000
+            } else {
+                !bits
+            };
+            Some(Decoded::Float(f64::from_bits(bits)))
+        }
+        ("Boolean", 1) => int(bytes[0] as u64),
+        ("BigInt", _) => number(&Integer::from_lexical(bytes).to_string()),
+        ("Decimal", _) => number(&Decimal::from_lexical(bytes).0),
+        _ => Some(Decoded::Text(String::from_utf8_lossy(bytes).to_string())),
+    }
+}
+
+/// The language tag of a language-tagged string, which is stored as
+/// `tag@text`.
+pub fn lang_tag(datatype: &str, bytes: &[u8]) -> Option<String> {
+    if datatype != "LangString" {
+        return None;
+    }
+    let text = String::from_utf8_lossy(bytes);
+    text.split_once('@').map(|(tag, _)| tag.to_string())
+}
+
+/// A stored value written as a Turtle literal, `"value"^^xsd:type` or
+/// `"text"@tag`.
+pub fn typed_literal(datatype: &str, bytes: &[u8]) -> String {
+    turtle_literal(&literal(datatype, bytes))
+}
+
+/// Whether an object is a string tagged with the given language.
+pub fn has_lang(object: &ObjectType, lang: &str) -> bool {
+    match object {
+        ObjectType::Value(value) => {
+            lang_tag(&format!("{:?}", value.datatype()), &value.to_bytes()).as_deref() == Some(lang)
+        }
+        ObjectType::Node(_) => false,
+    }
+}
+
+/// Count the language-tagged strings of a layer's chain per tag.
+pub fn lang_stats(layer: &SyncStoreLayer) -> BTreeMap<String, u64> {
+    let mut result = BTreeMap::new();
+    for id in 1..=layer.node_and_value_count() as u64 {
+        if let Some(ObjectType::Value(value)) = layer.id_object(id) {
+            if let Some(tag) = lang_tag(&format!("{:?}", value.datatype()), &value.to_bytes()) {
+                *result.entry(tag).or_default() += 1;
+            }
+        }
+    }
+
+    result
+}
+
+/// What to look for in the value dictionary.
+pub enum ValueQuery {
+    Equals(String),
+    /// Both bounds are inclusive.
+    Range(String, String),
+}
+
+/// Parse a range written as `A..B`.
+pub fn parse_range(s: &str) -> Result<(String, String), String> {
+    match s.split_once("..") {
+        Some((low, high)) => Ok((low.to_string(), high.to_string())),
+        None => Err("expected a range of the form A..B".to_string()),
+    }
+}
+
+/// A value found by a search.
+pub struct ValueMatch {
+    pub id: u64,
+    pub datatype: String,
+    pub value: Decoded,
+}
+
+/// Find the values of a layer's chain that match a query, optionally only
+/// those of one datatype or language. Values are compared after decoding,
+/// so `42` matches a stored integer whatever its width.
+pub fn search(
+    layer: &SyncStoreLayer,
+    query: &ValueQuery,
+    datatype: Option<&str>,
+    lang: Option<&str>,
+) -> Vec<ValueMatch> {
+    let datatype = datatype.map(datatype_name);
+    let mut result = Vec::new();
+    for id in 1..=layer.node_and_value_count() as u64 {
+        let value = match layer.id_object(id) {
+            Some(ObjectType::Value(value)) => value,
+            _ => continue,
+        };
+        let name = format!("{:?}", value.datatype());
+        if datatype.as_ref().map(|d| *d != name).unwrap_or(false) {
+            continue;
+        }
+        if lang.is_some() && lang_tag(&name, &value.to_bytes()).as_deref() != lang {
+            continue;
+        }
+        let decoded = match decode(&name, &value.to_bytes()) {
+            Some(decoded) => decoded,
+            None => continue,
+        };
+        let parse = |lexical: &str| Decoded::parse(lexical, &name);
+        let matches = match query {
+            ValueQuery::Equals(lexical) => {
+                parse(lexical).and_then(|q| decoded.compare(&q)) == Some(Ordering::Equal)
+            }
+            ValueQuery::Range(low, high) => {
+                let above = parse(low).and_then(|low| decoded.compare(&low));
+                let below = parse(high).and_then(|high| decoded.compare(&high));
+                matches!(above, Some(Ordering::Greater | Ordering::Equal))
+                    && matches!(below, Some(Ordering::Less | Ordering::Equal))
+            }
+        };
+        if matches {
+            result.push(ValueMatch {
+                id,
+                datatype: name,
+                value: decoded,
+            });
+        }
+    }
+
+    result
+}
+
+/// The ids of the values of a layer's chain whose decoded text contains
+/// the given string. Numbers are matched on their decimal form.
+pub fn containing(layer: &SyncStoreLayer, needle: &str) -> Vec<u64> {
+    let mut result = Vec::new();
+    for id in 1..=layer.node_and_value_count() as u64 {
+        if let Some(ObjectType::Value(value)) = layer.id_object(id) {
+            let decoded = decode(&format!("{:?}", value.datatype()), &value.to_bytes());
+            if decoded.map(|d| d.to_string().contains(needle)) == Some(true) {
+                result.push(id);
+            }
+        }
+    }
+
+    result
+}
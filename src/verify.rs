@@ -0,0 +1,304 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::Cursor,
+};
+
+use bytes::Bytes;
+use futures::StreamExt;
+use rand::Rng;
+use rayon::prelude::*;
+use terminus_store::{
+    storage::consts::LayerFileEnum,
+    structure::{stream::TfcDictStream, LogArray},
+};
+
+use crate::{
+    archive::{bitindex_segments, Archive},
+    checksum::verify_checksums,
+    codes::FindingCode,
+    dict::{find_truncations, validate_dict, DictType},
+    validate::{validate_archive, Finding},
+};
+
+/// Number of 64-bit words covered by one bitindex superblock.
+pub const SBLOCK_SIZE: usize = 52;
+
+/// Number of random blocks checked per bitindex in quick mode.
+const QUICK_PROBES: usize = 16;
+
+/// Number of entries decoded from the first and last dictionary blocks in
+/// quick mode.
+const QUICK_DICT_ENTRIES: usize = 8;
+
+fn finding(code: FindingCode, segment: LayerFileEnum, message: String) -> Finding {
+    Finding {
+        code,
+        segment: Some(segment),
+        message,
+    }
+}
+
+/// Decode up to `limit` dictionary entries starting at `offset` within the
+/// blocks segment, which must be a block boundary.
+async fn decode_entries(blocks: &Bytes, offset: usize, limit: usize) -> Result<usize, String> {
+    if offset > blocks.len() {
+        return Err(format!(
+            "block offset {offset} lies past the end of the segment ({} bytes)",
+            blocks.len()
+        ));
+    }
+    let mut stream = TfcDictStream::new(Cursor::new(blocks.slice(offset..))).take(limit);
+    let mut count = 0;
+    while let Some(element) = stream.next().await {
+        if let Err(e) = element {
+            return Err(format!("entry {count} after offset {offset}: {e}"));
+        }
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+async fn verify_dict(archive: &Archive, t: DictType, quick: bool, findings: &mut Vec<Finding>) {
+    let segment = t.blocks_segment();
+    let blocks = match archive.segment(segment) {
+        Ok(Some(blocks)) => blocks,
+        // absence and bounds errors are reported by the structural checks
+        _ => return,
+    };
+    if !quick {
+        if let Err(message) = validate_dict(archive, t).await {
+            findings.push(finding(FindingCode::DictionaryInvalid, segment, message));
+        }
+        if t == DictType::Values {
+            for truncation in find_truncations(archive, t).into_iter().flatten() {
+                findings.push(finding(
+                    FindingCode::ValueTruncated,
+                    segment,
+                    format!(
+                        "block {} entry {}: {}",
+                        truncation.block, truncation.entry, truncation.message
+                    ),
+                ));
+            }
+        }
+        return;
+    }
+
+    let last_block = match archive.segment(t.offsets_segment()).ok().flatten() {
+        Some(offsets) => match LogArray::parse(offsets) {
+            Ok(offsets) => match offsets.len() {
+                0 => 0,
+                n => offsets.entry(n - 1) as usize,
+            },
+            Err(_) => 0,
+        },
+        None => 0,
+    };
+    for offset in [0, last_block] {
+        if let Err(message) = decode_entries(&blocks, offset, QUICK_DICT_ENTRIES).await {
+            findings.push(finding(FindingCode::DictionaryInvalid, segment, message));
+        }
+    }
+}
+
+/// Which logarray of a bitindex an entry or size belongs to.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum BitIndexPart {
+    Blocks,
+    SBlocks,
+}
+
+/// A bitindex entry that disagrees with its bits.
+pub struct BitIndexMismatch {
+    pub part: BitIndexPart,
+    pub index: usize,
+    pub found: u64,
+    pub expected: u64,
+}
+
+impl BitIndexMismatch {
+    pub fn message(&self) -> String {
+        match self.part {
+            BitIndexPart::Blocks => format!(
+                "block {} counts {} ones to the end of its superblock, bits have {}",
+                self.index, self.found, self.expected
+            ),
+            BitIndexPart::SBlocks => format!(
+                "superblock {} accounts for {} ones, bits have {}",
+                self.index, self.found, self.expected
+            ),
+        }
+    }
+}
+
+/// Number of 64-bit words in the bits of a bitindex, which are followed by
+/// a control word.
+pub fn bitindex_words(bits: &[u8]) -> usize {
+    bits.len().saturating_sub(8) / 8
+}
+
+fn word_ones(bits: &[u8], i: usize) -> u64 {
+    u64::from_be_bytes(bits[i * 8..i * 8 + 8].try_into().unwrap()).count_ones() as u64
+}
+
+/// Check that a bitindex has a block for every word of its bits and a
+/// superblock for every `SBLOCK_SIZE` words.
+pub fn check_bitindex_lengths(
+    words: usize,
+    blocks: &LogArray,
+    sblocks: &LogArray,
+) -> Option<(BitIndexPart, String)> {
+    if blocks.len() != words {
+        return Some((
+            BitIndexPart::Blocks,
+            format!("{} blocks for {words} words of bits", blocks.len()),
+        ));
+    }
+    let sblock_count = words.div_ceil(SBLOCK_SIZE);
+    if sblocks.len() != sblock_count {
+        return Some((
+            BitIndexPart::SBlocks,
+            format!("{} sblocks for {sblock_count} superblocks", sblocks.len()),
+        ));
+    }
+
+    None
+}
+
+/// Check superblock `j` of a bitindex, and its blocks, against the bits.
+/// terminus-store builds each block as the ones from its word to the end
+/// of its superblock, and each superblock as the running count of ones up
+/// to its end; rank subtracts the one from the other. `block_indexes`
+/// picks the blocks to check, which must lie in the superblock; None
+/// checks them all. The lengths must have been checked first.
+pub fn check_sblock(
+    bits: &[u8],
+    blocks: &LogArray,
+    sblocks: &LogArray,
+    j: usize,
+    block_indexes: Option<&BTreeSet<usize>>,
+) -> Vec<BitIndexMismatch> {
+    let start = j * SBLOCK_SIZE;
+    let end = (start + SBLOCK_SIZE).min(bitindex_words(bits));
+    let mut to_end = vec![0; end - start + 1];
+    for i in (start..end).rev() {
+        to_end[i - start] = to_end[i - start + 1] + word_ones(bits, i);
+    }
+
+    let block_indexes: Vec<usize> = match block_indexes {
+        Some(indexes) => indexes.iter().copied().collect(),
+        None => (start..end).collect(),
+    };
+    let mut mismatches = Vec::new();
+    for i in block_indexes {
+        if blocks.entry(i) != to_end[i - start] {
+            mismatches.push(BitIndexMismatch {
+                part: BitIndexPart::Blocks,
+                index: i,
+                found: blocks.entry(i),
+                expected: to_end[i - start],
+            });
+        }
+    }
+    let previous = if j == 0 { 0 } else { sblocks.entry(j - 1) };
+    if sblocks.entry(j).wrapping_sub(previous) != to_end[0] {
+        mismatches.push(BitIndexMismatch {
+            part: BitIndexPart::SBlocks,
+            index: j,
+            found: sblocks.entry(j).wrapping_sub(previous),
+            expected: to_end[0],
+        });
+    }
+
+    mismatches
+}
+
+/// Check that the blocks and sblocks of a bitindex agree with its bits.
+/// Quick mode only checks a few random blocks and the superblocks they
+/// belong to.
+fn verify_bitindex(
+    archive: &Archive,
+    (bits_type, blocks_type, sblocks_type): (LayerFileEnum, LayerFileEnum, LayerFileEnum),
+    quick: bool,
+    findings: &mut Vec<Finding>,
+) {
+    let (bits, blocks, sblocks) = match (
+        archive.segment(bits_type),
+        archive.segment(blocks_type),
+        archive.segment(sblocks_type),
+    ) {
+        (Ok(Some(bits)), Ok(Some(blocks)), Ok(Some(sblocks))) if bits.len() >= 8 => {
+            match (LogArray::parse(blocks), LogArray::parse(sblocks)) {
+                (Ok(blocks), Ok(sblocks)) => (bits, blocks, sblocks),
+                _ => return,
+            }
+        }
+        _ => return,
+    };
+    let words = bitindex_words(&bits);
+    if let Some((part, message)) = check_bitindex_lengths(words, &blocks, &sblocks) {
+        let segment = match part {
+            BitIndexPart::Blocks => blocks_type,
+            BitIndexPart::SBlocks => sblocks_type,
+        };
+        findings.push(finding(FindingCode::BitIndexLength, segment, message));
+        return;
+    }
+
+    // check the superblocks in parallel, each with all its blocks or, in
+    // quick mode, with the sampled blocks that fall in it. Findings come
+    // out in superblock order regardless.
+    let by_sblock: Vec<(usize, Option<BTreeSet<usize>>)> = if quick {
+        let mut rng = rand::thread_rng();
+        let mut by_sblock: BTreeMap<usize, BTreeSet<usize>> = BTreeMap::new();
+        for _ in 0..QUICK_PROBES.min(words) {
+            let i = rng.gen_range(0..words);
+            by_sblock.entry(i / SBLOCK_SIZE).or_default().insert(i);
+        }
+        by_sblock.into_iter().map(|(j, i)| (j, Some(i))).collect()
+    } else {
+        (0..words.div_ceil(SBLOCK_SIZE))
+            .map(|j| (j, None))
+            .collect()
+    };
+    let results: Vec<Vec<Finding>> = by_sblock
+        .par_iter()
+        .map(|(j, block_indexes)| {
+            check_sblock(&bits, &blocks, &sblocks, *j, block_indexes.as_ref())
+                .into_iter()
+                .map(|mismatch| match mismatch.part {
+                    BitIndexPart::Blocks => finding(
+                        FindingCode::BitIndexBlockMismatch,
+                        blocks_type,
+                        mismatch.message(),
+                    ),
+                    BitIndexPart::SBlocks => finding(
+                        FindingCode::BitIndexSBlockMismatch,
+                        sblocks_type,
+                        mismatch.message(),
+                    ),
+                })
+                .collect()
+        })
+        .collect();
+    findings.extend(results.into_iter().flatten());
+}
+
+/// Verify an archive. Beyond the structural checks this decodes the
+/// dictionaries, checking that they are sorted, and checks bitindexes
+/// against their bits. In quick mode only the first and last dictionary
+/// blocks and a random sample of bitindex blocks are checked. If the
+/// archive carries a checksum trailer, every segment is compared against it.
+pub async fn verify(archive: &Archive, quick: bool) -> Vec<Finding> {
+    let mut findings = validate_archive(archive);
+    findings.extend(verify_checksums(archive));
+    for t in [DictType::Nodes, DictType::Predicates, DictType::Values] {
+        verify_dict(archive, t, quick, &mut findings).await;
+    }
+    for segments in bitindex_segments() {
+        verify_bitindex(archive, segments, quick, &mut findings);
+    }
+
+    findings
+}
@@ -0,0 +1,92 @@
+use std::{collections::HashSet, io, path::Path, time::Duration};
+
+use notify::{EventKind, RecursiveMode, Watcher};
+
+use crate::{
+    checks::Check,
+    fsck::{check_layer, print_result},
+    output::OutputFormat,
+};
+
+/// Watch a store directory and validate every layer archive that gets
+/// written to it. Runs until the watcher fails.
+///
+/// A layer is validated `settle` after the last event for it, so that
+/// archives still being written aren't reported as truncated. When
+/// `on_failure` is given, it is run through `sh -c` for every failing layer
+/// with the layer path and findings in `SURGERY_LAYER` and
+/// `SURGERY_FINDINGS`.
+pub async fn watch(
+    store: &Path,
+    settle: Duration,
+    on_failure: Option<String>,
+    checks: &[Box<dyn Check>],
+    format: OutputFormat,
+) -> io::Result<()> {
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        let _ = tx.send(res);
+    })
+    .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    watcher
+        .watch(store, RecursiveMode::Recursive)
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    eprintln!("watching {} for new layers", store.display());
+
+    let mut pending = HashSet::new();
+    loop {
+        // wait for activity, then keep collecting until the store is quiet
+        // for the settle period
+        let mut next = rx.recv().await;
+        loop {
+            match next {
+                None => return Ok(()),
+                Some(Err(e)) => return Err(io::Error::new(io::ErrorKind::Other, e)),
+                Some(Ok(event)) => {
+                    if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                        pending.extend(
+                            event
+                                .paths
+                                .into_iter()
+                                .filter(|p| p.extension().map(|e| e == "larch") == Some(true)),
+                        );
+                    }
+                }
+            }
+            match tokio::time::timeout(settle, rx.recv()).await {
+                Ok(event) => next = event,
+                Err(_) => break,
+            }
+        }
+
+        for path in pending.drain() {
+            if !path.exists() {
+                continue;
+            }
+            let findings = check_layer(&path, checks).await;
+            print_result(&path.display().to_string(), &findings, format);
+            if findings.is_empty() {
+                continue;
+            }
+            if let Some(command) = on_failure.as_ref() {
+                let status = tokio::process::Command::new("sh")
+                    .arg("-c")
+                    .arg(command)
+                    .env("SURGERY_LAYER", &path)
+                    .env(
+                        "SURGERY_FINDINGS",
+                        findings
+                            .iter()
+                            .map(|f| f.to_string())
+                            .collect::<Vec<_>>()
+                            .join("\n"),
+                    )
+                    .status()
+                    .await?;
+                if !status.success() {
+                    eprintln!("failure hook exited with {status}");
+                }
+            }
+        }
+    }
+}